@@ -28,6 +28,9 @@
 // - Genre: Genre
 // - Comment: Comment
 // - Lyrics: Lyrics
+// - Encoder: Encoder
+
+use std::io::{Read, Seek, SeekFrom};
 
 pub const APE_SIGNATURE: &[u8; 8] = b"APETAGEX";
 #[allow(dead_code)]
@@ -43,12 +46,12 @@ pub mod fields {
     pub const GENRE: &str = "Genre";
     pub const COMMENT: &str = "Comment";
     pub const LYRICS: &str = "Lyrics";
+    pub const ENCODER: &str = "Encoder";
 }
 
 // APE tag flags
 #[allow(dead_code)]
 pub mod flags {
-    #[allow(dead_code)]
     pub const CONTAINS_HEADER: u32 = 0x80000000;
     #[allow(dead_code)]
     pub const CONTAINS_FOOTER: u32 = 0x40000000;
@@ -73,12 +76,18 @@ pub struct ApeTagHeader {
 #[derive(Debug, Clone)]
 pub struct ApeTagItem {
     pub size: u32,
-    #[allow(dead_code)]
     pub flags: u32,
     pub key: String,
     pub value: Vec<u8>,
 }
 
+impl ApeTagItem {
+    /// Whether the item holds binary data (bits 1-2 of the flags set to 1)
+    pub fn is_binary(&self) -> bool {
+        (self.flags >> 1) & 0b11 == 1
+    }
+}
+
 /// APE metadata handler
 pub struct ApeFile {
     pub path: String,
@@ -103,6 +112,39 @@ impl ApeFile {
         Ok(None)
     }
 
+    /// Keys of the tag items in file order, without their values
+    pub fn read_item_keys(&self) -> std::io::Result<Vec<String>> {
+        let file_data = std::fs::read(&self.path)?;
+        Ok(self.parse_ape_tag(&file_data)
+            .map(|(_header, items)| items.into_iter().map(|item| item.key).collect())
+            .unwrap_or_default())
+    }
+
+    /// Count items holding binary data, such as embedded cover art
+    pub fn count_binary_items(&self) -> std::io::Result<usize> {
+        let file_data = std::fs::read(&self.path)?;
+        Ok(self.parse_ape_tag(&file_data)
+            .map(|(_header, items)| items.iter().filter(|item| item.is_binary()).count())
+            .unwrap_or(0))
+    }
+
+    /// Read the "Cover Art (...)" items as (item key, image data)
+    ///
+    /// A cover item's value is a file name, a NUL and the image data.
+    pub fn read_cover_items(&self) -> std::io::Result<Vec<(String, Vec<u8>)>> {
+        let file_data = std::fs::read(&self.path)?;
+        let Some((_header, items)) = self.parse_ape_tag(&file_data) else {
+            return Ok(Vec::new());
+        };
+        Ok(items.into_iter()
+            .filter(|item| item.is_binary() && item.key.to_ascii_lowercase().starts_with("cover art"))
+            .filter_map(|item| {
+                let name_end = item.value.iter().position(|&byte| byte == 0)?;
+                Some((item.key, item.value[name_end + 1..].to_vec()))
+            })
+            .collect())
+    }
+
     /// Parse APE tag from file data
     fn parse_ape_tag(&self, data: &[u8]) -> Option<(ApeTagHeader, Vec<ApeTagItem>)> {
         // Minimum file size: footer (32 bytes)
@@ -222,6 +264,7 @@ impl ApeFile {
                 fields::GENRE => metadata.genre = Some(value),
                 fields::COMMENT => metadata.comment = Some(value),
                 fields::LYRICS => metadata.lyrics = Some(value),
+                fields::ENCODER => metadata.encoder = Some(value),
                 _ => {}
             }
         }
@@ -253,6 +296,29 @@ pub struct ApeMetadata {
     pub genre: Option<String>,
     pub comment: Option<String>,
     pub lyrics: Option<String>,
+    pub encoder: Option<String>,
+}
+
+/// Total size of an APE tag (items, footer and optional header) ending at `end`
+///
+/// Returns 0 when no APE tag footer is found at that position.
+pub fn ape_tag_size_before<R: Read + Seek>(reader: &mut R, end: u64) -> std::io::Result<u64> {
+    if end < 32 {
+        return Ok(0);
+    }
+
+    reader.seek(SeekFrom::Start(end - 32))?;
+    let mut footer = [0u8; 32];
+    reader.read_exact(&mut footer)?;
+    if &footer[0..8] != APE_SIGNATURE {
+        return Ok(0);
+    }
+
+    let tag_size = u32::from_le_bytes(footer[12..16].try_into().unwrap()) as u64;
+    let tag_flags = u32::from_le_bytes(footer[20..24].try_into().unwrap());
+    let total = if tag_flags & flags::CONTAINS_HEADER != 0 { tag_size + 32 } else { tag_size };
+
+    Ok(total.min(end))
 }
 
 /// Detect if file is APE format
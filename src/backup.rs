@@ -0,0 +1,165 @@
+// Field-level tag backups
+//
+// Each audio file becomes one JSON line holding its path, format, audio
+// checksum, standard fields, extra fields (see `AudioFile::get_extra_fields`)
+// and cover. Covers are either inlined as base64 or referenced by SHA-256 and
+// size; a referenced cover can't be restored, so importing leaves the file's
+// current cover in place. Entries can be matched back to files by path or,
+// to survive renames, by audio checksum.
+
+use crate::{AudioFile, AudioFileError, AudioResult, CoverArt, MergePolicy, Metadata};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How covers are stored in a backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoverMode {
+    /// SHA-256 and size only
+    #[default]
+    Hash,
+    /// The image itself, base64-encoded
+    Inline,
+}
+
+/// Cover of a backed-up file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CoverBackup {
+    Inline(CoverArt),
+    Reference {
+        sha256: String,
+        size: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+    },
+}
+
+/// Backup of one file's tags
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub path: String,
+    pub format: String,
+    /// Audio checksum as 16 hex digits (see `AudioFile::get_audio_checksum`)
+    pub audio_checksum: String,
+    /// Standard fields, without the cover
+    pub metadata: Metadata,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<CoverBackup>,
+}
+
+impl BackupEntry {
+    /// Back up the tags of the file at `path`
+    pub fn from_file(path: &str, covers: CoverMode) -> AudioResult<Self> {
+        let audio = AudioFile::new(path.to_string())?;
+        let mut metadata = audio.read_metadata()?;
+        let cover = metadata.cover.take().map(|cover| match covers {
+            CoverMode::Inline => CoverBackup::Inline(cover),
+            CoverMode::Hash => CoverBackup::Reference {
+                sha256: format!("{:x}", Sha256::digest(&cover.data)),
+                size: cover.data.len(),
+                mime_type: cover.mime_type,
+            },
+        });
+        // The read-only raw genre would only make backups differ between formats
+        metadata.genre_raw = None;
+
+        Ok(BackupEntry {
+            path: path.to_string(),
+            format: audio.format.to_string(),
+            audio_checksum: format_checksum(audio.get_audio_checksum()?),
+            metadata,
+            extra: audio.get_extra_fields()?,
+            cover,
+        })
+    }
+
+    /// Parse one line of a backup file
+    pub fn parse(line: &str) -> AudioResult<Self> {
+        serde_json::from_str(line).map_err(|e| AudioFileError::ParseError(e.to_string()))
+    }
+
+    /// Encode the entry as one line of a backup file
+    pub fn to_line(&self) -> AudioResult<String> {
+        serde_json::to_string(self).map_err(|e| AudioFileError::ParseError(e.to_string()))
+    }
+
+    /// Write the backed-up tags to `audio`
+    ///
+    /// With `only_missing`, fields the file already has keep their value;
+    /// otherwise the standard fields are replaced wholesale. Extra fields are
+    /// added or overwritten but never removed.
+    pub fn restore(&self, audio: &AudioFile, only_missing: bool) -> AudioResult<()> {
+        let current = audio.read_metadata()?;
+        let mut metadata = self.metadata.clone();
+        metadata.cover = match &self.cover {
+            Some(CoverBackup::Inline(cover)) => Some(cover.clone()),
+            Some(CoverBackup::Reference { .. }) | None => current.cover.clone(),
+        };
+        if only_missing {
+            let mut merged = current;
+            merged.merge(&metadata, MergePolicy::FillEmpty);
+            metadata = merged;
+        }
+        audio.write_metadata(&metadata)?;
+
+        let existing = audio.get_extra_fields()?;
+        let extra: Vec<(String, String)> = self.extra.iter()
+            .filter(|(key, _)| !only_missing || !existing.iter().any(|(existing, _)| existing.eq_ignore_ascii_case(key)))
+            .cloned()
+            .collect();
+        if !extra.is_empty() {
+            audio.set_extra_fields(&extra)?;
+        }
+        Ok(())
+    }
+}
+
+/// Format an audio checksum the way backups store it
+pub fn format_checksum(checksum: u64) -> String {
+    format!("{:016x}", checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_backup_round_trip() {
+        let cover = serde_json::json!({ "cover": CoverArt::new(vec![0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 0xFF, 0xD9], None, None) });
+        let files = [
+            ("backup.flac", build_flac_file("vendor", &[("TITLE", "Song"), ("GENRE", "Rock"), ("MOOD", "Calm")], 64), build_flac_file("vendor", &[], 64)),
+            ("backup.mp3", build_id3v2_file(&[("TIT2", b"\x00Song".to_vec()), ("TXXX", b"\x00MOOD\x00Calm".to_vec())]), build_id3v2_file(&[])),
+        ];
+        for (name, tagged, stripped) in files {
+            let path = write_temp_file(name, &tagged);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            audio.set_metadata(cover.to_string()).unwrap();
+            assert_eq!(audio.get_extra_fields().unwrap(), [("MOOD".to_string(), "Calm".to_string())], "{}", name);
+
+            let export = BackupEntry::from_file(&path, CoverMode::Inline).unwrap().to_line().unwrap();
+            let hashed = BackupEntry::from_file(&path, CoverMode::Hash).unwrap();
+            assert!(hashed.to_line().unwrap().contains("\"size\":8"), "{}", name);
+
+            // Strip every tag, restore from the backup and export again
+            std::fs::write(&path, &stripped).unwrap();
+            assert!(audio.read_metadata().unwrap().title.is_none(), "{}", name);
+            BackupEntry::parse(&export).unwrap().restore(&audio, false).unwrap();
+            assert_eq!(BackupEntry::from_file(&path, CoverMode::Inline).unwrap().to_line().unwrap(), export, "{}", name);
+
+            // Only missing fields are filled, and a cover reference keeps the current cover
+            audio.set_metadata(r#"{"title": "Renamed", "artist": null}"#.to_string()).unwrap();
+            let mut entry = BackupEntry::parse(&export).unwrap();
+            entry.metadata.artist = Some("Artist".to_string());
+            hashed.restore(&audio, true).unwrap();
+            entry.restore(&audio, true).unwrap();
+            let metadata = audio.read_metadata().unwrap();
+            assert_eq!(metadata.title.as_deref(), Some("Renamed"), "{}", name);
+            assert_eq!(metadata.artist.as_deref(), Some("Artist"), "{}", name);
+            assert!(metadata.cover.is_some(), "{}", name);
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
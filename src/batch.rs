@@ -0,0 +1,96 @@
+// Writing tags to many files at once
+//
+// `apply` hands (path, metadata) pairs to a fixed number of worker threads,
+// each taking the next unclaimed item until none are left. Every write goes
+// through `AudioFile::write_metadata`, so through the same temp-file-and-rename
+// path as single writes. A path listed twice (compared after resolving
+// symlinks and relative parts) is never written: both entries fail, so no two
+// threads can touch one file and no entry silently wins. Workers log to the
+// caller's `tracing` subscriber, so a scoped subscriber sees the whole batch.
+
+use crate::{AudioFile, AudioFileError, AudioResult, Metadata};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Write each item's metadata to its file using up to `jobs` threads
+///
+/// Returns one result per item, in the order given. `jobs` of 0 is treated
+/// as 1.
+pub fn apply(items: Vec<(String, Metadata)>, jobs: usize) -> Vec<(String, AudioResult<()>)> {
+    let keys: Vec<PathBuf> = items.iter()
+        .map(|(path, _)| std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)))
+        .collect();
+    let duplicated = |index: usize| keys.iter().filter(|key| **key == keys[index]).count() > 1;
+
+    let results: Vec<Mutex<Option<AudioResult<()>>>> = items.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    let workers = jobs.clamp(1, items.len().max(1));
+    let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| tracing::dispatcher::with_default(&dispatch, || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some((path, metadata)) = items.get(index) else {
+                    break;
+                };
+                let opened = if duplicated(index) {
+                    Err(AudioFileError::ParseError(format!("{} is listed more than once", path)))
+                } else {
+                    AudioFile::new(path.clone())
+                };
+                let result = match opened {
+                    // Writes log their own outcome
+                    Ok(audio) => audio.write_metadata(metadata),
+                    Err(e) => {
+                        tracing::error!(event = "error", path = %path, message = %e);
+                        Err(e)
+                    }
+                };
+                *results[index].lock().unwrap() = Some(result);
+            }));
+        }
+    });
+
+    items.into_iter()
+        .zip(results)
+        .map(|((path, _), result)| (path, result.into_inner().unwrap().expect("every item is processed")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_apply_writes_files_in_parallel() {
+        let flac = build_flac_file("ref", &[], 64);
+        let paths: Vec<String> = (0..50).map(|i| write_temp_file(&format!("apply-{}.flac", i), &flac)).collect();
+        let items = paths.iter().enumerate()
+            .map(|(i, path)| (path.clone(), Metadata { title: Some(format!("Track {}", i)), ..Default::default() }))
+            .collect();
+
+        let results = apply(items, 8);
+        assert_eq!(results.len(), 50);
+        for (i, (path, result)) in results.iter().enumerate() {
+            assert_eq!(path, &paths[i]);
+            assert!(result.is_ok(), "{}: {:?}", path, result);
+            let title = AudioFile::new(path.clone()).unwrap().read_metadata().unwrap().title;
+            assert_eq!(title, Some(format!("Track {}", i)));
+        }
+
+        // The same file twice is never written
+        let twice = vec![
+            (paths[0].clone(), Metadata { title: Some("A".to_string()), ..Default::default() }),
+            (paths[0].clone(), Metadata { title: Some("B".to_string()), ..Default::default() }),
+        ];
+        assert!(apply(twice, 2).iter().all(|(_, result)| result.is_err()));
+        assert_eq!(AudioFile::new(paths[0].clone()).unwrap().read_metadata().unwrap().title.as_deref(), Some("Track 0"));
+
+        for path in paths {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
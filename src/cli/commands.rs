@@ -136,13 +136,11 @@ fn command_copy(source: String, targets: Vec<String>, formatter: &crate::cli_out
     let source_audio = oxidant::AudioFile::new(source.clone())
         .map_err(|e| CliError::Other(format!("Failed to open source file: {}", e)))?;
 
-    let metadata_json = source_audio.get_metadata()
-        .map_err(|e| CliError::Other(e.to_string()))?;
-
     for target in targets {
         match oxidant::AudioFile::new(target.clone()) {
             Ok(audio) => {
-                match audio.set_metadata(metadata_json.clone()) {
+                // Values are converted for the target's format, not copied as JSON
+                match audio.copy_metadata_from(&source_audio) {
                     Ok(()) => {
                         formatter.print_success(&format!("Copied metadata to {}", target));
                     }
@@ -297,7 +295,7 @@ fn command_detect(files: Vec<String>, formatter: &OutputFormatter) -> CliResult<
         match oxidant::AudioFile::new(file_path.clone()) {
             Ok(audio) => {
                 formatter.print_info(&format!("{}: {} (version: {})",
-                    file_path, audio.file_type, audio.get_version().unwrap_or_else(|| "N/A".to_string())));
+                    file_path, audio.format, audio.get_version().unwrap_or_else(|| "N/A".to_string())));
             }
             Err(e) => {
                 formatter.print_error(&format!("{}: Unknown format ({})", file_path, e));
@@ -380,7 +378,7 @@ fn command_info(files: Vec<String>, detailed: bool, formatter: &OutputFormatter)
         // Detect audio format
         let (format_type, version) = match oxidant::AudioFile::new(file_path.clone()) {
             Ok(audio) => {
-                let fmt = audio.file_type.clone();
+                let fmt = audio.format.to_string();
                 let ver = audio.get_version();
                 (Some(fmt), ver.ok())
             }
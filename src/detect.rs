@@ -0,0 +1,189 @@
+// Recognizing which format a file holds
+//
+// Detection runs an ordered list of (Format, detector) pairs over a `Probe`
+// of the file's first and last bytes; the first detector that matches names
+// the format. The standard order, that of the format handlers, is:
+//
+//   ID3v2     "ID3" at the start
+//   FLAC      "fLaC" at the start
+//   Opus      "OggS" at the start and "Opus" at byte 28
+//   OGG       "OggS" at the start
+//   MP4       "ftyp" at byte 4
+//   DSF       "DSD " at the start
+//   APE       "APETAGEX" footer 32 bytes from the end
+//   ID3v1     "TAG" 128 bytes from the end
+//   MP3       an MPEG audio frame at the start, with no tag at all
+//
+// Tags at the start win over tags at the end, and APE is tried before
+// ID3v1. `AudioFile::new` uses the process-wide order, which embedders can
+// extend with `register` or replace with `set_default_order`;
+// `AudioFile::with_detection` takes an order for a single file.
+
+use crate::{AudioResult, AudioFileError, Format};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::RwLock;
+
+/// Bytes read from the start of the file for detection
+pub const HEAD_LEN: usize = 4096;
+/// Bytes read from the end of the file for detection
+pub const TAIL_LEN: usize = 128;
+
+/// What a detector gets to look at
+#[derive(Debug, Clone, Copy)]
+pub struct Probe<'a> {
+    /// The first `HEAD_LEN` bytes, or the whole file if shorter
+    pub head: &'a [u8],
+    /// The last `TAIL_LEN` bytes, or the whole file if shorter
+    pub tail: &'a [u8],
+    /// File length in bytes
+    pub len: u64,
+}
+
+impl Probe<'_> {
+    /// The `len` bytes starting `offset` bytes before the end, if the file has them
+    pub fn from_end(&self, offset: usize, len: usize) -> Option<&[u8]> {
+        let start = self.tail.len().checked_sub(offset)?;
+        self.tail.get(start..start + len)
+    }
+}
+
+/// A check for one format's signature
+pub type DetectFn = fn(&Probe) -> bool;
+
+/// Detectors in the order they are tried
+#[derive(Debug, Clone)]
+pub struct DetectionOrder {
+    detectors: Vec<(Format, DetectFn)>,
+}
+
+impl Default for DetectionOrder {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl DetectionOrder {
+    /// The built-in detectors, in the order described at the top of this module
+    pub fn standard() -> Self {
+        DetectionOrder {
+            detectors: crate::handler::HANDLERS.iter()
+                .map(|handler| (handler.format(), handler.detector()))
+                .collect(),
+        }
+    }
+
+    /// The formats in the order their detectors are tried; a format may repeat
+    pub fn formats(&self) -> Vec<Format> {
+        self.detectors.iter().map(|(format, _)| *format).collect()
+    }
+
+    /// Try `detect` before every other detector
+    pub fn register(&mut self, format: Format, detect: DetectFn) {
+        self.detectors.insert(0, (format, detect));
+    }
+
+    /// Try `detect` after every other detector
+    pub fn push(&mut self, format: Format, detect: DetectFn) {
+        self.detectors.push((format, detect));
+    }
+
+    /// Move the detectors for `format` to the front, keeping their order
+    pub fn prioritize(&mut self, format: Format) {
+        // A stable sort keeps the order within both groups
+        self.detectors.sort_by_key(|(candidate, _)| *candidate != format);
+    }
+
+    /// Drop every detector for `format`, so such files are no longer recognized by it
+    pub fn remove(&mut self, format: Format) {
+        self.detectors.retain(|(candidate, _)| *candidate != format);
+    }
+
+    /// The format named by the first matching detector
+    pub fn detect(&self, probe: &Probe) -> Option<Format> {
+        self.detectors.iter()
+            .find(|(_, detect)| detect(probe))
+            .map(|(format, _)| *format)
+    }
+
+    /// Read the start and end of the file at `path` and detect its format
+    pub fn detect_file(&self, path: &str) -> AudioResult<Format> {
+        let mut file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+
+        let mut head = Vec::with_capacity(HEAD_LEN);
+        (&mut file).take(HEAD_LEN as u64).read_to_end(&mut head)?;
+        let tail_len = len.min(TAIL_LEN as u64);
+        let mut tail = vec![0u8; tail_len as usize];
+        file.seek(SeekFrom::End(-(tail_len as i64)))?;
+        file.read_exact(&mut tail)?;
+
+        self.detect(&Probe { head: &head, tail: &tail, len })
+            .ok_or_else(|| AudioFileError::UnsupportedFormat("Unknown audio format".to_string()))
+    }
+}
+
+/// The process-wide order; `None` until changed, meaning `DetectionOrder::standard`
+static DEFAULT_ORDER: RwLock<Option<DetectionOrder>> = RwLock::new(None);
+
+/// The order `AudioFile::new` uses
+pub fn default_order() -> DetectionOrder {
+    DEFAULT_ORDER.read().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_default()
+}
+
+/// Replace the order `AudioFile::new` uses, for every thread
+pub fn set_default_order(order: DetectionOrder) {
+    *DEFAULT_ORDER.write().unwrap_or_else(|e| e.into_inner()) = Some(order);
+}
+
+/// Have `AudioFile::new` try `detect` before the other detectors
+pub fn register(format: Format, detect: DetectFn) {
+    let mut order = DEFAULT_ORDER.write().unwrap_or_else(|e| e.into_inner());
+    order.get_or_insert_with(DetectionOrder::standard).register(format, detect);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use crate::AudioFile;
+
+    #[test]
+    fn test_custom_detector_routes_file() {
+        // A WavPack block header, which no built-in detector knows
+        let mut data = b"wvpk\x20\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[0u8; 64]);
+        let path = write_temp_file("custom.wv", &data);
+        assert!(matches!(AudioFile::new(path.clone()), Err(AudioFileError::UnsupportedFormat(_))));
+
+        let mut order = DetectionOrder::standard();
+        order.register(Format::Mp3, |probe| probe.head.starts_with(b"wvpk"));
+        assert_eq!(order.formats()[0], Format::Mp3);
+        assert_eq!(AudioFile::with_detection(path.clone(), &order).unwrap().format, Format::Mp3);
+        std::fs::remove_file(path).ok();
+
+        // Registered globally, the detector applies to AudioFile::new
+        let path = write_temp_file("custom-global.bin", b"OXDTtest file");
+        register(Format::Id3v1, |probe| probe.head.starts_with(b"OXDT"));
+        assert_eq!(AudioFile::new(path.clone()).unwrap().format, Format::Id3v1);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_detection_order_decides_overlapping_formats() {
+        // Ends in both an APE footer and, 128 bytes from the end, "TAG"
+        let mut data = vec![0u8; 300];
+        data[300 - 128..300 - 125].copy_from_slice(b"TAG");
+        data[300 - 32..300 - 24].copy_from_slice(b"APETAGEX");
+        let path = write_temp_file("overlap.bin", &data);
+
+        let mut order = DetectionOrder::standard();
+        assert_eq!(AudioFile::with_detection(path.clone(), &order).unwrap().format, Format::Ape);
+        order.prioritize(Format::Id3v1);
+        assert_eq!(AudioFile::with_detection(path.clone(), &order).unwrap().format, Format::Id3v1);
+        order.remove(Format::Id3v1);
+        order.remove(Format::Ape);
+        assert!(AudioFile::with_detection(path.clone(), &order).is_err());
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,109 @@
+// DSF (DSD Stream File) support
+//
+// A DSF file is three chunks followed by an optional ID3v2 tag, all sizes
+// and offsets little-endian:
+//
+// - "DSD " chunk (28 bytes):
+//   - Signature: "DSD " (4 bytes)
+//   - Chunk size: 28 (8 bytes)
+//   - Total file size (8 bytes)
+//   - Offset of the metadata chunk, 0 if there is none (8 bytes)
+// - "fmt " chunk (52 bytes): format version, format ID, channel type,
+//   channel count, sampling frequency, bits per sample, sample count per
+//   channel, block size per channel
+// - "data" chunk: 12-byte header, then the DSD samples
+// - Metadata chunk: an ID3v2 tag, normally at the end of the file
+//
+// The tag itself is read and written by the ID3v2 code; this module only
+// knows where it is and keeps the header in step when it moves or resizes.
+
+use crate::AudioProperties;
+use std::io::Read;
+
+/// DSF file signature
+pub const DSF_SIGNATURE: &[u8; 4] = b"DSD ";
+
+/// Size of the "DSD " chunk
+pub const HEADER_LEN: usize = 28;
+
+const FILE_SIZE_OFFSET: usize = 12;
+const METADATA_OFFSET_OFFSET: usize = 20;
+const FMT_OFFSET: usize = HEADER_LEN;
+const FMT_MIN_LEN: usize = 52;
+
+/// The "DSD " chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DsfHeader {
+    /// Total file size as recorded in the header
+    pub file_size: u64,
+    /// Offset of the ID3v2 tag; 0 when the file has none
+    pub metadata_offset: u64,
+}
+
+impl DsfHeader {
+    /// Parse the header at the start of `data`
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || !data.starts_with(DSF_SIGNATURE) {
+            return None;
+        }
+        let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        Some(DsfHeader { file_size: u64_at(FILE_SIZE_OFFSET), metadata_offset: u64_at(METADATA_OFFSET_OFFSET) })
+    }
+
+    /// Read the header from the start of `reader`
+    pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let mut data = [0u8; HEADER_LEN];
+        match reader.read_exact(&mut data) {
+            Ok(()) => Ok(Self::parse(&data)),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Offset of the ID3v2 tag, if the header points at one
+    pub fn tag_offset(&self) -> Option<u64> {
+        Some(self.metadata_offset).filter(|&offset| offset > 0)
+    }
+}
+
+/// Record a new file size and tag offset (0 for none) in the header at the start of `data`
+pub fn update_header(data: &mut [u8], file_size: u64, metadata_offset: u64) {
+    data[FILE_SIZE_OFFSET..FILE_SIZE_OFFSET + 8].copy_from_slice(&file_size.to_le_bytes());
+    data[METADATA_OFFSET_OFFSET..METADATA_OFFSET_OFFSET + 8].copy_from_slice(&metadata_offset.to_le_bytes());
+}
+
+/// Offset and length of the DSD samples in the "data" chunk
+pub fn audio_span(data: &[u8]) -> Option<(u64, u64)> {
+    let fmt_len = u64::from_le_bytes(data.get(FMT_OFFSET + 4..FMT_OFFSET + 12)?.try_into().ok()?);
+    let data_chunk = FMT_OFFSET as u64 + fmt_len;
+    let header = data.get(data_chunk as usize..data_chunk as usize + 12)?;
+    if &header[..4] != b"data" {
+        return None;
+    }
+    let chunk_len = u64::from_le_bytes(header[4..12].try_into().ok()?);
+    Some((data_chunk + 12, chunk_len.saturating_sub(12)))
+}
+
+/// Duration and stream format from the "fmt " chunk
+pub fn read_properties(data: &[u8]) -> Option<AudioProperties> {
+    let fmt = data.get(FMT_OFFSET..FMT_OFFSET + FMT_MIN_LEN)?;
+    if &fmt[..4] != b"fmt " {
+        return None;
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes(fmt[offset..offset + 4].try_into().unwrap());
+    let channels = u32_at(24);
+    let sample_rate = u32_at(28);
+    let bits_per_sample = u32_at(32);
+    let sample_count = u64::from_le_bytes(fmt[36..44].try_into().unwrap());
+    if sample_rate == 0 {
+        return None;
+    }
+    Some(AudioProperties {
+        duration_ms: sample_count * 1000 / sample_rate as u64,
+        sample_rate: Some(sample_rate),
+        channels: u8::try_from(channels).ok(),
+        bits_per_sample: u8::try_from(bits_per_sample).ok(),
+        codec: "DSD".to_string(),
+        ..Default::default()
+    })
+}
@@ -11,10 +11,8 @@
 //
 // This module standardizes field access across formats.
 
-use std::collections::HashMap;
-
 /// Standard metadata fields
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum StandardField {
     Title,
     Artist,
@@ -28,6 +26,21 @@ pub enum StandardField {
 }
 
 impl StandardField {
+    /// Every standard field, in canonical order
+    pub fn all() -> &'static [StandardField] {
+        &[
+            StandardField::Title,
+            StandardField::Artist,
+            StandardField::Album,
+            StandardField::Year,
+            StandardField::Track,
+            StandardField::Genre,
+            StandardField::Comment,
+            StandardField::Lyrics,
+            StandardField::Cover,
+        ]
+    }
+
     /// Get standard field name (lowercase)
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -44,6 +57,7 @@ impl StandardField {
     }
 
     /// Parse from string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "title" => Some(StandardField::Title),
@@ -60,6 +74,36 @@ impl StandardField {
     }
 }
 
+/// ID3v2 frames read into `Metadata` fields that aren't standard fields
+const ID3V2_OTHER_FIELDS: &[(&str, &str)] = &[
+    ("TPE2", "album_artist"),
+    ("TCOM", "composer"),
+    ("TLEN", "length_ms"),
+    ("TSSE", "encoder"),
+    ("TORY", "original_date"),
+    ("TDOR", "original_date"),
+    ("TOPE", "original_artist"),
+    ("TOAL", "original_album"),
+    ("TMCL", "performers"),
+];
+
+/// Vorbis comment keys read into `Metadata` fields that aren't standard fields
+const VORBIS_OTHER_FIELDS: &[(&str, &str)] = &[
+    ("ALBUMARTIST", "album_artist"),
+    ("COMPOSER", "composer"),
+    ("LENGTH", "length_ms"),
+    ("ENCODER", "encoder"),
+    ("ENCODERSETTINGS", "encoder_settings"),
+    ("ORIGINALDATE", "original_date"),
+    ("ORIGINALYEAR", "original_date"),
+    ("ORIGINALARTIST", "original_artist"),
+    ("ORIGINALALBUM", "original_album"),
+    ("PERFORMER", "performers"),
+];
+
+/// APE items read into `Metadata` fields that aren't standard fields
+const APE_OTHER_FIELDS: &[(&str, &str)] = &[("Encoder", "encoder")];
+
 /// Format-specific field mappings
 pub struct FieldMappings;
 
@@ -95,6 +139,12 @@ impl FieldMappings {
     pub const MP4_COMMENT: &[u8; 4] = b"\xA9cmt"; // ©cmt
     pub const MP4_LYRICS: &[u8; 4] = b"\xA9lyr"; // ©lyr
     pub const MP4_COVER: &[u8; 4] = b"covr";
+    // Under `itunes` in the metadata JSON
+    pub const MP4_MEDIA_KIND: &[u8; 4] = b"stik";
+    pub const MP4_GAPLESS: &[u8; 4] = b"pgap";
+    pub const MP4_TV_SHOW: &[u8; 4] = b"tvsh";
+    pub const MP4_TV_SEASON: &[u8; 4] = b"tvsn";
+    pub const MP4_TV_EPISODE: &[u8; 4] = b"tves";
 
     // APE tag fields
     pub const APE_TITLE: &str = "Title";
@@ -106,6 +156,21 @@ impl FieldMappings {
     pub const APE_COMMENT: &str = "Comment";
     pub const APE_LYRICS: &str = "Lyrics";
 
+    /// ID3v2.4 frame IDs of all standard fields, in `StandardField::all()` order
+    pub fn all_id3v2_frames() -> &'static [&'static str] {
+        &[
+            Self::ID3V2_TITLE,
+            Self::ID3V2_ARTIST,
+            Self::ID3V2_ALBUM,
+            Self::ID3V2_YEAR,
+            Self::ID3V2_TRACK,
+            Self::ID3V2_GENRE,
+            Self::ID3V2_COMMENT,
+            Self::ID3V2_LYRICS,
+            Self::ID3V2_COVER,
+        ]
+    }
+
     /// Get ID3v2 frame ID for a standard field
     pub fn to_id3v2(field: &StandardField) -> &'static str {
         match field {
@@ -174,7 +239,7 @@ impl FieldMappings {
             Self::VORBIS_ARTIST => Some(StandardField::Artist),
             Self::VORBIS_ALBUM => Some(StandardField::Album),
             Self::VORBIS_YEAR | "YEAR" => Some(StandardField::Year), // Also support YEAR
-            Self::VORBIS_TRACK | "TRACKNUMBER" => Some(StandardField::Track),
+            Self::VORBIS_TRACK | "TRACK" => Some(StandardField::Track), // Also support TRACK
             Self::VORBIS_GENRE => Some(StandardField::Genre),
             Self::VORBIS_COMMENT => Some(StandardField::Comment),
             Self::VORBIS_LYRICS => Some(StandardField::Lyrics),
@@ -198,6 +263,30 @@ impl FieldMappings {
             _ => None,
         }
     }
+
+    /// Name of the `Metadata` field a raw key of `format` is read into
+    ///
+    /// `format` is "id3v2" (or "mp3", "dsf"), "vorbis" (or "flac", "ogg", "opus") or "ape",
+    /// case-insensitive. Besides the standard fields this covers the other
+    /// fields the readers fill, so "TPE2" and "ALBUMARTIST" both give
+    /// "album_artist". Keys that aren't read into any field give `None`.
+    pub fn canonical_field_name(format: &str, key: &str) -> Option<&'static str> {
+        let lookup = |table: &[(&str, &'static str)], key: &str| {
+            table.iter().find(|(raw, _)| *raw == key).map(|(_, name)| *name)
+        };
+        match format.to_ascii_lowercase().as_str() {
+            "id3v2" | "mp3" | "dsf" => Self::from_id3v2(key).map(|field| field.as_str())
+                .or_else(|| lookup(ID3V2_OTHER_FIELDS, key)),
+            "vorbis" | "flac" | "ogg" | "opus" => {
+                let key = crate::flac::vorbis::canonical_key(key);
+                Self::from_vorbis(&key).map(|field| field.as_str())
+                    .or_else(|| lookup(VORBIS_OTHER_FIELDS, &key))
+            }
+            "ape" => Self::from_ape(key).map(|field| field.as_str())
+                .or_else(|| lookup(APE_OTHER_FIELDS, key)),
+            _ => None,
+        }
+    }
 }
 
 /// Metadata value converter for handling format-specific value formats
@@ -220,11 +309,53 @@ impl ValueConverter {
         track.split('/').next().unwrap_or(track).to_string()
     }
 
+    /// Normalize a date to ISO 8601 form: "2011/09/26" and "2011.09.26" become "2011-09-26"
+    ///
+    /// Dates in other layouts are only trimmed.
+    pub fn normalize_date(date: &str) -> String {
+        let date = date.trim();
+        let parts: Vec<&str> = date.split(['-', '/', '.']).collect();
+        let is_numeric = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+        let iso_layout = parts.len() <= 3
+            && parts[0].len() == 4
+            && parts[1..].iter().all(|part| part.len() == 2)
+            && parts.iter().all(|part| is_numeric(part));
+        if iso_layout {
+            parts.join("-")
+        } else {
+            date.to_string()
+        }
+    }
+
+    /// Clean text read from a tag before it is written to another format
+    ///
+    /// Repairs UTF-8 mis-decoded as Windows-1252, joins NUL-separated ID3v2.4
+    /// multi-values with "; " and drops control characters other than line
+    /// breaks and tabs.
+    pub fn clean_text(text: &str) -> String {
+        let text = crate::utils::encoding::repair_mojibake(text).unwrap_or_else(|| text.to_string());
+        text.split('\0')
+            .map(|part| part.chars().filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t')).collect::<String>())
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Split a "5/12" track or disc position into its number and total
+    ///
+    /// Leading zeros and surrounding spaces are ignored; a part that isn't a
+    /// number gives `None`.
+    pub fn parse_position(position: &str) -> (Option<u32>, Option<u32>) {
+        let mut parts = position.splitn(2, '/');
+        let number = parts.next().and_then(|number| number.trim().parse().ok());
+        let total = parts.next().and_then(|total| total.trim().parse().ok());
+        (number, total)
+    }
+
     /// Parse genre from numeric ID3v1 genre (if applicable)
     pub fn parse_genre_id3v1(genre_id: u8) -> Option<&'static str> {
-        // TODO: Implement ID3v1 genre lookup table
-        // This would map genre IDs (0-255) to genre names
-        None
+        crate::utils::genre::by_index(genre_id)
     }
 }
 
@@ -251,11 +382,41 @@ mod tests {
         assert_eq!(FieldMappings::from_ape("Title"), Some(StandardField::Title));
     }
 
+    #[test]
+    fn test_all_fields_map_to_id3v2_frames() {
+        let frames: Vec<_> = StandardField::all().iter().map(FieldMappings::to_id3v2).collect();
+        assert_eq!(frames, FieldMappings::all_id3v2_frames());
+        assert_eq!(
+            FieldMappings::all_id3v2_frames(),
+            ["TIT2", "TPE1", "TALB", "TDRC", "TRCK", "TCON", "COMM", "USLT", "APIC"]
+        );
+        for field in StandardField::all() {
+            assert_eq!(StandardField::from_str(field.as_str()), Some(*field));
+        }
+    }
+
+    #[test]
+    fn test_canonical_field_name() {
+        assert_eq!(FieldMappings::canonical_field_name("id3v2", "TIT2"), Some("title"));
+        assert_eq!(FieldMappings::canonical_field_name("id3v2", "TYER"), Some("year"));
+        assert_eq!(FieldMappings::canonical_field_name("ID3v2", "TPE2"), Some("album_artist"));
+        assert_eq!(FieldMappings::canonical_field_name("vorbis", "ALBUMARTIST"), Some("album_artist"));
+        assert_eq!(FieldMappings::canonical_field_name("flac", "Album Artist"), Some("album_artist"));
+        assert_eq!(FieldMappings::canonical_field_name("opus", "tracknumber"), Some("track"));
+        assert_eq!(FieldMappings::canonical_field_name("ape", "Title"), Some("title"));
+        assert_eq!(FieldMappings::canonical_field_name("ape", "Encoder"), Some("encoder"));
+
+        assert_eq!(FieldMappings::canonical_field_name("id3v2", "PRIV"), None);
+        assert_eq!(FieldMappings::canonical_field_name("vorbis", "REPLAYGAIN_TRACK_GAIN"), None);
+        assert_eq!(FieldMappings::canonical_field_name("mp4", "TIT2"), None);
+    }
+
     #[test]
     fn test_value_normalization() {
         assert_eq!(ValueConverter::normalize_year("2024-01-15"), "2024");
         assert_eq!(ValueConverter::normalize_year("2024"), "2024");
         assert_eq!(ValueConverter::normalize_track("1/10"), "1");
         assert_eq!(ValueConverter::normalize_track("5"), "5");
+        assert_eq!(ValueConverter::parse_position(" 2 / 3 "), (Some(2), Some(3)));
     }
 }
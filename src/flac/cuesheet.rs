@@ -0,0 +1,151 @@
+// CUESHEET block implementation for FLAC
+
+use serde::Serialize;
+use std::io::{Cursor, Read};
+
+/// Track numbers reserved for the lead-out track (CD-DA and non-CD cue sheets)
+const LEAD_OUT_CD: u8 = 170;
+const LEAD_OUT_NON_CD: u8 = 255;
+
+/// An index point within a cue sheet track
+#[derive(Debug, Clone, Serialize)]
+pub struct CueIndex {
+    /// Index number; 0 is the pre-gap, 1 the start of the track
+    pub index: u8,
+    /// Offset in samples from the start of the audio stream
+    pub sample_offset: u64,
+}
+
+/// A track in an embedded cue sheet
+#[derive(Debug, Clone, Serialize)]
+pub struct CueTrack {
+    pub number: u8,
+    /// ISRC code, empty if not set
+    pub isrc: String,
+    pub indices: Vec<CueIndex>,
+}
+
+/// Where a cue sheet track starts, for splitting a single-file album
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackPoint {
+    pub number: u8,
+    /// First sample of the track (index 1, or its first index without one)
+    pub start_sample: u64,
+    /// `start_sample` as milliseconds at the stream's sample rate
+    pub start_ms: u64,
+    /// ISRC code, empty if not set
+    pub isrc: String,
+}
+
+/// FLAC CUESHEET block
+#[derive(Debug, Clone)]
+pub struct FlacCueSheet {
+    #[allow(dead_code)]
+    pub media_catalog_number: String,
+    #[allow(dead_code)]
+    pub lead_in_samples: u64,
+    #[allow(dead_code)]
+    pub is_cd: bool,
+    /// Tracks in order, excluding the lead-out track
+    pub tracks: Vec<CueTrack>,
+}
+
+impl FlacCueSheet {
+    /// Read a CUESHEET block from its data
+    pub fn read_from_data(data: &[u8]) -> std::io::Result<Self> {
+        let mut cursor = Cursor::new(data);
+
+        let mut catalog = [0u8; 128];
+        cursor.read_exact(&mut catalog)?;
+        let lead_in_samples = read_u64(&mut cursor)?;
+
+        // 1 bit CD flag, then 7 bits + 258 bytes reserved
+        let mut flags = [0u8; 259];
+        cursor.read_exact(&mut flags)?;
+        let is_cd = flags[0] & 0x80 != 0;
+
+        let track_count = read_u8(&mut cursor)?;
+        let mut tracks = Vec::with_capacity(track_count as usize);
+
+        for _ in 0..track_count {
+            let track_offset = read_u64(&mut cursor)?;
+            let number = read_u8(&mut cursor)?;
+
+            let mut isrc = [0u8; 12];
+            cursor.read_exact(&mut isrc)?;
+
+            // Track type and pre-emphasis bits, then reserved bytes
+            let mut reserved = [0u8; 14];
+            cursor.read_exact(&mut reserved)?;
+
+            let index_count = read_u8(&mut cursor)?;
+            let mut indices = Vec::with_capacity(index_count as usize);
+            for _ in 0..index_count {
+                let offset = read_u64(&mut cursor)?;
+                let index = read_u8(&mut cursor)?;
+                let mut reserved = [0u8; 3];
+                cursor.read_exact(&mut reserved)?;
+                indices.push(CueIndex { index, sample_offset: track_offset + offset });
+            }
+
+            if number == LEAD_OUT_CD || number == LEAD_OUT_NON_CD {
+                continue;
+            }
+            tracks.push(CueTrack { number, isrc: parse_ascii(&isrc), indices });
+        }
+
+        Ok(FlacCueSheet {
+            media_catalog_number: parse_ascii(&catalog),
+            lead_in_samples,
+            is_cd,
+            tracks,
+        })
+    }
+
+    /// Start points of the tracks at `sample_rate`; tracks without indices are left out
+    pub fn track_points(&self, sample_rate: u32) -> Vec<TrackPoint> {
+        self.tracks.iter()
+            .filter_map(|track| {
+                let start = track.indices.iter().find(|i| i.index == 1).or(track.indices.first())?;
+                Some(TrackPoint {
+                    number: track.number,
+                    start_sample: start.sample_offset,
+                    start_ms: (start.sample_offset as u128 * 1000 / sample_rate as u128) as u64,
+                    isrc: track.isrc.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Check whether track 1 has a non-empty pre-gap (a hidden track before it)
+    pub fn has_hidden_track(&self) -> bool {
+        let track = match self.tracks.iter().find(|track| track.number == 1) {
+            Some(track) => track,
+            None => return false,
+        };
+        let offset_of = |index: u8| track.indices.iter().find(|i| i.index == index).map(|i| i.sample_offset);
+
+        match (offset_of(0), offset_of(1)) {
+            (Some(pregap), Some(start)) => start > pregap,
+            _ => false,
+        }
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut buffer = [0u8; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buffer = [0u8; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_be_bytes(buffer))
+}
+
+/// Parse a NUL-padded ASCII field
+fn parse_ascii(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
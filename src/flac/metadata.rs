@@ -1,6 +1,8 @@
 // FLAC metadata block implementation
 
-use std::io::Read;
+use super::picture::FlacPicture;
+use super::vorbis::VorbisComment;
+use std::io::{Read, Seek, SeekFrom};
 
 /// FLAC metadata block types
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -36,6 +38,8 @@ impl FlacMetadataBlockType {
 pub struct FlacMetadataBlockHeader {
     pub is_last: bool,
     pub block_type: FlacMetadataBlockType,
+    /// Raw block type, kept because unknown types all map to `Invalid`
+    pub type_code: u8,
     pub length: u32,
 }
 
@@ -55,7 +59,8 @@ impl FlacMetadataBlockHeader {
         reader.read_exact(&mut buffer)?;
 
         let is_last = (buffer[0] & 0x80) != 0;
-        let block_type = FlacMetadataBlockType::from_byte(buffer[0] & 0x7F);
+        let type_code = buffer[0] & 0x7F;
+        let block_type = FlacMetadataBlockType::from_byte(type_code);
 
         // Length is big-endian 24-bit
         let length = ((buffer[1] as u32) << 16) |
@@ -65,6 +70,7 @@ impl FlacMetadataBlockHeader {
         Ok(FlacMetadataBlockHeader {
             is_last,
             block_type,
+            type_code,
             length,
         })
     }
@@ -79,7 +85,272 @@ impl FlacMetadataBlock {
 
         Ok(FlacMetadataBlock { header, data })
     }
+
+    /// Serialize the block with the given last-block flag
+    pub fn to_bytes(&self, is_last: bool) -> Vec<u8> {
+        let length = self.data.len() as u32;
+        let mut bytes = vec![
+            self.header.type_code | if is_last { 0x80 } else { 0 },
+            (length >> 16) as u8,
+            (length >> 8) as u8,
+            length as u8,
+        ];
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Create a block of the given type
+    pub fn new(block_type: FlacMetadataBlockType, data: Vec<u8>) -> Self {
+        FlacMetadataBlock {
+            header: FlacMetadataBlockHeader {
+                is_last: false,
+                block_type,
+                type_code: block_type as u8,
+                length: data.len() as u32,
+            },
+            data,
+        }
+    }
+
+    /// Create a PADDING block of `length` zero bytes
+    pub fn padding(length: usize) -> Self {
+        Self::new(FlacMetadataBlockType::Padding, vec![0u8; length])
+    }
+}
+
+/// Streaming reader over the metadata blocks of a FLAC file
+///
+/// Blocks are read one at a time as the iterator advances; PADDING is skipped
+/// with a seek rather than read. Iteration ends after the block flagged as
+/// last, or after the first read error.
+pub struct FlacBlocks<R> {
+    reader: R,
+    done: bool,
+    skip_pictures: bool,
+}
+
+/// Typed view of a FLAC metadata block; the block data is only decoded when asked for
+#[derive(Debug, Clone)]
+pub enum FlacBlockView {
+    StreamInfo(Vec<u8>),
+    /// Length of the padding, whose bytes are never read
+    Padding(u32),
+    /// Registered application ID and the data after it
+    Application { id: [u8; 4], data: Vec<u8> },
+    SeekTable(Vec<u8>),
+    VorbisComment(Vec<u8>),
+    CueSheet(Vec<u8>),
+    Picture(Vec<u8>),
+    /// Reserved or invalid block type, or an APPLICATION block too short for its ID
+    Unknown { type_code: u8, data: Vec<u8> },
+}
+
+impl<R: Read + Seek> FlacBlocks<R> {
+    /// Iterate over the blocks of a FLAC file read from its start
+    ///
+    /// `None` if the data doesn't start with the `fLaC` signature.
+    ///
+    /// ```
+    /// use oxidant::{FlacBlockView, FlacBlocks};
+    /// use std::io::Cursor;
+    ///
+    /// // Empty STREAMINFO, then 1 KiB of padding flagged as the last block
+    /// let mut file = b"fLaC\x00\x00\x00\x22".to_vec();
+    /// file.extend_from_slice(&[0; 34]);
+    /// file.extend_from_slice(b"\x81\x00\x04\x00");
+    ///
+    /// let blocks: Vec<_> = FlacBlocks::new(Cursor::new(file)).unwrap().unwrap()
+    ///     .collect::<std::io::Result<_>>()
+    ///     .unwrap();
+    /// assert_eq!(blocks.len(), 2);
+    /// assert!(matches!(blocks[1], FlacBlockView::Padding(1024)));
+    /// ```
+    pub fn new(mut reader: R) -> std::io::Result<Option<Self>> {
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature)?;
+        if signature != *FLAC_SIGNATURE {
+            return Ok(None);
+        }
+        Ok(Some(Self::after_signature(reader)))
+    }
+
+    /// Iterate over the blocks starting at the reader's position, just after the signature
+    pub fn after_signature(reader: R) -> Self {
+        FlacBlocks { reader, done: false, skip_pictures: false }
+    }
+
+    /// Seek past PICTURE blocks instead of reading them; they are left out of the iteration
+    pub fn without_pictures(mut self) -> Self {
+        self.skip_pictures = true;
+        self
+    }
+
+    /// Read the next block not skipped; `None` if only skipped blocks were left
+    fn read_block(&mut self) -> std::io::Result<Option<FlacBlockView>> {
+        while !self.done {
+            let header = FlacMetadataBlockHeader::read(&mut self.reader)?;
+            self.done = header.is_last;
+
+            if header.block_type == FlacMetadataBlockType::Padding {
+                self.reader.seek(SeekFrom::Current(header.length as i64))?;
+                return Ok(Some(FlacBlockView::Padding(header.length)));
+            }
+            if self.skip_pictures && header.block_type == FlacMetadataBlockType::Picture {
+                self.reader.seek(SeekFrom::Current(header.length as i64))?;
+                continue;
+            }
+
+            let mut data = vec![0u8; header.length as usize];
+            self.reader.read_exact(&mut data)?;
+            return Ok(Some(FlacBlockView::new(header.type_code, data)));
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read + Seek> Iterator for FlacBlocks<R> {
+    type Item = std::io::Result<FlacBlockView>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let block = self.read_block();
+        if block.is_err() {
+            self.done = true;
+        }
+        block.transpose()
+    }
 }
 
+impl FlacBlockView {
+    fn new(type_code: u8, data: Vec<u8>) -> Self {
+        match FlacMetadataBlockType::from_byte(type_code) {
+            FlacMetadataBlockType::StreamInfo => FlacBlockView::StreamInfo(data),
+            FlacMetadataBlockType::Padding => FlacBlockView::Padding(data.len() as u32),
+            FlacMetadataBlockType::Application if data.len() >= 4 => FlacBlockView::Application {
+                id: [data[0], data[1], data[2], data[3]],
+                data: data[4..].to_vec(),
+            },
+            FlacMetadataBlockType::SeekTable => FlacBlockView::SeekTable(data),
+            FlacMetadataBlockType::VorbisComment => FlacBlockView::VorbisComment(data),
+            FlacMetadataBlockType::CueSheet => FlacBlockView::CueSheet(data),
+            FlacMetadataBlockType::Picture => FlacBlockView::Picture(data),
+            FlacMetadataBlockType::Application | FlacMetadataBlockType::Invalid => {
+                FlacBlockView::Unknown { type_code, data }
+            }
+        }
+    }
+
+    /// Raw block type, as stored in the block header
+    pub fn type_code(&self) -> u8 {
+        let block_type = match self {
+            FlacBlockView::StreamInfo(_) => FlacMetadataBlockType::StreamInfo,
+            FlacBlockView::Padding(_) => FlacMetadataBlockType::Padding,
+            FlacBlockView::Application { .. } => FlacMetadataBlockType::Application,
+            FlacBlockView::SeekTable(_) => FlacMetadataBlockType::SeekTable,
+            FlacBlockView::VorbisComment(_) => FlacMetadataBlockType::VorbisComment,
+            FlacBlockView::CueSheet(_) => FlacMetadataBlockType::CueSheet,
+            FlacBlockView::Picture(_) => FlacMetadataBlockType::Picture,
+            FlacBlockView::Unknown { type_code, .. } => return *type_code,
+        };
+        block_type as u8
+    }
+
+    /// Decode a VORBIS_COMMENT block
+    pub fn comment(&self) -> Option<std::io::Result<VorbisComment>> {
+        match self {
+            FlacBlockView::VorbisComment(data) => Some(VorbisComment::read(&mut std::io::Cursor::new(data))),
+            _ => None,
+        }
+    }
+
+    /// Decode a PICTURE block; the image data is copied out of the block only now
+    pub fn picture(&self) -> Option<std::io::Result<FlacPicture>> {
+        match self {
+            FlacBlockView::Picture(data) => Some(FlacPicture::read_from_data(data)),
+            _ => None,
+        }
+    }
+
+    /// Picture type code of a PICTURE block, read from its first four bytes
+    pub fn picture_type(&self) -> Option<u32> {
+        match self {
+            FlacBlockView::Picture(data) => Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// Length of a PICTURE block's image data, without copying the image
+    pub fn picture_data_len(&self) -> Option<usize> {
+        match self {
+            FlacBlockView::Picture(data) => FlacPicture::data_len(data),
+            _ => None,
+        }
+    }
+}
+
+/// Registered APPLICATION block IDs (https://xiph.org/flac/id.html)
+pub const KNOWN_APPLICATION_IDS: &[&[u8; 4]] = &[
+    b"ATCH", b"BSOL", b"BUGS", b"Cues", b"Fica", b"Ftol", b"MOTB", b"MPSE",
+    b"MuML", b"RIFF", b"SFFL", b"SONY", b"SQEZ", b"TtWv", b"UITS", b"aiff",
+    b"imag", b"peem", b"qfst", b"riff", b"tune", b"xbat", b"xmcd",
+];
+
+/// Largest block data length the 24-bit length field can hold
+pub const MAX_BLOCK_LENGTH: usize = 0xFF_FFFF;
+
 /// FLAC file signature
-pub const FLAC_SIGNATURE: &[u8; 4] = b"fLaC";
\ No newline at end of file
+pub const FLAC_SIGNATURE: &[u8; 4] = b"fLaC";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_flac_block_iterator() {
+        let data = build_flac_file("vendor", &[("TITLE", "Title")], 64);
+        let blocks: Vec<FlacBlockView> = FlacBlocks::new(std::io::Cursor::new(&data)).unwrap().unwrap()
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(blocks.iter().map(FlacBlockView::type_code).collect::<Vec<_>>(), [0, 4, 1]);
+        let comment = blocks[1].comment().unwrap().unwrap();
+        assert_eq!(comment.get("TITLE").map(String::as_str), Some("Title"));
+        assert!(blocks[0].comment().is_none());
+        assert!(FlacBlocks::new(std::io::Cursor::new(b"ID3\x03")).unwrap().is_none());
+
+        // Padding is skipped without being read, so one claiming more bytes than the file holds is fine
+        let mut truncated = data[..4 + 4 + 34].to_vec();
+        truncated.extend_from_slice(&[0x81, 0x10, 0x00, 0x00]);
+        let blocks: Vec<_> = FlacBlocks::new(std::io::Cursor::new(truncated)).unwrap().unwrap().collect();
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(blocks[1], Ok(FlacBlockView::Padding(0x10_0000))));
+
+        // A picture block is only decoded when asked for
+        let picture = FlacPicture::new(vec![0xFF, 0xD8, 0xFF, 0xD9], "image/jpeg".to_string(), String::new());
+        let mut with_picture = data[..4 + 4 + 34].to_vec();
+        with_picture.extend(FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes()).to_bytes(true));
+        let block = FlacBlocks::new(std::io::Cursor::new(with_picture)).unwrap().unwrap().nth(1).unwrap().unwrap();
+        assert!(matches!(block, FlacBlockView::Picture(_)));
+        assert_eq!(block.picture().unwrap().unwrap().data, [0xFF, 0xD8, 0xFF, 0xD9]);
+    }
+
+    #[test]
+    fn test_flac_blocks_without_pictures() {
+        let picture = FlacPicture::new(vec![0xAB; 1024 * 1024], "image/jpeg".to_string(), String::new());
+        let mut data = build_flac_file("vendor", &[("TITLE", "Title")], 0);
+        let comment_end = data.len() - 4 - 4;
+        data.splice(comment_end..comment_end, FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes()).to_bytes(false));
+
+        let bytes_read = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader { inner: std::io::Cursor::new(&data), bytes_read: bytes_read.clone() };
+        let blocks: Vec<_> = FlacBlocks::new(reader).unwrap().unwrap()
+            .without_pictures()
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert!(!blocks.iter().any(|block| matches!(block, FlacBlockView::Picture(_))));
+        assert!(bytes_read.get() < 4096, "read {} bytes", bytes_read.get());
+    }
+}
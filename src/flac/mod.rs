@@ -3,6 +3,7 @@
 pub mod metadata;
 pub mod vorbis;
 pub mod picture;
+pub mod cuesheet;
 
 pub use metadata::{FlacMetadataBlock, FlacMetadataBlockType, FLAC_SIGNATURE};
 // Note: VorbisComment, VorbisFields, and FlacPicture are exported but may be unused in current version
@@ -59,7 +59,7 @@ impl PictureType {
     }
 
     #[allow(dead_code)]
-pub fn to_string(&self) -> &'static str {
+pub fn to_string(self) -> &'static str {
         match self {
             PictureType::Other => "Other",
             PictureType::FileIcon => "File Icon",
@@ -180,6 +180,20 @@ impl FlacPicture {
         })
     }
 
+    /// Length of the image data in a PICTURE block, read from its header
+    ///
+    /// Returns `None` when the block is too short to hold the data it declares.
+    pub fn data_len(data: &[u8]) -> Option<usize> {
+        let field = |pos: usize| -> Option<usize> {
+            Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize)
+        };
+        // type, MIME length + MIME, description length + description, 4 fields, data length
+        let description_pos = 8 + field(4)?;
+        let length_pos = description_pos + 4 + field(description_pos)? + 16;
+        let data_len = field(length_pos)?;
+        (length_pos + 4 + data_len <= data.len()).then_some(data_len)
+    }
+
     /// Get file extension based on MIME type
     #[allow(dead_code)]
     pub fn get_extension(&self) -> &'static str {
@@ -209,7 +223,7 @@ impl FlacPicture {
 
         // Description length (32-bit big-endian)
         result.extend_from_slice(&(self.description.len() as u32).to_be_bytes());
-        // Description (UTF-8)
+        // Description (UTF-8); pictures from other tags had theirs decoded from the source encoding on read
         result.extend_from_slice(self.description.as_bytes());
 
         // Width (32-bit big-endian)
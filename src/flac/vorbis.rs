@@ -5,42 +5,60 @@ use std::io::Read;
 /// Vorbis comment structure
 #[derive(Debug, Default)]
 pub struct VorbisComment {
-    #[allow(dead_code)]
     pub vendor_string: String,
     pub comments: Vec<(String, String)>,
 }
 
 impl VorbisComment {
     /// Read Vorbis comment from reader
+    ///
+    /// The reader must hold just the comment (a FLAC block or OGG packet);
+    /// everything left in it is read and parsed with `from_bytes`.
     #[allow(dead_code)]
     pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
-        // Read vendor string length (little-endian 32-bit)
-        let mut vendor_length_bytes = [0u8; 4];
-        reader.read_exact(&mut vendor_length_bytes)?;
-        let vendor_length = u32::from_le_bytes(vendor_length_bytes) as usize;
-
-        // Read vendor string
-        let mut vendor_bytes = vec![0u8; vendor_length];
-        reader.read_exact(&mut vendor_bytes)?;
-        let vendor_string = String::from_utf8_lossy(&vendor_bytes).to_string();
-
-        // Read comment count (little-endian 32-bit)
-        let mut comment_count_bytes = [0u8; 4];
-        reader.read_exact(&mut comment_count_bytes)?;
-        let comment_count = u32::from_le_bytes(comment_count_bytes) as usize;
-
-        // Read comments
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Parse a Vorbis comment, checking every length against the bytes left
+    ///
+    /// A comment count or length larger than the remaining data is rejected
+    /// with `InvalidData` before anything is allocated for it, so a corrupt
+    /// or hostile header can't claim billions of comments.
+    pub fn from_bytes(data: &[u8]) -> std::io::Result<Self> {
+        let malformed = |what: &str| std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Malformed Vorbis comment: {}", what),
+        );
+        let mut rest = data;
+        let read_u32 = |rest: &mut &[u8]| -> std::io::Result<usize> {
+            let (bytes, tail) = rest.split_first_chunk::<4>().ok_or_else(|| malformed("truncated length field"))?;
+            *rest = tail;
+            Ok(u32::from_le_bytes(*bytes) as usize)
+        };
+        let take = |rest: &mut &[u8], length: usize, what: &str| -> std::io::Result<String> {
+            if length > rest.len() {
+                return Err(malformed(&format!("{} of {} bytes with only {} left", what, length, rest.len())));
+            }
+            let (bytes, tail) = rest.split_at(length);
+            *rest = tail;
+            Ok(String::from_utf8_lossy(bytes).to_string())
+        };
+
+        let vendor_length = read_u32(&mut rest)?;
+        let vendor_string = take(&mut rest, vendor_length, "vendor string")?;
+
+        // Every comment takes at least its 4-byte length field
+        let comment_count = read_u32(&mut rest)?;
+        if comment_count > rest.len() / 4 {
+            return Err(malformed(&format!("{} comments in {} bytes", comment_count, rest.len())));
+        }
+
         let mut comments = Vec::with_capacity(comment_count);
         for _ in 0..comment_count {
-            // Read comment length
-            let mut comment_length_bytes = [0u8; 4];
-            reader.read_exact(&mut comment_length_bytes)?;
-            let comment_length = u32::from_le_bytes(comment_length_bytes) as usize;
-
-            // Read comment string
-            let mut comment_bytes = vec![0u8; comment_length];
-            reader.read_exact(&mut comment_bytes)?;
-            let comment_string = String::from_utf8_lossy(&comment_bytes).to_string();
+            let comment_length = read_u32(&mut rest)?;
+            let comment_string = take(&mut rest, comment_length, "comment")?;
 
             // Parse comment (format: FIELD=value)
             if let Some((field, value)) = comment_string.split_once('=') {
@@ -57,25 +75,61 @@ impl VorbisComment {
     /// Get a comment value by field name
     #[allow(dead_code)]
     pub fn get(&self, field: &str) -> Option<&String> {
+        let field = canonical_key(field);
         self.comments
             .iter()
-            .find(|(f, _)| f.eq_ignore_ascii_case(field))
+            .find(|(f, _)| canonical_key(f) == field)
             .map(|(_, v)| v)
     }
 
     /// Set a comment value by field name
     #[allow(dead_code)]
     pub fn set(&mut self, field: &str, value: &str) {
-        // Remove existing comment with the same field (case-insensitive)
-        self.comments.retain(|(f, _)| !f.eq_ignore_ascii_case(field));
-        // Add new comment
-        self.comments.push((field.to_uppercase(), value.to_string()));
+        // Remove every spelling of the field, then add it under the canonical key
+        self.remove(field);
+        self.comments.push((canonical_key(field), value.to_string()));
     }
 
-    /// Remove a comment by field name
+    /// Remove a comment by field name, in any spelling (see `canonical_key`)
     #[allow(dead_code)]
     pub fn remove(&mut self, field: &str) {
-        self.comments.retain(|(f, _)| !f.eq_ignore_ascii_case(field));
+        let field = canonical_key(field);
+        self.comments.retain(|(f, _)| canonical_key(f) != field);
+    }
+
+    /// Rewrite every key in its canonical spelling and drop repeated comments
+    ///
+    /// A comment whose key and value both match an earlier one is removed.
+    /// Keys spelled differently with different values ("DATE=2001" and
+    /// "date=2003") are kept as separate values of one field; a description
+    /// of each such conflict is returned so callers can warn about it.
+    pub fn normalize(&mut self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let mut seen: Vec<(String, String, String)> = Vec::with_capacity(self.comments.len());
+        for (key, value) in std::mem::take(&mut self.comments) {
+            let canonical = canonical_key(&key);
+            if seen.iter().any(|(seen_key, _, seen_value)| *seen_key == canonical && *seen_value == value) {
+                continue;
+            }
+            if let Some((_, spelling, other)) = seen.iter().find(|(seen_key, spelling, _)| *seen_key == canonical && *spelling != key) {
+                warnings.push(format!(
+                    "{}: \"{}={}\" and \"{}={}\" differ; both values kept",
+                    canonical, spelling, other, key, value
+                ));
+            }
+            self.comments.push((canonical.clone(), value.clone()));
+            seen.push((canonical, key, value));
+        }
+        warnings
+    }
+
+    /// Size in bytes of the data `to_bytes` produces, computed without serializing
+    ///
+    /// Lengths are UTF-8 byte counts, so this is exact, not an upper bound.
+    pub fn estimated_byte_size(&self) -> usize {
+        4 + self.vendor_string.len()
+            + 4
+            + self.comments.iter().map(|(field, value)| 4 + field.len() + 1 + value.len()).sum::<usize>()
     }
 
     /// Convert Vorbis comment to bytes
@@ -103,6 +157,14 @@ impl VorbisComment {
     }
 }
 
+/// Canonical spelling of a Vorbis comment key: uppercase, without spaces
+///
+/// Keys are case-insensitive, and some taggers write "Track Number" for
+/// TRACKNUMBER; both forms name the same field.
+pub fn canonical_key(key: &str) -> String {
+    key.chars().filter(|c| *c != ' ').collect::<String>().to_ascii_uppercase()
+}
+
 /// Common Vorbis comment field names
 #[allow(dead_code)]
 pub struct VorbisFields;
@@ -126,4 +188,23 @@ impl VorbisFields {
 }
 
 #[allow(dead_code)]
-pub const VORBIS_FIELDS: VorbisFields = VorbisFields;
\ No newline at end of file
+pub const VORBIS_FIELDS: VorbisFields = VorbisFields;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vorbis_comment_estimated_byte_size() {
+        let comment = VorbisComment {
+            vendor_string: "reference libFLAC 1.4.3".to_string(),
+            comments: vec![
+                ("TITLE".to_string(), "Título ♫".to_string()),
+                ("ARTIST".to_string(), String::new()),
+                ("COMMENT".to_string(), "a=b".to_string()),
+            ],
+        };
+        assert_eq!(comment.estimated_byte_size(), comment.to_bytes().len());
+        assert_eq!(VorbisComment::default().estimated_byte_size(), 8);
+    }
+}
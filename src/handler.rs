@@ -0,0 +1,493 @@
+// One handler per format
+//
+// Everything `AudioFile` does differently per format for its core paths
+// (recognizing the file, reading and writing the tags, reading the cover and
+// the audio properties) goes through the format's `FormatHandler`. `HANDLERS`
+// lists them in detection priority order, and `detect::DetectionOrder::standard`
+// is built from that list, so a new format is a `Format` variant plus one
+// handler registered here. The built-in handlers wrap the parser modules
+// (`id3`, `flac`, `ogg`, `opus`, `mp4`, `ape`, `dsf`) through `AudioFile`.
+
+use crate::detect::DetectFn;
+use crate::history::HistoryEntry;
+use crate::id3::Id3v1Tag;
+use crate::mp4::Mp4File;
+use crate::{
+    dsf, properties, record_sources, AudioFile, AudioFileError, AudioProperties, AudioResult, CoverArt, FieldSources,
+    Format, MergePolicy, Metadata, WriteOptions, WriteReport,
+};
+use std::fs::File;
+use std::io::Read;
+
+/// How `AudioFile` recognizes, reads and writes one format
+pub(crate) trait FormatHandler: Sync {
+    /// The format handled
+    fn format(&self) -> Format;
+
+    /// The check for the format's signature
+    fn detector(&self) -> DetectFn;
+
+    /// Read the tags, with the tag structure each field came from
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)>;
+
+    /// Replace the tags with `metadata`
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport>;
+
+    /// The picture read into `Metadata::cover`
+    fn read_cover(&self, audio: &AudioFile) -> AudioResult<Option<CoverArt>>;
+
+    /// Audio properties of the stream at `span` (offset, length); the bitrate is filled in by the caller
+    fn properties(&self, audio: &AudioFile, span: (u64, u64)) -> AudioResult<Option<AudioProperties>>;
+}
+
+/// Every handler, in the order their detectors are tried
+///
+/// Tags at the start of the file come before tags at the end, and a bare
+/// MPEG stream is the last resort.
+pub(crate) static HANDLERS: [&dyn FormatHandler; 9] = [
+    &Id3v2Handler,
+    &FlacHandler,
+    &OpusHandler,
+    &OggVorbisHandler,
+    &Mp4Handler,
+    &DsfHandler,
+    &ApeHandler,
+    &Id3v1Handler,
+    &Mp3Handler,
+];
+
+/// The handler registered for `format`
+pub(crate) fn handler(format: Format) -> &'static dyn FormatHandler {
+    *HANDLERS.iter()
+        .find(|handler| handler.format() == format)
+        .expect("every format has a handler")
+}
+
+fn unsupported_write(format: Format) -> AudioResult<WriteReport> {
+    Err(AudioFileError::UnsupportedFormat(format!("Writing {} tags is not supported yet", format)))
+}
+
+/// ID3v2 metadata, with empty fields filled from a trailing ID3v1 tag
+fn read_id3v2_with_id3v1(audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+    let (mut metadata, mut sources) = audio.read_id3v2_metadata()?;
+    if Id3v1Tag::read_from_file(&audio.path)?.is_some() {
+        metadata.merge(&audio.read_id3v1_metadata()?.0, MergePolicy::FillEmpty);
+        record_sources(&mut sources, &metadata, |_| "ID3v1".to_string());
+    }
+    Ok((metadata, sources))
+}
+
+struct Id3v2Handler;
+
+impl FormatHandler for Id3v2Handler {
+    fn format(&self) -> Format {
+        Format::Id3v2
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.head.starts_with(b"ID3")
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        read_id3v2_with_id3v1(audio)
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_id3v2_metadata(metadata, options, &[], history)
+    }
+
+    fn read_cover(&self, audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        audio.read_id3v2_cover()
+    }
+
+    fn properties(&self, audio: &AudioFile, span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        Ok(properties::read_mpeg(&audio.path, span)?)
+    }
+}
+
+struct FlacHandler;
+
+impl FormatHandler for FlacHandler {
+    fn format(&self) -> Format {
+        Format::Flac
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.head.starts_with(crate::flac::FLAC_SIGNATURE)
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        audio.read_flac_metadata()
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_flac_metadata(metadata, options, &[], history)
+    }
+
+    fn read_cover(&self, audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        audio.read_flac_cover()
+    }
+
+    fn properties(&self, audio: &AudioFile, _span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        audio.read_flac_properties()
+    }
+}
+
+struct OpusHandler;
+
+impl FormatHandler for OpusHandler {
+    fn format(&self) -> Format {
+        Format::Opus
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.head.starts_with(crate::ogg::OGG_SIGNATURE) && probe.head.get(28..32) == Some(b"Opus")
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        audio.read_opus_metadata()
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        _history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_ogg_metadata(metadata, options, &[])
+    }
+
+    fn read_cover(&self, _audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        Ok(None)
+    }
+
+    fn properties(&self, audio: &AudioFile, _span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        Ok(properties::read_ogg(&audio.path)?)
+    }
+}
+
+struct OggVorbisHandler;
+
+impl FormatHandler for OggVorbisHandler {
+    fn format(&self) -> Format {
+        Format::OggVorbis
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.head.starts_with(crate::ogg::OGG_SIGNATURE)
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        audio.read_ogg_metadata()
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        _history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_ogg_metadata(metadata, options, &[])
+    }
+
+    fn read_cover(&self, _audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        Ok(None)
+    }
+
+    fn properties(&self, audio: &AudioFile, _span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        Ok(properties::read_ogg(&audio.path)?)
+    }
+}
+
+struct Mp4Handler;
+
+impl FormatHandler for Mp4Handler {
+    fn format(&self) -> Format {
+        Format::Mp4
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.head.get(4..8) == Some(b"ftyp")
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        audio.read_mp4_metadata()
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        _options: &WriteOptions,
+        _history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_mp4_metadata(metadata)
+    }
+
+    fn read_cover(&self, audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        audio.read_mp4_cover()
+    }
+
+    fn properties(&self, audio: &AudioFile, _span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        let mp4_file = Mp4File::new(audio.path.clone());
+        let entry = mp4_file.read_audio_entry()?;
+        Ok(mp4_file.read_duration_ms()?.map(|duration_ms| AudioProperties {
+            duration_ms,
+            sample_rate: entry.as_ref().map(|entry| entry.sample_rate).filter(|&rate| rate > 0),
+            channels: entry.as_ref().map(|entry| entry.channels as u8),
+            // Lossy entries carry a nominal 16-bit sample size
+            bits_per_sample: entry.as_ref()
+                .filter(|entry| properties::is_lossless(&entry.codec()))
+                .map(|entry| entry.sample_size as u8),
+            codec: entry.map(|entry| entry.codec()).unwrap_or_default(),
+            ..Default::default()
+        }))
+    }
+}
+
+struct DsfHandler;
+
+impl FormatHandler for DsfHandler {
+    fn format(&self) -> Format {
+        Format::Dsf
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.head.starts_with(dsf::DSF_SIGNATURE)
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        // Until its first write a DSF file has no tag to read
+        if !audio.has_id3v2_tag()? {
+            return Ok(Default::default());
+        }
+        audio.read_id3v2_metadata()
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_id3v2_metadata(metadata, options, &[], history)
+    }
+
+    fn read_cover(&self, audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        audio.read_id3v2_cover()
+    }
+
+    fn properties(&self, audio: &AudioFile, _span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        let mut head = Vec::new();
+        File::open(&audio.path)?.take(4096).read_to_end(&mut head)?;
+        Ok(dsf::read_properties(&head))
+    }
+}
+
+struct ApeHandler;
+
+impl FormatHandler for ApeHandler {
+    fn format(&self) -> Format {
+        Format::Ape
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.len > 32 && probe.from_end(32, 8) == Some(b"APETAGEX")
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        audio.read_ape_metadata()
+    }
+
+    fn write(
+        &self,
+        _audio: &AudioFile,
+        _metadata: &Metadata,
+        _options: &WriteOptions,
+        _history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        unsupported_write(Format::Ape)
+    }
+
+    fn read_cover(&self, _audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        Ok(None)
+    }
+
+    fn properties(&self, audio: &AudioFile, span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        Ok(properties::read_mpeg(&audio.path, span)?)
+    }
+}
+
+struct Id3v1Handler;
+
+impl FormatHandler for Id3v1Handler {
+    fn format(&self) -> Format {
+        Format::Id3v1
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| probe.len > 128 && probe.from_end(128, 3) == Some(b"TAG")
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        audio.read_id3v1_metadata()
+    }
+
+    fn write(
+        &self,
+        _audio: &AudioFile,
+        _metadata: &Metadata,
+        _options: &WriteOptions,
+        _history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        unsupported_write(Format::Id3v1)
+    }
+
+    fn read_cover(&self, _audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        Ok(None)
+    }
+
+    fn properties(&self, audio: &AudioFile, span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        Ok(properties::read_mpeg(&audio.path, span)?)
+    }
+}
+
+struct Mp3Handler;
+
+impl FormatHandler for Mp3Handler {
+    fn format(&self) -> Format {
+        Format::Mp3
+    }
+
+    fn detector(&self) -> DetectFn {
+        |probe| properties::starts_with_mpeg_frame(probe.head)
+    }
+
+    fn read(&self, audio: &AudioFile) -> AudioResult<(Metadata, FieldSources)> {
+        // Until its first write a bare MPEG stream has no tag to read
+        if !audio.has_id3v2_tag()? {
+            return Ok(Default::default());
+        }
+        read_id3v2_with_id3v1(audio)
+    }
+
+    fn write(
+        &self,
+        audio: &AudioFile,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        audio.write_id3v2_metadata(metadata, options, &[], history)
+    }
+
+    fn read_cover(&self, audio: &AudioFile) -> AudioResult<Option<CoverArt>> {
+        audio.read_id3v2_cover()
+    }
+
+    fn properties(&self, audio: &AudioFile, span: (u64, u64)) -> AudioResult<Option<AudioProperties>> {
+        Ok(properties::read_mpeg(&audio.path, span)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detect::DetectionOrder;
+    use crate::test_util::*;
+
+    const ALL_FORMATS: [Format; 9] = [
+        Format::Id3v2,
+        Format::Id3v1,
+        Format::Flac,
+        Format::OggVorbis,
+        Format::Opus,
+        Format::Mp4,
+        Format::Ape,
+        Format::Mp3,
+        Format::Dsf,
+    ];
+
+    #[test]
+    fn test_every_format_has_one_handler() {
+        for format in ALL_FORMATS {
+            assert_eq!(HANDLERS.iter().filter(|h| h.format() == format).count(), 1, "{}", format);
+            assert_eq!(handler(format).format(), format);
+        }
+        assert_eq!(
+            DetectionOrder::standard().formats(),
+            vec![
+                Format::Id3v2,
+                Format::Flac,
+                Format::Opus,
+                Format::OggVorbis,
+                Format::Mp4,
+                Format::Dsf,
+                Format::Ape,
+                Format::Id3v1,
+                Format::Mp3,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_handlers_match_format_readers() {
+        let mut tit2 = vec![0u8];
+        tit2.extend_from_slice(b"Title");
+        let mut id3v1 = vec![0u8; 64];
+        id3v1.extend_from_slice(b"TAGOld Title");
+        id3v1.resize(64 + 128, 0);
+        let fixtures = [
+            (Format::Id3v2, build_id3v2_file(&[("TIT2", tit2), ("APIC", build_apic_frame(b"\xFF\xD8\xFF\xE0jpeg"))])),
+            (Format::Flac, build_flac_file("ref", &[("TITLE", "Title")], 16)),
+            (Format::OggVorbis, build_ogg_file("ref", &[("TITLE", "Title")])),
+            (Format::Id3v1, id3v1),
+        ];
+        for (format, data) in fixtures {
+            let path = write_temp_file(&format!("handler.{}", format.as_str()), &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            assert_eq!(audio.format, format);
+
+            let direct = match format {
+                Format::Id3v2 => read_id3v2_with_id3v1(&audio),
+                Format::Flac => audio.read_flac_metadata(),
+                Format::OggVorbis => audio.read_ogg_metadata(),
+                _ => audio.read_id3v1_metadata(),
+            }
+            .unwrap();
+            let (metadata, sources) = handler(format).read(&audio).unwrap();
+            assert_eq!(serde_json::to_value(&metadata).unwrap(), serde_json::to_value(&direct.0).unwrap());
+            assert_eq!(sources, direct.1);
+            assert!(metadata.title.as_deref().unwrap().ends_with("Title"), "{}", format);
+
+            let cover = handler(format).read_cover(&audio).unwrap();
+            assert_eq!(cover.map(|c| c.data), (format == Format::Id3v2).then(|| b"\xFF\xD8\xFF\xE0jpeg".to_vec()));
+
+            // Writes go through the same handler that read the file
+            let written = audio.set_metadata_with_options(serde_json::to_string(&metadata).unwrap(), &WriteOptions::default());
+            assert_eq!(written.is_ok(), format != Format::Id3v1, "{}", format);
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
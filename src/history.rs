@@ -0,0 +1,76 @@
+// Append-only edit history
+//
+// Each write made with `WriteOptions::history` appends a compact JSON entry to
+// a JSON array kept in a PRIV frame (owner `org.oxidant.history`) for ID3v2
+// or an APPLICATION block (ID `oxhi`) for FLAC. Only the newest
+// `MAX_ENTRIES` entries are kept.
+
+use crate::Metadata;
+use serde::{Deserialize, Serialize};
+
+/// Owner identifier of the ID3v2 PRIV frame holding the history
+pub const ID3_OWNER: &str = "org.oxidant.history";
+
+/// ID of the FLAC APPLICATION block holding the history
+pub const FLAC_APPLICATION_ID: &[u8; 4] = b"oxhi";
+
+/// Number of entries kept; the oldest are evicted first
+pub const MAX_ENTRIES: usize = 64;
+
+/// One recorded edit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Metadata fields whose value changed, by JSON name
+    pub fields_changed: Vec<String>,
+    /// Version of oxidant that made the edit
+    pub tool_version: String,
+}
+
+impl HistoryEntry {
+    /// Entry for an edit from `before` to `after`, stamped with the current time
+    pub fn new(before: &Metadata, after: &Metadata) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        HistoryEntry {
+            timestamp,
+            fields_changed: changed_fields(before, after),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Parse stored history; unreadable data counts as no history
+pub fn parse(data: &[u8]) -> Vec<HistoryEntry> {
+    serde_json::from_slice(data).unwrap_or_default()
+}
+
+/// Append `entry` to `history`, evicting the oldest entries, and encode the result
+pub fn append(mut history: Vec<HistoryEntry>, entry: HistoryEntry) -> Vec<u8> {
+    history.push(entry);
+    let excess = history.len().saturating_sub(MAX_ENTRIES);
+    history.drain(..excess);
+    serde_json::to_vec(&history).unwrap_or_default()
+}
+
+/// Names of the fields that differ between two versions of the metadata
+pub(crate) fn changed_fields(before: &Metadata, after: &Metadata) -> Vec<String> {
+    let to_object = |metadata: &Metadata| match serde_json::to_value(metadata) {
+        Ok(serde_json::Value::Object(object)) => object,
+        _ => serde_json::Map::new(),
+    };
+    let before = to_object(before);
+    let after = to_object(after);
+
+    let mut fields: Vec<String> = before.keys()
+        .chain(after.keys())
+        .filter(|key| *key != "genre_raw" && before.get(*key) != after.get(*key))
+        .cloned()
+        .collect();
+    fields.sort();
+    fields.dedup();
+    fields
+}
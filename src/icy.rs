@@ -0,0 +1,107 @@
+// ICY (SHOUTcast/Icecast) metadata in ripped internet-radio streams
+//
+// A server asked for metadata ("Icy-MetaData: 1") sends a metadata block
+// after every `icy-metaint` bytes of audio:
+//
+// - Length byte: block size in 16-byte units, 0 when nothing changed
+// - Block: NUL-padded text such as "StreamTitle='Artist - Title';StreamUrl='';"
+//
+// Rippers that save the raw response leave these blocks inside the MPEG
+// stream, usually after a prepended ID3v2 tag. The interval isn't stored in
+// the file, so it is recovered from the first StreamTitle block and checked
+// against every block after it.
+
+use serde::Serialize;
+
+/// Metadata block sizes are counted in units of this many bytes
+const BLOCK_UNIT: usize = 16;
+
+const STREAM_TITLE: &[u8] = b"StreamTitle='";
+
+/// Blocks before the first StreamTitle that may be empty, when guessing the interval
+const MAX_LEADING_BLOCKS: usize = 8;
+
+/// Largest interval looked for; servers use 8192 to 32768
+const MAX_INTERVAL: usize = 65536;
+
+/// ICY metadata found in an audio stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IcyStream {
+    /// Audio bytes between metadata blocks (the server's `icy-metaint`)
+    pub interval: usize,
+    /// Number of metadata blocks, including empty ones
+    pub blocks: usize,
+    /// The last non-empty StreamTitle value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_title: Option<String>,
+}
+
+/// Find the metadata interval of `audio`, if it carries ICY blocks
+pub fn find_interval(audio: &[u8]) -> Option<usize> {
+    let search = &audio[..audio.len().min(MAX_INTERVAL * (MAX_LEADING_BLOCKS + 1))];
+    let title = search.windows(STREAM_TITLE.len()).position(|window| window == STREAM_TITLE)?;
+    // The length byte comes right before the title, after `k` intervals and `k - 1` empty blocks
+    let length_byte = title.checked_sub(1)?;
+    (1..=MAX_LEADING_BLOCKS)
+        .filter(|k| (length_byte + 1) % k == 0)
+        .map(|k| (length_byte + 1) / k - 1)
+        .filter(|&interval| interval > 0 && interval <= MAX_INTERVAL)
+        .find(|&interval| blocks(audio, interval).all(is_metadata_block))
+}
+
+/// `audio` without its metadata blocks, and what the blocks held
+pub fn strip(audio: &[u8], interval: usize) -> (Vec<u8>, IcyStream) {
+    let mut stripped = Vec::with_capacity(audio.len());
+    let mut stream = IcyStream { interval, blocks: 0, stream_title: None };
+    let mut pos = 0;
+    while pos < audio.len() {
+        let chunk_end = (pos + interval).min(audio.len());
+        stripped.extend_from_slice(&audio[pos..chunk_end]);
+        let Some(block) = block_at(audio, chunk_end) else {
+            break;
+        };
+        stream.blocks += 1;
+        if let Some(title) = stream_title(block).filter(|title| !title.is_empty()) {
+            stream.stream_title = Some(title);
+        }
+        pos = chunk_end + 1 + block.len();
+    }
+    (stripped, stream)
+}
+
+/// The metadata blocks of `audio`, the last one possibly cut short
+fn blocks(audio: &[u8], interval: usize) -> impl Iterator<Item = &[u8]> {
+    let mut pos = interval;
+    std::iter::from_fn(move || {
+        let block = block_at(audio, pos)?;
+        pos += 1 + block.len() + interval;
+        Some(block)
+    })
+}
+
+/// The metadata block whose length byte is at `pos`
+fn block_at(audio: &[u8], pos: usize) -> Option<&[u8]> {
+    let length = *audio.get(pos)? as usize * BLOCK_UNIT;
+    let start = pos + 1;
+    Some(&audio[start.min(audio.len())..(start + length).min(audio.len())])
+}
+
+/// Whether `block` looks like ICY metadata: empty, or "Stream..." key-value text
+fn is_metadata_block(block: &[u8]) -> bool {
+    b"Stream".starts_with(&block[..block.len().min(6)])
+}
+
+/// The StreamTitle value of a metadata block
+fn stream_title(block: &[u8]) -> Option<String> {
+    let start = block.windows(STREAM_TITLE.len()).position(|window| window == STREAM_TITLE)? + STREAM_TITLE.len();
+    let value = &block[start..];
+    let end = value.windows(2).position(|window| window == b"';")
+        .or_else(|| value.iter().position(|&byte| byte == 0))
+        .unwrap_or(value.len());
+    let value = &value[..end];
+    // Servers send UTF-8 or, older ones, Latin-1
+    Some(match std::str::from_utf8(value) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(value).0.into_owned(),
+    })
+}
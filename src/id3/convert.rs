@@ -0,0 +1,246 @@
+// ID3v2 version conversion
+//
+// Frame ID translation and encoding restrictions used when a tag is written
+// for a different ID3v2 version than the one it was read from.
+
+use super::frames::{
+    decode_text_frame, decode_text_frame_with_encoding, encode_string, encode_text_frame,
+    find_terminator, is_latin1, terminator, TextEncoding,
+};
+use super::v2::Id3Frame;
+
+/// ID3v2.2 (3-char) to ID3v2.3 (4-char) frame IDs
+const V22_TO_V23: &[(&str, &str)] = &[
+    ("BUF", "RBUF"), ("CNT", "PCNT"), ("COM", "COMM"), ("CRA", "AENC"),
+    ("ETC", "ETCO"), ("EQU", "EQUA"), ("GEO", "GEOB"), ("IPL", "IPLS"),
+    ("LNK", "LINK"), ("MCI", "MCDI"), ("MLL", "MLLT"), ("PIC", "APIC"),
+    ("POP", "POPM"), ("REV", "RVRB"), ("RVA", "RVAD"), ("SLT", "SYLT"),
+    ("STC", "SYTC"), ("TAL", "TALB"), ("TBP", "TBPM"), ("TCM", "TCOM"),
+    ("TCO", "TCON"), ("TCR", "TCOP"), ("TDA", "TDAT"), ("TDY", "TDLY"),
+    ("TEN", "TENC"), ("TFT", "TFLT"), ("TIM", "TIME"), ("TKE", "TKEY"),
+    ("TLA", "TLAN"), ("TLE", "TLEN"), ("TMT", "TMED"), ("TOA", "TOPE"),
+    ("TOF", "TOFN"), ("TOL", "TOLY"), ("TOR", "TORY"), ("TOT", "TOAL"),
+    ("TP1", "TPE1"), ("TP2", "TPE2"), ("TP3", "TPE3"), ("TP4", "TPE4"),
+    ("TPA", "TPOS"), ("TPB", "TPUB"), ("TRC", "TSRC"), ("TRD", "TRDA"),
+    ("TRK", "TRCK"), ("TSI", "TSIZ"), ("TSS", "TSSE"), ("TT1", "TIT1"),
+    ("TT2", "TIT2"), ("TT3", "TIT3"), ("TXT", "TEXT"), ("TXX", "TXXX"),
+    ("TYE", "TYER"), ("UFI", "UFID"), ("ULT", "USLT"), ("WAF", "WOAF"),
+    ("WAR", "WOAR"), ("WAS", "WOAS"), ("WCM", "WCOM"), ("WCP", "WCOP"),
+    ("WPB", "WPUB"), ("WXX", "WXXX"),
+];
+
+/// Frames renamed between ID3v2.3 and ID3v2.4
+const V23_TO_V24: &[(&str, &str)] = &[
+    ("TYER", "TDRC"), ("TORY", "TDOR"), ("IPLS", "TIPL"),
+];
+
+/// ID3v2.3 frames with no ID3v2.4 equivalent
+const V23_ONLY: &[&str] = &["TDAT", "TIME", "TRDA", "TSIZ", "RVAD", "EQUA"];
+
+/// ID3v2.4 frames with no ID3v2.3 equivalent
+const V24_ONLY: &[&str] = &[
+    "TDEN", "TDRL", "TDTG", "TMOO", "TPRO", "TSST", "TMCL",
+    "SEEK", "ASPI", "SIGN", "EQU2", "RVA2",
+];
+
+/// Frame flags that change how the data must be interpreted (v2.3 and v2.4 layouts)
+const V23_FORMAT_FLAGS: u16 = 0x00E0;
+const V24_FORMAT_FLAGS: u16 = 0x004F;
+
+/// Translate a frame ID from one major version to another
+///
+/// Returns `None` if the frame has no equivalent in the target version.
+pub fn translate_frame_id(frame_id: &str, from: u8, to: u8) -> Option<&str> {
+    let mut id = frame_id;
+
+    if from == 2 {
+        id = V22_TO_V23.iter().find(|(old, _)| *old == id).map(|(_, new)| *new)?;
+    }
+    let from = from.max(3);
+
+    if from == 3 && to >= 4 {
+        if V23_ONLY.contains(&id) {
+            return None;
+        }
+        if let Some((_, new)) = V23_TO_V24.iter().find(|(old, _)| *old == id) {
+            id = new;
+        }
+    } else if from >= 4 && to == 3 {
+        if V24_ONLY.contains(&id) {
+            return None;
+        }
+        if let Some((old, _)) = V23_TO_V24.iter().find(|(_, new)| *new == id) {
+            id = old;
+        }
+    }
+
+    Some(id)
+}
+
+/// Convert a frame read from a tag of version `from` for writing as version `to`
+///
+/// Returns the reason as an error when the frame cannot be carried over.
+pub fn convert_frame(frame: &Id3Frame, from: u8, to: u8) -> Result<Id3Frame, String> {
+    if from == to {
+        return Ok(frame.clone());
+    }
+
+    let format_flags = match from {
+        2 => 0,
+        3 => V23_FORMAT_FLAGS,
+        _ => V24_FORMAT_FLAGS,
+    };
+    if frame.flags & format_flags != 0 {
+        return Err(format!("{}: compressed, encrypted or grouped frames are not converted", frame.frame_id));
+    }
+
+    let frame_id = translate_frame_id(&frame.frame_id, from, to)
+        .ok_or_else(|| format!("{}: no equivalent frame in ID3v2.{}", frame.frame_id, to))?;
+
+    let mut data = if from == 2 && frame.frame_id == "PIC" {
+        pic_to_apic(&frame.data)
+            .ok_or_else(|| format!("{}: malformed picture frame", frame.frame_id))?
+    } else {
+        frame.data.clone()
+    };
+
+    // v2.3 only has a year here; a full v2.4 timestamp would lose the rest
+    if to == 3 && (frame_id == "TYER" || frame_id == "TORY") {
+        let timestamp = decode_text_frame(&data);
+        let year: String = timestamp.chars().take(4).collect();
+        if year != timestamp {
+            return Err(format!("{}: {:?} does not fit in ID3v2.3 {}", frame.frame_id, timestamp, frame_id));
+        }
+        data = encode_text_frame(&year, TextEncoding::Iso8859_1);
+    }
+
+    if to == 3 {
+        data = restrict_encoding(frame_id, &data)
+            .ok_or_else(|| format!("{}: text encoding not supported in ID3v2.3", frame.frame_id))?;
+    }
+
+    Ok(Id3Frame::new(frame_id, data))
+}
+
+/// Split an ID3v2.4 timestamp ("2001-05-12T10:30") into ID3v2.3 TYER, TDAT (DDMM) and TIME (HHMM) values
+///
+/// TDAT needs a full date and TIME a date plus hours and minutes; anything
+/// else in the timestamp, such as seconds, has no place in ID3v2.3.
+pub fn split_timestamp(timestamp: &str) -> (String, Option<String>, Option<String>) {
+    let year: String = timestamp.chars().take(4).collect();
+    let rest = timestamp.get(4..).unwrap_or_default();
+    let (day, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let date = match day.strip_prefix('-').and_then(|day| day.split_once('-')) {
+        Some((month, day)) if is_two_digits(month) && is_two_digits(day) => Some(format!("{}{}", day, month)),
+        _ => None,
+    };
+    let time = match time.split(':').collect::<Vec<_>>()[..] {
+        [hour, minute, ..] if date.is_some() && is_two_digits(hour) && is_two_digits(minute) => {
+            Some(format!("{}{}", hour, minute))
+        }
+        _ => None,
+    };
+    (year, date, time)
+}
+
+/// Rebuild an ID3v2.4 timestamp from ID3v2.3 TYER, TDAT and TIME values
+///
+/// A TDAT or TIME value that isn't four digits is ignored.
+pub fn join_timestamp(year: &str, date: Option<&str>, time: Option<&str>) -> String {
+    let is_four_digits = |text: &&str| text.len() == 4 && text.bytes().all(|byte| byte.is_ascii_digit());
+    let mut timestamp = year.to_string();
+    if let Some(date) = date.filter(is_four_digits).filter(|_| year.len() == 4) {
+        timestamp.push_str(&format!("-{}-{}", &date[2..], &date[..2]));
+        if let Some(time) = time.filter(is_four_digits) {
+            timestamp.push_str(&format!("T{}:{}", &time[..2], &time[2..]));
+        }
+    }
+    timestamp
+}
+
+fn is_two_digits(text: &str) -> bool {
+    text.len() == 2 && text.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Pick the text encoding to write for a target major version
+pub fn encoding_for(text: &str, major: u8) -> TextEncoding {
+    if is_latin1(text) {
+        TextEncoding::Iso8859_1
+    } else if major >= 4 {
+        TextEncoding::Utf8
+    } else {
+        TextEncoding::Utf16
+    }
+}
+
+/// Convert an ID3v2.2 PIC frame body to an APIC frame body
+fn pic_to_apic(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 5 {
+        return None;
+    }
+
+    let mime_type = match &data[1..4] {
+        b"JPG" => "image/jpeg",
+        b"PNG" => "image/png",
+        b"GIF" => "image/gif",
+        b"BMP" => "image/bmp",
+        _ => "image/",
+    };
+
+    let mut result = vec![data[0]];
+    result.extend_from_slice(mime_type.as_bytes());
+    result.push(0);
+    result.extend_from_slice(&data[4..]);
+    Some(result)
+}
+
+/// Re-encode UTF-8/UTF-16BE strings, which ID3v2.3 does not allow
+///
+/// Returns `None` for frames whose layout isn't known.
+fn restrict_encoding(frame_id: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let encoding = match data.first() {
+        Some(2) => TextEncoding::Utf16BE,
+        Some(3) => TextEncoding::Utf8,
+        _ => return Some(data.to_vec()),
+    };
+
+    // (bytes before the strings, whether only the first string is text)
+    let (prefix_len, description_only) = match frame_id {
+        "COMM" | "USLT" => (4, false),
+        "APIC" => {
+            let mime_end = data[1..].iter().position(|&b| b == 0)? + 1;
+            (mime_end + 2, true)
+        }
+        "WXXX" => (1, true),
+        id if id.starts_with('T') || id == "IPLS" => (1, false),
+        _ => return None,
+    };
+    if prefix_len > data.len() {
+        return None;
+    }
+
+    let (text, binary) = if description_only {
+        let end = prefix_len + find_terminator(&data[prefix_len..], encoding)?;
+        (&data[prefix_len..end], &data[end + terminator(encoding).len()..])
+    } else {
+        (&data[prefix_len..], &[][..])
+    };
+
+    let decoded = decode_text_frame_with_encoding(text, encoding).replace('\u{feff}', "");
+    let target = encoding_for(&decoded, 3);
+
+    let mut result = vec![target as u8];
+    result.extend_from_slice(&data[1..prefix_len]);
+    for (i, part) in decoded.split('\0').enumerate() {
+        if i > 0 {
+            result.extend_from_slice(terminator(target));
+        }
+        result.extend(encode_string(part, target));
+    }
+    if description_only {
+        result.extend_from_slice(terminator(target));
+        result.extend_from_slice(binary);
+    }
+
+    Some(result)
+}
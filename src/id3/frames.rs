@@ -55,9 +55,9 @@ pub fn decode_text_frame(data: &[u8]) -> String {
         TextEncoding::Utf16 => {
             // Detect BOM
             if text_data.len() >= 2 {
-                if &text_data[0..2] == [0xFF, 0xFE] {
+                if text_data[0..2] == [0xFF, 0xFE] {
                     UTF_16LE.decode(&text_data[2..]).0.to_string()
-                } else if &text_data[0..2] == [0xFE, 0xFF] {
+                } else if text_data[0..2] == [0xFE, 0xFF] {
                     UTF_16BE.decode(&text_data[2..]).0.to_string()
                 } else {
                     UTF_16LE.decode(text_data).0.to_string()
@@ -76,33 +76,63 @@ pub fn decode_text_frame(data: &[u8]) -> String {
 }
 
 /// Encode text frame data
-#[allow(dead_code)]
 pub fn encode_text_frame(text: &str, encoding: TextEncoding) -> Vec<u8> {
     let mut result = vec![encoding as u8];
+    result.extend(encode_string(text, encoding));
+    result
+}
 
-    let encoded = match encoding {
+/// Encode a string without terminator
+///
+/// encoding_rs only decodes UTF-16, so UTF-16 output is produced by hand.
+pub fn encode_string(text: &str, encoding: TextEncoding) -> Vec<u8> {
+    match encoding {
         TextEncoding::Iso8859_1 => {
             WINDOWS_1252.encode(text).0.to_vec()
         }
         TextEncoding::Utf16 => {
-            let mut bom = vec![0xFF, 0xFE];
-            let encoded = UTF_16LE.encode(text).0.to_vec();
-            bom.extend(encoded);
-            bom
+            let mut result = vec![0xFF, 0xFE];
+            for unit in text.encode_utf16() {
+                result.extend_from_slice(&unit.to_le_bytes());
+            }
+            result
         }
         TextEncoding::Utf16BE => {
-            let mut bom = vec![0xFE, 0xFF];
-            let encoded = UTF_16BE.encode(text).0.to_vec();
-            bom.extend(encoded);
-            bom
+            let mut result = Vec::new();
+            for unit in text.encode_utf16() {
+                result.extend_from_slice(&unit.to_be_bytes());
+            }
+            result
         }
         TextEncoding::Utf8 => {
-            UTF_8.encode(text).0.to_vec()
+            text.as_bytes().to_vec()
         }
-    };
+    }
+}
 
-    result.extend(encoded);
-    result
+/// String terminator for an encoding
+pub fn terminator(encoding: TextEncoding) -> &'static [u8] {
+    match encoding {
+        TextEncoding::Utf16 | TextEncoding::Utf16BE => &[0, 0],
+        _ => &[0],
+    }
+}
+
+/// Find the end of a terminated string, returning the terminator position
+pub fn find_terminator(data: &[u8], encoding: TextEncoding) -> Option<usize> {
+    match encoding {
+        TextEncoding::Utf16 | TextEncoding::Utf16BE => {
+            (0..data.len().saturating_sub(1))
+                .step_by(2)
+                .find(|&i| data[i] == 0 && data[i + 1] == 0)
+        }
+        _ => data.iter().position(|&b| b == 0),
+    }
+}
+
+/// Check whether text can be stored as ISO-8859-1 without loss
+pub fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) < 0x100)
 }
 
 /// Picture type for ID3v2 APIC frame
@@ -132,6 +162,35 @@ pub enum PictureType {
     PublisherLogo = 0x14,
 }
 
+impl PictureType {
+    /// Picture type from the APIC type byte; unknown values map to `Other`
+    pub fn from_byte(value: u8) -> Self {
+        match value {
+            0x01 => PictureType::FileIcon,
+            0x02 => PictureType::OtherFileIcon,
+            0x03 => PictureType::CoverFront,
+            0x04 => PictureType::CoverBack,
+            0x05 => PictureType::LeafletPage,
+            0x06 => PictureType::Media,
+            0x07 => PictureType::LeadArtist,
+            0x08 => PictureType::Artist,
+            0x09 => PictureType::Conductor,
+            0x0A => PictureType::Band,
+            0x0B => PictureType::Composer,
+            0x0C => PictureType::Lyricist,
+            0x0D => PictureType::RecordingLocation,
+            0x0E => PictureType::DuringRecording,
+            0x0F => PictureType::DuringPerformance,
+            0x10 => PictureType::VideoScreenCapture,
+            0x11 => PictureType::BrightColouredFish,
+            0x12 => PictureType::Illustration,
+            0x13 => PictureType::BandLogo,
+            0x14 => PictureType::PublisherLogo,
+            _ => PictureType::Other,
+        }
+    }
+}
+
 /// Encode APIC (Attached Picture) frame
 #[allow(dead_code)]
 pub fn encode_apic_frame(
@@ -165,6 +224,15 @@ pub fn encode_apic_frame(
 /// Decode APIC (Attached Picture) frame
 #[allow(dead_code)]
 pub fn decode_apic_frame(data: &[u8]) -> Option<(String, PictureType, String, Vec<u8>)> {
+    let (mime_type, _picture_type, description, image_data) = split_apic_frame(data)?;
+    // Picture type simplified
+    Some((mime_type, PictureType::Other, description, image_data.to_vec()))
+}
+
+/// Split APIC frame data into (MIME type, picture type byte, description, image data)
+///
+/// The image data is borrowed from `data`, not copied.
+pub fn split_apic_frame(data: &[u8]) -> Option<(String, u8, String, &[u8])> {
     if data.is_empty() {
         return None;
     }
@@ -184,18 +252,12 @@ pub fn decode_apic_frame(data: &[u8]) -> Option<(String, PictureType, String, Ve
     }
     let mime_type = String::from_utf8_lossy(&data[pos + 1..mime_end]).to_string();
 
-    // Picture type
-    let picture_type = PictureType::Other; // Simplified
+    // Picture type; a frame truncated right after the MIME type has none
+    let picture_type = *data.get(mime_end + 1)?;
 
     // Find description (null-terminated)
     let desc_start = mime_end + 2;
-    let mut desc_end = desc_start;
-    while desc_end < data.len() && data[desc_end] != 0 {
-        desc_end += 1;
-    }
-    if desc_end >= data.len() {
-        return None;
-    }
+    let desc_end = desc_start + find_terminator(data.get(desc_start..)?, encoding)?;
 
     // Decode description based on encoding
     let description = if desc_end > desc_start {
@@ -205,13 +267,13 @@ pub fn decode_apic_frame(data: &[u8]) -> Option<(String, PictureType, String, Ve
     };
 
     // Image data
-    let image_data = data[desc_end + 1..].to_vec();
+    let image_data = data.get(desc_end + terminator(encoding).len()..)?;
 
     Some((mime_type, picture_type, description, image_data))
 }
 
 /// Decode text with specific encoding
-fn decode_text_frame_with_encoding(data: &[u8], encoding: TextEncoding) -> String {
+pub fn decode_text_frame_with_encoding(data: &[u8], encoding: TextEncoding) -> String {
     if data.is_empty() {
         return String::new();
     }
@@ -223,9 +285,9 @@ fn decode_text_frame_with_encoding(data: &[u8], encoding: TextEncoding) -> Strin
         TextEncoding::Utf16 => {
             // Detect BOM
             if data.len() >= 2 {
-                if &data[0..2] == [0xFF, 0xFE] {
+                if data[0..2] == [0xFF, 0xFE] {
                     UTF_16LE.decode(&data[2..]).0.to_string()
-                } else if &data[0..2] == [0xFE, 0xFF] {
+                } else if data[0..2] == [0xFE, 0xFF] {
                     UTF_16BE.decode(&data[2..]).0.to_string()
                 } else {
                     UTF_16LE.decode(data).0.to_string()
@@ -243,33 +305,79 @@ fn decode_text_frame_with_encoding(data: &[u8], encoding: TextEncoding) -> Strin
     }
 }
 
+/// Encode TXXX (user-defined text) frame data
+pub fn encode_txxx_frame(description: &str, value: &str, encoding: TextEncoding) -> Vec<u8> {
+    let mut result = vec![encoding as u8];
+    result.extend(encode_string(description, encoding));
+    result.extend_from_slice(terminator(encoding));
+    result.extend(encode_string(value, encoding));
+    result
+}
+
+/// Decode TXXX (user-defined text) frame data into (description, value)
+pub fn decode_txxx_frame(data: &[u8]) -> Option<(String, String)> {
+    let encoding = TextEncoding::from_byte(*data.first()?);
+    let text = &data[1..];
+    let desc_end = find_terminator(text, encoding)?;
+    let value_start = desc_end + terminator(encoding).len();
+
+    let description = decode_text_frame_with_encoding(&text[..desc_end], encoding);
+    let value = decode_text_frame_with_encoding(&text[value_start..], encoding);
+    Some((description, value.trim_end_matches('\0').to_string()))
+}
+
+/// Encode PRIV (private) frame data: a Latin-1 owner identifier and binary data
+pub fn encode_priv_frame(owner: &str, data: &[u8]) -> Vec<u8> {
+    let mut result = encode_string(owner, TextEncoding::Iso8859_1);
+    result.push(0);
+    result.extend_from_slice(data);
+    result
+}
+
+/// Decode PRIV (private) frame data into (owner, data)
+pub fn decode_priv_frame(data: &[u8]) -> Option<(String, &[u8])> {
+    let owner_end = data.iter().position(|&byte| byte == 0)?;
+    let owner = decode_text_frame_with_encoding(&data[..owner_end], TextEncoding::Iso8859_1);
+    Some((owner, &data[owner_end + 1..]))
+}
+
+/// Encode UFID (unique file identifier) frame data: a Latin-1 owner and a binary identifier
+pub fn encode_ufid_frame(owner: &str, identifier: &[u8]) -> Vec<u8> {
+    encode_priv_frame(owner, identifier)
+}
+
+/// Decode UFID (unique file identifier) frame data into (owner, identifier)
+///
+/// The layout is the same as PRIV; MusicBrainz uses the owner
+/// "http://musicbrainz.org" with the recording ID as identifier.
+pub fn decode_ufid_frame(data: &[u8]) -> Option<(String, Vec<u8>)> {
+    decode_priv_frame(data).map(|(owner, identifier)| (owner, identifier.to_vec()))
+}
+
 /// Encode USLT (Unsynchronized Lyrics) frame
-#[allow(dead_code)]
+///
+/// COMM frames share this layout and are encoded with the same function.
 pub fn encode_uslt_frame(
     language: &str,
     description: &str,
     lyrics: &str,
+    encoding: TextEncoding,
 ) -> Vec<u8> {
     let mut result = Vec::new();
 
-    // Text encoding (use UTF-8 for better multilingual support)
-    result.push(TextEncoding::Utf8 as u8);
+    // Text encoding
+    result.push(encoding as u8);
 
-    // Language (3 bytes, ISO-639-2)
-    let lang_bytes = language.as_bytes();
-    if lang_bytes.len() >= 3 {
-        result.extend_from_slice(&lang_bytes[0..3]);
-    } else {
-        result.extend_from_slice(lang_bytes);
-        result.extend_from_slice(&vec![0u8; 3 - lang_bytes.len()]);
-    }
+    // Language (3 bytes, ISO-639-2); callers validate it, anything else is written as "und"
+    let language = crate::utils::language::normalize(language).unwrap_or(crate::utils::language::UNDETERMINED);
+    result.extend_from_slice(language.as_bytes());
 
     // Description (null-terminated)
-    result.extend_from_slice(UTF_8.encode(description).0.as_ref());
-    result.push(0);
+    result.extend(encode_string(description, encoding));
+    result.extend_from_slice(terminator(encoding));
 
     // Lyrics text
-    result.extend_from_slice(UTF_8.encode(lyrics).0.as_ref());
+    result.extend(encode_string(lyrics, encoding));
 
     result
 }
@@ -289,15 +397,9 @@ pub fn decode_uslt_frame(data: &[u8]) -> Option<(String, String, String)> {
     }
     let language = String::from_utf8_lossy(&data[1..4]).to_string();
 
-    // Find description (null-terminated)
+    // Find description (null-terminated); a frame without one is truncated
     let desc_start = 4;
-    let mut desc_end = desc_start;
-    while desc_end < data.len() && data[desc_end] != 0 {
-        desc_end += 1;
-    }
-    if desc_end >= data.len() {
-        return None;
-    }
+    let desc_end = desc_start + find_terminator(data.get(desc_start..)?, encoding)?;
 
     // Decode description based on encoding
     let description = if desc_end > desc_start {
@@ -307,13 +409,40 @@ pub fn decode_uslt_frame(data: &[u8]) -> Option<(String, String, String)> {
     };
 
     // Lyrics (remaining data after null terminator)
-    let lyrics_start = desc_end + 1;
-    let lyrics = if lyrics_start < data.len() {
-        decode_text_frame_with_encoding(&data[lyrics_start..], encoding)
-    } else {
-        String::new()
+    let lyrics_start = desc_end + terminator(encoding).len();
+    let lyrics = match data.get(lyrics_start..) {
+        Some(text) if !text.is_empty() => decode_text_frame_with_encoding(text, encoding),
+        _ => String::new(),
     };
 
     Some((language, description, lyrics))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_truncated_apic_and_uslt_frames() {
+
+        // Ends right after the MIME type's terminator: no picture type, description or image
+        assert!(decode_apic_frame(b"\x00image/png\x00").is_none());
+        // Picture type present but nothing after it
+        assert!(decode_apic_frame(b"\x00image/png\x00\x03").is_none());
+        let (mime, _, description, image) = decode_apic_frame(b"\x00image/png\x00\x03\x00").unwrap();
+        assert_eq!((mime.as_str(), description.as_str(), image.len()), ("image/png", "", 0));
+
+        // Language only, no description terminator
+        assert!(decode_uslt_frame(b"\x00eng").is_none());
+        assert!(decode_uslt_frame(b"\x01eng\x00").is_none());
+        // Empty description and no lyrics text
+        assert_eq!(
+            decode_uslt_frame(b"\x00eng\x00").unwrap(),
+            ("eng".to_string(), String::new(), String::new())
+        );
+        assert_eq!(
+            decode_uslt_frame(b"\x01eng\x00\x00").unwrap(),
+            ("eng".to_string(), String::new(), String::new())
+        );
+    }
+}
@@ -2,6 +2,7 @@
 pub mod v1;
 pub mod v2;
 pub mod frames;
+pub mod convert;
 
 pub use v1::Id3v1Tag;
 pub use v2::Id3v2Tag;
\ No newline at end of file
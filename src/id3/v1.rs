@@ -1,24 +1,54 @@
 // ID3v1 tag implementation
 
+use encoding_rs::WINDOWS_1252;
+use serde::Serialize;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 
+/// Size of the title, artist, album and comment fields
+pub const TEXT_FIELD_LEN: usize = 30;
+/// Comment field size when an ID3v1.1 track number is present
+pub const COMMENT_WITH_TRACK_LEN: usize = 28;
+/// Size of the year field
+pub const YEAR_LEN: usize = 4;
+/// Size of the title, artist and album continuations in a TAG+ extended tag
+pub const EXTENDED_TEXT_LEN: usize = 60;
+
 /// ID3v1 tag structure
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Id3v1Tag {
+    /// Up to 30 characters, or 90 when a TAG+ extended tag continues it
     pub title: String,
     pub artist: String,
     pub album: String,
     pub year: String,
     pub comment: String,
+    /// ID3v1.1 track number, stored in the last two bytes of the comment field
     pub track: Option<u8>,
-    #[allow(dead_code)]
     pub genre: u8,
+    /// The "TAG+" extended tag stored right before this one, if any
+    pub extended: Option<Id3v1Extended>,
+}
+
+/// Fields of a "TAG+" extended tag besides the title, artist and album continuations
+///
+/// The 227-byte block sits right before the standard tag: "TAG+", then 60
+/// more characters each of title, artist and album, a speed byte, a 30-byte
+/// free-text genre, and start and end times as "mmm:ss".
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Id3v1Extended {
+    /// 0 unset, 1 slow, 2 medium, 3 fast, 4 hardcore
+    pub speed: u8,
+    pub genre: String,
+    pub start_time: String,
+    pub end_time: String,
 }
 
 impl Id3v1Tag {
-    const TAG_SIZE: usize = 128;
+    pub const TAG_SIZE: usize = 128;
+    pub const EXTENDED_TAG_SIZE: usize = 227;
     const TAG_ID: [u8; 3] = [b'T', b'A', b'G'];
+    const EXTENDED_TAG_ID: [u8; 4] = [b'T', b'A', b'G', b'+'];
 
     /// Read ID3v1 tag from file
     pub fn read_from_file(path: &str) -> std::io::Result<Option<Self>> {
@@ -34,23 +64,70 @@ impl Id3v1Tag {
         file.read_exact(&mut buffer)?;
 
         // Check for TAG identifier
-        if &buffer[0..3] != Self::TAG_ID {
+        if buffer[0..3] != Self::TAG_ID {
             return Ok(None);
         }
 
-        Ok(Some(Self::parse(&buffer)))
+        let mut tag = Self::parse(&buffer);
+        let total_size = (Self::TAG_SIZE + Self::EXTENDED_TAG_SIZE) as u64;
+        if file_size >= total_size {
+            file.seek(SeekFrom::End(-(total_size as i64)))?;
+            let mut extended = [0u8; Self::EXTENDED_TAG_SIZE];
+            file.read_exact(&mut extended)?;
+            if Self::is_extended(&extended) {
+                tag.merge_extended(&buffer, &extended);
+            }
+        }
+        Ok(Some(tag))
+    }
+
+    /// Check for the "TAG+" identifier at the start of `bytes`
+    pub fn is_extended(bytes: &[u8]) -> bool {
+        bytes.starts_with(&Self::EXTENDED_TAG_ID)
+    }
+
+    /// Continue the title, artist and album of the standard tag `buffer` with a TAG+ block
+    ///
+    /// A field only continues when the standard one is full, without a NUL.
+    pub(crate) fn merge_extended(&mut self, buffer: &[u8; 128], extended: &[u8; 227]) {
+        let join = |short: &[u8], long: &[u8]| {
+            if short.contains(&0) {
+                Self::parse_string(short)
+            } else {
+                Self::parse_string(&[short, long].concat())
+            }
+        };
+        self.title = join(&buffer[3..33], &extended[4..64]);
+        self.artist = join(&buffer[33..63], &extended[64..124]);
+        self.album = join(&buffer[63..93], &extended[124..184]);
+        self.extended = Some(Id3v1Extended {
+            speed: extended[184],
+            genre: Self::parse_string(&extended[185..215]),
+            start_time: Self::parse_string(&extended[215..221]),
+            end_time: Self::parse_string(&extended[221..227]),
+        });
     }
 
     /// Parse ID3v1 tag from buffer
-    fn parse(buffer: &[u8; 128]) -> Self {
+    ///
+    /// Old rippers pad fields with spaces rather than NULs and fill unknown
+    /// years with "0" or spaces; such fields are read as empty.
+    pub(crate) fn parse(buffer: &[u8; 128]) -> Self {
         let title = Self::parse_string(&buffer[3..33]);
         let artist = Self::parse_string(&buffer[33..63]);
         let album = Self::parse_string(&buffer[63..93]);
-        let year = Self::parse_string(&buffer[93..97]);
+        let year = Self::parse_year(&buffer[93..97]);
         let comment = Self::parse_string(&buffer[97..127]);
 
-        // Check for ID3v1.1 track number
-        let (comment, track) = if buffer[125] == 0 && buffer[126] != 0 {
+        // ID3v1.1 keeps the track in byte 126 behind a NUL. Space-padding
+        // rippers put a space there instead, so a space also counts, but only
+        // when byte 126 couldn't be part of a space-padded comment.
+        let is_separator = match buffer[125] {
+            0 => true,
+            b' ' => !(b' '..=b'~').contains(&buffer[126]),
+            _ => false,
+        };
+        let (comment, track) = if is_separator && buffer[126] != 0 {
             (Self::parse_string(&buffer[97..125]), Some(buffer[126]))
         } else {
             (comment, None)
@@ -66,12 +143,173 @@ impl Id3v1Tag {
             comment,
             track,
             genre,
+            extended: None,
+        }
+    }
+
+    /// Whether writing the track number cuts a comment that would otherwise fit
+    ///
+    /// With a track number (ID3v1.1) the comment field shrinks from 30 to
+    /// 28 bytes; byte 125 must be NUL and byte 126 holds the track.
+    pub fn track_truncates_comment(&self) -> bool {
+        let length = self.comment.chars().count();
+        self.track.is_some() && length > COMMENT_WITH_TRACK_LEN && length <= TEXT_FIELD_LEN
+    }
+
+    /// Serialize the tag; text is stored as Latin-1 and cut to the field size
+    ///
+    /// A track number limits the comment to 28 bytes; without one the
+    /// comment may use all 30.
+    pub fn to_bytes(&self) -> [u8; 128] {
+        let mut buffer = [0u8; Self::TAG_SIZE];
+        buffer[0..3].copy_from_slice(&Self::TAG_ID);
+        Self::write_string(&mut buffer[3..33], &self.title);
+        Self::write_string(&mut buffer[33..63], &self.artist);
+        Self::write_string(&mut buffer[63..93], &self.album);
+        Self::write_string(&mut buffer[93..97], &self.year);
+
+        match self.track {
+            Some(track) => {
+                Self::write_string(&mut buffer[97..125], &self.comment);
+                buffer[125] = 0;
+                buffer[126] = track;
+            }
+            None => Self::write_string(&mut buffer[97..127], &self.comment),
         }
+
+        buffer[127] = self.genre;
+        buffer
+    }
+
+    /// Serialize the TAG+ extended tag, if the tag has one
+    ///
+    /// Title, artist and album continue with their characters after the
+    /// first 30, which `to_bytes` stores.
+    pub fn extended_to_bytes(&self) -> Option<[u8; 227]> {
+        let extended = self.extended.as_ref()?;
+        let rest = |text: &str| text.chars().skip(TEXT_FIELD_LEN).collect::<String>();
+        let mut buffer = [0u8; Self::EXTENDED_TAG_SIZE];
+        buffer[0..4].copy_from_slice(&Self::EXTENDED_TAG_ID);
+        Self::write_string(&mut buffer[4..64], &rest(&self.title));
+        Self::write_string(&mut buffer[64..124], &rest(&self.artist));
+        Self::write_string(&mut buffer[124..184], &rest(&self.album));
+        buffer[184] = extended.speed;
+        Self::write_string(&mut buffer[185..215], &extended.genre);
+        Self::write_string(&mut buffer[215..221], &extended.start_time);
+        Self::write_string(&mut buffer[221..227], &extended.end_time);
+        Some(buffer)
+    }
+
+    /// Longest title, artist or album that fits: 30 characters, 90 with a TAG+ tag
+    pub fn text_len(&self) -> usize {
+        if self.extended.is_some() { TEXT_FIELD_LEN + EXTENDED_TEXT_LEN } else { TEXT_FIELD_LEN }
     }
 
-    /// Parse null-terminated string
+    /// Write a string into a zero-filled field, truncating if needed
+    ///
+    /// Characters outside Windows-1252 are replaced with '?', one byte each.
+    fn write_string(field: &mut [u8], text: &str) {
+        let encoded = text.chars().map(|c| {
+            let mut utf8 = [0u8; 4];
+            let (bytes, _, had_errors) = WINDOWS_1252.encode(c.encode_utf8(&mut utf8));
+            if had_errors { b'?' } else { bytes[0] }
+        });
+        for (byte, value) in field.iter_mut().zip(encoded) {
+            *byte = value;
+        }
+    }
+
+    /// Parse null-terminated Latin-1 string; an all-space field is empty
     fn parse_string(bytes: &[u8]) -> String {
         let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
-        String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+        WINDOWS_1252.decode(&bytes[..end]).0.trim().to_string()
     }
-}
\ No newline at end of file
+
+    /// Parse the year field, which must be 4 digits; "0000" means unknown
+    fn parse_year(bytes: &[u8]) -> String {
+        let year = Self::parse_string(bytes);
+        if year.len() == YEAR_LEN && year.bytes().all(|b| b.is_ascii_digit()) && year != "0000" {
+            year
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Check whether an ID3v1 value agrees with an ID3v2 value
+///
+/// ID3v1 fields hold at most `max_len` Latin-1 bytes, so a v1 value that is
+/// the v2 value cut to that length (and trimmed) is not a mismatch.
+pub fn matches_truncated(v1: &str, v2: &str, max_len: usize) -> bool {
+    let v1 = v1.trim();
+    let v2 = v2.trim();
+    if v1 == v2 {
+        return true;
+    }
+
+    // Windows-1252 is one byte per character, unrepresentable ones included
+    let truncated: String = v2.chars().take(max_len).collect();
+    v2.chars().count() > max_len && v1 == truncated.trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id3v1_matches_truncated() {
+        assert!(matches_truncated("Song", "Song", 30));
+        assert!(matches_truncated("Song ", "Song", 30));
+        let long_title = "A Very Long Song Title That Keeps Going";
+        assert!(matches_truncated(&long_title[..30], long_title, 30));
+        // Only a cut at exactly the field size counts as truncation
+        assert!(!matches_truncated(&long_title[..20], long_title, 30));
+        assert!(!matches_truncated("Song", "Other", 30));
+        assert!(!matches_truncated("", "Song", 30));
+        assert!(matches_truncated("2021", "2021-05-04", 4));
+    }
+
+    #[test]
+    fn test_id3v1_parse_padded_fields() {
+        // (title, year, comment field bytes 97..127, expected year, comment, track)
+        type Case = (&'static [u8], &'static [u8], &'static [u8], &'static str, &'static str, Option<u8>);
+        let cases: &[Case] = &[
+            (b"Song", b"1999", b"Nice", "1999", "Nice", None),
+            (&[b' '; 30], b"1999", b"", "1999", "", None),
+            (b"Song                          ", b"0   ", b"", "", "", None),
+            (b"Song", b"    ", b"", "", "", None),
+            (b"Song", b"0000", b"", "", "", None),
+            (b"Song", b"99\0\0", b"", "", "", None),
+            (b"Song", b"19x9", b"", "", "", None),
+            // ID3v1.1: NUL at 125, track at 126
+            (b"Song", b"2001", b"Comment\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x07", "2001", "Comment", Some(7)),
+            // Space-padded ID3v1.1 comment with the track behind a space
+            (b"Song", b"2001", b"Comment                      \x03", "2001", "Comment", Some(3)),
+            // Fully space-padded comment: no track
+            (b"Song", b"2001", &[b' '; 30], "2001", "", None),
+            // Comment text reaching the end of the field: no track
+            (b"Song", b"2001", b"A comment that fills 30 bytes!", "2001", "A comment that fills 30 bytes!", None),
+            // Space then a letter is still comment text
+            (b"Song", b"2001", b"Ends with a spaced letter    X", "2001", "Ends with a spaced letter    X", None),
+            // NUL at 125 but nothing at 126: plain ID3v1
+            (b"Song", b"2001", b"Short", "2001", "Short", None),
+        ];
+
+        for (title, year, comment, expected_year, expected_comment, expected_track) in cases {
+            let mut buffer = [0u8; 128];
+            buffer[0..3].copy_from_slice(b"TAG");
+            buffer[3..3 + title.len()].copy_from_slice(title);
+            buffer[93..97].copy_from_slice(year);
+            buffer[97..97 + comment.len()].copy_from_slice(comment);
+            buffer[127] = 17;
+
+            let tag = Id3v1Tag::parse(&buffer);
+            let context = String::from_utf8_lossy(comment);
+            assert_eq!(tag.title, String::from_utf8_lossy(title).trim(), "{}", context);
+            assert_eq!(tag.year, *expected_year, "{}", context);
+            assert_eq!(tag.comment, *expected_comment, "{}", context);
+            assert_eq!(tag.track, *expected_track, "{}", context);
+            assert_eq!(tag.genre, 17);
+        }
+    }
+}
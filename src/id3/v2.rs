@@ -1,12 +1,12 @@
 // ID3v2 tag implementation
 
-use std::io::Read;
+use super::frames;
+use std::io::{Read, Seek, SeekFrom};
 
 /// ID3v2 header structure
 #[derive(Debug)]
 pub struct Id3v2Header {
     pub version: (u8, u8),
-    #[allow(dead_code)]
     pub flags: u8,
     pub size: u32,
 }
@@ -14,17 +14,15 @@ pub struct Id3v2Header {
 /// ID3v2 tag structure
 #[derive(Debug)]
 pub struct Id3v2Tag {
-    #[allow(dead_code)]
     pub header: Id3v2Header,
     pub frames: Vec<Id3Frame>,
 }
 
 /// ID3v2 frame structure
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Id3Frame {
     pub frame_id: String,
     pub size: u32,
-    #[allow(dead_code)]
     pub flags: u16,
     pub data: Vec<u8>,
 }
@@ -32,6 +30,27 @@ pub struct Id3Frame {
 impl Id3v2Header {
     const HEADER_SIZE: usize = 10;
     const ID: [u8; 3] = [b'I', b'D', b'3'];
+    const FOOTER_ID: [u8; 3] = [b'3', b'D', b'I'];
+    const FLAG_EXPERIMENTAL: u8 = 0x20;
+    const FLAG_FOOTER: u8 = 0x10;
+
+    /// Check for an ID3v2.4 footer, a copy of the header after the frames
+    ///
+    /// `size` never includes the footer, so the frames end where it starts.
+    pub fn has_footer(&self) -> bool {
+        self.version.0 >= 4 && self.flags & Self::FLAG_FOOTER != 0
+    }
+
+    /// Check the experimental flag (ID3v2.3 and later)
+    pub fn is_experimental(&self) -> bool {
+        self.version.0 >= 3 && self.flags & Self::FLAG_EXPERIMENTAL != 0
+    }
+
+    /// Size of the whole tag on disk: header, frames, padding and optional footer
+    pub fn total_size(&self) -> u64 {
+        let footer = if self.has_footer() { Self::HEADER_SIZE as u64 } else { 0 };
+        Self::HEADER_SIZE as u64 + self.size as u64 + footer
+    }
 
     /// Read ID3v2 header from reader
     pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
@@ -39,7 +58,7 @@ impl Id3v2Header {
         reader.read_exact(&mut buffer)?;
 
         // Check for ID3 identifier
-        if &buffer[0..3] != Self::ID {
+        if buffer[0..3] != Self::ID {
             return Ok(None);
         }
 
@@ -61,63 +80,387 @@ impl Id3v2Header {
         ((bytes[2] as u32) << 7) |
         (bytes[3] as u32)
     }
+
+    /// Encode synchsafe integer (7 bits per byte)
+    fn encode_synchsafe(value: u32) -> [u8; 4] {
+        [
+            ((value >> 21) & 0x7F) as u8,
+            ((value >> 14) & 0x7F) as u8,
+            ((value >> 7) & 0x7F) as u8,
+            (value & 0x7F) as u8,
+        ]
+    }
+
+    /// Serialize header for a tag body of `size` bytes (flags are cleared)
+    pub fn to_bytes(version: (u8, u8), size: u32) -> [u8; Self::HEADER_SIZE] {
+        Self::encode(Self::ID, version, 0, size)
+    }
+
+    /// Serialize a header and matching footer for a tag body of `size` bytes
+    fn to_bytes_with_footer(version: (u8, u8), size: u32) -> ([u8; Self::HEADER_SIZE], [u8; Self::HEADER_SIZE]) {
+        (
+            Self::encode(Self::ID, version, Self::FLAG_FOOTER, size),
+            Self::encode(Self::FOOTER_ID, version, Self::FLAG_FOOTER, size),
+        )
+    }
+
+    fn encode(id: [u8; 3], version: (u8, u8), flags: u8, size: u32) -> [u8; Self::HEADER_SIZE] {
+        let mut buffer = [0u8; Self::HEADER_SIZE];
+        buffer[0..3].copy_from_slice(&id);
+        buffer[3] = version.0;
+        buffer[4] = version.1;
+        buffer[5] = flags;
+        buffer[6..10].copy_from_slice(&Self::encode_synchsafe(size));
+        buffer
+    }
+}
+
+/// Streaming reader over the frames of an ID3v2 tag
+///
+/// Frames are read one at a time as the iterator advances, so only the
+/// current frame is held in memory. Iteration ends at the padding, at a frame
+/// running past the end of the tag, or after the first read error.
+///
+/// A frame header with non-printable bytes in its ID, or whose frame runs
+/// past the end of the tag, is taken as corruption. The reader then moves
+/// forward a byte at a time until a header with a valid ID (see
+/// `Id3Frame::is_valid_id`) that fits in the tag lines up, so one damaged
+/// frame doesn't lose the frames after it. Other nonstandard IDs, such as
+/// "COM " or lowercase experimental frames, are read as ordinary frames.
+pub struct Id3v2Frames<R> {
+    reader: R,
+    header: Id3v2Header,
+    remaining: usize,
+}
+
+/// Typed view of an ID3v2 frame; the frame data is only decoded when asked for
+#[derive(Debug, Clone)]
+pub enum Id3FrameView {
+    /// Text information frame (T***, except TXXX)
+    Text(Id3Frame),
+    /// COMM frame
+    Comment(Id3Frame),
+    /// USLT frame
+    Lyrics(Id3Frame),
+    /// APIC frame
+    Picture(Id3Frame),
+    /// Any other frame, undecoded
+    Binary { id: String, bytes: Vec<u8> },
+}
+
+/// Picture of an APIC frame, with the image data borrowed from the frame
+#[derive(Debug)]
+pub struct Id3Picture<'a> {
+    pub mime_type: String,
+    /// Picture type byte, 3 for the front cover
+    pub picture_type: u8,
+    pub description: String,
+    pub data: &'a [u8],
 }
 
 impl Id3v2Tag {
     /// Read ID3v2 tag from reader
     pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
-        let header = match Id3v2Header::read(reader)? {
-            Some(h) => h,
+        let mut frames = match Self::frames(reader)? {
+            Some(frames) => frames,
+            None => return Ok(None),
+        };
+
+        let mut tag_frames = Vec::new();
+        while let Some(frame) = frames.next_frame()? {
+            tag_frames.push(frame);
+        }
+
+        Ok(Some(Id3v2Tag { header: frames.header, frames: tag_frames }))
+    }
+
+    /// Iterate over the frames of the tag at the reader's position
+    ///
+    /// `None` if there is no ID3v2 tag there.
+    ///
+    /// ```
+    /// use oxidant::{Id3FrameView, Id3v2Tag};
+    ///
+    /// // ID3v2.3 tag holding a single TIT2 frame
+    /// let mut tag = b"ID3\x03\x00\x00\x00\x00\x00\x11".to_vec();
+    /// tag.extend_from_slice(b"TIT2\x00\x00\x00\x07\x00\x00\x00Title\x00");
+    ///
+    /// let mut frames = Id3v2Tag::frames(tag.as_slice()).unwrap().unwrap();
+    /// let title = frames.next().unwrap().unwrap();
+    /// assert!(matches!(title, Id3FrameView::Text(_)));
+    /// assert_eq!(title.text().as_deref(), Some("Title"));
+    /// assert!(frames.next().is_none());
+    /// ```
+    pub fn frames<R: Read>(mut reader: R) -> std::io::Result<Option<Id3v2Frames<R>>> {
+        let header = match Id3v2Header::read(&mut reader)? {
+            Some(header) => header,
             None => return Ok(None),
         };
+        let remaining = header.size as usize;
+        Ok(Some(Id3v2Frames { reader, header, remaining }))
+    }
+}
+
+impl<R: Read> Id3v2Frames<R> {
+    /// Header of the tag being read
+    pub fn header(&self) -> &Id3v2Header {
+        &self.header
+    }
+
+    /// Read the next frame without wrapping it in a view
+    pub fn next_frame(&mut self) -> std::io::Result<Option<Id3Frame>> {
+        let frame_header_len = Id3Frame::header_len(self.header.version.0);
+
+        let Some((frame_id, size, flags)) = self.read_frame_header()? else {
+            return Ok(None);
+        };
+        let mut data = vec![0u8; size as usize];
+        self.reader.read_exact(&mut data)?;
+        let frame = Id3Frame { frame_id, size, flags, data };
+
+        let frame_total_size = frame.size as usize + frame_header_len;
+        if frame_total_size > self.remaining {
+            self.remaining = 0;
+            return Ok(None);
+        }
+        self.remaining -= frame_total_size;
+        Ok(Some(frame))
+    }
 
-        let mut frames = Vec::new();
-        let mut remaining = header.size as usize;
+    /// Read the next frame header, skipping bytes until a plausible one
+    ///
+    /// `None` at the padding or when no frame header is left in the tag.
+    fn read_frame_header(&mut self) -> std::io::Result<Option<(String, u32, u16)>> {
+        let frame_header_len = Id3Frame::header_len(self.header.version.0);
 
-        while remaining > 0 {
-            let frame = match Id3Frame::read(reader, header.version)? {
-                Some(f) => f,
-                None => break,
+        // Anything shorter than a frame header is padding
+        if self.remaining < frame_header_len {
+            return Ok(None);
+        }
+        let mut buffer = vec![0u8; frame_header_len];
+        self.reader.read_exact(&mut buffer)?;
+
+        let id_len = if self.header.version.0 == 2 { 3 } else { 4 };
+        let mut resyncing = false;
+        loop {
+            if buffer.iter().all(|&b| b == 0) {
+                self.remaining = 0;
+                return Ok(None);
+            }
+            let (frame_id, size, flags) = Id3Frame::parse_header(&buffer, self.header.version);
+            let fits = size as usize + frame_header_len <= self.remaining;
+            let plausible = if resyncing {
+                // Frame data that happens to look like a header must have a standard ID
+                Id3Frame::is_valid_id(&frame_id)
+            } else {
+                buffer[..id_len].iter().all(|b| (0x20..=0x7E).contains(b))
             };
+            if plausible && fits {
+                return Ok(Some((frame_id, size, flags)));
+            }
 
-            let frame_total_size = frame.size as usize + 10; // frame header is 10 bytes
-            if frame_total_size > remaining {
-                break;
+            // Slide the header window one byte forward
+            resyncing = true;
+            self.remaining -= 1;
+            if self.remaining < frame_header_len {
+                self.remaining = 0;
+                return Ok(None);
             }
-            remaining -= frame_total_size;
-            frames.push(frame);
+            let mut next = [0u8; 1];
+            self.reader.read_exact(&mut next)?;
+            buffer.remove(0);
+            buffer.push(next[0]);
         }
+    }
+}
+
+impl<R: Read + Seek> Id3v2Frames<R> {
+    /// Read the next frame whose ID passes `wanted`, seeking past the data of the others
+    ///
+    /// `wanted` sees the frame ID as stored, so ID3v2.2 tags give 3-character IDs.
+    pub fn next_frame_where(&mut self, mut wanted: impl FnMut(&str) -> bool) -> std::io::Result<Option<Id3Frame>> {
+        let frame_header_len = Id3Frame::header_len(self.header.version.0);
+
+        loop {
+            if self.remaining < frame_header_len {
+                return Ok(None);
+            }
+            let Some((frame_id, size, flags)) = self.read_frame_header()? else {
+                return Ok(None);
+            };
+
+            // Like `next_frame`, a wanted frame cut short by the end of the file is an error
+            let data = if wanted(&frame_id) {
+                let mut data = vec![0u8; size as usize];
+                self.reader.read_exact(&mut data)?;
+                Some(data)
+            } else {
+                None
+            };
 
-        Ok(Some(Id3v2Tag { header, frames }))
+            let frame_total_size = size as usize + frame_header_len;
+            if frame_total_size > self.remaining {
+                self.remaining = 0;
+                return Ok(None);
+            }
+            self.remaining -= frame_total_size;
+
+            match data {
+                Some(data) => return Ok(Some(Id3Frame { frame_id, size, flags, data })),
+                None => {
+                    self.reader.seek(SeekFrom::Current(size as i64))?;
+                }
+            }
+        }
     }
 }
 
-impl Id3Frame {
-    /// Read ID3v2 frame from reader
-    pub fn read<R: Read>(reader: &mut R, version: (u8, u8)) -> std::io::Result<Option<Self>> {
-        let mut buffer = [0u8; 10];
-        reader.read_exact(&mut buffer)?;
+impl<R: Read> Iterator for Id3v2Frames<R> {
+    type Item = std::io::Result<Id3FrameView>;
 
-        // Check for padding (all zeros)
-        if buffer.iter().all(|&b| b == 0) {
-            return Ok(None);
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_frame() {
+            Ok(frame) => frame.map(|frame| Ok(Id3FrameView::from(frame))),
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
         }
+    }
+}
 
-        let frame_id = String::from_utf8_lossy(&buffer[0..4]).to_string();
+impl From<Id3Frame> for Id3FrameView {
+    fn from(frame: Id3Frame) -> Self {
+        match frame.frame_id.as_str() {
+            "TXXX" => Id3FrameView::Binary { id: frame.frame_id, bytes: frame.data },
+            id if id.starts_with('T') => Id3FrameView::Text(frame),
+            "COMM" => Id3FrameView::Comment(frame),
+            "USLT" => Id3FrameView::Lyrics(frame),
+            "APIC" => Id3FrameView::Picture(frame),
+            _ => Id3FrameView::Binary { id: frame.frame_id, bytes: frame.data },
+        }
+    }
+}
 
-        // Frame size parsing depends on version
-        let size = if version.0 >= 4 {
+impl Id3FrameView {
+    /// Frame ID, e.g. "TIT2"
+    pub fn id(&self) -> &str {
+        match self {
+            Id3FrameView::Text(frame)
+            | Id3FrameView::Comment(frame)
+            | Id3FrameView::Lyrics(frame)
+            | Id3FrameView::Picture(frame) => &frame.frame_id,
+            Id3FrameView::Binary { id, .. } => id,
+        }
+    }
+
+    /// Decoded text of a text information frame
+    pub fn text(&self) -> Option<String> {
+        match self {
+            Id3FrameView::Text(frame) => Some(frames::decode_text_frame(&frame.data).trim_end_matches('\0').to_string()),
+            _ => None,
+        }
+    }
+
+    /// (language, description, text) of a comment or lyrics frame
+    pub fn comment(&self) -> Option<(String, String, String)> {
+        match self {
+            Id3FrameView::Comment(frame) | Id3FrameView::Lyrics(frame) => frames::decode_uslt_frame(&frame.data),
+            _ => None,
+        }
+    }
+
+    /// Picture of an APIC frame; the image data is not copied
+    pub fn picture(&self) -> Option<Id3Picture<'_>> {
+        match self {
+            Id3FrameView::Picture(frame) => {
+                let (mime_type, picture_type, description, data) = frames::split_apic_frame(&frame.data)?;
+                Some(Id3Picture { mime_type, picture_type, description, data })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Id3v2Tag {
+    /// Serialize the tag for the given version, followed by `padding` zero bytes
+    ///
+    /// Frames are written as-is; converting them between versions is the
+    /// caller's job (see `id3::convert`).
+    pub fn to_bytes(frames: &[Id3Frame], version: (u8, u8), padding: usize) -> Vec<u8> {
+        let mut body = Vec::new();
+        for frame in frames {
+            body.extend(frame.to_bytes(version.0));
+        }
+        body.resize(body.len() + padding, 0);
+
+        let mut result = Id3v2Header::to_bytes(version, body.len() as u32).to_vec();
+        result.extend(body);
+        result
+    }
+
+    /// Serialize an ID3v2.4 tag with a footer
+    ///
+    /// A tag with a footer may not have padding, so none is written.
+    pub fn to_bytes_with_footer(frames: &[Id3Frame]) -> Vec<u8> {
+        let version = (4, 0);
+        let mut body = Vec::new();
+        for frame in frames {
+            body.extend(frame.to_bytes(version.0));
+        }
+
+        let (header, footer) = Id3v2Header::to_bytes_with_footer(version, body.len() as u32);
+        let mut result = header.to_vec();
+        result.extend(body);
+        result.extend_from_slice(&footer);
+        result
+    }
+}
+
+impl Id3Frame {
+    /// Create a frame with no flags set
+    pub fn new(frame_id: &str, data: Vec<u8>) -> Self {
+        Id3Frame {
+            frame_id: frame_id.to_string(),
+            size: data.len() as u32,
+            flags: 0,
+            data,
+        }
+    }
+
+    /// Frame header length: 6 bytes in ID3v2.2, 10 bytes afterwards
+    pub fn header_len(major: u8) -> usize {
+        if major == 2 { 6 } else { 10 }
+    }
+
+    /// Serialize frame header and data for ID3v2.3 or ID3v2.4
+    pub fn to_bytes(&self, major: u8) -> Vec<u8> {
+        let mut result = Vec::with_capacity(10 + self.data.len());
+        result.extend_from_slice(self.frame_id.as_bytes());
+
+        let size = self.data.len() as u32;
+        if major >= 4 {
             // ID3v2.4 uses synchsafe integers
-            Id3v2Header::parse_synchsafe(&buffer[4..8])
+            result.extend_from_slice(&Id3v2Header::encode_synchsafe(size));
         } else {
-            // ID3v2.3 uses regular integers
-            ((buffer[4] as u32) << 24) |
-            ((buffer[5] as u32) << 16) |
-            ((buffer[6] as u32) << 8) |
-            (buffer[7] as u32)
-        };
+            result.extend_from_slice(&size.to_be_bytes());
+        }
+
+        result.extend_from_slice(&self.flags.to_be_bytes());
+        result.extend_from_slice(&self.data);
+        result
+    }
 
-        let flags = ((buffer[8] as u16) << 8) | (buffer[9] as u16);
+    /// Read ID3v2 frame from reader
+    pub fn read<R: Read>(reader: &mut R, version: (u8, u8)) -> std::io::Result<Option<Self>> {
+        let mut header = vec![0u8; Self::header_len(version.0)];
+        reader.read_exact(&mut header)?;
+
+        // Check for padding (all zeros)
+        if header.iter().all(|&b| b == 0) {
+            return Ok(None);
+        }
+        let (frame_id, size, flags) = Self::parse_header(&header, version);
 
         // Read frame data
         let mut data = vec![0u8; size as usize];
@@ -130,4 +473,116 @@ impl Id3Frame {
             data,
         }))
     }
-}
\ No newline at end of file
+
+    /// Whether `frame_id` is a well-formed ID: 4 uppercase letters or digits, 3 in ID3v2.2
+    pub fn is_valid_id(frame_id: &str) -> bool {
+        matches!(frame_id.len(), 3 | 4)
+            && frame_id.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+    }
+
+    /// Split a frame header into ID, data size and flags
+    ///
+    /// `header` holds `header_len` bytes; ID3v2.2 headers have a 3-char ID,
+    /// a 24-bit size and no flags.
+    fn parse_header(header: &[u8], version: (u8, u8)) -> (String, u32, u16) {
+        if version.0 == 2 {
+            let frame_id = String::from_utf8_lossy(&header[0..3]).to_string();
+            let size = ((header[3] as u32) << 16) | ((header[4] as u32) << 8) | (header[5] as u32);
+            return (frame_id, size, 0);
+        }
+
+        let frame_id = String::from_utf8_lossy(&header[0..4]).to_string();
+
+        // Frame size parsing depends on version
+        let size = if version.0 >= 4 {
+            // ID3v2.4 uses synchsafe integers
+            Id3v2Header::parse_synchsafe(&header[4..8])
+        } else {
+            // ID3v2.3 uses regular integers
+            ((header[4] as u32) << 24) |
+            ((header[5] as u32) << 16) |
+            ((header[6] as u32) << 8) |
+            (header[7] as u32)
+        };
+
+        let flags = ((header[8] as u16) << 8) | (header[9] as u16);
+
+        (frame_id, size, flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_id3v2_frame_iterator() {
+        let image = [0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3, 4, 0xFF, 0xD9];
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x00Title".to_vec()),
+            ("COMM", b"\x00engdesc\x00Comment".to_vec()),
+            ("USLT", b"\x00eng\x00Lyrics".to_vec()),
+            ("APIC", build_apic_frame(&image)),
+            ("PRIV", b"owner\x00data".to_vec()),
+        ]);
+
+        let frames = Id3v2Tag::frames(data.as_slice()).unwrap().unwrap();
+        assert_eq!(frames.header().version, (3, 0));
+        let frames: Vec<Id3FrameView> = frames.collect::<std::io::Result<_>>().unwrap();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames.iter().map(Id3FrameView::id).collect::<Vec<_>>(), ["TIT2", "COMM", "USLT", "APIC", "PRIV"]);
+
+        assert_eq!(frames[0].text().as_deref(), Some("Title"));
+        assert_eq!(frames[1].comment(), Some(("eng".to_string(), "desc".to_string(), "Comment".to_string())));
+        assert_eq!(frames[2].comment().map(|(_, _, lyrics)| lyrics).as_deref(), Some("Lyrics"));
+        assert!(matches!(&frames[4], Id3FrameView::Binary { bytes, .. } if bytes == b"owner\x00data"));
+
+        // The picture borrows its bytes from the frame instead of copying them
+        let Id3FrameView::Picture(frame) = &frames[3] else { panic!("APIC is not a picture view") };
+        let picture = frames[3].picture().unwrap();
+        assert_eq!(picture.data, image);
+        assert!(frame.data.as_ptr_range().contains(&picture.data.as_ptr()));
+
+        // Id3v2Tag::read is built on the same iterator
+        let tag = Id3v2Tag::read(&mut data.as_slice()).unwrap().unwrap();
+        assert_eq!(tag.frames.len(), 5);
+    }
+
+
+    #[test]
+    fn test_id3v2_resyncs_past_corrupt_frame_ids() {
+        let frames = [
+            ("TIT2", b"\x00Title".to_vec()),
+            ("TPE1", b"\x00Artist".to_vec()),
+            ("TALB", b"\x00Album".to_vec()),
+        ];
+        let frame_ids = |data: &[u8]| {
+            let tag = Id3v2Tag::read(&mut &data[..]).unwrap().unwrap();
+            tag.frames.into_iter().map(|frame| (frame.frame_id, frame.data)).collect::<Vec<_>>()
+        };
+
+        // One corrupt byte in the middle of the TPE1 frame ID drops only that frame
+        let mut data = build_id3v2_file(&frames);
+        let at = data.windows(4).position(|window| window == b"TPE1").unwrap();
+        data[at + 2] = 0xFF;
+        assert_eq!(frame_ids(&data), [
+            ("TIT2".to_string(), b"\x00Title".to_vec()),
+            ("TALB".to_string(), b"\x00Album".to_vec()),
+        ]);
+
+        // A stray byte after TIT2 misaligns the next header; the frames after it are still found
+        let mut stray = frames.clone();
+        stray[0].1.push(0xFF);
+        let mut data = build_id3v2_file(&stray);
+        data[10 + 7] -= 1;
+        let ids: Vec<String> = frame_ids(&data).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, ["TIT2", "TPE1", "TALB"]);
+
+        assert!(Id3Frame::is_valid_id("TIT2"));
+        assert!(Id3Frame::is_valid_id("TT2"));
+        assert!(!Id3Frame::is_valid_id("Tit2"));
+        assert!(!Id3Frame::is_valid_id("COM "));
+        assert!(!Id3Frame::is_valid_id("T\u{FFFD}E1"));
+    }
+}
@@ -8,10 +8,12 @@ use pyo3::prelude::*;
 #[cfg(feature = "python")]
 use pyo3::Bound;
 #[cfg(feature = "python")]
-use pyo3::types::PyList;
+use pyo3::types::{PyBool, PyDict, PyIterator, PyList, PyString};
 
 use std::fs::File;
 use std::io::{BufReader, Read, Seek};
+use std::ops::Range;
+use std::path::Path;
 use serde::{Serialize, Deserialize, Serializer};
 
 mod id3;
@@ -20,14 +22,46 @@ mod ogg;
 mod opus;
 mod mp4;
 mod ape;
+mod dsf;
+mod icy;
 mod utils;
+mod properties;
+mod soundcheck;
+mod history;
+mod sidecar;
+mod batch;
+pub mod field_mapping;
+pub mod template;
+pub mod backup;
+pub mod walk;
+pub mod logging;
+pub mod storage;
+pub mod detect;
+mod handler;
+#[cfg(test)]
+mod test_util;
 
-use id3::{Id3v1Tag, Id3v2Tag};
+use id3::Id3v1Tag;
+pub use id3::v1::Id3v1Extended;
 use flac::{FlacMetadataBlock, FlacMetadataBlockType, FLAC_SIGNATURE};
-use ogg::{OGG_SIGNATURE, vorbis::OggVorbisFile};
+use ogg::vorbis::OggVorbisFile;
 use opus::OpusFile;
 use mp4::Mp4File;
 use ape::ApeFile;
+use field_mapping::{FieldMappings, StandardField, ValueConverter};
+
+pub use flac::cuesheet::{CueIndex, CueTrack, TrackPoint};
+pub use flac::metadata::{FlacBlockView, FlacBlocks};
+pub use flac::{FlacPicture, VorbisComment};
+pub use id3::Id3v2Tag;
+pub use id3::v2::{Id3Frame, Id3FrameView, Id3Picture, Id3v2Frames, Id3v2Header};
+pub use properties::{AudioProperties, LameHeader};
+pub use soundcheck::SoundCheck;
+pub use history::HistoryEntry;
+pub use icy::IcyStream;
+pub use batch::apply;
+#[cfg(feature = "http")]
+pub use utils::http::DownloadOptions;
 
 // Alias for our custom Result type to avoid conflicts with std::result::Result
 pub type AudioResult<T> = std::result::Result<T, AudioFileError>;
@@ -40,15 +74,307 @@ pub type AudioResult<T> = std::result::Result<T, AudioFileError>;
 #[derive(Debug)]
 pub struct AudioFile {
     pub path: String,
-    pub file_type: String,
+    pub format: Format,
+}
+
+/// File format, detected from the file contents
+///
+/// MP3 files are identified by their tags: `Id3v2` if the file starts with
+/// an ID3v2 tag, otherwise `Ape` or `Id3v1` if one of those trails the audio.
+/// A bare MPEG stream with none of them is `Mp3`; it reads as empty, and the
+/// first write puts an ID3v2 tag in front of the audio.
+///
+/// `Dsf` files keep an ID3v2 tag wherever the DSF header points, normally
+/// at the end of the file, and are read and written through the ID3v2 code.
+///
+/// A new format needs a variant here and a `FormatHandler` in
+/// `handler::HANDLERS`, which detection, reads, writes, the cover and the
+/// audio properties go through. The other `match`es on the format are
+/// exhaustive, so the compiler lists the rest of the places to handle it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Id3v2,
+    Id3v1,
+    Flac,
+    OggVorbis,
+    Opus,
+    Mp4,
+    Ape,
+    Mp3,
+    Dsf,
+}
+
+impl Format {
+    /// Short lowercase name, e.g. "flac", as exposed to Python as `file_type`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Id3v2 => "id3v2",
+            Format::Id3v1 => "id3v1",
+            Format::Flac => "flac",
+            Format::OggVorbis => "ogg",
+            Format::Opus => "opus",
+            Format::Mp4 => "mp4",
+            Format::Ape => "ape",
+            Format::Mp3 => "mp3",
+            Format::Dsf => "dsf",
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = AudioFileError;
+
+    /// Parse a name as returned by `as_str`, ignoring case
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        const FORMATS: [Format; 9] = [
+            Format::Id3v2, Format::Id3v1, Format::Flac, Format::OggVorbis, Format::Opus, Format::Mp4, Format::Ape,
+            Format::Mp3, Format::Dsf,
+        ];
+        FORMATS.into_iter()
+            .find(|format| format.as_str().eq_ignore_ascii_case(name))
+            .ok_or_else(|| AudioFileError::UnsupportedFormat(format!(
+                "Unknown format '{}', expected one of: {}",
+                name,
+                FORMATS.map(|format| format.as_str()).join(", ")
+            )))
+    }
+}
+
+/// Options controlling what `get_metadata` reads
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// Read embedded cover art (serialized as base64 in JSON output)
+    pub include_cover: bool,
+    /// Add the audio properties under a `properties` key of the JSON output
+    pub include_properties: bool,
+    /// Look for ICY metadata blocks in the MPEG stream of an internet-radio rip
+    ///
+    /// When found, properties are read from the audio without them and the
+    /// last StreamTitle is added under a `stream_title` key of the JSON output.
+    pub icy_aware: bool,
+    /// Give each field of the JSON output as `{"value": ..., "source": ...}`,
+    /// naming the tag structure it was read from (see `FieldSources`)
+    pub with_sources: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self { include_cover: true, include_properties: false, icy_aware: false, with_sources: false }
+    }
+}
+
+/// The tag structure each populated `Metadata` field was read from
+///
+/// Keys are JSON field names; values name the tag and its frame, comment,
+/// atom or item, e.g. "ID3v2.4/TIT2", "ID3v1", "FLAC/TITLE" or "MP4/©nam".
+pub type FieldSources = std::collections::BTreeMap<String, String>;
+
+/// MP4 atom each `Metadata` field is read from, named as in `FieldSources`
+const MP4_FIELD_ATOMS: &[(&str, &str)] = &[
+    ("title", "©nam"),
+    ("artist", "©ART"),
+    ("album", "©alb"),
+    ("year", "©day"),
+    ("comment", "©cmt"),
+    ("track", "trkn"),
+    ("genre", "©gen"),
+    ("genres", "©gen"),
+    ("album_artist", "aART"),
+    ("composer", "©wrt"),
+    ("original_date", "----:ORIGINALDATE"),
+    ("original_artist", "----:ORIGINALARTIST"),
+    ("original_album", "----:ORIGINALALBUM"),
+    ("lyrics", "©lyr"),
+    ("encoder", "©too"),
+    ("encoder_settings", "----:ENCODERSETTINGS"),
+    ("cover", "covr"),
+];
+
+/// APEv2 item each `Metadata` field is read from
+const APE_FIELD_ITEMS: &[(&str, &str)] = &[
+    ("title", ape::fields::TITLE),
+    ("artist", ape::fields::ARTIST),
+    ("album", ape::fields::ALBUM),
+    ("year", ape::fields::YEAR),
+    ("comment", ape::fields::COMMENT),
+    ("track", ape::fields::TRACK),
+    ("genre", ape::fields::GENRE),
+    ("genres", ape::fields::GENRE),
+    ("lyrics", ape::fields::LYRICS),
+    ("encoder", ape::fields::ENCODER),
+];
+
+/// Attribute the fields of `metadata` that have no source yet to `source(key)`
+fn record_sources(sources: &mut FieldSources, metadata: &Metadata, source: impl Fn(&str) -> String) {
+    for key in metadata.populated_keys() {
+        if !sources.contains_key(key) {
+            sources.insert(key.to_string(), source(key));
+        }
+    }
+}
+
+/// Standard fields an ID3v1 tag holds
+const ID3V1_FIELDS: [StandardField; 7] = [
+    StandardField::Title,
+    StandardField::Artist,
+    StandardField::Album,
+    StandardField::Year,
+    StandardField::Comment,
+    StandardField::Track,
+    StandardField::Genre,
+];
+
+/// Options controlling how `set_metadata` writes tags
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// ID3v2 version to write, `(2, 3)` or `(2, 4)`; `None` preserves the input version
+    pub target_version: Option<(u8, u8)>,
+    /// Produce byte-identical output for identical input
+    ///
+    /// Frames and Vorbis comments not managed by `Metadata` are sorted by
+    /// ID/key and value and written before the managed ones (which always
+    /// use a fixed order), padding is a fixed size rather than reusing the
+    /// old tag space, and the Vorbis vendor string is replaced with
+    /// `"oxidant"`. Nothing time-dependent is ever written.
+    pub deterministic: bool,
+    /// Normalize genres to standard names before writing; `None` writes them as given
+    pub genre_policy: Option<GenrePolicy>,
+    /// Set `length_ms` from the audio stream's duration (see `get_properties`)
+    ///
+    /// The encoder is never filled in automatically; it is only preserved or
+    /// changed when set explicitly.
+    pub auto_length: bool,
+    /// Append an entry to the file's edit history (see `get_edit_history`)
+    ///
+    /// Entries are time-stamped, so this can't be combined with `deterministic`.
+    /// Only ID3v2 and FLAC files have a place for the history; other formats
+    /// are written without one.
+    pub history: bool,
+    /// ISO-639-2 language of the ID3v2 COMM and USLT frames; `None` writes "eng"
+    ///
+    /// 2-letter codes such as "en" are converted and "und" marks an unknown
+    /// language. Anything else is rejected before the file is touched.
+    pub language: Option<String>,
+    /// Keep Vorbis comment keys as spelled in the file
+    ///
+    /// By default FLAC, OGG Vorbis and Opus comments are normalized on write
+    /// (see `VorbisComment::normalize`): keys are uppercased and spaces
+    /// removed, and repeated comments are merged.
+    pub preserve_vorbis_keys: bool,
+    /// Padding after the last ID3v2 frame when the tag has to grow; `None` uses 1024 bytes
+    ///
+    /// A tag that still fits in the space of the old one is written in place,
+    /// keeping the rest of that space as padding, so the audio doesn't move.
+    pub id3v2_padding: Option<usize>,
+    /// Check a new cover against the image types the format allows; `None` writes it as given
+    pub cover_policy: Option<CoverPolicy>,
+    /// Fail, writing nothing, when a changed field can't be stored in this format
+    ///
+    /// Otherwise such fields are left out and listed in `WriteReport::skipped`.
+    pub strict: bool,
+}
+
+/// Details about a completed write
+#[derive(Debug, Clone, Default)]
+pub struct WriteReport {
+    /// Frames that could not be carried over to the target version, with the reason
+    pub lossy_frames: Vec<String>,
+    /// Problems noticed while writing that didn't stop it, e.g. Vorbis keys
+    /// spelled differently with conflicting values
+    pub warnings: Vec<String>,
+    /// Changed fields that were written, by their `Metadata` name
+    pub written: Vec<String>,
+    /// Changed fields the format has no place for, left out of the write
+    pub skipped: Vec<SkippedField>,
+}
+
+/// A changed field left out of a write, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedField {
+    /// `Metadata` field name, e.g. "performers"
+    pub field: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for SkippedField {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} not written: {}", self.field, self.reason)
+    }
+}
+
+/// What `AudioFile::optimize` changed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OptimizeReport {
+    /// Reduction in file size; negative if the file grew
+    pub bytes_saved: i64,
+    /// Human-readable description of each change
+    pub changes: Vec<String>,
+}
+
+/// Padding added after the frames when an ID3v2 tag has to grow
+const ID3V2_PADDING: usize = 1024;
+
+/// Padding left in an ID3v2 tag by `optimize`
+const ID3V2_OPTIMIZED_PADDING: usize = 512;
+
+/// Padding after rebuilt FLAC metadata, and the most `optimize` keeps
+const FLAC_PADDING: usize = 4096;
+
+/// Cover images larger than this are reported by `validate`
+const LARGE_COVER_BYTES: usize = 2 * 1024 * 1024;
+
+/// Vorbis comment keys rebuilt from `Metadata` on every write, in the order written
+const MANAGED_VORBIS_FIELDS: &[&str] = &[
+    "TITLE", "ARTIST", "ALBUM", "DATE", "TRACKNUMBER", "GENRE",
+    "ALBUMARTIST", "COMPOSER", "COMMENT", "LYRICS", "LENGTH", "ENCODER",
+    "ENCODERSETTINGS", "ORIGINALDATE", "ORIGINALYEAR", "ORIGINALARTIST", "ORIGINALALBUM", "PERFORMER",
+];
+
+/// Check whether `frame_id` names an experimental ID3v2 frame: X, Y or Z, then three uppercase letters or digits
+fn is_custom_id3v2_frame_id(frame_id: &str) -> bool {
+    frame_id.len() == 4
+        && frame_id.starts_with(['X', 'Y', 'Z'])
+        && frame_id.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+}
+
+/// Check whether a Vorbis comment key is mapped to a `Metadata` field
+fn is_managed_vorbis_field(key: &str) -> bool {
+    MANAGED_VORBIS_FIELDS.contains(&flac::vorbis::canonical_key(key).as_str())
 }
 
+/// Vendor string used by deterministic writes
+const DETERMINISTIC_VENDOR: &str = "oxidant";
+
+/// ID3v2 frames rebuilt from `Metadata` on every write in addition to the
+/// standard fields' frames (ID3v2.4 names)
+const EXTRA_MANAGED_ID3V2_FRAMES: &[&str] = &[
+    "TPE2", "TCOM", "TLEN", "TSSE", "TDOR", "TOPE", "TOAL", "TMCL",
+];
+
+/// Description of the ID3v2 TXXX frame holding `Metadata::encoder_settings`
+const ENCODER_SETTINGS_TXXX: &str = "ENCODERSETTINGS";
+
 // Error type for AudioFile operations
 #[derive(Debug)]
 pub enum AudioFileError {
     IoError(std::io::Error),
     UnsupportedFormat(String),
     ParseError(String),
+    AudioIntegrityError(String),
+    /// The file (path included) is marked read-only
+    ReadOnlyFile(String),
+    /// Another program holds the file (path included) open without sharing it
+    FileInUse(String),
+    /// The file (path included) or its directory can't be written by this user
+    PermissionDenied(String),
+    /// An external program (name included) that the operation runs isn't installed
+    ToolNotFound(String),
 }
 
 impl std::fmt::Display for AudioFileError {
@@ -57,6 +383,11 @@ impl std::fmt::Display for AudioFileError {
             AudioFileError::IoError(e) => write!(f, "I/O error: {}", e),
             AudioFileError::UnsupportedFormat(msg) => write!(f, "Unsupported format: {}", msg),
             AudioFileError::ParseError(msg) => write!(f, "Parse error: {}", msg),
+            AudioFileError::AudioIntegrityError(msg) => write!(f, "Audio integrity error: {}", msg),
+            AudioFileError::ReadOnlyFile(path) => write!(f, "File is read-only: {}", path),
+            AudioFileError::FileInUse(path) => write!(f, "File is in use by another program: {}", path),
+            AudioFileError::PermissionDenied(path) => write!(f, "Permission denied: {}", path),
+            AudioFileError::ToolNotFound(tool) => write!(f, "External tool not found: {}", tool),
         }
     }
 }
@@ -81,7 +412,6 @@ where
 }
 
 /// Custom deserialization for base64 string to Vec<u8>
-#[allow(dead_code)]
 fn deserialize_base64_to_vec<'de, D>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -91,6 +421,57 @@ where
     BASE64_STANDARD.decode(&s).map_err(serde::de::Error::custom)
 }
 
+/// Description of a TXXX or COMM frame (ID3v2.4 IDs); `None` for other frames
+fn frame_description(frame_id: &str, data: &[u8]) -> Option<String> {
+    match frame_id {
+        "TXXX" => id3::frames::decode_txxx_frame(data).map(|(description, _)| description),
+        "COMM" => id3::frames::decode_uslt_frame(data).map(|(_, description, _)| description),
+        _ => None,
+    }
+}
+
+/// Check whether a COMM description marks iTunes data rather than a comment
+fn is_itunes_comment(description: &str) -> bool {
+    description.starts_with("iTun")
+}
+
+/// Parse a Vorbis PERFORMER value, "Instrument (Name)", into (instrument, name)
+///
+/// A value without the parenthesized name is taken as a name with no instrument.
+fn parse_performer(text: &str) -> (String, String) {
+    match text.trim().strip_suffix(')').and_then(|rest| rest.split_once(" (")) {
+        Some((instrument, name)) => (instrument.trim().to_string(), name.trim().to_string()),
+        None => (String::new(), text.trim().to_string()),
+    }
+}
+
+/// Format a performer as a Vorbis PERFORMER value (see `parse_performer`)
+fn format_performer((instrument, name): &(String, String)) -> String {
+    if instrument.is_empty() {
+        name.clone()
+    } else {
+        format!("{} ({})", instrument, name)
+    }
+}
+
+/// Parse a stored length: milliseconds ("215000") or a clock time ("3:35", "1:02:03.5")
+fn parse_length_ms(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if !text.contains(':') {
+        return text.parse().ok();
+    }
+
+    let mut seconds = 0.0;
+    for part in text.split(':') {
+        let value: f64 = part.parse().ok()?;
+        if value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some((seconds * 1000.0).round() as u64)
+}
+
 // Private implementation block for internal methods
 impl AudioFile {
     /// Decode text frame data
@@ -119,137 +500,216 @@ impl AudioFile {
 
     /// Read metadata from the audio file (internal method)
     fn read_metadata_internal(&self) -> AudioResult<Metadata> {
-        match self.file_type.as_str() {
-            "id3v2" => self.read_id3v2_metadata(),
-            "id3v1" => self.read_id3v1_metadata(),
-            "flac" => self.read_flac_metadata(),
-            "ogg" => self.read_ogg_metadata(),
-            "opus" => self.read_opus_metadata(),
-            "mp4" => self.read_mp4_metadata(),
-            "ape" => self.read_ape_metadata(),
-            _ => Ok(Metadata::default()),
-        }
+        Ok(self.read_metadata_sourced()?.0)
     }
 
-    /// Detect file type
-    fn detect_file_type(path: &str) -> AudioResult<String> {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+    /// Read metadata along with the tag structure each field came from
+    fn read_metadata_sourced(&self) -> AudioResult<(Metadata, FieldSources)> {
+        let _span = tracing::debug_span!("parse", format = %self.format).entered();
+        handler::handler(self.format).read(self)
+    }
 
-        // Check for ID3v2
-        let mut id3_signature = [0u8; 3];
-        if reader.read_exact(&mut id3_signature).is_ok() {
-            if &id3_signature == b"ID3" {
-                return Ok("id3v2".to_string());
+    /// Check whether the file has a tag or stream header of `self.format`
+    fn has_tag(&self) -> AudioResult<bool> {
+        Ok(match self.format {
+            Format::Id3v2 => self.has_id3v2_tag()?,
+            Format::Id3v1 => Id3v1Tag::read_from_file(&self.path)?.is_some(),
+            Format::Flac => FlacBlocks::new(BufReader::new(File::open(&self.path)?))?.is_some(),
+            Format::OggVorbis => OggVorbisFile::new(self.path.clone()).read_comment()?.is_some(),
+            Format::Opus => OpusFile::new(self.path.clone()).read_comment()?.is_some(),
+            Format::Mp4 => {
+                let mut header = [0u8; 8];
+                File::open(&self.path)?.read_exact(&mut header).is_ok() && &header[4..] == b"ftyp"
+            }
+            Format::Ape => ApeFile::new(self.path.clone()).read_metadata()?.is_some(),
+            Format::Mp3 => {
+                let mut head = Vec::new();
+                File::open(&self.path)?.take(4096).read_to_end(&mut head)?;
+                properties::starts_with_mpeg_frame(&head) || self.has_id3v2_tag()?
             }
+            Format::Dsf => dsf::DsfHeader::read(&mut File::open(&self.path)?)?.is_some(),
+        })
+    }
+
+    /// Store `value` in `field` unless the field already has one
+    ///
+    /// Empty values are ignored; a later non-empty value for a field that is
+    /// already set is logged as a warning naming its `source` frame or key.
+    fn keep_first(&self, field: &mut Option<String>, value: Option<String>, source: &str) {
+        let Some(value) = value.filter(|value| !value.is_empty()) else {
+            return;
+        };
+        if field.is_none() {
+            *field = Some(value);
+        } else {
+            self.report_duplicate(source, &value);
         }
+    }
+
+    /// Log that a later `source` value was ignored in favour of the first one
+    fn report_duplicate(&self, source: &str, value: &str) {
+        tracing::warn!(
+            event = "warning",
+            path = %self.path,
+            message = %format!("ignoring duplicate {} value {:?}; the first one is used", source, value),
+        );
+    }
+
+    /// Read ID3v2 metadata
+    fn read_id3v2_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut metadata = Metadata::default();
+        let mut sources = FieldSources::new();
 
-        // Check for FLAC
-        reader.seek(std::io::SeekFrom::Start(0))?;
-        let mut flac_signature = [0u8; 4];
-        if reader.read_exact(&mut flac_signature).is_ok() {
-            if &flac_signature == FLAC_SIGNATURE {
-                return Ok("flac".to_string());
+        // Pictures and other binary frames are seeked past unread
+        let wanted = |id: &str| id.starts_with('T') || id == "COMM" || id == "USLT";
+        let (mut date, mut time) = (None, None);
+        let major = self.scan_id3v2_frames(reader, wanted, |frame| {
+            if !Self::take_id3v23_date_frame(&frame, &mut date, &mut time) {
+                self.apply_id3v2_frame(&mut metadata, &frame);
+                record_sources(&mut sources, &metadata, |_| frame.frame_id.clone());
             }
+            true
+        })?;
+        let Some(major) = major else {
+            return Err(AudioFileError::ParseError("No ID3v2 tag found".to_string()));
+        };
+        Self::join_id3v23_date(&mut metadata.year, date, time);
+        for source in sources.values_mut() {
+            *source = format!("ID3v2.{}/{}", major, source);
         }
 
-        // Check for OGG
-        reader.seek(std::io::SeekFrom::Start(0))?;
-        let mut ogg_signature = [0u8; 4];
-        if reader.read_exact(&mut ogg_signature).is_ok() {
-            if &ogg_signature == OGG_SIGNATURE {
-                // Further check for Opus or Vorbis
-                let mut opus_sig = [0u8; 4];
-                if reader.seek(std::io::SeekFrom::Start(28)).is_ok() {
-                    if reader.read_exact(&mut opus_sig).is_ok() {
-                        if &opus_sig == b"Opus" {
-                            return Ok("opus".to_string());
-                        }
-                    }
+        Ok((metadata, sources))
+    }
+
+    /// Read the given standard fields from the ID3v2 tag, stopping once all are found
+    ///
+    /// Frames for other fields are seeked past unread. Empty if there is no tag.
+    fn read_id3v2_fields<R: Read + Seek>(&self, reader: R, fields: &[StandardField]) -> AudioResult<Metadata> {
+        let mut metadata = Metadata::default();
+        let wants_year = fields.contains(&StandardField::Year);
+        let wanted = |id: &str| {
+            FieldMappings::from_id3v2(id).is_some_and(|field| fields.contains(&field))
+                || (wants_year && matches!(id, "TDAT" | "TIME"))
+        };
+        let (mut date, mut time) = (None, None);
+        self.scan_id3v2_frames(reader, wanted, |frame| {
+            if frame.frame_id == FieldMappings::ID3V2_COVER {
+                if metadata.cover.is_none() {
+                    metadata.cover = Self::apic_cover(&frame);
                 }
-                return Ok("ogg".to_string());
+            } else if !Self::take_id3v23_date_frame(&frame, &mut date, &mut time) {
+                self.apply_id3v2_frame(&mut metadata, &frame);
             }
+            // A bare year may still be followed by TDAT and TIME
+            let bare_year = wants_year && time.is_none() && metadata.year.as_ref().is_some_and(|year| year.len() == 4);
+            bare_year || !fields.iter().all(|&field| metadata.has_field(field))
+        })?;
+        Self::join_id3v23_date(&mut metadata.year, date, time);
+        Ok(metadata)
+    }
+
+    /// Keep the first ID3v2.3 TDAT or TIME value in `date` or `time`; `false` for any other frame
+    fn take_id3v23_date_frame(frame: &Id3Frame, date: &mut Option<String>, time: &mut Option<String>) -> bool {
+        let value = match frame.frame_id.as_str() {
+            "TDAT" => date,
+            "TIME" => time,
+            _ => return false,
+        };
+        if value.is_none() {
+            *value = Self::decode_text_frame(&frame.data);
         }
+        true
+    }
 
-        // Check for MP4
-        reader.seek(std::io::SeekFrom::Start(4))?;
-        let mut mp4_signature = [0u8; 4];
-        if reader.read_exact(&mut mp4_signature).is_ok() {
-            let sig_str = std::str::from_utf8(&mp4_signature).unwrap_or("");
-            if sig_str == "ftyp" {
-                return Ok("mp4".to_string());
-            }
+    /// Add the day and time an ID3v2.3 tag keeps in TDAT and TIME to a TYER year
+    fn join_id3v23_date(year: &mut Option<String>, date: Option<String>, time: Option<String>) {
+        if let Some(year) = year.as_mut().filter(|year| year.len() == 4) {
+            *year = id3::convert::join_timestamp(year, date.as_deref(), time.as_deref());
         }
+    }
 
-        // Check for APE (at end of file)
-        let file = File::open(path)?;
-        let metadata = file.metadata()?;
-        let file_size = metadata.len();
-        if file_size > 32 {
-            let mut reader = BufReader::new(file);
-            reader.seek(std::io::SeekFrom::End(-32))?;
-            let mut ape_signature = [0u8; 8];
-            if reader.read_exact(&mut ape_signature).is_ok() {
-                if &ape_signature == b"APETAGEX" {
-                    return Ok("ape".to_string());
+    /// Store the value of an ID3v2.3/2.4 frame in `metadata`; the first non-empty value of each field wins
+    fn apply_id3v2_frame(&self, metadata: &mut Metadata, frame: &Id3Frame) {
+        let id = frame.frame_id.as_str();
+        match id {
+            "TIT2" => self.keep_first(&mut metadata.title, Self::decode_text_frame(&frame.data), id),
+            "TPE1" => self.keep_first(&mut metadata.artist, Self::decode_text_frame(&frame.data), id),
+            "TALB" => self.keep_first(&mut metadata.album, Self::decode_text_frame(&frame.data), id),
+            "TYER" | "TDRC" => self.keep_first(&mut metadata.year, Self::decode_text_frame(&frame.data), id),
+            "TRCK" => self.keep_first(&mut metadata.track, Self::decode_text_frame(&frame.data), id),
+            "TCON" => {
+                let text = Self::decode_text_frame(&frame.data);
+                if metadata.genre_raw.is_none() {
+                    if let Some(text) = text.filter(|text| !text.is_empty()) {
+                        metadata.set_genres(utils::genre::parse_tcon(&text));
+                        metadata.genre_raw = Some(text);
+                    }
+                } else {
+                    self.keep_first(&mut metadata.genre_raw, text, id);
                 }
             }
-        }
-
-        // Check for ID3v1 (at end of file)
-        let file = File::open(path)?;
-        let metadata = file.metadata()?;
-        let file_size = metadata.len();
-        if file_size > 128 {
-            let mut reader = BufReader::new(file);
-            reader.seek(std::io::SeekFrom::End(-128))?;
-            let mut tag = [0u8; 3];
-            if reader.read_exact(&mut tag).is_ok() {
-                if &tag == b"TAG" {
-                    return Ok("id3v1".to_string());
+            "TPE2" => self.keep_first(&mut metadata.album_artist, Self::decode_text_frame(&frame.data), id),
+            "TCOM" => self.keep_first(&mut metadata.composer, Self::decode_text_frame(&frame.data), id),
+            "TLEN" => {
+                let length = Self::decode_text_frame(&frame.data).and_then(|text| parse_length_ms(&text));
+                match (metadata.length_ms, length) {
+                    (None, length) => metadata.length_ms = length,
+                    (Some(_), Some(length)) => self.report_duplicate(id, &length.to_string()),
+                    (Some(_), None) => {}
                 }
             }
-        }
-
-        Err(AudioFileError::UnsupportedFormat("Unknown audio format".to_string()))
-    }
-
-    /// Read ID3v2 metadata
-    fn read_id3v2_metadata(&self) -> AudioResult<Metadata> {
-        let file = File::open(&self.path)?;
-        let mut reader = BufReader::new(file);
-        let tag = Id3v2Tag::read(&mut reader)?
-            .ok_or_else(|| AudioFileError::ParseError("No ID3v2 tag found".to_string()))?;
-
-        let mut metadata = Metadata::default();
-
-        // Parse frames
-        for frame in &tag.frames {
-            match frame.frame_id.as_str() {
-                "TIT2" => metadata.title = Self::decode_text_frame(&frame.data),
-                "TPE1" => metadata.artist = Self::decode_text_frame(&frame.data),
-                "TALB" => metadata.album = Self::decode_text_frame(&frame.data),
-                "TYER" | "TDRC" => metadata.year = Self::decode_text_frame(&frame.data),
-                "TRCK" => metadata.track = Self::decode_text_frame(&frame.data),
-                "TCON" => metadata.genre = Self::decode_text_frame(&frame.data),
-                "COMM" => metadata.comment = Self::decode_text_frame(&frame.data),
-                "USLT" => {
-                    if let Some((_language, _description, lyrics)) = id3::frames::decode_uslt_frame(&frame.data) {
-                        metadata.lyrics = Some(lyrics);
+            "TSSE" => self.keep_first(&mut metadata.encoder, Self::decode_text_frame(&frame.data), id),
+            "TXXX" => {
+                if let Some((description, value)) = id3::frames::decode_txxx_frame(&frame.data) {
+                    if description.eq_ignore_ascii_case(ENCODER_SETTINGS_TXXX) {
+                        self.keep_first(&mut metadata.encoder_settings, Some(value), "TXXX:ENCODERSETTINGS");
                     }
                 }
-                _ => {}
             }
+            "TORY" | "TDOR" => self.keep_first(&mut metadata.original_date, Self::decode_text_frame(&frame.data), id),
+            "TOPE" => self.keep_first(&mut metadata.original_artist, Self::decode_text_frame(&frame.data), id),
+            "TOAL" => self.keep_first(&mut metadata.original_album, Self::decode_text_frame(&frame.data), id),
+            "TMCL" => {
+                // Alternating instrument and performer names, NUL-separated
+                if let Some(text) = Self::decode_text_frame(&frame.data).filter(|text| !text.is_empty()) {
+                    if metadata.performers.is_empty() {
+                        let parts: Vec<&str> = text.split('\0').collect();
+                        metadata.performers = parts.chunks_exact(2)
+                            .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                            .collect();
+                    } else {
+                        self.report_duplicate(id, &text.replace('\0', ", "));
+                    }
+                }
+            }
+            "COMM" => {
+                // COMM shares the USLT layout: encoding, language, description, text
+                if let Some((_language, description, text)) = id3::frames::decode_uslt_frame(&frame.data) {
+                    if !is_itunes_comment(&description) {
+                        self.keep_first(&mut metadata.comment, Some(text), id);
+                    }
+                }
+            }
+            "USLT" => {
+                if let Some((_language, _description, lyrics)) = id3::frames::decode_uslt_frame(&frame.data) {
+                    self.keep_first(&mut metadata.lyrics, Some(lyrics), id);
+                }
+            }
+            _ => {}
         }
-
-        Ok(metadata)
     }
 
     /// Read ID3v1 metadata
-    fn read_id3v1_metadata(&self) -> AudioResult<Metadata> {
+    fn read_id3v1_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
         let tag = Id3v1Tag::read_from_file(&self.path)?
             .ok_or_else(|| AudioFileError::ParseError("No ID3v1 tag found".to_string()))?;
 
+        // A TAG+ free-text genre is more specific than the genre index
+        let genre = tag.extended.as_ref()
+            .map(|extended| extended.genre.clone())
+            .filter(|genre| !genre.is_empty())
+            .or_else(|| utils::genre::by_index(tag.genre).map(str::to_string));
         let metadata = Metadata {
             title: if !tag.title.is_empty() { Some(tag.title) } else { None },
             artist: if !tag.artist.is_empty() { Some(tag.artist) } else { None },
@@ -257,433 +717,9292 @@ impl AudioFile {
             year: if !tag.year.is_empty() { Some(tag.year) } else { None },
             comment: if !tag.comment.is_empty() { Some(tag.comment) } else { None },
             track: tag.track.map(|t| t.to_string()),
+            genres: genre.clone().into_iter().collect(),
+            genre,
             ..Default::default()
         };
+        let mut sources = FieldSources::new();
+        record_sources(&mut sources, &metadata, |_| "ID3v1".to_string());
 
-        Ok(metadata)
+        Ok((metadata, sources))
     }
 
     /// Read FLAC metadata
-    fn read_flac_metadata(&self) -> AudioResult<Metadata> {
-        use flac::vorbis::VorbisComment;
-        use std::io::Cursor;
-
-        let file = File::open(&self.path)?;
-        let mut reader = BufReader::new(file);
-
-        // Check FLAC signature
-        let mut signature = [0u8; 4];
-        reader.read_exact(&mut signature)?;
-
-        if signature != *FLAC_SIGNATURE {
-            return Ok(Metadata::default());
-        }
-
-        let mut metadata = Metadata::default();
-
-        // Read metadata blocks
-        loop {
-            match FlacMetadataBlock::read(&mut reader) {
-                Ok(block) => {
-                    if block.header.block_type == FlacMetadataBlockType::VorbisComment {
-                        if let Ok(vorbis) = VorbisComment::read(&mut Cursor::new(&block.data)) {
-                            // Convert VorbisComment to Metadata
-                            for (key, value) in vorbis.comments {
-                                match key.to_uppercase().as_str() {
-                                    "TITLE" => metadata.title = Some(value),
-                                    "ARTIST" => metadata.artist = Some(value),
-                                    "ALBUM" => metadata.album = Some(value),
-                                    "DATE" => metadata.year = Some(value),
-                                    "TRACKNUMBER" => metadata.track = Some(value),
-                                    "GENRE" => metadata.genre = Some(value),
-                                    "COMMENT" => metadata.comment = Some(value),
-                                    "LYRICS" => metadata.lyrics = Some(value),
-                                    "ALBUMARTIST" => metadata.album_artist = Some(value),
-                                    "COMPOSER" => metadata.composer = Some(value),
-                                    _ => {}
-                                }
-                            }
-                        }
-                    }
+    fn read_flac_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
+        let Some(blocks) = FlacBlocks::new(BufReader::new(File::open(&self.path)?))? else {
+            return Ok(Default::default());
+        };
 
-                    if block.header.is_last {
-                        break;
-                    }
-                }
-                Err(_) => break,
+        // The cover is read on its own, so pictures are seeked past
+        let mut read = Default::default();
+        for block in blocks.without_pictures().map_while(Result::ok) {
+            if let Some(Ok(vorbis)) = block.comment() {
+                read = self.vorbis_to_metadata(vorbis);
             }
         }
 
-        Ok(metadata)
+        Ok(read)
     }
 
     /// Read OGG metadata
-    fn read_ogg_metadata(&self) -> AudioResult<Metadata> {
+    fn read_ogg_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
         let ogg_file = OggVorbisFile::new(self.path.clone());
         if let Some(comment) = ogg_file.read_comment()? {
-            Ok(Self::vorbis_to_metadata(comment))
+            Ok(self.vorbis_to_metadata(comment))
         } else {
-            Ok(Metadata::default())
+            Ok(Default::default())
         }
     }
 
     /// Read OPUS metadata
-    fn read_opus_metadata(&self) -> AudioResult<Metadata> {
+    fn read_opus_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
         let opus_file = OpusFile::new(self.path.clone());
         if let Some(comment) = opus_file.read_comment()? {
-            Ok(Self::vorbis_to_metadata(comment))
+            Ok(self.vorbis_to_metadata(comment))
         } else {
-            Ok(Metadata::default())
+            Ok(Default::default())
         }
     }
 
     /// Read MP4 metadata
-    fn read_mp4_metadata(&self) -> AudioResult<Metadata> {
+    fn read_mp4_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
         let mp4_file = Mp4File::new(self.path.clone());
-        if let Some(meta) = mp4_file.read_metadata()? {
-            Ok(Self::mp4_to_metadata(meta))
-        } else {
-            Ok(Metadata::default())
-        }
+        let Some(meta) = mp4_file.read_metadata()? else {
+            return Ok(Default::default());
+        };
+        let metadata = Self::mp4_to_metadata(meta);
+        let mut sources = FieldSources::new();
+        record_sources(&mut sources, &metadata, |key| {
+            let atom = MP4_FIELD_ATOMS.iter().find(|(field, _)| *field == key).map_or("ilst", |(_, atom)| atom);
+            format!("MP4/{}", atom)
+        });
+        Ok((metadata, sources))
     }
 
     /// Read APE metadata
-    fn read_ape_metadata(&self) -> AudioResult<Metadata> {
+    fn read_ape_metadata(&self) -> AudioResult<(Metadata, FieldSources)> {
         let ape_file = ApeFile::new(self.path.clone());
-        if let Some(meta) = ape_file.read_metadata()? {
-            Ok(Self::ape_to_metadata(meta))
-        } else {
-            Ok(Metadata::default())
-        }
+        let Some(meta) = ape_file.read_metadata()? else {
+            return Ok(Default::default());
+        };
+        let metadata = Self::ape_to_metadata(meta);
+        let mut sources = FieldSources::new();
+        record_sources(&mut sources, &metadata, |key| {
+            let item = APE_FIELD_ITEMS.iter().find(|(field, _)| *field == key).map_or("", |(_, item)| item);
+            format!("APEv2/{}", item)
+        });
+        Ok((metadata, sources))
     }
 
-    /// Convert VorbisComment to Metadata
-    fn vorbis_to_metadata(comment: flac::vorbis::VorbisComment) -> Metadata {
-        let mut metadata = Metadata::default();
-        for (key, value) in comment.comments {
-            match key.to_uppercase().as_str() {
-                "TITLE" => metadata.title = Some(value),
-                "ARTIST" => metadata.artist = Some(value),
-                "ALBUM" => metadata.album = Some(value),
-                "DATE" => metadata.year = Some(value),
-                "TRACKNUMBER" => metadata.track = Some(value),
-                "GENRE" => metadata.genre = Some(value),
-                "COMMENT" => metadata.comment = Some(value),
-                "LYRICS" => metadata.lyrics = Some(value),
-                "ALBUMARTIST" => metadata.album_artist = Some(value),
-                "COMPOSER" => metadata.composer = Some(value),
-                _ => {}
+    /// Read cover art from the audio file
+    fn read_cover(&self) -> AudioResult<Option<CoverArt>> {
+        handler::handler(self.format).read_cover(self)
+    }
+
+    /// Read every embedded picture in file order, with its picture type
+    ///
+    /// MP4 `covr` images have no type and are read as front covers. APE
+    /// "Cover Art (Front)" and "(Back)" items keep those types, other APE
+    /// cover items are read as `Other`.
+    fn read_pictures(&self) -> AudioResult<Vec<FlacPicture>> {
+        use base64::prelude::*;
+        use flac::picture::PictureType;
+
+        let picture = |data: Vec<u8>, mime_type: Option<&str>, description: String, picture_type: PictureType| {
+            let mime_type = mime_type.filter(|mime_type| !mime_type.is_empty())
+                .or_else(|| utils::image::detect_mime_type(&data))
+                .unwrap_or("image/jpeg")
+                .to_string();
+            FlacPicture { picture_type, ..FlacPicture::new(data, mime_type, description) }
+        };
+
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let frames = self.read_id3v2_tag()?.map(|tag| tag.frames).unwrap_or_default();
+                Ok(frames.iter()
+                    .filter(|frame| frame.frame_id == "APIC")
+                    .filter_map(|frame| id3::frames::split_apic_frame(&frame.data))
+                    .map(|(mime_type, picture_type, description, data)| {
+                        picture(data.to_vec(), Some(&mime_type), description, PictureType::from_u32(picture_type as u32))
+                    })
+                    .collect())
             }
+            Format::Flac => Ok(self.flac_blocks()?
+                .map_while(Result::ok)
+                .filter_map(|block| block.picture()?.ok())
+                .collect()),
+            Format::OggVorbis | Format::Opus => Ok(self.get_vorbis_comments()?.1.iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE"))
+                .filter_map(|(_, value)| BASE64_STANDARD.decode(value).ok())
+                .filter_map(|data| FlacPicture::read_from_data(&data).ok())
+                .collect()),
+            Format::Mp4 => {
+                let covers = Mp4File::new(self.path.clone()).read_metadata()?.map(|meta| meta.covers).unwrap_or_default();
+                Ok(covers.into_iter()
+                    .map(|cover| {
+                        let mime_type = cover.mime_type();
+                        picture(cover.data, mime_type, String::new(), PictureType::CoverFront)
+                    })
+                    .collect())
+            }
+            Format::Ape => Ok(ApeFile::new(self.path.clone()).read_cover_items()?.into_iter()
+                .map(|(key, data)| {
+                    let picture_type = match key.to_ascii_lowercase().as_str() {
+                        "cover art (front)" => PictureType::CoverFront,
+                        "cover art (back)" => PictureType::CoverBack,
+                        _ => PictureType::Other,
+                    };
+                    picture(data, None, String::new(), picture_type)
+                })
+                .collect()),
+            Format::Id3v1 => Ok(Vec::new()),
         }
-        metadata
     }
 
-    /// Convert Mp4Metadata to Metadata
-    fn mp4_to_metadata(meta: mp4::Mp4Metadata) -> Metadata {
-        Metadata {
-            title: meta.title,
-            artist: meta.artist,
-            album: meta.album,
-            year: meta.year,
-            comment: meta.comment,
-            track: meta.track,
-            genre: meta.genre,
-            album_artist: None,
-            composer: None,
-            lyrics: meta.lyrics,
-            cover: None,
+    /// Replace every embedded picture with `pictures`, leaving the other tags alone
+    fn write_pictures(&self, pictures: &[FlacPicture]) -> AudioResult<()> {
+        use base64::prelude::*;
+
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                use id3::frames::{encode_apic_frame, PictureType};
+
+                self.rewrite_id3v2_frames(|frames| {
+                    frames.retain(|frame| frame.frame_id != "APIC");
+                    for picture in pictures {
+                        let picture_type = PictureType::from_byte(picture.picture_type as u8);
+                        let data = encode_apic_frame(&picture.mime_type, picture_type, &picture.description, &picture.data);
+                        frames.push(id3::v2::Id3Frame::new("APIC", data));
+                    }
+                })
+            }
+            Format::Flac => {
+                let file_data = std::fs::read(&self.path)?;
+                let mut cursor = std::io::Cursor::new(&file_data);
+                cursor.set_position(FLAC_SIGNATURE.len() as u64);
+
+                let mut blocks = Vec::new();
+                loop {
+                    let block = FlacMetadataBlock::read(&mut cursor)?;
+                    let is_last = block.header.is_last;
+                    if !matches!(block.header.block_type, FlacMetadataBlockType::Picture | FlacMetadataBlockType::Padding) {
+                        blocks.push(block);
+                    }
+                    if is_last {
+                        break;
+                    }
+                }
+                let audio_start = cursor.position() as usize;
+
+                blocks.extend(pictures.iter()
+                    .map(|picture| FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes())));
+                self.write_flac_blocks(&file_data, blocks, audio_start, false)
+            }
+            Format::OggVorbis | Format::Opus => {
+                let is_opus = self.format == Format::Opus;
+                let existing = if is_opus {
+                    OpusFile::new(self.path.clone()).read_comment()?
+                } else {
+                    OggVorbisFile::new(self.path.clone()).read_comment()?
+                };
+
+                let mut comment = existing.unwrap_or_default();
+                comment.remove("METADATA_BLOCK_PICTURE");
+                for picture in pictures {
+                    comment.comments.push(("METADATA_BLOCK_PICTURE".to_string(), BASE64_STANDARD.encode(picture.to_bytes())));
+                }
+
+                self.write_ogg_comment(&comment)
+            }
+            Format::Mp4 => {
+                // MP4 has no picture types or descriptions; only the images are kept
+                let mp4_file = Mp4File::new(self.path.clone());
+                let mut mp4_metadata = mp4_file.read_metadata()?.unwrap_or_default();
+                mp4_metadata.covers = pictures.iter()
+                    .map(|picture| mp4::Mp4Cover::for_image(picture.data.clone(), Some(&picture.mime_type)))
+                    .collect::<Result<_, _>>()
+                    .map_err(AudioFileError::UnsupportedFormat)?;
+                for message in mp4_metadata.covers.iter().filter_map(Self::mp4_cover_warning) {
+                    tracing::warn!(event = "warning", path = %self.path, message = %message);
+                }
+                self.guarded_write(|| Ok(mp4_file.write_metadata(&mp4_metadata)?))
+            }
+            Format::Id3v1 | Format::Ape => Err(AudioFileError::UnsupportedFormat(
+                format!("Writing {} pictures is not supported", self.format)
+            )),
         }
     }
 
-    /// Convert ApeMetadata to Metadata
-    fn ape_to_metadata(meta: ape::ApeMetadata) -> Metadata {
-        Metadata {
-            title: meta.title,
-            artist: meta.artist,
-            album: meta.album,
-            year: meta.year,
-            comment: meta.comment,
-            track: meta.track,
-            genre: meta.genre,
-            album_artist: None,
-            composer: None,
-            lyrics: meta.lyrics,
-            cover: None,
+    /// Change the frames of the ID3v2 tag with `edit` and write them back
+    ///
+    /// Frames are handled as read (ID3v2.2 upgraded to ID3v2.3) and the tag
+    /// is rewritten in place when it still fits, so the audio doesn't move.
+    /// ID3v2.2 frames with no ID3v2.3 equivalent are logged as warnings.
+    fn rewrite_id3v2_frames(&self, edit: impl FnOnce(&mut Vec<id3::v2::Id3Frame>)) -> AudioResult<()> {
+        let file_data = std::fs::read(&self.path)?;
+        let (tag, dropped) = self.read_upgraded_id3v2_tag()?;
+        let (tag, span) = self.id3v2_tag_for_write(tag, &file_data)?;
+        // ID3v2.2 frames were upgraded on read, so the tag is written as ID3v2.3
+        let version = if tag.header.version.0 == 2 { (3, 0) } else { tag.header.version };
+        for reason in &dropped {
+            tracing::warn!(event = "warning", path = %self.path, message = %format!("Dropped {}", reason));
         }
+
+        let mut frames = tag.frames;
+        edit(&mut frames);
+
+        let frames_len: usize = frames.iter().map(|frame| frame.data.len() + 10).sum();
+        let padding = if frames_len + 10 <= span.len() { span.len() - frames_len - 10 } else { ID3V2_PADDING };
+        let tag_data = if tag.header.has_footer() {
+            Id3v2Tag::to_bytes_with_footer(&frames)
+        } else {
+            Id3v2Tag::to_bytes(&frames, version, padding)
+        };
+        let new_data = self.splice_id3v2_tag(&file_data, span, &tag_data);
+
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &new_data)?;
+            Ok(())
+        })
     }
-}
 
-/// Public API for AudioFile (no PyO3 dependencies)
-impl AudioFile {
-    /// Create a new AudioFile instance
-    pub fn new(path: String) -> AudioResult<Self> {
-        let file_type = Self::detect_file_type(&path)?;
-        Ok(Self { path, file_type })
+    /// The ID3v2 tag to rewrite, given the one read from `file_data`, and the bytes it takes up
+    ///
+    /// A bare MPEG stream gets a new, empty ID3v2.3 tag in front of its
+    /// audio, and a DSF file without a tag gets one at the end.
+    fn id3v2_tag_for_write(&self, tag: Option<Id3v2Tag>, file_data: &[u8]) -> AudioResult<(Id3v2Tag, Range<usize>)> {
+        let start = match self.format {
+            Format::Dsf => dsf::DsfHeader::parse(file_data)
+                .and_then(|header| header.tag_offset())
+                .map_or(file_data.len(), |offset| (offset as usize).min(file_data.len())),
+            _ => 0,
+        };
+        match tag {
+            Some(tag) => {
+                let end = (start + tag.header.total_size() as usize).min(file_data.len());
+                Ok((tag, start..end))
+            }
+            None if matches!(self.format, Format::Mp3 | Format::Dsf) => {
+                let header = Id3v2Header { version: (3, 0), flags: 0, size: 0 };
+                Ok((Id3v2Tag { header, frames: Vec::new() }, start..start))
+            }
+            None => Err(AudioFileError::ParseError("No ID3v2 tag found".to_string())),
+        }
     }
 
-    /// Get metadata as JSON string
-    pub fn get_metadata(&self) -> AudioResult<String> {
-        let metadata = self.read_metadata_internal()?;
-        serde_json::to_string(&metadata)
-            .map_err(|e| AudioFileError::ParseError(e.to_string()))
+    /// `file_data` with `tag_data` in place of the ID3v2 tag at `span`
+    ///
+    /// A DSF header is updated with the new file size and tag offset.
+    fn splice_id3v2_tag(&self, file_data: &[u8], span: Range<usize>, tag_data: &[u8]) -> Vec<u8> {
+        let mut new_data = Vec::with_capacity(file_data.len() - span.len() + tag_data.len());
+        new_data.extend_from_slice(&file_data[..span.start]);
+        new_data.extend_from_slice(tag_data);
+        new_data.extend_from_slice(&file_data[span.end..]);
+        if self.format == Format::Dsf {
+            let file_size = new_data.len() as u64;
+            dsf::update_header(&mut new_data, file_size, span.start as u64);
+        }
+        new_data
     }
 
-    /// Get metadata as serde_json Value
-    pub fn get_metadata_value(&self) -> AudioResult<serde_json::Value> {
-        let metadata = self.read_metadata_internal()?;
-        serde_json::to_value(&metadata)
-            .map_err(|e| AudioFileError::ParseError(e.to_string()))
+    /// Move `reader` to where the ID3v2 tag is stored: the start of the file, or for DSF where the header points
+    ///
+    /// `false` for a DSF file whose header points at no tag.
+    fn seek_id3v2_tag<R: Read + Seek>(&self, reader: &mut R) -> AudioResult<bool> {
+        if self.format == Format::Dsf {
+            match dsf::DsfHeader::read(reader)?.and_then(|header| header.tag_offset()) {
+                Some(offset) => reader.seek(std::io::SeekFrom::Start(offset))?,
+                None => return Ok(false),
+            };
+        }
+        Ok(true)
     }
 
-    /// Set metadata from JSON string
-    pub fn set_metadata(&self, metadata_json: String) -> AudioResult<()> {
-        // Parse JSON to validate it
-        let _value: serde_json::Value = serde_json::from_str(&metadata_json)
-            .map_err(|e| AudioFileError::ParseError(e.to_string()))?;
+    /// Read the ID3v2 tag as stored: at the start of the file, or for DSF where the header points
+    fn read_raw_id3v2_tag<R: Read + Seek>(&self, reader: &mut R) -> AudioResult<Option<Id3v2Tag>> {
+        if !self.seek_id3v2_tag(reader)? {
+            return Ok(None);
+        }
+        Ok(Id3v2Tag::read(reader)?)
+    }
 
-        // For now, just return success - full implementation would write to file
-        // This is a placeholder
-        Ok(())
+    /// Check for an ID3v2 tag header, without reading the frames
+    fn has_id3v2_tag(&self) -> AudioResult<bool> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        Ok(self.seek_id3v2_tag(&mut reader)? && Id3v2Header::read(&mut reader)?.is_some())
     }
 
-    /// Get the file type/version
-    pub fn get_version(&self) -> AudioResult<String> {
-        match self.file_type.as_str() {
-            "id3v2" => {
-                // Read ID3v2 version
-                let file = File::open(&self.path)?;
-                let mut reader = BufReader::new(file);
-                let mut header = [0u8; 10];
-                reader.read_exact(&mut header)?;
-                if header.len() >= 4 {
-                    Ok(format!("2.{}", header[3]))
-                } else {
-                    Ok("2.x".to_string())
-                }
+    /// Pass the ID3v2 frames whose ID3v2.3/2.4 ID passes `wanted` to `visit`, until it returns `false`
+    ///
+    /// Other frames are seeked past unread, and ID3v2.2 frames are upgraded
+    /// to ID3v2.3 first. Gives the major version of the tag, or `None` if
+    /// there is no ID3v2 tag.
+    fn scan_id3v2_frames<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        wanted: impl Fn(&str) -> bool,
+        mut visit: impl FnMut(Id3Frame) -> bool,
+    ) -> AudioResult<Option<u8>> {
+        use id3::convert::{convert_frame, translate_frame_id};
+
+        if !self.seek_id3v2_tag(&mut reader)? {
+            return Ok(None);
+        }
+        let Some(mut frames) = Id3v2Tag::frames(reader)? else {
+            return Ok(None);
+        };
+
+        let major = frames.header().version.0;
+        let is_wanted = |id: &str| match major {
+            2 => translate_frame_id(id, 2, 3).is_some_and(&wanted),
+            _ => wanted(id),
+        };
+        while let Some(frame) = frames.next_frame_where(is_wanted)? {
+            // Nothing is written back from here, so a frame without an ID3v2.3 equivalent is only skipped
+            let frame = match major {
+                2 => match convert_frame(&frame, 2, 3) {
+                    Ok(frame) => frame,
+                    Err(_) => continue,
+                },
+                _ => frame,
+            };
+            if !visit(frame) {
+                break;
             }
-            _ => Ok(self.file_type.clone()),
         }
+        Ok(Some(major))
     }
-}
 
-/// Metadata container
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct Metadata {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub title: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub artist: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub album: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub year: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub comment: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub track: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub genre: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub album_artist: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub composer: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub lyrics: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub cover: Option<CoverArt>,
-}
+    /// Read the ID3v2 tag, upgrading ID3v2.2 frames to their ID3v2.3 equivalents
+    fn read_id3v2_tag(&self) -> AudioResult<Option<Id3v2Tag>> {
+        Ok(self.read_upgraded_id3v2_tag()?.0)
+    }
 
-/// Cover art data
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CoverArt {
-    #[serde(serialize_with = "serialize_as_base64")]
-    pub data: Vec<u8>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub mime_type: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub description: Option<String>,
-}
+    /// Read the ID3v2 tag as `read_id3v2_tag` does, with the reason each ID3v2.2 frame was left out
+    ///
+    /// Paths that write the tag back use this, since a left-out frame is
+    /// lost from the file once the upgraded tag is written.
+    fn read_upgraded_id3v2_tag(&self) -> AudioResult<(Option<Id3v2Tag>, Vec<String>)> {
+        let file = File::open(&self.path)?;
+        let mut reader = BufReader::new(file);
+        let mut tag = match self.read_raw_id3v2_tag(&mut reader)? {
+            Some(tag) => tag,
+            None => return Ok((None, Vec::new())),
+        };
 
-// ============================================================================
-// PyO3 Bindings (only compiled when "python" feature is enabled)
-// ============================================================================
+        let mut dropped = Vec::new();
+        if tag.header.version.0 == 2 {
+            tag.frames = tag.frames.iter()
+                .filter_map(|frame| id3::convert::convert_frame(frame, 2, 3)
+                    .map_err(|reason| dropped.push(reason))
+                    .ok())
+                .collect();
+        }
 
-#[cfg(feature = "python")]
-#[pymodule]
-fn oxidant(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_class::<PyAudioFile>()?;
-    m.add_class::<PyMetadata>()?;
-    m.add_class::<PyCoverArt>()?;
-    m.add_class::<BatchProcessor>()?;
-    m.add_class::<PyBatchResult>()?;
-    Ok(())
-}
+        Ok((Some(tag), dropped))
+    }
 
-#[cfg(feature = "python")]
-#[pyclass(name = "AudioFile")]
-pub struct PyAudioFile {
-    #[pyo3(get)]
-    path: String,
-    #[pyo3(get)]
-    file_type: String,
-    audio: AudioFile,
-}
+    /// Read cover art from the first ID3v2 APIC frame
+    fn read_id3v2_cover(&self) -> AudioResult<Option<CoverArt>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut cover = None;
+        self.scan_id3v2_frames(reader, |id| id == "APIC", |frame| {
+            cover = Self::apic_cover(&frame);
+            cover.is_none()
+        })?;
+        Ok(cover)
+    }
 
-#[cfg(feature = "python")]
-#[pymethods]
-impl PyAudioFile {
-    #[new]
-    fn new(path: String) -> PyResult<Self> {
-        let audio = AudioFile::new(path)
-            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
-        let file_type = audio.file_type.clone();
-        Ok(Self { path: audio.path.clone(), file_type, audio })
+    /// The picture frame, block or atom `read_cover` reads, named as in `FieldSources`
+    fn cover_source(&self) -> AudioResult<String> {
+        Ok(match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let mut reader = BufReader::new(File::open(&self.path)?);
+                self.seek_id3v2_tag(&mut reader)?;
+                let major = Id3v2Header::read(&mut reader)?.map_or(4, |header| header.version.0);
+                format!("ID3v2.{}/APIC", major)
+            }
+            Format::Flac => "FLAC/PICTURE".to_string(),
+            Format::Mp4 => "MP4/covr".to_string(),
+            Format::Id3v1 | Format::OggVorbis | Format::Opus | Format::Ape => String::new(),
+        })
     }
 
-    fn get_metadata(&self) -> PyResult<String> {
-        self.audio.get_metadata()
-            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    /// Cover art held by an APIC frame
+    fn apic_cover(frame: &Id3Frame) -> Option<CoverArt> {
+        let (mime_type, _picture_type, description, data) = id3::frames::decode_apic_frame(&frame.data)?;
+        Some(CoverArt {
+            data,
+            mime_type: if mime_type.is_empty() { None } else { Some(mime_type) },
+            description: if description.is_empty() { None } else { Some(description) },
+        })
     }
 
-    fn set_metadata(&self, metadata_json: String) -> PyResult<()> {
-        self.audio.set_metadata(metadata_json)
-            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    /// Read cover art from the first FLAC PICTURE block
+    fn read_flac_cover(&self) -> AudioResult<Option<CoverArt>> {
+        let Some(blocks) = FlacBlocks::new(BufReader::new(File::open(&self.path)?))? else {
+            return Ok(None);
+        };
+
+        for block in blocks.map_while(Result::ok) {
+            if let Some(Ok(picture)) = block.picture() {
+                return Ok(Some(CoverArt {
+                    data: picture.data,
+                    mime_type: if picture.mime_type.is_empty() { None } else { Some(picture.mime_type) },
+                    description: if picture.description.is_empty() { None } else { Some(picture.description) },
+                }));
+            }
+        }
+
+        Ok(None)
     }
 
-    fn get_version(&self) -> PyResult<String> {
-        self.audio.get_version()
-            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    /// Iterate over the metadata blocks of a FLAC file, skipping the signature unchecked
+    fn flac_blocks(&self) -> AudioResult<FlacBlocks<BufReader<File>>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(std::io::SeekFrom::Start(FLAC_SIGNATURE.len() as u64))?;
+        Ok(FlacBlocks::after_signature(reader))
     }
-}
 
-#[cfg(feature = "python")]
-#[pyclass(name = "Metadata")]
-pub struct PyMetadata {
-    #[pyo3(get, set)]
-    title: Option<String>,
-    #[pyo3(get, set)]
-    artist: Option<String>,
-    #[pyo3(get, set)]
-    album: Option<String>,
-    #[pyo3(get, set)]
-    year: Option<String>,
-    #[pyo3(get, set)]
-    comment: Option<String>,
-    #[pyo3(get, set)]
-    track: Option<String>,
-    #[pyo3(get, set)]
-    genre: Option<String>,
-    #[pyo3(get, set)]
-    album_artist: Option<String>,
-    #[pyo3(get, set)]
-    composer: Option<String>,
-    #[pyo3(get, set)]
-    lyrics: Option<String>,
-    #[pyo3(get, set)]
-    cover: Option<PyCoverArt>,
-}
+    /// Read the STREAMINFO block of a FLAC file
+    fn read_flac_properties(&self) -> AudioResult<Option<AudioProperties>> {
+        for block in self.flac_blocks()?.map_while(Result::ok) {
+            if let FlacBlockView::StreamInfo(data) = block {
+                return Ok(properties::read_flac_streaminfo(&data));
+            }
+        }
 
-#[cfg(feature = "python")]
-#[pyclass(name = "CoverArt")]
-#[derive(Clone)]
-pub struct PyCoverArt {
-    #[pyo3(get, set)]
-    data: Vec<u8>,
-    #[pyo3(get, set)]
-    mime_type: Option<String>,
-    #[pyo3(get, set)]
-    description: Option<String>,
-}
+        Ok(None)
+    }
 
-// Batch processing types (only for Python)
-#[cfg(feature = "python")]
-#[pyclass]
-pub struct BatchProcessor {
-    #[pyo3(get, set)]
-    pub show_progress: bool,
-}
+    /// Overwrite this file's STREAMINFO block with the one from `source`
+    fn copy_streaminfo_from(&self, source: &AudioFile) -> AudioResult<()> {
+        let find_streaminfo = |data: &[u8]| -> AudioResult<std::ops::Range<usize>> {
+            let mut cursor = std::io::Cursor::new(data);
+            cursor.set_position(FLAC_SIGNATURE.len() as u64);
+            let block = FlacMetadataBlock::read(&mut cursor)?;
+            if block.header.block_type != FlacMetadataBlockType::StreamInfo {
+                return Err(AudioFileError::ParseError("FLAC file does not start with STREAMINFO".to_string()));
+            }
+            let end = cursor.position() as usize;
+            Ok(end - block.data.len()..end)
+        };
 
-#[cfg(feature = "python")]
-#[pymethods]
-impl BatchProcessor {
-    #[new]
-    fn new() -> Self {
-        BatchProcessor {
-            show_progress: true,
+        let source_data = std::fs::read(&source.path)?;
+        let mut file_data = std::fs::read(&self.path)?;
+        let from = find_streaminfo(&source_data)?;
+        let to = find_streaminfo(&file_data)?;
+        if from.len() != to.len() {
+            return Err(AudioFileError::ParseError("STREAMINFO blocks differ in size".to_string()));
         }
+        file_data[to].copy_from_slice(&source_data[from]);
+
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &file_data)?;
+            Ok(())
+        })
     }
 
-    fn read_metadata_batch(&self, file_paths: Vec<String>) -> PyResult<Vec<String>> {
-        let mut results = Vec::new();
-        let total = file_paths.len();
+    /// Read the first CUESHEET block of a FLAC file
+    fn read_cue_sheet(&self) -> AudioResult<Option<flac::cuesheet::FlacCueSheet>> {
+        if self.format != Format::Flac {
+            return Ok(None);
+        }
 
-        for (index, path) in file_paths.iter().enumerate() {
-            if self.show_progress {
-                println!("Reading {}/{}: {}", index + 1, total, path);
+        for block in self.flac_blocks()?.map_while(Result::ok) {
+            if let FlacBlockView::CueSheet(data) = block {
+                return Ok(Some(flac::cuesheet::FlacCueSheet::read_from_data(&data)?));
             }
+        }
 
-            match AudioFile::new(path.clone()) {
-                Ok(audio) => {
-                    match audio.get_metadata() {
-                        Ok(metadata) => results.push(metadata),
-                        Err(e) => {
-                            let error_json = format!(r#"{{"error": "{}", "file": "{}"}}"#, e, path);
-                            results.push(error_json);
-                        }
-                    }
+        Ok(None)
+    }
+
+    /// Read the first VORBIS_COMMENT block of a FLAC file
+    fn read_flac_comment(&self) -> AudioResult<Option<VorbisComment>> {
+        for block in self.flac_blocks()?.map_while(Result::ok) {
+            if let Some(comment) = block.comment() {
+                return Ok(Some(comment?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Read cover art from the MP4 covr atom
+    fn read_mp4_cover(&self) -> AudioResult<Option<CoverArt>> {
+        let mp4_file = Mp4File::new(self.path.clone());
+        let cover = mp4_file.read_metadata()?.and_then(|meta| meta.covers.into_iter().next());
+        Ok(cover.map(|cover| CoverArt {
+            mime_type: cover.mime_type().map(str::to_string),
+            description: None,
+            data: cover.data,
+        }))
+    }
+
+    /// Compute the (offset, length) of the audio stream, excluding tags
+    fn compute_audio_span(&self) -> AudioResult<(u64, u64)> {
+        let file = File::open(&self.path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let (start, mut end) = match self.format {
+            Format::Id3v2 => {
+                let header = id3::v2::Id3v2Header::read(&mut reader)?
+                    .ok_or_else(|| AudioFileError::ParseError("No ID3v2 tag found".to_string()))?;
+                (header.total_size().min(file_len), file_len)
+            }
+            Format::Mp3 => {
+                let header = id3::v2::Id3v2Header::read(&mut reader)?;
+                (header.map_or(0, |header| header.total_size().min(file_len)), file_len)
+            }
+            Format::Flac => {
+                let blocks = self.get_all_metadata_blocks()?;
+                let pos = blocks.last().map_or(4, |block| block.offset + 4 + block.size_bytes) as u64;
+                (pos.min(file_len), file_len)
+            }
+            Format::Mp4 => {
+                match mp4::find_top_level_atom(&mut reader, file_len, mp4::atoms::MDAT)? {
+                    Some((offset, length)) => return Ok((offset, length)),
+                    None => (file_len, file_len),
                 }
-                Err(e) => {
-                    let error_json = format!(r#"{{"error": "{}", "file": "{}"}}"#, e, path);
-                    results.push(error_json);
+            }
+            // OGG pages interleave headers with audio, so the whole file is the span
+            Format::OggVorbis | Format::Opus => return Ok((0, file_len)),
+            // The samples fill the data chunk; the ID3v2 tag comes after it
+            Format::Dsf => {
+                let mut head = Vec::new();
+                (&mut reader).take(4096).read_to_end(&mut head)?;
+                let (offset, length) = dsf::audio_span(&head)
+                    .ok_or_else(|| AudioFileError::ParseError("No DSF data chunk found".to_string()))?;
+                let offset = offset.min(file_len);
+                return Ok((offset, length.min(file_len - offset)));
+            }
+            Format::Id3v1 | Format::Ape => (0, file_len),
+        };
+
+        // Trailing tags: ID3v1 is always last, an APE tag may sit right before it
+        if end >= start + 128 {
+            reader.seek(std::io::SeekFrom::Start(end - 128))?;
+            let mut tag = [0u8; 3];
+            reader.read_exact(&mut tag)?;
+            if &tag == b"TAG" {
+                end -= 128;
+                // A TAG+ extended tag sits right before it
+                if end >= start + Id3v1Tag::EXTENDED_TAG_SIZE as u64 {
+                    reader.seek(std::io::SeekFrom::Start(end - Id3v1Tag::EXTENDED_TAG_SIZE as u64))?;
+                    let mut id = [0u8; 4];
+                    reader.read_exact(&mut id)?;
+                    if Id3v1Tag::is_extended(&id) {
+                        end -= Id3v1Tag::EXTENDED_TAG_SIZE as u64;
+                    }
                 }
             }
         }
+        end -= ape::ape_tag_size_before(&mut reader, end)?.min(end - start);
 
-        Ok(results)
+        Ok((start, end - start))
     }
 
-    fn write_metadata_batch(&self, updates: Vec<(String, String)>) -> PyResult<Vec<PyBatchResult>> {
-        let mut results = Vec::new();
-        let total = updates.len();
+    /// Checksum of the audio stream, used to verify that tag writes leave it untouched
+    fn audio_checksum(&self) -> AudioResult<u64> {
+        let (offset, length) = self.compute_audio_span()?;
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        Ok(utils::io::checksum_range(&mut reader, offset, length)?)
+    }
 
-        for (index, (path, _metadata_json)) in updates.iter().enumerate() {
-            if self.show_progress {
-                println!("Writing {}/{}: {}", index + 1, total, path);
-            }
+    /// Check that the file can be written, before anything is read for a write
+    ///
+    /// Opens the file with write intent without changing it, so a read-only,
+    /// locked or inaccessible file fails with a typed error up front.
+    pub fn check_writable(&self) -> AudioResult<()> {
+        if std::fs::metadata(&self.path)?.permissions().readonly() {
+            return Err(AudioFileError::ReadOnlyFile(self.path.clone()));
+        }
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .map_err(|e| self.write_error(AudioFileError::IoError(e)))?;
+        Ok(())
+    }
 
-            let result = PyBatchResult {
-                file_path: path.clone(),
-                success: false,
-                error_message: None,
-            };
+    /// Classify an I/O error from writing this file as read-only, in use or permission denied
+    fn write_error(&self, error: AudioFileError) -> AudioFileError {
+        let AudioFileError::IoError(e) = error else {
+            return error;
+        };
+        if std::fs::metadata(&self.path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+            return AudioFileError::ReadOnlyFile(self.path.clone());
+        }
+        // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+        if cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33)) {
+            return AudioFileError::FileInUse(self.path.clone());
+        }
+        match e.kind() {
+            std::io::ErrorKind::ResourceBusy | std::io::ErrorKind::ExecutableFileBusy => {
+                AudioFileError::FileInUse(self.path.clone())
+            }
+            std::io::ErrorKind::PermissionDenied => AudioFileError::PermissionDenied(self.path.clone()),
+            _ => AudioFileError::IoError(e),
+        }
+    }
 
-            results.push(result);
+    /// Run a tag write, verifying in debug builds that the audio stream is unchanged
+    fn guarded_write<F>(&self, write: F) -> AudioResult<()>
+    where
+        F: FnOnce() -> AudioResult<()>,
+    {
+        self.check_writable()?;
+        if !cfg!(debug_assertions) {
+            return write().map_err(|e| self.write_error(e));
         }
 
-        Ok(results)
+        let before = self.audio_checksum()?;
+        write().map_err(|e| self.write_error(e))?;
+        let after = self.audio_checksum()?;
+        if before != after {
+            return Err(AudioFileError::AudioIntegrityError(format!(
+                "audio stream of {} changed during a tag write", self.path
+            )));
+        }
+        Ok(())
     }
 
-    fn process_directory(
+    /// Write metadata to the ID3v2 tag, keeping frames that `Metadata` doesn't cover
+    ///
+    /// `described` holds (frame ID, description, value) triples for TXXX and
+    /// COMM frames, each replacing the frame with the same ID and description.
+    fn write_id3v2_metadata(
         &self,
-        _directory: String,
-        _pattern: String,
-        _operation: String,
-        _metadata: Option<String>,
-        py: Python,
-    ) -> PyResult<Py<PyAny>> {
-        let results = Vec::<PyBatchResult>::new();
-        Ok(PyList::new(py, results)?.into())
-    }
-}
+        metadata: &Metadata,
+        options: &WriteOptions,
+        described: &[(&str, &str, String)],
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        use id3::convert::{convert_frame, encoding_for, translate_frame_id};
+        use id3::frames::{
+            decode_priv_frame, encode_apic_frame, encode_priv_frame, encode_text_frame, encode_txxx_frame,
+            encode_uslt_frame, split_apic_frame, PictureType,
+        };
+        use id3::v2::Id3Frame;
 
-#[cfg(feature = "python")]
-#[pyclass(name = "BatchResult")]
-#[derive(Clone)]
-pub struct PyBatchResult {
-    #[pyo3(get, set)]
-    pub file_path: String,
-    #[pyo3(get, set)]
-    pub success: bool,
-    #[pyo3(get, set)]
-    pub error_message: Option<String>,
+        let file_data = std::fs::read(&self.path)?;
+        let raw_tag = self.read_raw_id3v2_tag(&mut std::io::Cursor::new(&file_data))?;
+        let (tag, span) = self.id3v2_tag_for_write(raw_tag, &file_data)?;
+
+        let source = tag.header.version.0;
+        let version = match options.target_version {
+            Some((2, 3)) => (3, 0),
+            Some((2, 4)) => (4, 0),
+            Some((major, minor)) => {
+                return Err(AudioFileError::UnsupportedFormat(
+                    format!("Cannot write ID3v{}.{} tags", major, minor)
+                ));
+            }
+            // ID3v2.2 can't be written back; upgrade to the closest version
+            None if source == 2 => (3, 0),
+            None => tag.header.version,
+        };
+        let target = version.0;
+
+        let language = match options.language.as_deref() {
+            None => utils::language::DEFAULT,
+            Some(code) => utils::language::normalize(code).ok_or_else(|| {
+                AudioFileError::ParseError(format!("Invalid ISO-639-2 language code: {:?}", code))
+            })?,
+        };
+
+        let mut report = WriteReport::default();
+        let mut frames = Vec::new();
+        let mut past_history = Vec::new();
+
+        for frame in &tag.frames {
+            let id = translate_frame_id(&frame.frame_id, source, 4).unwrap_or_default();
+            if history.is_some() && id == "PRIV" {
+                if let Some((_, data)) = decode_priv_frame(&frame.data).filter(|(owner, _)| owner == history::ID3_OWNER) {
+                    past_history = history::parse(data);
+                    continue;
+                }
+            }
+            let description = frame_description(id, &frame.data);
+            // iTunes keeps its own data (iTunNORM, iTunSMPB, ...) in described COMM frames
+            let managed = (FieldMappings::all_id3v2_frames().contains(&id) || EXTRA_MANAGED_ID3V2_FRAMES.contains(&id))
+                && !(id == "COMM" && description.as_deref().is_some_and(is_itunes_comment));
+            // TDAT and TIME are rewritten from `Metadata::year`
+            let date_part = source < 4 && matches!(translate_frame_id(&frame.frame_id, source, 3), Some("TDAT" | "TIME"));
+            let encoder_settings = id == "TXXX"
+                && description.as_deref().is_some_and(|description| description.eq_ignore_ascii_case(ENCODER_SETTINGS_TXXX));
+            let replaced = description.is_some_and(|description| {
+                described.iter().any(|(frame_id, name, _)| *frame_id == id && name.eq_ignore_ascii_case(&description))
+            });
+            if managed || date_part || encoder_settings || replaced {
+                continue;
+            }
+            match convert_frame(frame, source, target) {
+                Ok(frame) => frames.push(frame),
+                Err(reason) => report.lossy_frames.push(reason),
+            }
+        }
+        if options.deterministic {
+            frames.sort_by(|a, b| (&a.frame_id, &a.data).cmp(&(&b.frame_id, &b.data)));
+        }
+
+        // TDRC and TDOR take a full timestamp; ID3v2.3 splits the release date
+        // over TYER, TDAT and TIME, and TORY holds just the year
+        let (year_id, year, date, time, original_id, original_date) = if target >= 4 {
+            ("TDRC", metadata.year.clone(), None, None, "TDOR", metadata.original_date.clone())
+        } else {
+            let (year, date, time) = match &metadata.year {
+                Some(timestamp) => {
+                    let (year, date, time) = id3::convert::split_timestamp(timestamp);
+                    let kept = id3::convert::join_timestamp(&year, date.as_deref(), time.as_deref());
+                    if kept != *timestamp {
+                        report.lossy_frames.push(format!("TDRC: {:?} cut to {:?} for ID3v2.3", timestamp, kept));
+                    }
+                    (Some(year), date, time)
+                }
+                None => (None, None, None),
+            };
+            let original_date = metadata.original_date.as_ref().map(|timestamp| {
+                let year: String = timestamp.chars().take(4).collect();
+                if year != *timestamp {
+                    report.lossy_frames.push(format!("TDOR: {:?} cut to {:?} for ID3v2.3", timestamp, year));
+                }
+                year
+            });
+            ("TYER", year, date, time, "TORY", original_date)
+        };
+        // ID3v2.4 separates genres with NUL; ID3v2.3 uses "(17)" references plus free text
+        let genres = metadata.all_genres();
+        let genre = if target >= 4 {
+            genres.join("\0")
+        } else {
+            utils::genre::format_tcon_v23(&genres)
+        };
+        let genre = Some(genre).filter(|_| !genres.is_empty());
+        let length = metadata.length_ms.map(|ms| ms.to_string());
+        let text_fields = [
+            ("TIT2", &metadata.title),
+            ("TPE1", &metadata.artist),
+            ("TALB", &metadata.album),
+            (year_id, &year),
+            ("TDAT", &date),
+            ("TIME", &time),
+            ("TRCK", &metadata.track),
+            ("TCON", &genre),
+            ("TPE2", &metadata.album_artist),
+            ("TCOM", &metadata.composer),
+            ("TLEN", &length),
+            ("TSSE", &metadata.encoder),
+            (original_id, &original_date),
+            ("TOPE", &metadata.original_artist),
+            ("TOAL", &metadata.original_album),
+        ];
+        for (frame_id, value) in text_fields {
+            if let Some(text) = value {
+                let data = encode_text_frame(text, encoding_for(text, target));
+                frames.push(Id3Frame::new(frame_id, data));
+            }
+        }
+        if !metadata.performers.is_empty() {
+            // TMCL is new in ID3v2.4; ID3v2.3's IPLS holds production credits instead
+            if target >= 4 {
+                let text = metadata.performers.iter()
+                    .flat_map(|(instrument, name)| [instrument.as_str(), name.as_str()])
+                    .collect::<Vec<_>>()
+                    .join("\0");
+                frames.push(Id3Frame::new("TMCL", encode_text_frame(&text, encoding_for(&text, target))));
+            } else {
+                report.lossy_frames.push("TMCL: no equivalent frame in ID3v2.3".to_string());
+            }
+        }
+        for (frame_id, description, value) in described {
+            // An empty value only removes the existing frame
+            if value.is_empty() {
+                continue;
+            }
+            let encoding = encoding_for(value, target);
+            let data = match *frame_id {
+                "COMM" => encode_uslt_frame(language, description, value, encoding),
+                _ => encode_txxx_frame(description, value, encoding),
+            };
+            frames.push(Id3Frame::new(frame_id, data));
+        }
+
+        if let Some(settings) = &metadata.encoder_settings {
+            let data = encode_txxx_frame(ENCODER_SETTINGS_TXXX, settings, encoding_for(settings, target));
+            frames.push(Id3Frame::new("TXXX", data));
+        }
+        if let Some(comment) = &metadata.comment {
+            let data = encode_uslt_frame(language, "", comment, encoding_for(comment, target));
+            frames.push(Id3Frame::new("COMM", data));
+        }
+        if let Some(lyrics) = &metadata.lyrics {
+            let data = encode_uslt_frame(language, "", lyrics, encoding_for(lyrics, target));
+            frames.push(Id3Frame::new("USLT", data));
+        }
+        // An unchanged cover keeps every existing picture, not just the first,
+        // and a new one only replaces the front cover
+        let pictures: Vec<Id3Frame> = tag.frames.iter()
+            .filter(|frame| translate_frame_id(&frame.frame_id, source, 4) == Some("APIC"))
+            .filter_map(|frame| convert_frame(frame, source, target).ok())
+            .collect();
+        let first_picture = pictures.first().and_then(|frame| split_apic_frame(&frame.data));
+        match &metadata.cover {
+            None => {}
+            Some(cover) if first_picture.is_some_and(|(_, _, _, image)| image == cover.data.as_slice()) => {
+                frames.extend(pictures.iter().cloned());
+            }
+            Some(cover) => {
+                let mime_type = cover.mime_type.as_deref()
+                    .or_else(|| utils::image::detect_mime_type(&cover.data))
+                    .unwrap_or("image/jpeg");
+                let description = cover.description.as_deref().unwrap_or("");
+                let data = encode_apic_frame(mime_type, PictureType::CoverFront, description, &cover.data);
+                frames.push(Id3Frame::new("APIC", data));
+                frames.extend(pictures.iter().filter(|frame| {
+                    !matches!(split_apic_frame(&frame.data), Some((_, picture_type, _, _)) if picture_type == PictureType::CoverFront as u8)
+                }).cloned());
+            }
+        }
+        if let Some(entry) = history {
+            let data = history::append(past_history, entry.clone());
+            frames.push(Id3Frame::new("PRIV", encode_priv_frame(history::ID3_OWNER, &data)));
+        }
+
+        // Reuse the existing tag space when the new frames fit, so the audio doesn't move
+        let frames_len: usize = frames.iter().map(|frame| frame.data.len() + 10).sum();
+        let grow_padding = options.id3v2_padding.unwrap_or(ID3V2_PADDING);
+        let padding = if options.deterministic {
+            grow_padding
+        } else if frames_len + 10 <= span.len() {
+            span.len() - frames_len - 10
+        } else {
+            grow_padding
+        };
+
+        // Keep an ID3v2.4 footer; it only exists in ID3v2.4 and rules out padding
+        let tag_data = if target >= 4 && tag.header.has_footer() {
+            Id3v2Tag::to_bytes_with_footer(&frames)
+        } else {
+            Id3v2Tag::to_bytes(&frames, version, padding)
+        };
+        let new_data = self.splice_id3v2_tag(&file_data, span, &tag_data);
+
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &new_data)?;
+            Ok(())
+        })?;
+
+        Ok(report)
+    }
+
+    /// Replace the trailing ID3v1 tag, or append one if the file has none
+    ///
+    /// An ID3v1.1 track number takes the last two bytes of the comment
+    /// field, so a 29 or 30 character comment is cut to 28 when the tag has
+    /// a track; that is logged as a warning. Removing the track frees the
+    /// bytes again, since the whole tag is rebuilt.
+    ///
+    /// A TAG+ extended tag before the old tag is replaced along with it: it
+    /// is written again when `tag.extended` is set and dropped, with a
+    /// warning, when it isn't.
+    fn write_id3v1_tag(&self, tag: &Id3v1Tag) -> AudioResult<()> {
+        let mut file_data = std::fs::read(&self.path)?;
+        let len = file_data.len();
+        let mut existing = None;
+        if len >= Id3v1Tag::TAG_SIZE && &file_data[len - Id3v1Tag::TAG_SIZE..len - 125] == b"TAG" {
+            let bytes: [u8; 128] = file_data[len - Id3v1Tag::TAG_SIZE..].try_into().expect("tag is 128 bytes");
+            let mut old = Id3v1Tag::parse(&bytes);
+            file_data.truncate(len - Id3v1Tag::TAG_SIZE);
+
+            let len = file_data.len();
+            if len >= Id3v1Tag::EXTENDED_TAG_SIZE && Id3v1Tag::is_extended(&file_data[len - Id3v1Tag::EXTENDED_TAG_SIZE..]) {
+                let extended: &[u8; 227] = file_data[len - Id3v1Tag::EXTENDED_TAG_SIZE..].try_into().expect("tag is 227 bytes");
+                old.merge_extended(&bytes, extended);
+                file_data.truncate(len - Id3v1Tag::EXTENDED_TAG_SIZE);
+                if tag.extended.is_none() {
+                    tracing::warn!(
+                        event = "warning",
+                        path = %self.path,
+                        message = "dropping the ID3v1 extended tag (TAG+); title, artist and album are cut to 30 characters",
+                    );
+                }
+            }
+            existing = Some(old);
+        }
+
+        if let (true, Some(track)) = (tag.track_truncates_comment(), tag.track) {
+            let message = match existing {
+                Some(old) if old.track.is_none() && old.comment == tag.comment => format!(
+                    "adding ID3v1.1 track {} cuts the existing {}-character comment to {}",
+                    track, tag.comment.chars().count(), id3::v1::COMMENT_WITH_TRACK_LEN
+                ),
+                _ => format!(
+                    "ID3v1 comment cut to {} characters to make room for track {}",
+                    id3::v1::COMMENT_WITH_TRACK_LEN, track
+                ),
+            };
+            tracing::warn!(event = "warning", path = %self.path, message = %message);
+        }
+        if let Some(extended) = tag.extended_to_bytes() {
+            file_data.extend_from_slice(&extended);
+        }
+        file_data.extend_from_slice(&tag.to_bytes());
+
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &file_data)?;
+            Ok(())
+        })
+    }
+
+    /// Warning for a cover that is valid MP4 but that many players won't show
+    fn mp4_cover_warning(cover: &mp4::Mp4Cover) -> Option<String> {
+        (cover.type_flag == mp4::data_types::GIF)
+            .then(|| "GIF cover written with type flag 12, which many players don't show; prefer JPEG or PNG".to_string())
+    }
+
+    /// Write metadata to the MP4 ilst atom
+    fn write_mp4_metadata(&self, metadata: &Metadata) -> AudioResult<WriteReport> {
+        let mp4_file = Mp4File::new(self.path.clone());
+        let existing = mp4_file.read_metadata()?.unwrap_or_default();
+        let itunnorm = existing.itunnorm.clone();
+        let mut report = WriteReport { warnings: existing.warnings.clone(), ..WriteReport::default() };
+
+        // An unchanged cover keeps every existing picture, not just the first
+        let covers = match &metadata.cover {
+            None => Vec::new(),
+            Some(cover) if existing.covers.first().is_some_and(|first| first.data == cover.data) => {
+                existing.covers
+            }
+            Some(cover) => {
+                let cover = mp4::Mp4Cover::for_image(cover.data.clone(), cover.mime_type.as_deref())
+                    .map_err(AudioFileError::UnsupportedFormat)?;
+                report.warnings.extend(Self::mp4_cover_warning(&cover));
+                vec![cover]
+            }
+        };
+
+        let mp4_metadata = mp4::Mp4Metadata {
+            title: metadata.title.clone(),
+            artist: metadata.artist.clone(),
+            album: metadata.album.clone(),
+            year: metadata.year.clone(),
+            track: metadata.track.clone(),
+            genre: metadata.genre.clone(),
+            comment: metadata.comment.clone(),
+            lyrics: metadata.lyrics.clone(),
+            album_artist: metadata.album_artist.clone(),
+            composer: metadata.composer.clone(),
+            original_date: metadata.original_date.clone(),
+            original_artist: metadata.original_artist.clone(),
+            original_album: metadata.original_album.clone(),
+            encoder: metadata.encoder.clone(),
+            encoder_settings: metadata.encoder_settings.clone(),
+            itunnorm,
+            media_kind: metadata.itunes.media_kind,
+            gapless: metadata.itunes.gapless,
+            tv_show: metadata.itunes.tv_show.clone(),
+            tv_season: metadata.itunes.tv_season,
+            tv_episode: metadata.itunes.tv_episode,
+            covers,
+            warnings: Vec::new(),
+        };
+
+        self.guarded_write(|| Ok(mp4_file.write_metadata(&mp4_metadata)?))?;
+        Ok(report)
+    }
+
+    /// Write metadata to the FLAC VORBIS_COMMENT and PICTURE blocks
+    ///
+    /// `extra` holds (key, value) comments outside `Metadata`, each replacing
+    /// the comments with the same key; an empty value removes them.
+    fn write_flac_metadata(
+        &self,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        extra: &[(String, String)],
+        history: Option<&HistoryEntry>,
+    ) -> AudioResult<WriteReport> {
+        use flac::vorbis::VorbisComment;
+
+        let file_data = std::fs::read(&self.path)?;
+        let mut cursor = std::io::Cursor::new(&file_data);
+        cursor.set_position(FLAC_SIGNATURE.len() as u64);
+
+        let mut blocks = Vec::new();
+        let mut comment = None;
+        // Where the comment goes among the kept blocks; a new one follows STREAMINFO
+        let mut comment_index = 1;
+        let mut pictures = Vec::new();
+        let mut past_history = Vec::new();
+        loop {
+            let block = FlacMetadataBlock::read(&mut cursor)?;
+            let is_last = block.header.is_last;
+            match block.header.block_type {
+                FlacMetadataBlockType::Application
+                    if history.is_some() && block.data.starts_with(history::FLAC_APPLICATION_ID) =>
+                {
+                    past_history = history::parse(&block.data[4..]);
+                }
+                FlacMetadataBlockType::VorbisComment if comment.is_none() => {
+                    comment = Some(VorbisComment::read(&mut std::io::Cursor::new(&block.data))?);
+                    comment_index = blocks.len();
+                }
+                FlacMetadataBlockType::VorbisComment | FlacMetadataBlockType::Padding => {}
+                FlacMetadataBlockType::Picture => pictures.push(block),
+                _ => blocks.push(block),
+            }
+            if is_last {
+                break;
+            }
+        }
+        let audio_start = cursor.position() as usize;
+
+        let mut comment = comment.unwrap_or_default();
+        let mut report = WriteReport::default();
+        if !options.preserve_vorbis_keys {
+            report.warnings = comment.normalize();
+        }
+        Self::metadata_to_vorbis(metadata, &mut comment, options.deterministic);
+        Self::set_vorbis_fields(&mut comment, extra);
+        // Checked before serializing so an oversized comment is never built
+        if comment.estimated_byte_size() > flac::metadata::MAX_BLOCK_LENGTH {
+            return Err(AudioFileError::ParseError(format!(
+                "VorbisComment block of {} bytes is too large for FLAC", comment.estimated_byte_size()
+            )));
+        }
+        let comment_index = comment_index.min(blocks.len());
+        blocks.insert(comment_index, FlacMetadataBlock::new(FlacMetadataBlockType::VorbisComment, comment.to_bytes()));
+        if let Some(entry) = history {
+            let mut data = history::FLAC_APPLICATION_ID.to_vec();
+            data.extend(history::append(past_history, entry.clone()));
+            blocks.push(FlacMetadataBlock::new(FlacMetadataBlockType::Application, data));
+        }
+
+        // An unchanged cover keeps every existing picture, not just the first,
+        // and a new one only replaces the front cover
+        let first_picture = pictures.first().and_then(|block| FlacPicture::read_from_data(&block.data).ok());
+        match &metadata.cover {
+            None => {}
+            Some(cover) if first_picture.is_some_and(|picture| picture.data == cover.data) => {
+                blocks.extend(pictures);
+            }
+            Some(cover) => {
+                let mime_type = cover.mime_type.as_deref()
+                    .or_else(|| utils::image::detect_mime_type(&cover.data))
+                    .unwrap_or("image/jpeg");
+                let description = cover.description.clone().unwrap_or_default();
+                let picture = FlacPicture::new(cover.data.clone(), mime_type.to_string(), description);
+                blocks.push(FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes()));
+                blocks.extend(pictures.into_iter().filter(|block| {
+                    !matches!(FlacPicture::read_from_data(&block.data), Ok(picture) if picture.picture_type == flac::picture::PictureType::CoverFront)
+                }));
+            }
+        }
+
+        self.write_flac_blocks(&file_data, blocks, audio_start, options.deterministic)?;
+        Ok(report)
+    }
+
+    /// Replace the metadata blocks of a FLAC file, which end at `audio_start` in `file_data`
+    ///
+    /// `blocks` must not include PADDING; it is added to fill the old metadata
+    /// space when the blocks fit, so the audio doesn't move.
+    fn write_flac_blocks(
+        &self,
+        file_data: &[u8],
+        mut blocks: Vec<FlacMetadataBlock>,
+        audio_start: usize,
+        deterministic: bool,
+    ) -> AudioResult<()> {
+        if let Some(block) = blocks.iter().find(|block| block.data.len() > flac::metadata::MAX_BLOCK_LENGTH) {
+            return Err(AudioFileError::ParseError(format!(
+                "{:?} block of {} bytes is too large for FLAC", block.header.block_type, block.data.len()
+            )));
+        }
+
+        let metadata_len: usize = FLAC_SIGNATURE.len() + blocks.iter().map(|block| 4 + block.data.len()).sum::<usize>();
+        let padding = if deterministic {
+            Some(FLAC_PADDING)
+        } else if metadata_len == audio_start {
+            None
+        } else if metadata_len + 4 <= audio_start {
+            Some(audio_start - metadata_len - 4)
+        } else {
+            Some(FLAC_PADDING)
+        };
+        if let Some(padding) = padding {
+            blocks.push(FlacMetadataBlock::padding(padding));
+        }
+
+        let mut new_data = FLAC_SIGNATURE.to_vec();
+        for (index, block) in blocks.iter().enumerate() {
+            new_data.extend(block.to_bytes(index + 1 == blocks.len()));
+        }
+        new_data.extend_from_slice(&file_data[audio_start..]);
+
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &new_data)?;
+            Ok(())
+        })
+    }
+
+    /// Write metadata to the Vorbis comment packet of an OGG Vorbis or Opus file
+    ///
+    /// `extra` is handled as in `write_flac_metadata`.
+    fn write_ogg_metadata(
+        &self,
+        metadata: &Metadata,
+        options: &WriteOptions,
+        extra: &[(String, String)],
+    ) -> AudioResult<WriteReport> {
+        let is_opus = self.format == Format::Opus;
+        let existing = if is_opus {
+            OpusFile::new(self.path.clone()).read_comment()?
+        } else {
+            OggVorbisFile::new(self.path.clone()).read_comment()?
+        };
+
+        let mut comment = existing.unwrap_or_default();
+        let mut report = WriteReport::default();
+        if !options.preserve_vorbis_keys {
+            report.warnings = comment.normalize();
+        }
+        Self::metadata_to_vorbis(metadata, &mut comment, options.deterministic);
+        Self::set_vorbis_fields(&mut comment, extra);
+
+        self.write_ogg_comment(&comment)?;
+        Ok(report)
+    }
+
+    /// Write the comment header of an OGG Vorbis or Opus file
+    fn write_ogg_comment(&self, comment: &VorbisComment) -> AudioResult<()> {
+        // OGG pages interleave headers and audio, so there is no audio span to guard
+        self.check_writable()?;
+        let result = if self.format == Format::Opus {
+            OpusFile::new(self.path.clone()).write_comment(comment)
+        } else {
+            OggVorbisFile::new(self.path.clone()).write_comment(comment)
+        };
+        result.map_err(|e| self.write_error(AudioFileError::IoError(e)))
+    }
+
+    /// Replace the comments of each key in `fields`; empty values only remove them
+    fn set_vorbis_fields(comment: &mut flac::vorbis::VorbisComment, fields: &[(String, String)]) {
+        for (key, value) in fields {
+            comment.remove(key);
+            if !value.is_empty() {
+                comment.comments.push((flac::vorbis::canonical_key(key), value.clone()));
+            }
+        }
+    }
+
+    /// Rebuild a Vorbis comment from `Metadata`, keeping keys it doesn't cover
+    fn metadata_to_vorbis(metadata: &Metadata, comment: &mut flac::vorbis::VorbisComment, deterministic: bool) {
+        let length = metadata.length_ms.map(|ms| ms.to_string());
+        let original_year = metadata.original_date.as_ref().map(|date| date.chars().take(4).collect());
+        let values = [
+            &metadata.title,
+            &metadata.artist,
+            &metadata.album,
+            &metadata.year,
+            &metadata.track,
+            &metadata.genre,
+            &metadata.album_artist,
+            &metadata.composer,
+            &metadata.comment,
+            &metadata.lyrics,
+            &length,
+            &metadata.encoder,
+            &metadata.encoder_settings,
+            &metadata.original_date,
+            &original_year,
+            &metadata.original_artist,
+            &metadata.original_album,
+            &None,
+        ];
+
+        comment.comments.retain(|(key, _)| !is_managed_vorbis_field(key));
+        if deterministic {
+            comment.comments.sort();
+            comment.vendor_string = DETERMINISTIC_VENDOR.to_string();
+        }
+
+        for (key, value) in MANAGED_VORBIS_FIELDS.iter().zip(values) {
+            if *key == "GENRE" {
+                // One GENRE entry per genre
+                for genre in metadata.all_genres() {
+                    comment.comments.push((key.to_string(), genre));
+                }
+            } else if *key == "PERFORMER" {
+                for performer in &metadata.performers {
+                    comment.comments.push((key.to_string(), format_performer(performer)));
+                }
+            } else if let Some(value) = value {
+                comment.comments.push((key.to_string(), value.clone()));
+            }
+        }
+    }
+
+    /// Drop duplicate frames and reset the padding of the ID3v2 tag
+    fn optimize_id3v2(&self) -> AudioResult<OptimizeReport> {
+        let file_data = std::fs::read(&self.path)?;
+        let (tag, dropped) = self.read_upgraded_id3v2_tag()?;
+        let tag = tag.ok_or_else(|| AudioFileError::ParseError("No ID3v2 tag found".to_string()))?;
+        let (tag, span) = self.id3v2_tag_for_write(Some(tag), &file_data)?;
+        // ID3v2.2 frames were upgraded on read, so the tag is written as ID3v2.3
+        let version = if tag.header.version.0 == 2 { (3, 0) } else { tag.header.version };
+
+        let mut report = OptimizeReport::default();
+        report.changes.extend(dropped.iter().map(|reason| format!("Dropped {}", reason)));
+        let mut frames: Vec<id3::v2::Id3Frame> = Vec::new();
+        for frame in tag.frames {
+            if frames.iter().any(|kept| kept.frame_id == frame.frame_id && kept.data == frame.data) {
+                report.changes.push(format!("Removed duplicate {} frame", frame.frame_id));
+            } else {
+                frames.push(frame);
+            }
+        }
+
+        let tag_data = if tag.header.has_footer() {
+            Id3v2Tag::to_bytes_with_footer(&frames)
+        } else {
+            Id3v2Tag::to_bytes(&frames, version, ID3V2_OPTIMIZED_PADDING)
+        };
+        if report.changes.is_empty() && tag_data.len() == span.len() {
+            return Ok(report);
+        }
+        if tag_data.len() != span.len() {
+            report.changes.push(format!(
+                "Resized ID3v2 tag from {} to {} bytes with {} bytes of padding",
+                span.len(), tag_data.len(), ID3V2_OPTIMIZED_PADDING
+            ));
+        }
+        let new_data = self.splice_id3v2_tag(&file_data, span, &tag_data);
+
+        report.bytes_saved = file_data.len() as i64 - new_data.len() as i64;
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &new_data)?;
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Merge PADDING blocks and drop unregistered APPLICATION blocks other than the edit history
+    fn optimize_flac(&self) -> AudioResult<OptimizeReport> {
+        let file_data = std::fs::read(&self.path)?;
+        let mut cursor = std::io::Cursor::new(&file_data);
+        cursor.set_position(FLAC_SIGNATURE.len() as u64);
+
+        let mut report = OptimizeReport::default();
+        let mut blocks = Vec::new();
+        let mut padding_blocks = 0;
+        let mut padding_total = 0;
+
+        loop {
+            let block = FlacMetadataBlock::read(&mut cursor)?;
+            let is_last = block.header.is_last;
+
+            match block.header.block_type {
+                FlacMetadataBlockType::Padding => {
+                    padding_blocks += 1;
+                    padding_total += block.data.len();
+                }
+                FlacMetadataBlockType::Application => {
+                    // oxidant's own edit history is kept as well
+                    let known = block.data.get(..4).is_some_and(|id| {
+                        flac::metadata::KNOWN_APPLICATION_IDS.iter().any(|known| known[..] == *id)
+                            || id == history::FLAC_APPLICATION_ID
+                    });
+                    if known {
+                        blocks.push(block);
+                    } else {
+                        let id = String::from_utf8_lossy(block.data.get(..4).unwrap_or(&block.data)).to_string();
+                        report.changes.push(format!(
+                            "Removed APPLICATION block with unknown ID \"{}\" ({} bytes)", id, block.data.len()
+                        ));
+                    }
+                }
+                _ => blocks.push(block),
+            }
+
+            if is_last {
+                break;
+            }
+        }
+        let audio_start = cursor.position() as usize;
+
+        let padding = padding_total.min(FLAC_PADDING);
+        if padding_blocks > 1 || padding < padding_total {
+            report.changes.push(format!(
+                "Replaced {} PADDING block(s) totalling {} bytes with one {}-byte block",
+                padding_blocks, padding_total, padding
+            ));
+        }
+        if report.changes.is_empty() {
+            return Ok(report);
+        }
+        if padding_blocks > 0 {
+            blocks.push(FlacMetadataBlock::padding(padding));
+        }
+
+        let mut new_data = FLAC_SIGNATURE.to_vec();
+        for (index, block) in blocks.iter().enumerate() {
+            new_data.extend(block.to_bytes(index + 1 == blocks.len()));
+        }
+        new_data.extend_from_slice(&file_data[audio_start..]);
+
+        report.bytes_saved = file_data.len() as i64 - new_data.len() as i64;
+        self.guarded_write(|| {
+            utils::io::replace_file(&self.path, &new_data)?;
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Remove Vorbis comment entries with empty values from an OGG/Opus file
+    fn optimize_vorbis_comments(&self) -> AudioResult<OptimizeReport> {
+        let mut report = OptimizeReport::default();
+        let is_opus = self.format == Format::Opus;
+        let comment = if is_opus {
+            OpusFile::new(self.path.clone()).read_comment()?
+        } else {
+            OggVorbisFile::new(self.path.clone()).read_comment()?
+        };
+        let mut comment = match comment {
+            Some(comment) => comment,
+            None => return Ok(report),
+        };
+
+        let before = comment.comments.len();
+        comment.comments.retain(|(_, value)| !value.is_empty());
+        let removed = before - comment.comments.len();
+        if removed == 0 {
+            return Ok(report);
+        }
+        report.changes.push(format!("Removed {} empty Vorbis comment entries", removed));
+
+        let old_len = std::fs::metadata(&self.path)?.len();
+        self.write_ogg_comment(&comment)?;
+        report.bytes_saved = old_len as i64 - std::fs::metadata(&self.path)?.len() as i64;
+        Ok(report)
+    }
+
+    /// Convert VorbisComment to Metadata
+    ///
+    /// GENRE and PERFORMER may repeat; for other keys the first non-empty
+    /// value wins (see `keep_first`).
+    fn vorbis_to_metadata(&self, comment: flac::vorbis::VorbisComment) -> (Metadata, FieldSources) {
+        let tag = match self.format {
+            Format::OggVorbis => "Ogg Vorbis",
+            Format::Opus => "Opus",
+            _ => "FLAC",
+        };
+        let mut metadata = Metadata::default();
+        let mut sources = FieldSources::new();
+        let mut genres = Vec::new();
+        let mut genre_source = None;
+        let mut original_year = None;
+        let mut original_year_source = None;
+        for (raw_key, value) in comment.comments {
+            let key = flac::vorbis::canonical_key(&raw_key);
+            let source = || format!("{}/{}", tag, raw_key);
+            if !value.is_empty() {
+                match key.as_str() {
+                    "GENRE" => { genre_source.get_or_insert_with(source); }
+                    "ORIGINALYEAR" => { original_year_source.get_or_insert_with(source); }
+                    _ => {}
+                }
+            }
+            let value = Some(value);
+            match key.as_str() {
+                "TITLE" => self.keep_first(&mut metadata.title, value, &key),
+                "ARTIST" => self.keep_first(&mut metadata.artist, value, &key),
+                "ALBUM" => self.keep_first(&mut metadata.album, value, &key),
+                "DATE" => self.keep_first(&mut metadata.year, value, &key),
+                "TRACKNUMBER" => self.keep_first(&mut metadata.track, value, &key),
+                "GENRE" => genres.extend(value),
+                "COMMENT" => self.keep_first(&mut metadata.comment, value, &key),
+                "LYRICS" => self.keep_first(&mut metadata.lyrics, value, &key),
+                "ALBUMARTIST" => self.keep_first(&mut metadata.album_artist, value, &key),
+                "COMPOSER" => self.keep_first(&mut metadata.composer, value, &key),
+                "LENGTH" => {
+                    let length = value.as_deref().and_then(parse_length_ms);
+                    match (metadata.length_ms, length) {
+                        (None, length) => metadata.length_ms = length,
+                        (Some(_), Some(length)) => self.report_duplicate(&key, &length.to_string()),
+                        (Some(_), None) => {}
+                    }
+                }
+                "ENCODER" => self.keep_first(&mut metadata.encoder, value, &key),
+                "ENCODERSETTINGS" => self.keep_first(&mut metadata.encoder_settings, value, &key),
+                "ORIGINALDATE" => self.keep_first(&mut metadata.original_date, value, &key),
+                "ORIGINALYEAR" => self.keep_first(&mut original_year, value, &key),
+                "ORIGINALARTIST" => self.keep_first(&mut metadata.original_artist, value, &key),
+                "ORIGINALALBUM" => self.keep_first(&mut metadata.original_album, value, &key),
+                "PERFORMER" => metadata.performers.extend(value.as_deref().map(parse_performer)),
+                _ => {}
+            }
+            record_sources(&mut sources, &metadata, |_| source());
+        }
+        // ORIGINALDATE is more precise and wins when both are present
+        if metadata.original_date.is_none() {
+            metadata.original_date = original_year;
+        }
+        metadata.set_genres(genres);
+        record_sources(&mut sources, &metadata, |key| match key {
+            "original_date" => original_year_source.clone().unwrap_or_default(),
+            _ => genre_source.clone().unwrap_or_default(),
+        });
+        (metadata, sources)
+    }
+
+    /// Convert Mp4Metadata to Metadata
+    fn mp4_to_metadata(meta: mp4::Mp4Metadata) -> Metadata {
+        Metadata {
+            title: meta.title,
+            artist: meta.artist,
+            album: meta.album,
+            year: meta.year,
+            comment: meta.comment,
+            track: meta.track,
+            genres: meta.genre.as_deref().map(utils::genre::split).unwrap_or_default(),
+            genre: meta.genre,
+            genre_raw: None,
+            album_artist: meta.album_artist,
+            composer: meta.composer,
+            original_date: meta.original_date,
+            original_artist: meta.original_artist,
+            original_album: meta.original_album,
+            performers: Vec::new(),
+            lyrics: meta.lyrics,
+            length_ms: None,
+            encoder: meta.encoder,
+            encoder_settings: meta.encoder_settings,
+            itunes: ItunesFields {
+                media_kind: meta.media_kind,
+                gapless: meta.gapless,
+                tv_show: meta.tv_show,
+                tv_season: meta.tv_season,
+                tv_episode: meta.tv_episode,
+            },
+            cover: None,
+        }
+    }
+
+    /// Convert ApeMetadata to Metadata
+    fn ape_to_metadata(meta: ape::ApeMetadata) -> Metadata {
+        Metadata {
+            title: meta.title,
+            artist: meta.artist,
+            album: meta.album,
+            year: meta.year,
+            comment: meta.comment,
+            track: meta.track,
+            genres: meta.genre.as_deref().map(utils::genre::split).unwrap_or_default(),
+            genre: meta.genre,
+            genre_raw: None,
+            album_artist: None,
+            composer: None,
+            original_date: None,
+            original_artist: None,
+            original_album: None,
+            performers: Vec::new(),
+            lyrics: meta.lyrics,
+            length_ms: None,
+            encoder: meta.encoder,
+            encoder_settings: None,
+            itunes: ItunesFields::default(),
+            cover: None,
+        }
+    }
+}
+
+/// Public API for AudioFile (no PyO3 dependencies)
+impl AudioFile {
+    /// Create a new AudioFile instance
+    ///
+    /// The format is detected with `detect::default_order()`.
+    pub fn new(path: String) -> AudioResult<Self> {
+        Self::with_detection(path, &detect::default_order())
+    }
+
+    /// Open a file, detecting its format with `order` instead of the default order
+    pub fn with_detection(path: String, order: &detect::DetectionOrder) -> AudioResult<Self> {
+        let format = order.detect_file(&path)?;
+        tracing::debug!(event = "file_opened", path = %path, format = %format);
+        Ok(Self { path, format })
+    }
+
+    /// Open a file as `format`, skipping detection
+    ///
+    /// For files detection gets wrong, or to test one parser on a file with
+    /// several tags. Fails if the file has no valid tag of that format.
+    pub fn with_format(path: String, format: Format) -> AudioResult<Self> {
+        let audio = Self { path, format };
+        if !audio.has_tag()? {
+            return Err(AudioFileError::ParseError(format!("No valid {} tag found in {}", format, audio.path)));
+        }
+        tracing::debug!(event = "file_opened", path = %audio.path, format = %format);
+        Ok(audio)
+    }
+
+    /// Rename the file and point `path` at its new location
+    ///
+    /// A bare file name stays in the current directory; anything with a
+    /// directory part is used as the full new path. Fails without touching
+    /// the file if the target already exists or its directory is read-only.
+    /// The renamed file is checked to still hold a `format` tag, and moved
+    /// back if it somehow doesn't.
+    pub fn rename_and_update_path(&mut self, new_name: &str) -> AudioResult<()> {
+        let new_path = Path::new(new_name);
+        let target = if new_path.components().count() == 1 {
+            Path::new(&self.path).with_file_name(new_name)
+        } else {
+            new_path.to_path_buf()
+        };
+        if target.symlink_metadata().is_ok() {
+            return Err(AudioFileError::IoError(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.display()),
+            )));
+        }
+        let directory = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if std::fs::metadata(directory)?.permissions().readonly() {
+            return Err(AudioFileError::PermissionDenied(directory.display().to_string()));
+        }
+
+        std::fs::rename(&self.path, &target).map_err(|e| self.write_error(AudioFileError::IoError(e)))?;
+        let target = target.to_string_lossy().to_string();
+        if let Err(e) = Self::with_format(target.clone(), self.format) {
+            std::fs::rename(&target, &self.path)?;
+            return Err(e);
+        }
+        self.path = target;
+        Ok(())
+    }
+
+    /// Read metadata as a `Metadata` struct
+    pub fn read_metadata(&self) -> AudioResult<Metadata> {
+        self.read_metadata_with_options(&ReadOptions::default())
+    }
+
+    /// Read metadata as a `Metadata` struct using the given options
+    pub fn read_metadata_with_options(&self, options: &ReadOptions) -> AudioResult<Metadata> {
+        Ok(self.read_metadata_with_sources(options)?.0)
+    }
+
+    /// Read metadata along with the tag structure each field was read from
+    ///
+    /// Within a tag the first non-empty frame or comment for a field wins.
+    /// In MPEG files the ID3v2 tag takes precedence and a trailing ID3v1 tag
+    /// only fills the fields it lacks; APEv2 tags are read for APE files only.
+    /// ID3v2.2 frames are named by their ID3v2.3 equivalents.
+    pub fn read_metadata_with_sources(&self, options: &ReadOptions) -> AudioResult<(Metadata, FieldSources)> {
+        let (mut metadata, mut sources) = self.read_metadata_sourced()?;
+        if options.include_cover {
+            metadata.cover = self.read_cover()?;
+            if metadata.cover.is_some() {
+                sources.insert("cover".to_string(), self.cover_source()?);
+            }
+        }
+        Ok((metadata, sources))
+    }
+
+    /// Read only the given standard fields, leaving the others empty
+    ///
+    /// ID3v2 frames holding other fields are seeked past unread and the scan
+    /// stops once every field is found, so the title of a file with a large
+    /// cover is read without touching the picture. FLAC PICTURE blocks are
+    /// only read for `Cover`. Other formats are read in full and narrowed down.
+    pub fn read_fields(&self, fields: &[StandardField]) -> AudioResult<Metadata> {
+        let mut metadata = match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let reader = BufReader::new(File::open(&self.path)?);
+                let mut metadata = self.read_id3v2_fields(reader, fields)?;
+                let missing = fields.iter().any(|&field| !metadata.has_field(field));
+                if self.format != Format::Dsf && missing && Id3v1Tag::read_from_file(&self.path)?.is_some() {
+                    metadata.merge(&self.read_id3v1_metadata()?.0, MergePolicy::FillEmpty);
+                }
+                metadata
+            }
+            _ => {
+                let include_cover = fields.contains(&StandardField::Cover);
+                self.read_metadata_with_options(&ReadOptions { include_cover, ..Default::default() })?
+            }
+        };
+        metadata.retain_fields(fields);
+        Ok(metadata)
+    }
+
+    /// Read metadata, or empty metadata if the tags can't be parsed
+    ///
+    /// Only a missing file is still an error; any other read failure, such as
+    /// a corrupted tag, yields `Metadata::default()`.
+    pub fn get_or_create_metadata(&self) -> AudioResult<Metadata> {
+        match self.read_metadata_internal() {
+            Ok(metadata) => Ok(metadata),
+            Err(AudioFileError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(AudioFileError::IoError(e))
+            }
+            Err(_) => Ok(Metadata::default()),
+        }
+    }
+
+    /// Read the duration and format of the audio stream from its codec headers
+    pub fn get_properties(&self) -> AudioResult<AudioProperties> {
+        self.read_properties(false)
+    }
+
+    /// Read the audio properties, with `icy_aware` leaving out ICY metadata blocks
+    fn read_properties(&self, icy_aware: bool) -> AudioResult<AudioProperties> {
+        let mut span = self.compute_audio_span()?;
+        let icy_audio = if icy_aware { self.read_icy_audio()? } else { None };
+        let properties = match icy_audio {
+            // Only MPEG streams carry ICY blocks
+            Some((audio, _)) => {
+                span.1 = audio.len() as u64;
+                properties::mpeg_properties(&audio)
+            }
+            None => handler::handler(self.format).properties(self, span)?,
+        };
+
+        let mut properties = properties.ok_or_else(|| AudioFileError::UnsupportedFormat(
+            format!("Cannot read audio properties of {}", self.path)
+        ))?;
+        properties.bitrate_kbps = (span.1 * 8).checked_div(properties.duration_ms).map(|kbps| kbps as u32);
+        properties.lossless = properties::is_lossless(&properties.codec);
+        Ok(properties)
+    }
+
+    /// ICY metadata interleaved in the MPEG stream of an internet-radio rip
+    ///
+    /// `None` when the stream has no ICY blocks and for formats other than MP3.
+    pub fn read_icy_stream(&self) -> AudioResult<Option<IcyStream>> {
+        Ok(self.read_icy_audio()?.map(|(_, stream)| stream))
+    }
+
+    /// Remove the ICY metadata blocks from the MPEG stream, returning how many there were
+    ///
+    /// Tags before and after the audio are kept as they are. Files without
+    /// ICY blocks are left untouched.
+    pub fn strip_icy_metadata(&self) -> AudioResult<usize> {
+        let Some((audio, stream)) = self.read_icy_audio()? else {
+            return Ok(0);
+        };
+        let (start, length) = self.compute_audio_span()?;
+        let file_data = std::fs::read(&self.path)?;
+        let mut new_data = file_data[..start as usize].to_vec();
+        new_data.extend_from_slice(&audio);
+        new_data.extend_from_slice(&file_data[(start + length) as usize..]);
+
+        // Removing the blocks changes the audio span by design, so it isn't guarded
+        self.check_writable()?;
+        utils::io::replace_file(&self.path, &new_data).map_err(|e| self.write_error(AudioFileError::IoError(e)))?;
+        Ok(stream.blocks)
+    }
+
+    /// The MPEG audio span without its ICY metadata blocks, and what they held
+    fn read_icy_audio(&self) -> AudioResult<Option<(Vec<u8>, IcyStream)>> {
+        if !matches!(self.format, Format::Id3v2 | Format::Id3v1 | Format::Ape | Format::Mp3) {
+            return Ok(None);
+        }
+        let data = properties::read_span(&self.path, self.compute_audio_span()?)?;
+        Ok(icy::find_interval(&data).map(|interval| icy::strip(&data, interval)))
+    }
+
+    /// Read the LAME header of an MP3 file: encoder delay and padding for gapless playback
+    ///
+    /// `None` for other formats and for MP3 files without a Xing/Info frame
+    /// carrying a LAME extension.
+    pub fn get_mp3_lame_header(&self) -> AudioResult<Option<LameHeader>> {
+        match self.format {
+            Format::Id3v2 | Format::Id3v1 | Format::Mp3 => {
+                let span = self.compute_audio_span()?;
+                Ok(properties::read_lame_header(&self.path, span)?)
+            }
+            Format::Flac | Format::OggVorbis | Format::Opus | Format::Mp4 | Format::Ape | Format::Dsf => Ok(None),
+        }
+    }
+
+    /// Output gain of the Opus identification header, in dB
+    ///
+    /// Decoders apply it at playback, on top of any R128 comment tags. `None`
+    /// for other formats.
+    pub fn get_opus_output_gain(&self) -> AudioResult<Option<f64>> {
+        if self.format != Format::Opus {
+            return Ok(None);
+        }
+        let gain = OpusFile::new(self.path.clone()).read_output_gain()?;
+        Ok(gain.map(|gain| f64::from(gain) / 256.0))
+    }
+
+    /// Set the output gain of the Opus identification header, in dB
+    ///
+    /// The gain is stored as Q7.8 fixed point, so it is rounded to 1/256 dB
+    /// and must lie within -128 to +127.996 dB. R128 comment tags are left as
+    /// they are.
+    pub fn set_opus_output_gain(&self, gain_db: f64) -> AudioResult<()> {
+        if self.format != Format::Opus {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no Opus output gain", self.format)));
+        }
+        let gain = (gain_db * 256.0).round();
+        if !(f64::from(i16::MIN)..=f64::from(i16::MAX)).contains(&gain) {
+            return Err(AudioFileError::ParseError(format!("Output gain {} dB is out of range", gain_db)));
+        }
+        self.check_writable()?;
+        OpusFile::new(self.path.clone())
+            .write_output_gain(gain as i16)
+            .map_err(|e| self.write_error(AudioFileError::IoError(e)))
+    }
+
+    /// Get metadata as JSON string
+    pub fn get_metadata(&self) -> AudioResult<String> {
+        self.get_metadata_with_options(&ReadOptions::default())
+    }
+
+    /// Get metadata as JSON string using the given options
+    ///
+    /// With `include_properties`, `properties` holds the audio properties, or
+    /// `null` if the audio stream can't be read. With `icy_aware`,
+    /// `stream_title` holds the last ICY StreamTitle, if any. With
+    /// `with_sources`, each metadata field is an object holding its `value`
+    /// and `source` (see `read_metadata_with_sources`). Keys follow
+    /// `METADATA_JSON_KEY_ORDER`, so the same tags always give the same text.
+    pub fn get_metadata_with_options(&self, options: &ReadOptions) -> AudioResult<String> {
+        let (metadata, sources) = self.read_metadata_with_sources(options)?;
+        let to_json_error = |e: serde_json::Error| AudioFileError::ParseError(e.to_string());
+        let mut value = serde_json::to_value(&metadata).map_err(to_json_error)?;
+        if options.with_sources {
+            if let Some(fields) = value.as_object_mut() {
+                for (key, field) in fields.iter_mut() {
+                    let source = sources.get(key).cloned().unwrap_or_default();
+                    *field = serde_json::json!({ "value": field.take(), "source": source });
+                }
+            }
+        }
+        if options.include_properties {
+            let properties = self.read_properties(options.icy_aware).ok();
+            value["properties"] = serde_json::to_value(properties).map_err(to_json_error)?;
+        }
+        if options.icy_aware {
+            if let Some(title) = self.read_icy_stream()?.and_then(|stream| stream.stream_title) {
+                value["stream_title"] = title.into();
+            }
+        }
+        Ok(ordered_json(&value))
+    }
+
+    /// Get metadata as serde_json Value
+    pub fn get_metadata_value(&self) -> AudioResult<serde_json::Value> {
+        let metadata = self.read_metadata()?;
+        serde_json::to_value(&metadata)
+            .map_err(|e| AudioFileError::ParseError(e.to_string()))
+    }
+
+    /// Get metadata as serde_json Value with the cover described in full
+    ///
+    /// Like `get_metadata_value`, but `cover` is `null` or an object with
+    /// `mime_type`, `width`, `height`, `depth`, `description` and base64 `data`.
+    /// Dimensions come from the picture block when the format stores them and
+    /// from the image header otherwise; unknown values are 0.
+    pub fn get_metadata_value_deep(&self) -> AudioResult<serde_json::Value> {
+        use base64::prelude::*;
+
+        let metadata = self.read_metadata_with_options(&ReadOptions::default())?;
+        let cover = match &metadata.cover {
+            Some(cover) => {
+                let picture = self.read_pictures()?.into_iter().find(|picture| picture.data == cover.data);
+                let (width, height) = picture.as_ref()
+                    .map(|picture| (picture.width, picture.height))
+                    .filter(|&(width, height)| width > 0 && height > 0)
+                    .or_else(|| cover.dimensions())
+                    .unwrap_or((0, 0));
+                serde_json::json!({
+                    "mime_type": cover.mime_type.clone().unwrap_or_default(),
+                    "width": width,
+                    "height": height,
+                    "depth": picture.map_or(0, |picture| picture.depth),
+                    "description": cover.description.clone().unwrap_or_default(),
+                    "data": BASE64_STANDARD.encode(&cover.data),
+                })
+            }
+            None => serde_json::Value::Null,
+        };
+
+        let mut value = serde_json::to_value(&metadata)
+            .map_err(|e| AudioFileError::ParseError(e.to_string()))?;
+        value["cover"] = cover;
+        Ok(value)
+    }
+
+    /// Set metadata from JSON string
+    ///
+    /// Fields missing from the JSON keep their current value; `null` or an
+    /// empty string removes them.
+    pub fn set_metadata(&self, metadata_json: String) -> AudioResult<()> {
+        self.set_metadata_with_options(metadata_json, &WriteOptions::default())?;
+        Ok(())
+    }
+
+    /// Set metadata from JSON string using the given options
+    pub fn set_metadata_with_options(&self, metadata_json: String, options: &WriteOptions) -> AudioResult<WriteReport> {
+        let updates: serde_json::Value = serde_json::from_str(&metadata_json)
+            .map_err(|e| AudioFileError::ParseError(e.to_string()))?;
+        self.update_metadata(options, |metadata| metadata.apply_json_updates(&updates))
+    }
+
+    /// Write `metadata` as the file's complete set of tags
+    ///
+    /// The counterpart of `read_metadata`: fields that are `None` or empty are
+    /// removed, and read-only fields such as `genre_raw` are ignored.
+    pub fn write_metadata(&self, metadata: &Metadata) -> AudioResult<()> {
+        self.write_metadata_with_options(metadata, &WriteOptions::default())?;
+        Ok(())
+    }
+
+    /// Write `metadata` as the file's complete set of tags using the given options
+    pub fn write_metadata_with_options(&self, metadata: &Metadata, options: &WriteOptions) -> AudioResult<WriteReport> {
+        self.update_metadata(options, |current| {
+            *current = metadata.clone();
+            Ok(())
+        })
+    }
+
+    /// Write `metadata` as the file's ID3v2 tag, leaving `padding_bytes` of padding if the tag grows
+    ///
+    /// Later writes that fit in the padding don't have to move the audio. A
+    /// tag that fits in the space of the old one is rewritten in place instead.
+    pub fn write_id3v2_metadata_with_padding(&self, metadata: &Metadata, padding_bytes: usize) -> AudioResult<WriteReport> {
+        if !matches!(self.format, Format::Id3v2 | Format::Mp3 | Format::Dsf) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no ID3v2 tag", self.format)));
+        }
+        let options = WriteOptions { id3v2_padding: Some(padding_bytes), ..Default::default() };
+        self.write_metadata_with_options(metadata, &options)
+    }
+
+    /// Read the tags, change them with `update` and write them back
+    fn update_metadata(
+        &self,
+        options: &WriteOptions,
+        update: impl FnOnce(&mut Metadata) -> AudioResult<()>,
+    ) -> AudioResult<WriteReport> {
+        let started = std::time::Instant::now();
+        let _span = tracing::debug_span!("write", format = %self.format).entered();
+        let mut fields_changed = Vec::new();
+        let result = self.write_updated(options, update, &mut fields_changed);
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        match &result {
+            Ok(report) => {
+                for warning in report.lossy_frames.iter().chain(&report.warnings) {
+                    tracing::warn!(event = "warning", path = %self.path, message = %warning);
+                }
+                for skipped in &report.skipped {
+                    tracing::warn!(event = "warning", path = %self.path, message = %skipped);
+                }
+                let bytes_written = std::fs::metadata(&self.path).map(|metadata| metadata.len()).unwrap_or(0);
+                tracing::info!(
+                    event = "metadata_written",
+                    path = %self.path,
+                    format = %self.format,
+                    fields_changed = %fields_changed.join(","),
+                    bytes_written,
+                    duration_ms,
+                );
+            }
+            Err(e) => tracing::error!(event = "error", path = %self.path, message = %e, duration_ms),
+        }
+        result
+    }
+
+    /// Body of `update_metadata`, recording the names of the fields it changes
+    fn write_updated(
+        &self,
+        options: &WriteOptions,
+        update: impl FnOnce(&mut Metadata) -> AudioResult<()>,
+        fields_changed: &mut Vec<String>,
+    ) -> AudioResult<WriteReport> {
+        if options.history && options.deterministic {
+            return Err(AudioFileError::ParseError(
+                "Edit history is time-stamped and can't be written deterministically".to_string()
+            ));
+        }
+        self.check_writable()?;
+
+        // The update sees the fields a trailing ID3v1 tag fills in, as reads do
+        let tag_metadata = self.read_tag_metadata()?;
+        let id3v1 = match self.format {
+            Format::Id3v2 => Id3v1Tag::read_from_file(&self.path)?,
+            _ => None,
+        };
+        let mut metadata = tag_metadata.clone();
+        if id3v1.is_some() {
+            metadata.merge(&self.read_id3v1_metadata()?.0, MergePolicy::FillEmpty);
+        }
+        let before = metadata.clone();
+        update(&mut metadata)?;
+        if let Some(policy) = options.genre_policy {
+            metadata.normalize_genres(policy)?;
+        }
+        if let (Some(policy), Some(cover)) = (options.cover_policy, metadata.cover.as_mut()) {
+            self.apply_cover_policy(cover, policy)?;
+        }
+        if options.auto_length {
+            metadata.length_ms = Some(self.get_properties()?.duration_ms);
+        }
+        let unwritable = self.unwritable_fields();
+        let (skipped, written): (Vec<String>, Vec<String>) = history::changed_fields(&before, &metadata)
+            .into_iter()
+            .partition(|field| unwritable.iter().any(|(name, _)| name == field));
+        let skipped: Vec<SkippedField> = skipped.into_iter()
+            .map(|field| {
+                let reason = unwritable.iter().find(|(name, _)| *name == field).map(|(_, reason)| reason.to_string());
+                SkippedField { field, reason: reason.unwrap_or_default() }
+            })
+            .collect();
+        if options.strict && !skipped.is_empty() {
+            let fields: Vec<String> = skipped.iter().map(|skipped| skipped.to_string()).collect();
+            return Err(AudioFileError::UnsupportedFormat(fields.join("; ")));
+        }
+        *fields_changed = written.clone();
+        let history = options.history.then(|| HistoryEntry::new(&before, &metadata));
+
+        // ID3v1 values the update left alone stay out of the ID3v2 tag, and the
+        // fields it cleared are cleared in the ID3v1 tag, or reads would fill them back in
+        let mut id3v1_cleared = Vec::new();
+        if id3v1.is_some() {
+            for field in ID3V1_FIELDS {
+                let changed = written.iter()
+                    .any(|name| name == field.as_str() || (field == StandardField::Genre && name == "genres"));
+                if !changed {
+                    metadata.copy_field_from(field, &tag_metadata);
+                } else if !metadata.has_field(field) {
+                    id3v1_cleared.push(field);
+                }
+            }
+        }
+
+        let mut report = handler::handler(self.format).write(self, &metadata, options, history.as_ref())?;
+        if let Some(mut tag) = id3v1.filter(|_| !id3v1_cleared.is_empty()) {
+            for field in id3v1_cleared {
+                match field {
+                    StandardField::Title => tag.title.clear(),
+                    StandardField::Artist => tag.artist.clear(),
+                    StandardField::Album => tag.album.clear(),
+                    StandardField::Year => tag.year.clear(),
+                    StandardField::Comment => tag.comment.clear(),
+                    StandardField::Track => tag.track = None,
+                    StandardField::Genre => {
+                        tag.genre = 255;
+                        if let Some(extended) = tag.extended.as_mut() {
+                            extended.genre.clear();
+                        }
+                    }
+                    StandardField::Lyrics | StandardField::Cover => {}
+                }
+            }
+            self.write_id3v1_tag(&tag)?;
+        }
+        report.written = written;
+        report.skipped = skipped;
+        Ok(report)
+    }
+
+    /// Metadata of the tag a write replaces, with the cover
+    ///
+    /// An ID3v2 tag is read alone: `read_metadata` fills its empty fields from
+    /// a trailing ID3v1 tag, and rewriting the tag would copy those into it.
+    fn read_tag_metadata(&self) -> AudioResult<Metadata> {
+        let mut metadata = match self.format {
+            Format::Id3v2 => self.read_id3v2_metadata()?.0,
+            _ => return self.read_metadata(),
+        };
+        metadata.cover = self.read_cover()?;
+        Ok(metadata)
+    }
+
+    /// `Metadata` fields the write path of this format has no place for, with the reason
+    ///
+    /// ID3v1 and APE tags can't be written at all, so they have no entries.
+    fn unwritable_fields(&self) -> &'static [(&'static str, &'static str)] {
+        match self.format {
+            Format::Mp4 => &[
+                ("length_ms", "MP4 tags have no stored-duration field"),
+                ("performers", "MP4 tags have no performer credits"),
+            ],
+            Format::OggVorbis | Format::Opus => &[
+                ("cover", "OGG and Opus pictures are not written from metadata; use copy_cover_from"),
+                ("itunes", "only MP4 files hold iTunes media atoms"),
+            ],
+            Format::Id3v2 | Format::Flac | Format::Id3v1 | Format::Ape | Format::Mp3 | Format::Dsf => &[
+                ("itunes", "only MP4 files hold iTunes media atoms"),
+            ],
+        }
+    }
+
+    /// Image types every player of this format is expected to show
+    fn allowed_cover_types(&self) -> &'static [&'static str] {
+        match self.format {
+            Format::Mp4 => &["image/jpeg", "image/png", "image/bmp"],
+            _ => &["image/jpeg", "image/png"],
+        }
+    }
+
+    /// Check a cover about to be written against `allowed_cover_types`
+    fn apply_cover_policy(&self, cover: &mut CoverArt, policy: CoverPolicy) -> AudioResult<()> {
+        let allowed = self.allowed_cover_types();
+        let kind = match utils::image::detect_mime_type(&cover.data) {
+            Some(mime) if utils::image::is_animated_png(&cover.data) => format!("animated {}", mime),
+            Some(mime) if allowed.contains(&mime) => return Ok(()),
+            Some(mime) => mime.to_string(),
+            None => "unrecognized image data".to_string(),
+        };
+
+        match policy {
+            CoverPolicy::Strict => Err(AudioFileError::UnsupportedFormat(format!(
+                "Cover is {}; {} covers must be one of: {}", kind, self.format, allowed.join(", ")
+            ))),
+            #[cfg(feature = "image")]
+            CoverPolicy::AutoConvert => {
+                cover.data = cover.thumbnail(u32::MAX)?;
+                cover.mime_type = Some("image/jpeg".to_string());
+                Ok(())
+            }
+            #[cfg(not(feature = "image"))]
+            CoverPolicy::AutoConvert => Err(AudioFileError::UnsupportedFormat(format!(
+                "Cover is {}; converting it requires the `image` feature", kind
+            ))),
+        }
+    }
+
+    /// Check the file's tags for problems that don't prevent reading
+    pub fn validate(&self) -> AudioResult<Vec<ValidationIssue>> {
+        let mut issues = Vec::new();
+
+        if let Some(size) = self.get_cover_art_size_bytes()?.filter(|size| *size > LARGE_COVER_BYTES) {
+            issues.push(ValidationIssue {
+                code: "large-cover".to_string(),
+                field: "cover".to_string(),
+                message: format!(
+                    "cover image is {:.1} MB; images over {} MB bloat the tag",
+                    size as f64 / (1024.0 * 1024.0),
+                    LARGE_COVER_BYTES / (1024 * 1024)
+                ),
+            });
+        }
+        if let Some(cover) = self.read_cover()? {
+            if utils::image::appears_truncated(&cover.data) {
+                issues.push(ValidationIssue {
+                    code: "truncated-cover".to_string(),
+                    field: "cover".to_string(),
+                    message: format!(
+                        "image data appears truncated ({} bytes, missing end-of-image marker)",
+                        cover.data.len()
+                    ),
+                });
+            }
+        }
+
+        if matches!(self.format, Format::Id3v2 | Format::Mp3) {
+            let header = id3::v2::Id3v2Header::read(&mut BufReader::new(File::open(&self.path)?))?;
+            if let Some(header) = header.filter(|header| header.is_experimental()) {
+                issues.push(ValidationIssue {
+                    code: "id3-experimental".to_string(),
+                    field: "id3v2".to_string(),
+                    message: format!(
+                        "ID3v2.{} tag has the experimental flag set; some players ignore such tags",
+                        header.version.0
+                    ),
+                });
+            }
+        }
+
+        if matches!(self.format, Format::Flac | Format::OggVorbis | Format::Opus) {
+            let (vendor_string, comments) = self.get_vorbis_comments()?;
+            let mut reported: Vec<&str> = Vec::new();
+            for (key, _) in &comments {
+                let canonical = flac::vorbis::canonical_key(key);
+                if *key != canonical && !reported.contains(&key.as_str()) {
+                    reported.push(key);
+                    issues.push(ValidationIssue {
+                        code: "noncanonical-vorbis-key".to_string(),
+                        field: key.clone(),
+                        message: format!("non-canonical Vorbis comment key, normally written \"{}\"", canonical),
+                    });
+                }
+            }
+            let mut comment = flac::vorbis::VorbisComment { vendor_string, comments };
+            for conflict in comment.normalize() {
+                issues.push(ValidationIssue {
+                    code: "vorbis-key-conflict".to_string(),
+                    field: "vorbis".to_string(),
+                    message: conflict,
+                });
+            }
+        }
+
+        for mismatch in self.compare_id3_tags()? {
+            issues.push(ValidationIssue {
+                code: "id3-mismatch".to_string(),
+                field: mismatch.field,
+                message: format!("ID3v1 has \"{}\" but ID3v2 has \"{}\"", mismatch.id3v1, mismatch.id3v2),
+            });
+        }
+
+        Ok(issues)
+    }
+
+    /// Describe each embedded picture whose stored details disagree with its image data
+    ///
+    /// Reports empty or unrecognized image data, a MIME type naming another
+    /// image format, FLAC/OGG picture dimensions that are zero or differ from
+    /// the image header, descriptions with control characters, and several
+    /// different pictures of the same type (other than "Other"). MP4 images
+    /// have no type, so they are never reported as conflicting.
+    pub fn validate_cover_art_consistency(&self) -> AudioResult<Vec<String>> {
+        let pictures = self.read_pictures()?;
+        let stores_dimensions = matches!(self.format, Format::Flac | Format::OggVorbis | Format::Opus);
+        let mut warnings = Vec::new();
+
+        for (index, picture) in pictures.iter().enumerate() {
+            let label = format!("picture {} [{}]", index + 1, picture.picture_type.to_string());
+            if picture.data.is_empty() {
+                warnings.push(format!("{}: image data is empty", label));
+                continue;
+            }
+            let Some(actual_mime) = utils::image::detect_mime_type(&picture.data) else {
+                warnings.push(format!("{}: image data is not a recognized image format", label));
+                continue;
+            };
+            let declared_mime = picture.mime_type.to_ascii_lowercase().replace("image/jpg", "image/jpeg");
+            if declared_mime != actual_mime {
+                warnings.push(format!("{}: MIME type is {} but the data is {}", label, picture.mime_type, actual_mime));
+            }
+            match utils::image::dimensions(&picture.data) {
+                Some((0, _) | (_, 0)) => warnings.push(format!("{}: image header gives zero dimensions", label)),
+                Some((width, height)) if stores_dimensions && (picture.width, picture.height) == (0, 0) => {
+                    warnings.push(format!("{}: dimensions are 0×0 but the image is {}×{}", label, width, height));
+                }
+                Some((width, height)) if stores_dimensions && (picture.width, picture.height) != (width, height) => {
+                    warnings.push(format!(
+                        "{}: dimensions are {}×{} but the image is {}×{}",
+                        label, picture.width, picture.height, width, height
+                    ));
+                }
+                _ => {}
+            }
+            if picture.description.chars().any(char::is_control) {
+                warnings.push(format!("{}: description {:?} contains non-printable characters", label, picture.description));
+            }
+        }
+
+        if self.format != Format::Mp4 {
+            let mut reported: Vec<flac::picture::PictureType> = Vec::new();
+            for picture in &pictures {
+                let picture_type = picture.picture_type;
+                if picture_type == flac::picture::PictureType::Other || reported.contains(&picture_type) {
+                    continue;
+                }
+                let same_type: Vec<&FlacPicture> = pictures.iter().filter(|other| other.picture_type == picture_type).collect();
+                if same_type.iter().any(|other| other.data != picture.data) {
+                    warnings.push(format!(
+                        "{} different [{}] pictures; players show only one",
+                        same_type.len(), picture_type.to_string()
+                    ));
+                    reported.push(picture_type);
+                }
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Raw genre byte of the file's ID3v1 tag, `None` if it has no ID3v1 tag
+    ///
+    /// Indexes the Winamp genre list; 255 means no genre. Values outside the
+    /// list aren't mapped to a `Metadata` genre, so this is the only way to see them.
+    pub fn get_id3v1_genre(&self) -> AudioResult<Option<u8>> {
+        Ok(Id3v1Tag::read_from_file(&self.path)?.map(|tag| tag.genre))
+    }
+
+    /// The TAG+ extended tag stored before the file's ID3v1 tag, if any
+    pub fn get_id3v1_extended(&self) -> AudioResult<Option<Id3v1Extended>> {
+        Ok(Id3v1Tag::read_from_file(&self.path)?.and_then(|tag| tag.extended))
+    }
+
+    /// Remove the TAG+ extended tag, keeping the standard ID3v1 tag
+    ///
+    /// Title, artist and album are cut to 30 characters; that is logged as a
+    /// warning. Returns whether there was an extended tag.
+    pub fn remove_id3v1_extended(&self) -> AudioResult<bool> {
+        let Some(mut tag) = Id3v1Tag::read_from_file(&self.path)? else {
+            return Ok(false);
+        };
+        if tag.extended.take().is_none() {
+            return Ok(false);
+        }
+        self.write_id3v1_tag(&tag)?;
+        Ok(true)
+    }
+
+    /// Compare the ID3v1 and ID3v2 tags of a file that has both
+    ///
+    /// Values that only differ because ID3v1 truncates them are not reported.
+    pub fn compare_id3_tags(&self) -> AudioResult<Vec<TagMismatch>> {
+        use id3::v1::{matches_truncated, COMMENT_WITH_TRACK_LEN, TEXT_FIELD_LEN, YEAR_LEN};
+
+        if self.format != Format::Id3v2 {
+            return Ok(Vec::new());
+        }
+        let v1 = match Id3v1Tag::read_from_file(&self.path)? {
+            Some(tag) => tag,
+            None => return Ok(Vec::new()),
+        };
+        let v2 = self.read_id3v2_metadata()?.0;
+
+        let comment_len = if v1.track.is_some() { COMMENT_WITH_TRACK_LEN } else { TEXT_FIELD_LEN };
+        let fields = [
+            (StandardField::Title, &v1.title, &v2.title, v1.text_len()),
+            (StandardField::Artist, &v1.artist, &v2.artist, v1.text_len()),
+            (StandardField::Album, &v1.album, &v2.album, v1.text_len()),
+            (StandardField::Year, &v1.year, &v2.year, YEAR_LEN),
+            (StandardField::Comment, &v1.comment, &v2.comment, comment_len),
+        ];
+
+        let mut mismatches = Vec::new();
+        for (field, v1_value, v2_value, max_len) in fields {
+            let v2_value = v2_value.as_deref().unwrap_or("");
+            if !matches_truncated(v1_value, v2_value, max_len) {
+                mismatches.push(TagMismatch {
+                    field: field.as_str().to_string(),
+                    id3v1: v1_value.clone(),
+                    id3v2: v2_value.to_string(),
+                });
+            }
+        }
+
+        // ID3v1.0 has no track number, so only compare when both tags carry one
+        let v2_track = v2.track.as_deref()
+            .and_then(|track| track.split('/').next())
+            .and_then(|number| number.trim().parse::<u8>().ok());
+        if let (Some(v1_track), Some(v2_track)) = (v1.track, v2_track) {
+            if v1_track != v2_track {
+                mismatches.push(TagMismatch {
+                    field: StandardField::Track.as_str().to_string(),
+                    id3v1: v1_track.to_string(),
+                    id3v2: v2.track.clone().unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Make the ID3v1 and ID3v2 tags agree, returning the fields that differed
+    pub fn resolve_id3_tags(&self, resolution: TagResolution) -> AudioResult<Vec<TagMismatch>> {
+        let mismatches = self.compare_id3_tags()?;
+        if mismatches.is_empty() {
+            return Ok(mismatches);
+        }
+
+        match resolution {
+            TagResolution::V2Wins => {
+                let v2 = self.read_id3v2_metadata()?.0;
+                let mut v1 = Id3v1Tag::read_from_file(&self.path)?.unwrap_or_default();
+                v1.title = v2.title.unwrap_or_default();
+                v1.artist = v2.artist.unwrap_or_default();
+                v1.album = v2.album.unwrap_or_default();
+                v1.year = v2.year.unwrap_or_default();
+                v1.comment = v2.comment.unwrap_or_default();
+                v1.track = v2.track.as_deref()
+                    .and_then(|track| track.split('/').next())
+                    .and_then(|number| number.trim().parse().ok())
+                    .or(v1.track);
+                self.write_id3v1_tag(&v1)?;
+            }
+            TagResolution::V1Wins => {
+                let mut metadata = self.read_tag_metadata()?;
+                for mismatch in &mismatches {
+                    let field = StandardField::from_str(&mismatch.field)
+                        .and_then(|field| metadata.text_field_mut(field));
+                    if let Some(field) = field {
+                        *field = Some(mismatch.id3v1.clone()).filter(|value| !value.is_empty());
+                    }
+                }
+                self.write_id3v2_metadata(&metadata, &WriteOptions::default(), &[], None)?;
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Shrink the file by cleaning up tag overhead
+    ///
+    /// ID3v2: removes duplicate frames and resets padding to 512 bytes; an
+    /// ID3v2.2 tag is upgraded to ID3v2.3, dropping frames with no equivalent.
+    /// FLAC: merges PADDING blocks into one (at most 4096 bytes) and removes
+    /// APPLICATION blocks with unregistered IDs. OGG/Opus: removes empty
+    /// Vorbis comment entries. The file is only rewritten if something changed.
+    pub fn optimize(&self) -> AudioResult<OptimizeReport> {
+        match self.format {
+            Format::Mp3 if self.read_id3v2_tag()?.is_none() => Ok(OptimizeReport::default()),
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => self.optimize_id3v2(),
+            Format::Flac => self.optimize_flac(),
+            Format::OggVorbis | Format::Opus => self.optimize_vorbis_comments(),
+            Format::Id3v1 | Format::Mp4 | Format::Ape => Err(AudioFileError::UnsupportedFormat(
+                format!("Optimizing {} files is not supported", self.format)
+            )),
+        }
+    }
+
+    /// Read the iTunes Sound Check values (ID3v2 COMM:iTunNORM, MP4 ----:iTunNORM)
+    pub fn get_sound_check(&self) -> AudioResult<Option<SoundCheck>> {
+        let text = match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let tag = match self.read_id3v2_tag()? {
+                    Some(tag) => tag,
+                    None => return Ok(None),
+                };
+                tag.frames.iter()
+                    .filter(|frame| frame.frame_id == "COMM")
+                    .filter_map(|frame| id3::frames::decode_uslt_frame(&frame.data))
+                    .find(|(_, description, _)| description == soundcheck::ITUNNORM)
+                    .map(|(_, _, text)| text)
+            }
+            Format::Mp4 => Mp4File::new(self.path.clone()).read_metadata()?.and_then(|meta| meta.itunnorm),
+            Format::Id3v1 | Format::Flac | Format::OggVorbis | Format::Opus | Format::Ape => None,
+        };
+
+        match text {
+            Some(text) => SoundCheck::parse(&text).map(Some).ok_or_else(|| {
+                AudioFileError::ParseError(format!("Invalid iTunNORM value: {:?}", text))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Store iTunes Sound Check values, replacing any existing ones
+    pub fn set_sound_check(&self, sound_check: &SoundCheck) -> AudioResult<()> {
+        let text = sound_check.to_itunnorm();
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let metadata = self.read_tag_metadata()?;
+                let described = [("COMM", soundcheck::ITUNNORM, text)];
+                self.write_id3v2_metadata(&metadata, &WriteOptions::default(), &described, None)?;
+                Ok(())
+            }
+            Format::Mp4 => {
+                let mp4_file = Mp4File::new(self.path.clone());
+                let mut meta = mp4_file.read_metadata()?.unwrap_or_default();
+                meta.itunnorm = Some(text);
+                self.guarded_write(|| Ok(mp4_file.write_metadata(&meta)?))
+            }
+            Format::Id3v1 | Format::Flac | Format::OggVorbis | Format::Opus | Format::Ape => {
+                Err(AudioFileError::UnsupportedFormat(
+                    format!("Sound Check values cannot be stored in {} files", self.format)
+                ))
+            }
+        }
+    }
+
+    /// Copy audio properties from another encoding of the same recording
+    ///
+    /// ID3v2: writes the source duration to TLEN, its encoder (if tagged) to
+    /// TSSE, and sample rate, channels and bit depth to TXXX frames. FLAC:
+    /// copies the source STREAMINFO block, but only when the source is a FLAC
+    /// file whose audio frames are byte-identical to this file's.
+    pub fn clone_audio_properties_from(&self, source_path: &str) -> AudioResult<()> {
+        let source = AudioFile::new(source_path.to_string())?;
+
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let properties = source.get_properties()?;
+                let mut metadata = self.read_tag_metadata()?;
+                metadata.length_ms = Some(properties.duration_ms);
+                if let Some(encoder) = source.read_metadata()?.encoder {
+                    metadata.encoder = Some(encoder);
+                }
+
+                let user_text: Vec<(&str, &str, String)> = [
+                    ("SAMPLERATE", properties.sample_rate.map(|rate| rate.to_string())),
+                    ("CHANNELS", properties.channels.map(|channels| channels.to_string())),
+                    ("BITSPERSAMPLE", properties.bits_per_sample.map(|bits| bits.to_string())),
+                ].into_iter()
+                    .filter_map(|(description, value)| Some(("TXXX", description, value?)))
+                    .collect();
+                self.write_id3v2_metadata(&metadata, &WriteOptions::default(), &user_text, None)?;
+                Ok(())
+            }
+            Format::Flac => {
+                if source.format != Format::Flac {
+                    return Err(AudioFileError::UnsupportedFormat(format!(
+                        "STREAMINFO can only be copied from a FLAC file, not {}", source.format
+                    )));
+                }
+                if source.audio_checksum()? != self.audio_checksum()? {
+                    return Err(AudioFileError::AudioIntegrityError(format!(
+                        "audio of {} differs from {}; not copying STREAMINFO", source_path, self.path
+                    )));
+                }
+                self.copy_streaminfo_from(&source)
+            }
+            Format::Id3v1 | Format::OggVorbis | Format::Opus | Format::Mp4 | Format::Ape => {
+                Err(AudioFileError::UnsupportedFormat(
+                    format!("Cloning audio properties into {} files is not supported", self.format)
+                ))
+            }
+        }
+    }
+
+    /// Get metadata as TOML string
+    #[cfg(feature = "toml-output")]
+    pub fn get_metadata_as_toml(&self) -> AudioResult<String> {
+        let metadata = self.read_metadata()?;
+        toml::to_string(&metadata)
+            .map_err(|e| AudioFileError::ParseError(e.to_string()))
+    }
+
+    /// Set metadata from TOML string
+    #[cfg(feature = "toml-output")]
+    pub fn set_metadata_from_toml(&self, toml_str: &str) -> AudioResult<()> {
+        let metadata: Metadata = toml::from_str(toml_str)
+            .map_err(|e| AudioFileError::ParseError(e.to_string()))?;
+        let metadata_json = serde_json::to_string(&metadata)
+            .map_err(|e| AudioFileError::ParseError(e.to_string()))?;
+        self.set_metadata(metadata_json)
+    }
+
+    /// Get the (offset, length) in bytes of the audio stream, excluding all tags
+    ///
+    /// MP3: after the ID3v2 tag (including padding) and before any APE/ID3v1 tag.
+    /// FLAC: after the last metadata block. OGG/Opus: the whole file.
+    /// MP4: the payload of the `mdat` atom.
+    pub fn get_audio_span(&self) -> AudioResult<(u64, u64)> {
+        self.compute_audio_span()
+    }
+
+    /// Bytes spent on metadata and on audio: `(metadata_bytes, audio_bytes)`
+    ///
+    /// The two add up to the file size. Metadata is everything outside
+    /// `get_audio_span`: the ID3v2 tag plus any trailing APE/ID3v1 tag for
+    /// MP3, all metadata blocks for FLAC, every atom but `mdat` for MP4. As
+    /// OGG/Opus audio spans the whole file, the header pages (identification,
+    /// comments and setup, all with granule position 0) are counted instead.
+    pub fn tag_overhead(&self) -> AudioResult<(u64, u64)> {
+        let file_len = std::fs::metadata(&self.path)?.len();
+        let metadata_bytes = match self.format {
+            Format::OggVorbis | Format::Opus => self.ogg_header_pages_len()?.min(file_len),
+            _ => file_len - self.compute_audio_span()?.1,
+        };
+        Ok((metadata_bytes, file_len - metadata_bytes))
+    }
+
+    /// Length of the OGG pages before the first one carrying audio
+    fn ogg_header_pages_len(&self) -> AudioResult<u64> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut len = 0;
+        while let Some(header) = ogg::page::OggPageHeader::read(&mut reader) {
+            if header.granule_position != 0 {
+                break;
+            }
+            let data_size = header.get_data_size() as u64;
+            reader.seek(std::io::SeekFrom::Current(data_size as i64))?;
+            len += 27 + header.segment_table.len() as u64 + data_size;
+        }
+        Ok(len)
+    }
+
+    /// IDs of every frame, block, comment or item in the tag, without decoding values
+    ///
+    /// ID3v2: frame IDs as stored, repeats included (three characters in
+    /// ID3v2.2); frame data is seeked past. FLAC: block type names as in
+    /// `get_all_metadata_blocks`. OGG Vorbis and Opus: Vorbis comment keys.
+    /// MP4: ilst item types, e.g. "©nam", or "----" for freeform items. APE:
+    /// item keys. ID3v1 tags have no frames.
+    pub fn get_all_frame_ids(&self) -> AudioResult<Vec<String>> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let mut reader = BufReader::new(File::open(&self.path)?);
+                let mut ids = Vec::new();
+                if self.seek_id3v2_tag(&mut reader)? {
+                    if let Some(mut frames) = Id3v2Tag::frames(reader)? {
+                        frames.next_frame_where(|id| {
+                            ids.push(id.to_string());
+                            false
+                        })?;
+                    }
+                }
+                Ok(ids)
+            }
+            Format::Flac => Ok(self.get_all_metadata_blocks()?.into_iter().map(|block| block.block_type).collect()),
+            Format::OggVorbis | Format::Opus => {
+                Ok(self.get_vorbis_comments()?.1.into_iter().map(|(key, _)| key).collect())
+            }
+            Format::Mp4 => Ok(Mp4File::new(self.path.clone()).read_item_types()?),
+            Format::Ape => Ok(ApeFile::new(self.path.clone()).read_item_keys()?),
+            Format::Id3v1 => Ok(Vec::new()),
+        }
+    }
+
+    /// List FLAC metadata blocks by reading only their 4-byte headers
+    pub fn get_all_metadata_blocks(&self) -> AudioResult<Vec<MetadataBlockInfo>> {
+        if self.format != Format::Flac {
+            return Err(AudioFileError::UnsupportedFormat(
+                format!("Metadata blocks are only available for FLAC files, not {}", self.format)
+            ));
+        }
+
+        let file = File::open(&self.path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        reader.seek(std::io::SeekFrom::Start(FLAC_SIGNATURE.len() as u64))?;
+
+        let mut blocks = Vec::new();
+        let mut offset = FLAC_SIGNATURE.len() as u64;
+
+        while offset + 4 <= file_len {
+            let header = flac::metadata::FlacMetadataBlockHeader::read(&mut reader)?;
+            let block_type = match header.type_code {
+                0 => "STREAMINFO".to_string(),
+                1 => "PADDING".to_string(),
+                2 => "APPLICATION".to_string(),
+                3 => "SEEKTABLE".to_string(),
+                4 => "VORBIS_COMMENT".to_string(),
+                5 => "CUESHEET".to_string(),
+                6 => "PICTURE".to_string(),
+                127 => "INVALID".to_string(),
+                code => format!("UNKNOWN({})", code),
+            };
+            blocks.push(MetadataBlockInfo {
+                block_type,
+                size_bytes: header.length as usize,
+                is_last: header.is_last,
+                offset: offset as usize,
+            });
+
+            offset += 4 + header.length as u64;
+            if header.is_last {
+                break;
+            }
+            reader.seek(std::io::SeekFrom::Current(header.length as i64))?;
+        }
+
+        Ok(blocks)
+    }
+
+    /// Check whether the file has an embedded CUESHEET block (FLAC only)
+    pub fn has_embedded_cue(&self) -> AudioResult<bool> {
+        Ok(self.read_cue_sheet()?.is_some())
+    }
+
+    /// Get the tracks of the embedded cue sheet, excluding the lead-out
+    pub fn get_cue_tracks(&self) -> AudioResult<Vec<CueTrack>> {
+        Ok(self.read_cue_sheet()?.map(|cue| cue.tracks).unwrap_or_default())
+    }
+
+    /// Get the start of each track of the embedded cue sheet as sample and time
+    ///
+    /// Times use the STREAMINFO sample rate. Empty without a cue sheet.
+    pub fn get_track_points(&self) -> AudioResult<Vec<TrackPoint>> {
+        let Some(cue) = self.read_cue_sheet()? else {
+            return Ok(Vec::new());
+        };
+        let sample_rate = self.read_flac_properties()?
+            .and_then(|properties| properties.sample_rate)
+            .filter(|&rate| rate > 0)
+            .ok_or_else(|| AudioFileError::ParseError("STREAMINFO has no sample rate".to_string()))?;
+        Ok(cue.track_points(sample_rate))
+    }
+
+    /// Get the edit history recorded by writes with `WriteOptions::history`, oldest first
+    ///
+    /// Empty for files without a history and for formats that can't hold one.
+    pub fn get_edit_history(&self) -> AudioResult<Vec<HistoryEntry>> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let Some(tag) = self.read_id3v2_tag()? else {
+                    return Ok(Vec::new());
+                };
+                let history = tag.frames.iter()
+                    .filter(|frame| frame.frame_id == "PRIV")
+                    .filter_map(|frame| id3::frames::decode_priv_frame(&frame.data))
+                    .find(|(owner, _)| owner == history::ID3_OWNER)
+                    .map(|(_, data)| history::parse(data));
+                Ok(history.unwrap_or_default())
+            }
+            Format::Flac => {
+                for block in self.flac_blocks()?.map_while(Result::ok) {
+                    if let FlacBlockView::Application { id, data } = block {
+                        if id == *history::FLAC_APPLICATION_ID {
+                            return Ok(history::parse(&data));
+                        }
+                    }
+                }
+                Ok(Vec::new())
+            }
+            Format::Id3v1 | Format::OggVorbis | Format::Opus | Format::Mp4 | Format::Ape => Ok(Vec::new()),
+        }
+    }
+
+    /// Get the vendor string and every comment exactly as stored
+    ///
+    /// Unlike `read_metadata`, keys keep their case and duplicates and order
+    /// are preserved. Only FLAC, OGG Vorbis and Opus files have Vorbis comments;
+    /// a file without a comment block gives an empty vendor and no comments.
+    pub fn get_vorbis_comments(&self) -> AudioResult<(String, Vec<(String, String)>)> {
+        let comment = match self.format {
+            Format::Flac => self.read_flac_comment()?,
+            Format::OggVorbis => OggVorbisFile::new(self.path.clone()).read_comment()?,
+            Format::Opus => OpusFile::new(self.path.clone()).read_comment()?,
+            Format::Id3v2 | Format::Id3v1 | Format::Mp4 | Format::Ape | Format::Mp3 | Format::Dsf => {
+                return Err(AudioFileError::UnsupportedFormat(format!("{} files have no Vorbis comments", self.format)));
+            }
+        };
+        let comment = comment.unwrap_or_default();
+        Ok((comment.vendor_string, comment.comments))
+    }
+
+    /// Get user-defined fields that `Metadata` doesn't cover, as (key, value) pairs
+    ///
+    /// ID3v2: TXXX frames by description. FLAC, OGG Vorbis and Opus: comments
+    /// whose key isn't mapped to a `Metadata` field. Other formats have none.
+    pub fn get_extra_fields(&self) -> AudioResult<Vec<(String, String)>> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let Some(tag) = self.read_id3v2_tag()? else {
+                    return Ok(Vec::new());
+                };
+                Ok(tag.frames.iter()
+                    .filter(|frame| frame.frame_id == "TXXX")
+                    .filter_map(|frame| id3::frames::decode_txxx_frame(&frame.data))
+                    .filter(|(description, _)| !description.eq_ignore_ascii_case(ENCODER_SETTINGS_TXXX))
+                    .collect())
+            }
+            Format::Flac | Format::OggVorbis | Format::Opus => {
+                let (_, comments) = self.get_vorbis_comments()?;
+                Ok(comments.into_iter()
+                    .filter(|(key, _)| !is_managed_vorbis_field(key))
+                    .collect())
+            }
+            Format::Id3v1 | Format::Mp4 | Format::Ape => Ok(Vec::new()),
+        }
+    }
+
+    /// Set user-defined fields, replacing any with the same key; an empty value removes the field
+    ///
+    /// Standard fields are left alone. Keys mapped to a `Metadata` field
+    /// (including the ID3v2 ENCODERSETTINGS TXXX frame) are rejected; use
+    /// `set_metadata` for those.
+    pub fn set_extra_fields(&self, fields: &[(String, String)]) -> AudioResult<()> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                if let Some((key, _)) = fields.iter().find(|(key, _)| key.eq_ignore_ascii_case(ENCODER_SETTINGS_TXXX)) {
+                    return Err(AudioFileError::ParseError(format!("'{}' can't be set as an extra field", key)));
+                }
+                let described: Vec<(&str, &str, String)> = fields.iter()
+                    .map(|(key, value)| ("TXXX", key.as_str(), value.clone()))
+                    .collect();
+                self.write_id3v2_metadata(&self.read_tag_metadata()?, &WriteOptions::default(), &described, None)?;
+                Ok(())
+            }
+            Format::Flac | Format::OggVorbis | Format::Opus => {
+                if let Some((key, _)) = fields.iter().find(|(key, _)| is_managed_vorbis_field(key) || key.contains('=')) {
+                    return Err(AudioFileError::ParseError(format!("'{}' can't be set as an extra field", key)));
+                }
+                let metadata = self.read_metadata()?;
+                if self.format == Format::Flac {
+                    self.write_flac_metadata(&metadata, &WriteOptions::default(), fields, None)?;
+                } else {
+                    self.write_ogg_metadata(&metadata, &WriteOptions::default(), fields)?;
+                }
+                Ok(())
+            }
+            Format::Id3v1 | Format::Mp4 | Format::Ape => Err(AudioFileError::UnsupportedFormat(
+                format!("Extra fields cannot be stored in {} files", self.format)
+            )),
+        }
+    }
+
+    /// Text of the ID3v2 COMM frame with this language and description
+    ///
+    /// `language` may be a 2- or 3-letter code ("en" finds "eng"); the
+    /// description is matched case-insensitively, "" being the main comment.
+    pub fn get_id3v2_comment(&self, language: &str, description: &str) -> AudioResult<Option<String>> {
+        self.find_described_id3v2_frame("COMM", Some(language), description)
+    }
+
+    /// Text of the ID3v2 USLT frame with this language and description
+    pub fn get_id3v2_lyrics(&self, language: &str, description: &str) -> AudioResult<Option<String>> {
+        self.find_described_id3v2_frame("USLT", Some(language), description)
+    }
+
+    /// The lyrics `read_metadata` reports, as stored and without decoding
+    ///
+    /// ID3v2: the USLT frame data after the encoding byte and language, i.e.
+    /// the description, its terminator and the text in the frame's encoding.
+    /// FLAC, OGG Vorbis and Opus: the UTF-8 bytes of the LYRICS comment. MP4
+    /// and APE store lyrics as UTF-8 text, which is returned encoded as such.
+    pub fn get_lyrics_raw(&self) -> AudioResult<Option<Vec<u8>>> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => Ok(self.read_id3v2_tag()?.and_then(|tag| {
+                tag.frames.into_iter()
+                    .filter(|frame| frame.frame_id == "USLT")
+                    .find(|frame| {
+                        id3::frames::decode_uslt_frame(&frame.data).is_some_and(|(_, _, text)| !text.is_empty())
+                    })
+                    .map(|frame| frame.data[4..].to_vec())
+            })),
+            Format::Flac | Format::OggVorbis | Format::Opus => {
+                let (_, comments) = self.get_vorbis_comments()?;
+                Ok(comments.into_iter()
+                    .find(|(key, value)| flac::vorbis::canonical_key(key) == "LYRICS" && !value.is_empty())
+                    .map(|(_, value)| value.into_bytes()))
+            }
+            Format::Mp4 | Format::Ape => Ok(self.read_metadata()?.lyrics.map(String::into_bytes)),
+            Format::Id3v1 => Ok(None),
+        }
+    }
+
+    /// Value of the ID3v2 TXXX frame with this description, matched case-insensitively
+    pub fn get_id3v2_user_text_by_description(&self, description: &str) -> AudioResult<Option<String>> {
+        self.find_described_id3v2_frame("TXXX", None, description)
+    }
+
+    /// Text of the first `frame_id` frame (COMM, USLT or TXXX) with a matching language and description
+    fn find_described_id3v2_frame(
+        &self,
+        frame_id: &str,
+        language: Option<&str>,
+        description: &str,
+    ) -> AudioResult<Option<String>> {
+        use id3::frames::{decode_txxx_frame, decode_uslt_frame};
+
+        if !matches!(self.format, Format::Id3v2 | Format::Mp3 | Format::Dsf) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no ID3v2 tag", self.format)));
+        }
+        let language = language.map(|code| utils::language::normalize(code).ok_or_else(|| {
+            AudioFileError::ParseError(format!("Invalid ISO-639-2 language code: {:?}", code))
+        })).transpose()?;
+        let Some(tag) = self.read_id3v2_tag()? else {
+            return Ok(None);
+        };
+
+        Ok(tag.frames.iter()
+            .filter(|frame| frame.frame_id == frame_id)
+            .find_map(|frame| {
+                let (frame_language, frame_description, text) = if frame_id == "TXXX" {
+                    let (frame_description, text) = decode_txxx_frame(&frame.data)?;
+                    (None, frame_description, text)
+                } else {
+                    let (frame_language, frame_description, text) = decode_uslt_frame(&frame.data)?;
+                    (Some(frame_language), frame_description, text)
+                };
+                let language_matches = match (language, frame_language) {
+                    (Some(language), Some(frame_language)) => {
+                        utils::language::normalize(&frame_language).unwrap_or(&frame_language).eq_ignore_ascii_case(language)
+                    }
+                    _ => true,
+                };
+                (language_matches && frame_description.eq_ignore_ascii_case(description))
+                    .then(|| text.trim_end_matches('\0').to_string())
+            }))
+    }
+
+    /// The metadata as `MusicBrainzFields`, with the fields `Metadata` lacks read from the tags
+    ///
+    /// ID3v2: TPOS, TSRC, the TXXX "MusicBrainz Album Id" frame and the UFID
+    /// frame owned by "http://musicbrainz.org". Vorbis comments: DISCNUMBER,
+    /// DISCTOTAL (or TOTALDISCS), ISRC, MUSICBRAINZ_TRACKID and
+    /// MUSICBRAINZ_ALBUMID. Other formats only have the `Metadata` fields.
+    pub fn read_musicbrainz_fields(&self) -> AudioResult<MusicBrainzFields> {
+        let mut fields = self.read_metadata_with_options(&ReadOptions { include_cover: false, ..Default::default() })?
+            .to_musicbrainz();
+        let text = |value: String| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let frames = self.read_id3v2_tag()?.map(|tag| tag.frames).unwrap_or_default();
+                for frame in &frames {
+                    match frame.frame_id.as_str() {
+                        "TPOS" if fields.discnumber.is_none() => {
+                            let position = Self::decode_text_frame(&frame.data).unwrap_or_default();
+                            (fields.discnumber, fields.totaldiscs) = ValueConverter::parse_position(&position);
+                        }
+                        "TSRC" if fields.isrc.is_none() => {
+                            fields.isrc = Self::decode_text_frame(&frame.data).and_then(text);
+                        }
+                        "TXXX" if fields.musicbrainz_albumid.is_none() => {
+                            if let Some((description, value)) = id3::frames::decode_txxx_frame(&frame.data) {
+                                if description.eq_ignore_ascii_case("MusicBrainz Album Id") {
+                                    fields.musicbrainz_albumid = text(value);
+                                }
+                            }
+                        }
+                        "UFID" if fields.musicbrainz_trackid.is_none() => {
+                            if let Some((owner, identifier)) = id3::frames::decode_ufid_frame(&frame.data) {
+                                if owner == "http://musicbrainz.org" {
+                                    fields.musicbrainz_trackid = text(String::from_utf8_lossy(&identifier).into_owned());
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Format::Flac | Format::OggVorbis | Format::Opus => {
+                let (_, comments) = self.get_vorbis_comments()?;
+                let first = |keys: &[&str]| comments.iter()
+                    .find(|(key, _)| keys.iter().any(|wanted| key.eq_ignore_ascii_case(wanted)))
+                    .and_then(|(_, value)| text(value.clone()));
+                if let Some(disc) = first(&["DISCNUMBER"]) {
+                    (fields.discnumber, fields.totaldiscs) = ValueConverter::parse_position(&disc);
+                }
+                if let Some(total) = first(&["DISCTOTAL", "TOTALDISCS"]) {
+                    fields.totaldiscs = total.parse().ok();
+                }
+                fields.isrc = first(&["ISRC"]);
+                fields.musicbrainz_trackid = first(&["MUSICBRAINZ_TRACKID"]);
+                fields.musicbrainz_albumid = first(&["MUSICBRAINZ_ALBUMID"]);
+            }
+            Format::Id3v1 | Format::Mp4 | Format::Ape => {}
+        }
+        Ok(fields)
+    }
+
+    /// Owner and identifier of the first ID3v2 UFID (unique file identifier) frame
+    ///
+    /// MusicBrainz Picard writes one with the owner "http://musicbrainz.org"
+    /// and the recording ID as identifier.
+    pub fn get_id3v2_unique_file_id(&self) -> AudioResult<Option<(String, Vec<u8>)>> {
+        if !matches!(self.format, Format::Id3v2 | Format::Mp3 | Format::Dsf) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no ID3v2 tag", self.format)));
+        }
+        let frames = self.read_id3v2_tag()?.map(|tag| tag.frames).unwrap_or_default();
+        Ok(frames.iter()
+            .filter(|frame| frame.frame_id == "UFID")
+            .find_map(|frame| id3::frames::decode_ufid_frame(&frame.data)))
+    }
+
+    /// Set the ID3v2 UFID frame of `owner`, replacing any with the same owner
+    ///
+    /// UFID frames of other owners are kept. The owner must not be empty and
+    /// the identifier may be at most 64 bytes.
+    pub fn set_id3v2_unique_file_id(&self, owner: &str, identifier: &[u8]) -> AudioResult<()> {
+        use id3::frames::{decode_ufid_frame, encode_ufid_frame};
+
+        if !matches!(self.format, Format::Id3v2 | Format::Mp3 | Format::Dsf) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no ID3v2 tag", self.format)));
+        }
+        if owner.is_empty() {
+            return Err(AudioFileError::ParseError("UFID owner must not be empty".to_string()));
+        }
+        if identifier.len() > 64 {
+            return Err(AudioFileError::ParseError(format!(
+                "UFID identifier is {} bytes, at most 64 are allowed", identifier.len()
+            )));
+        }
+
+        self.rewrite_id3v2_frames(|frames| {
+            frames.retain(|frame| {
+                frame.frame_id != "UFID" || decode_ufid_frame(&frame.data).is_none_or(|(existing, _)| existing != owner)
+            });
+            frames.push(id3::v2::Id3Frame::new("UFID", encode_ufid_frame(owner, identifier)));
+        })
+    }
+
+    /// Experimental ID3v2 frames, those whose ID starts with X, Y or Z, as (frame ID, value) pairs
+    ///
+    /// Taggers use them for non-standard fields such as XSOP (artist sort
+    /// order). Frames starting with a valid text encoding byte are decoded
+    /// as text frames; others as UTF-8, falling back to Windows-1252.
+    pub fn list_id3v2_custom_frames(&self) -> AudioResult<Vec<(String, String)>> {
+        if !matches!(self.format, Format::Id3v2 | Format::Mp3 | Format::Dsf) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no ID3v2 tag", self.format)));
+        }
+        let frames = self.read_id3v2_tag()?.map(|tag| tag.frames).unwrap_or_default();
+        Ok(frames.iter()
+            .filter(|frame| is_custom_id3v2_frame_id(&frame.frame_id))
+            .map(|frame| {
+                let value = Self::decode_text_frame(&frame.data).unwrap_or_else(|| match std::str::from_utf8(&frame.data) {
+                    Ok(text) => text.trim_end_matches('\0').to_string(),
+                    Err(_) => encoding_rs::WINDOWS_1252.decode(&frame.data).0.trim_end_matches('\0').to_string(),
+                });
+                (frame.frame_id.clone(), value)
+            })
+            .collect())
+    }
+
+    /// Set an experimental ID3v2 frame as a text frame, replacing any with the same ID
+    ///
+    /// The ID must be four uppercase letters or digits starting with X, Y or
+    /// Z. An empty value removes the frame.
+    pub fn set_id3v2_custom_frame(&self, frame_id: &str, value: &str) -> AudioResult<()> {
+        use id3::convert::encoding_for;
+        use id3::frames::encode_text_frame;
+
+        if !matches!(self.format, Format::Id3v2 | Format::Mp3 | Format::Dsf) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no ID3v2 tag", self.format)));
+        }
+        if !is_custom_id3v2_frame_id(frame_id) {
+            return Err(AudioFileError::ParseError(format!(
+                "{:?} is not a custom ID3v2 frame ID; expected four uppercase letters or digits starting with X, Y or Z",
+                frame_id
+            )));
+        }
+
+        self.rewrite_id3v2_frames(|frames| {
+            frames.retain(|frame| frame.frame_id != frame_id);
+            if !value.is_empty() {
+                // Latin-1 or UTF-16 are valid in both ID3v2.3 and ID3v2.4
+                let data = encode_text_frame(value, encoding_for(value, 3));
+                frames.push(id3::v2::Id3Frame::new(frame_id, data));
+            }
+        })
+    }
+
+    /// Get the APPLICATION blocks of a FLAC file as (application ID, data) pairs
+    ///
+    /// The ID is the block's 4-byte registered application ID and the data
+    /// follows it. oxidant's own edit history block (`oxhi`) is included.
+    pub fn get_flac_applications(&self) -> AudioResult<Vec<(String, Vec<u8>)>> {
+        if self.format != Format::Flac {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no APPLICATION blocks", self.format)));
+        }
+        Ok(self.flac_blocks()?
+            .map_while(Result::ok)
+            .filter_map(|block| match block {
+                FlacBlockView::Application { id, data } => Some((String::from_utf8_lossy(&id).to_string(), data)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// MD5 signature of the decoded audio stored in FLAC STREAMINFO, as lowercase hex
+    ///
+    /// The encoder computes it over the unencoded samples, so decoding the
+    /// file and hashing the result verifies a rip. Returns `None` when the
+    /// encoder left it unset (all zeros).
+    pub fn get_flac_audio_md5(&self) -> AudioResult<Option<String>> {
+        if self.format != Format::Flac {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no STREAMINFO block", self.format)));
+        }
+        for block in self.flac_blocks()?.map_while(Result::ok) {
+            if let FlacBlockView::StreamInfo(data) = block {
+                // STREAMINFO is 34 bytes and ends with the 16-byte signature
+                let Some(md5) = data.get(18..34) else {
+                    return Err(AudioFileError::ParseError("STREAMINFO block is too short".to_string()));
+                };
+                if md5.iter().all(|&byte| byte == 0) {
+                    return Ok(None);
+                }
+                return Ok(Some(md5.iter().map(|byte| format!("{:02x}", byte)).collect()));
+            }
+        }
+        Err(AudioFileError::ParseError("FLAC file has no STREAMINFO block".to_string()))
+    }
+
+    /// Stored audio MD5 like `get_flac_audio_md5`, but `None` for non-FLAC files
+    ///
+    /// Nothing is hashed: the value is the one the encoder wrote, which
+    /// only FLAC files carry. Suited to scanning mixed collections.
+    pub fn compute_audio_md5(&self) -> AudioResult<Option<String>> {
+        match self.format {
+            Format::Flac => self.get_flac_audio_md5(),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decode the audio with the `flac` tool and check it against the STREAMINFO MD5
+    ///
+    /// `None` when the encoder left the MD5 unset, in which case nothing is
+    /// decoded. `flac` must be on the PATH; without it this fails with
+    /// `ToolNotFound`. A file it can't decode gives `AudioIntegrityError`.
+    #[cfg(feature = "verify-audio")]
+    pub fn get_flac_md5_matches_audio(&self) -> AudioResult<Option<bool>> {
+        use std::process::{Command, Stdio};
+
+        let Some(expected) = self.get_flac_audio_md5()? else {
+            return Ok(None);
+        };
+        // Raw output is interleaved signed little-endian samples, the layout the MD5 covers
+        let mut child = Command::new("flac")
+            .args(["--decode", "--stdout", "--silent", "--force-raw-format", "--endian=little", "--sign=signed"])
+            .arg(&self.path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => AudioFileError::ToolNotFound("flac".to_string()),
+                _ => AudioFileError::IoError(e),
+            })?;
+
+        let mut md5 = utils::md5::Md5::new();
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = stdout.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            md5.update(&buffer[..read]);
+        }
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            // flac checks the MD5 itself and fails the decode on a mismatch
+            if stderr.contains("MD5 signature mismatch") {
+                return Ok(Some(false));
+            }
+            return Err(AudioFileError::AudioIntegrityError(format!(
+                "flac could not decode the audio: {}",
+                stderr.trim()
+            )));
+        }
+
+        let actual: String = md5.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(Some(actual == expected))
+    }
+
+    /// Bitstream serial numbers of the OGG pages, sorted and without repeats
+    ///
+    /// A plain Vorbis or Opus file has exactly one; more than one in such a
+    /// file usually means damaged pages (see `rewrite_ogg_serial_numbers`).
+    pub fn get_ogg_serial_numbers(&self) -> AudioResult<Vec<u32>> {
+        if !matches!(self.format, Format::OggVorbis | Format::Opus) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no OGG pages", self.format)));
+        }
+        let data = std::fs::read(&self.path)?;
+        let mut serials: Vec<u32> = ogg::page::page_ranges(&data).into_iter()
+            .map(|range| {
+                let offset = range.start + ogg::page::SERIAL_OFFSET;
+                u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+            })
+            .collect();
+        serials.sort_unstable();
+        serials.dedup();
+        Ok(serials)
+    }
+
+    /// Set the bitstream serial number of every OGG page to `new_serial`
+    ///
+    /// A repair for files whose pages disagree on the serial number. Each
+    /// page's CRC is recomputed; nothing else changes. Every page is treated
+    /// as part of one logical stream, so this must not be used on files
+    /// multiplexing several streams.
+    pub fn rewrite_ogg_serial_numbers(&self, new_serial: u32) -> AudioResult<()> {
+        if !matches!(self.format, Format::OggVorbis | Format::Opus) {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no OGG pages", self.format)));
+        }
+        let mut data = std::fs::read(&self.path)?;
+        let ranges = ogg::page::page_ranges(&data);
+        if ranges.is_empty() {
+            return Err(AudioFileError::ParseError("No OGG pages found".to_string()));
+        }
+        for range in ranges {
+            let page = &mut data[range];
+            page[ogg::page::SERIAL_OFFSET..ogg::page::SERIAL_OFFSET + 4].copy_from_slice(&new_serial.to_le_bytes());
+            ogg::page::update_crc(page);
+        }
+
+        // The serial numbers are part of the audio pages, so there is no audio span to guard
+        self.check_writable()?;
+        utils::io::replace_file(&self.path, &data).map_err(|e| self.write_error(AudioFileError::IoError(e)))
+    }
+
+    /// Add an APPLICATION block to a FLAC file, replacing any with the same ID
+    ///
+    /// `id` must be 4 ASCII characters. Other blocks, including other
+    /// applications, are kept in place.
+    pub fn set_flac_application(&self, id: &str, data: &[u8]) -> AudioResult<()> {
+        if self.format != Format::Flac {
+            return Err(AudioFileError::UnsupportedFormat(format!("{} files have no APPLICATION blocks", self.format)));
+        }
+        if id.len() != 4 || !id.is_ascii() {
+            return Err(AudioFileError::ParseError(format!("Application ID must be 4 ASCII characters, got {:?}", id)));
+        }
+        let mut application = id.as_bytes().to_vec();
+        application.extend_from_slice(data);
+        let mut application = Some(FlacMetadataBlock::new(FlacMetadataBlockType::Application, application));
+
+        let file_data = std::fs::read(&self.path)?;
+        let mut cursor = std::io::Cursor::new(&file_data);
+        cursor.set_position(FLAC_SIGNATURE.len() as u64);
+
+        let mut blocks = Vec::new();
+        loop {
+            let block = FlacMetadataBlock::read(&mut cursor)?;
+            let is_last = block.header.is_last;
+            match block.header.block_type {
+                FlacMetadataBlockType::Padding => {}
+                // The first block with this ID is replaced in place, later ones are dropped
+                FlacMetadataBlockType::Application if block.data.starts_with(id.as_bytes()) => {
+                    blocks.extend(application.take());
+                }
+                _ => blocks.push(block),
+            }
+            if is_last {
+                break;
+            }
+        }
+        blocks.extend(application);
+        let audio_start = cursor.position() as usize;
+
+        self.write_flac_blocks(&file_data, blocks, audio_start, false)
+    }
+
+    /// Checksum of the audio stream alone, excluding all tags
+    ///
+    /// Stays the same across tag edits and renames, so it identifies a file's
+    /// audio; it is not a cryptographic hash.
+    pub fn get_audio_checksum(&self) -> AudioResult<u64> {
+        self.audio_checksum()
+    }
+
+    /// Size in bytes of the cover image that `cover` holds, without decoding it
+    ///
+    /// Reads the first ID3v2 APIC frame, FLAC PICTURE block or MP4 `covr`
+    /// image. Returns `None` when there is no cover or the format has none.
+    pub fn get_cover_art_size_bytes(&self) -> AudioResult<Option<usize>> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                let Some(tag) = self.read_id3v2_tag()? else {
+                    return Ok(None);
+                };
+                Ok(tag.frames.iter()
+                    .filter(|frame| frame.frame_id == "APIC")
+                    .find_map(|frame| id3::frames::split_apic_frame(&frame.data))
+                    .map(|(_, _, _, image)| image.len()))
+            }
+            Format::Flac => {
+                let Some(blocks) = FlacBlocks::new(BufReader::new(File::open(&self.path)?))? else {
+                    return Ok(None);
+                };
+                Ok(blocks.map_while(Result::ok).find_map(|block| block.picture_data_len()))
+            }
+            Format::Mp4 => Ok(Mp4File::new(self.path.clone()).read_metadata()?
+                .and_then(|meta| meta.covers.first().map(|cover| cover.data.len()))),
+            Format::Id3v1 | Format::OggVorbis | Format::Opus | Format::Ape => Ok(None),
+        }
+    }
+
+    /// Picture type codes of every embedded picture, sorted and without repeats
+    ///
+    /// Codes follow the ID3v2 / FLAC picture type list (3 is the front
+    /// cover, 4 the back cover). Only the type is read, not the images. MP4
+    /// `covr` images count as front covers and APE cover items map as in
+    /// `read_pictures`; ID3v1 tags have none.
+    pub fn list_cover_types(&self) -> AudioResult<Vec<u8>> {
+        use base64::prelude::*;
+
+        let codes: Vec<u32> = match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => self.read_id3v2_tag()?.map(|tag| tag.frames).unwrap_or_default().iter()
+                .filter(|frame| frame.frame_id == "APIC")
+                .filter_map(|frame| id3::frames::split_apic_frame(&frame.data))
+                .map(|(_, picture_type, _, _)| picture_type as u32)
+                .collect(),
+            Format::Flac => self.flac_blocks()?
+                .map_while(Result::ok)
+                .filter_map(|block| block.picture_type())
+                .collect(),
+            Format::OggVorbis | Format::Opus => self.get_vorbis_comments()?.1.iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE"))
+                .filter_map(|(_, value)| BASE64_STANDARD.decode(value).ok())
+                .filter_map(|data| Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?)))
+                .collect(),
+            Format::Mp4 | Format::Ape => self.read_pictures()?.iter()
+                .map(|picture| picture.picture_type as u32)
+                .collect(),
+            Format::Id3v1 => Vec::new(),
+        };
+        // Codes above 255 are outside the defined range and can't be valid
+        let mut codes: Vec<u8> = codes.into_iter().filter_map(|code| u8::try_from(code).ok()).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        Ok(codes)
+    }
+
+    /// Every embedded picture in file order, with its image data and MIME type
+    ///
+    /// Unlike `cover` in `read_metadata`, this includes every picture, e.g.
+    /// each image in an MP4 `covr` atom. MP4 MIME types come from the
+    /// atom's type flag, falling back to the image data when it is implicit.
+    pub fn read_covers(&self) -> AudioResult<Vec<CoverArt>> {
+        Ok(self.read_pictures()?.into_iter()
+            .map(|picture| CoverArt {
+                data: picture.data,
+                mime_type: Some(picture.mime_type),
+                description: Some(picture.description).filter(|description| !description.is_empty()),
+            })
+            .collect())
+    }
+
+    /// Whether a picture of type `picture_type` is embedded
+    pub fn has_cover_type(&self, picture_type: u8) -> AudioResult<bool> {
+        Ok(self.list_cover_types()?.contains(&picture_type))
+    }
+
+    /// Number of binary objects embedded in the tags
+    ///
+    /// Counts ID3v2 APIC and GEOB frames, FLAC PICTURE blocks, Vorbis
+    /// METADATA_BLOCK_PICTURE comments, MP4 `covr` images and APE binary
+    /// items. ID3v1 tags can't hold any.
+    pub fn get_embedded_object_count(&self) -> AudioResult<usize> {
+        match self.format {
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => Ok(self.read_id3v2_tag()?.map_or(0, |tag| {
+                tag.frames.iter().filter(|frame| matches!(frame.frame_id.as_str(), "APIC" | "GEOB")).count()
+            })),
+            Format::Flac => Ok(self.flac_blocks()?
+                .map_while(Result::ok)
+                .filter(|block| matches!(block, FlacBlockView::Picture(_)))
+                .count()),
+            Format::OggVorbis | Format::Opus => Ok(self.get_vorbis_comments()?.1.iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE"))
+                .count()),
+            Format::Mp4 => Ok(Mp4File::new(self.path.clone()).read_metadata()?.map_or(0, |meta| meta.covers.len())),
+            Format::Ape => Ok(ApeFile::new(self.path.clone()).count_binary_items()?),
+            Format::Id3v1 => Ok(0),
+        }
+    }
+
+    /// Copy the tags of `source` into this file, converting values for this file's format
+    ///
+    /// Goes through `Metadata` and `Metadata::normalize_for` rather than the
+    /// source's JSON, so an ID3v1 genre reference lands in a FLAC file as
+    /// "Rock" and mis-decoded text is repaired. Fields `source` lacks are left
+    /// as they are; the cover is copied with the other fields.
+    pub fn copy_metadata_from(&self, source: &AudioFile) -> AudioResult<()> {
+        let mut metadata = source.read_metadata()?;
+        metadata.normalize_for(self.format);
+        self.update_metadata(&WriteOptions::default(), |current| {
+            current.merge(&metadata, MergePolicy::PreferOther);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Replace this file's pictures with every picture embedded in `source`
+    ///
+    /// Picture types, MIME types and descriptions carry over where the target
+    /// format can hold them (MP4 keeps only the images). Other tags are left
+    /// alone, and nothing is written when `source` has no pictures.
+    pub fn copy_cover_from(&self, source: &AudioFile) -> AudioResult<()> {
+        let pictures = source.read_pictures()?;
+        if pictures.is_empty() {
+            return Ok(());
+        }
+        self.write_pictures(&pictures)
+    }
+
+    /// Remove every embedded picture, not just the cover that `set_metadata` replaces
+    pub fn remove_all_covers(&self) -> AudioResult<()> {
+        if self.read_pictures()?.is_empty() {
+            return Ok(());
+        }
+        self.write_pictures(&[])
+    }
+
+    /// Replace the cover with an image; the MIME type is detected when not given
+    ///
+    /// Like setting `cover` through `set_metadata`, this replaces the front
+    /// cover and leaves the other tags alone.
+    pub fn set_cover_from_bytes(&self, data: Vec<u8>, mime_type: Option<String>) -> AudioResult<()> {
+        let cover = CoverArt::new(data, mime_type, None);
+        if cover.mime_type.is_none() {
+            return Err(AudioFileError::ParseError("Cover data is not a recognized image format".to_string()));
+        }
+        self.update_metadata(&WriteOptions::default(), |metadata| {
+            metadata.cover = Some(cover);
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Embed the image at `image_path` as a picture of type `picture_type`
+    ///
+    /// Uses the ID3v2/FLAC picture type table (3 front cover, 4 back cover,
+    /// 8 artist, 19 band logo, ...). A picture already stored with that type
+    /// is replaced and pictures of other types are kept. An empty `mime_type`
+    /// is detected from the image data. MP4 has no picture types, so there
+    /// the image is just added to the others.
+    pub fn set_cover_for_type(&self, picture_type: u8, image_path: &str, mime_type: &str, description: &str) -> AudioResult<()> {
+        use flac::picture::PictureType;
+
+        if picture_type > PictureType::PublisherLogo as u8 {
+            return Err(AudioFileError::ParseError(format!("Invalid picture type {}", picture_type)));
+        }
+        let data = std::fs::read(image_path)?;
+        let mime_type = Some(mime_type)
+            .filter(|mime_type| !mime_type.is_empty())
+            .or_else(|| utils::image::detect_mime_type(&data))
+            .ok_or_else(|| AudioFileError::ParseError("Cover data is not a recognized image format".to_string()))?
+            .to_string();
+
+        let picture_type = PictureType::from_u32(picture_type as u32);
+        let mut pictures = self.read_pictures()?;
+        if self.format != Format::Mp4 {
+            pictures.retain(|picture| picture.picture_type != picture_type);
+        }
+        pictures.push(FlacPicture { picture_type, ..FlacPicture::new(data, mime_type, description.to_string()) });
+        self.write_pictures(&pictures)
+    }
+
+    /// Download an image and embed it as the cover (see `set_cover_from_bytes`)
+    ///
+    /// Without `mime_type` the type is detected from the image data, falling
+    /// back to the response's Content-Type. Nothing is written if the
+    /// download fails or exceeds `options.max_bytes`.
+    #[cfg(feature = "http")]
+    pub fn set_cover_from_url(
+        &self,
+        url: &str,
+        mime_type: Option<String>,
+        options: &DownloadOptions,
+    ) -> AudioResult<()> {
+        let (data, content_type) = utils::http::download(url, options)?;
+        let mime_type = mime_type
+            .or_else(|| utils::image::detect_mime_type(&data).map(str::to_string))
+            .or(content_type);
+        self.set_cover_from_bytes(data, mime_type)
+    }
+
+    /// Write the tags to a sidecar file for media managers
+    ///
+    /// `format` is "nfo" (Kodi/Plex `<track>` XML), "xmp" (Adobe XMP with
+    /// Dublin Core and Dynamic Media properties) or "json". The audio file
+    /// itself is not modified.
+    pub fn export_metadata_to_sidecar(&self, format: &str, output_path: &str) -> AudioResult<()> {
+        let contents = sidecar::render(&self.read_metadata()?, format)?;
+        std::fs::write(output_path, contents)?;
+        Ok(())
+    }
+
+    /// Check whether the embedded cue sheet has a hidden track before track 1
+    pub fn has_hidden_track(&self) -> AudioResult<bool> {
+        Ok(self.read_cue_sheet()?.is_some_and(|cue| cue.has_hidden_track()))
+    }
+
+    /// Get the file type/version
+    pub fn get_version(&self) -> AudioResult<String> {
+        match self.format {
+            Format::Mp3 if self.read_id3v2_tag()?.is_none() => Ok(self.format.to_string()),
+            Format::Id3v2 | Format::Mp3 | Format::Dsf => {
+                // Read ID3v2 version
+                let file = File::open(&self.path)?;
+                let mut reader = BufReader::new(file);
+                let mut header = [0u8; 10];
+                reader.read_exact(&mut header)?;
+                if header.len() >= 4 {
+                    Ok(format!("2.{}", header[3]))
+                } else {
+                    Ok("2.x".to_string())
+                }
+            }
+            Format::Id3v1 | Format::Flac | Format::OggVorbis | Format::Opus | Format::Mp4 | Format::Ape => {
+                Ok(self.format.to_string())
+            }
+        }
+    }
+}
+
+/// Order of the keys in `get_metadata` JSON
+///
+/// Fields without a value are left out. Keys not listed, such as
+/// `properties`, follow in alphabetical order, and the keys of nested
+/// objects are sorted.
+pub const METADATA_JSON_KEY_ORDER: &[&str] = &[
+    "title", "artist", "album", "year", "comment", "track", "genre", "genres", "genre_raw",
+    "album_artist", "composer", "original_date", "original_artist", "original_album",
+    "performers", "lyrics", "length_ms", "encoder", "encoder_settings", "itunes", "cover",
+];
+
+/// Render a JSON value with object keys in a fixed order
+///
+/// Top-level keys follow `METADATA_JSON_KEY_ORDER`; everything else is sorted.
+fn ordered_json(value: &serde_json::Value) -> String {
+    fn render(value: &serde_json::Value, top_level: bool, json: &mut String) {
+        match value {
+            serde_json::Value::Object(object) => {
+                let rank = |key: &str| match top_level {
+                    true => METADATA_JSON_KEY_ORDER.iter().position(|known| *known == key).unwrap_or(usize::MAX),
+                    false => usize::MAX,
+                };
+                let mut keys: Vec<&String> = object.keys().collect();
+                keys.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+
+                json.push('{');
+                for (index, key) in keys.into_iter().enumerate() {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    json.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                    json.push(':');
+                    render(&object[key], false, json);
+                }
+                json.push('}');
+            }
+            serde_json::Value::Array(items) => {
+                json.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        json.push(',');
+                    }
+                    render(item, false, json);
+                }
+                json.push(']');
+            }
+            other => json.push_str(&other.to_string()),
+        }
+    }
+
+    let mut json = String::new();
+    render(value, true, &mut json);
+    json
+}
+
+/// iTunes media fields, kept under `itunes` in the metadata JSON
+///
+/// Only MP4 files store them (`stik`, `pgap`, `tvsh`, `tvsn`, `tves`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ItunesFields {
+    /// Media kind (`stik`): 1 music, 2 audiobook, 10 TV show, ... (see `MEDIA_KINDS`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_kind: Option<u8>,
+    /// Part of a gapless album (`pgap`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gapless: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tv_show: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tv_season: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tv_episode: Option<u32>,
+}
+
+impl ItunesFields {
+    /// Media kind names and their `stik` values, as used by iTunes and Apple Books
+    pub const MEDIA_KINDS: &'static [(&'static str, u8)] = &[
+        ("music", 1),
+        ("audiobook", 2),
+        ("musicvideo", 6),
+        ("movie", 9),
+        ("tvshow", 10),
+        ("booklet", 11),
+        ("ringtone", 14),
+        ("podcast", 21),
+    ];
+
+    /// The `stik` value for a media kind name such as "audiobook"
+    pub fn media_kind_code(name: &str) -> Option<u8> {
+        Self::MEDIA_KINDS.iter()
+            .find(|(kind, _)| kind.eq_ignore_ascii_case(name))
+            .map(|(_, code)| *code)
+    }
+
+    /// Check whether no field is set
+    pub fn is_empty(&self) -> bool {
+        *self == ItunesFields::default()
+    }
+}
+
+/// Fields named the way MusicBrainz lookup tools (AcoustID, beets) expect them
+///
+/// Missing values serialize as `null`, so the keys are always present.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MusicBrainzFields {
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub release: Option<String>,
+    pub date: Option<String>,
+    pub tracknumber: Option<u32>,
+    pub totaltracks: Option<u32>,
+    pub discnumber: Option<u32>,
+    pub totaldiscs: Option<u32>,
+    pub isrc: Option<String>,
+    pub musicbrainz_trackid: Option<String>,
+    pub musicbrainz_albumid: Option<String>,
+}
+
+/// Metadata container
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<String>,
+    /// All genres joined with "; "
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    /// All genres, for tags that can hold several (ID3v2 TCON, repeated Vorbis GENRE)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    /// ID3v2 TCON text as stored, before references such as "(17)" are resolved
+    ///
+    /// Read-only: it is never written back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre_raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub composer: Option<String>,
+    /// Release date of the original recording (ID3v2.4 TDOR, ID3v2.3 TORY, Vorbis ORIGINALDATE)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_date: Option<String>,
+    /// Artist of the original recording (ID3v2 TOPE, Vorbis ORIGINALARTIST)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_artist: Option<String>,
+    /// Album of the original release (ID3v2 TOAL, Vorbis ORIGINALALBUM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_album: Option<String>,
+    /// (instrument, name) pairs (ID3v2.4 TMCL, Vorbis "Instrument (Name)" PERFORMER entries)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub performers: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lyrics: Option<String>,
+    /// Duration in milliseconds, as stored in the tag (ID3v2 TLEN, Vorbis LENGTH)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length_ms: Option<u64>,
+    /// Encoder software (ID3v2 TSSE, Vorbis ENCODER, MP4 ©too, APE Encoder)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoder: Option<String>,
+    /// Options the encoder ran with (ID3v2 TXXX:ENCODERSETTINGS, Vorbis and MP4 freeform ENCODERSETTINGS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoder_settings: Option<String>,
+    /// iTunes media fields (MP4 only)
+    #[serde(default, skip_serializing_if = "ItunesFields::is_empty")]
+    pub itunes: ItunesFields,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<CoverArt>,
+}
+
+impl Metadata {
+    /// Number of populated fields (the cover counts as one field)
+    pub fn len(&self) -> usize {
+        let text_fields = [
+            &self.title,
+            &self.artist,
+            &self.album,
+            &self.year,
+            &self.comment,
+            &self.track,
+            &self.genre,
+            &self.album_artist,
+            &self.composer,
+            &self.original_date,
+            &self.original_artist,
+            &self.original_album,
+            &self.lyrics,
+            &self.encoder,
+            &self.encoder_settings,
+        ];
+        let populated = text_fields.iter().filter(|field| field.is_some()).count();
+        populated
+            + usize::from(self.length_ms.is_some())
+            + usize::from(!self.performers.is_empty())
+            + usize::from(!self.itunes.is_empty())
+            + usize::from(self.cover.is_some())
+    }
+
+    /// Check whether no field is populated
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// JSON keys of the populated fields
+    fn populated_keys(&self) -> Vec<&'static str> {
+        let text_fields = [
+            ("title", &self.title),
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("year", &self.year),
+            ("comment", &self.comment),
+            ("track", &self.track),
+            ("genre", &self.genre),
+            ("genre_raw", &self.genre_raw),
+            ("album_artist", &self.album_artist),
+            ("composer", &self.composer),
+            ("original_date", &self.original_date),
+            ("original_artist", &self.original_artist),
+            ("original_album", &self.original_album),
+            ("lyrics", &self.lyrics),
+            ("encoder", &self.encoder),
+            ("encoder_settings", &self.encoder_settings),
+        ];
+        let mut keys: Vec<&'static str> = text_fields.iter()
+            .filter(|(_, value)| value.is_some())
+            .map(|(key, _)| *key)
+            .collect();
+        let other_fields = [
+            ("genres", !self.genres.is_empty()),
+            ("performers", !self.performers.is_empty()),
+            ("length_ms", self.length_ms.is_some()),
+            ("itunes", !self.itunes.is_empty()),
+            ("cover", self.cover.is_some()),
+        ];
+        keys.extend(other_fields.iter().filter(|(_, populated)| *populated).map(|(key, _)| *key));
+        keys
+    }
+
+    /// The plain text field behind a standard field
+    ///
+    /// `None` for genre, which also carries the `genres` list, and for the cover.
+    fn text_field_mut(&mut self, field: StandardField) -> Option<&mut Option<String>> {
+        match field {
+            StandardField::Title => Some(&mut self.title),
+            StandardField::Artist => Some(&mut self.artist),
+            StandardField::Album => Some(&mut self.album),
+            StandardField::Year => Some(&mut self.year),
+            StandardField::Track => Some(&mut self.track),
+            StandardField::Comment => Some(&mut self.comment),
+            StandardField::Lyrics => Some(&mut self.lyrics),
+            StandardField::Genre | StandardField::Cover => None,
+        }
+    }
+
+    /// Check whether a standard field is populated
+    pub fn has_field(&self, field: StandardField) -> bool {
+        match field {
+            StandardField::Genre => self.genre.is_some(),
+            StandardField::Cover => self.cover.is_some(),
+            text => self.text_field(text).is_some(),
+        }
+    }
+
+    /// The plain text value behind a standard field; `None` for genre and the cover
+    fn text_field(&self, field: StandardField) -> Option<&String> {
+        match field {
+            StandardField::Title => self.title.as_ref(),
+            StandardField::Artist => self.artist.as_ref(),
+            StandardField::Album => self.album.as_ref(),
+            StandardField::Year => self.year.as_ref(),
+            StandardField::Track => self.track.as_ref(),
+            StandardField::Comment => self.comment.as_ref(),
+            StandardField::Lyrics => self.lyrics.as_ref(),
+            StandardField::Genre | StandardField::Cover => None,
+        }
+    }
+
+    /// Clear everything but the given standard fields
+    pub fn retain_fields(&mut self, fields: &[StandardField]) {
+        let mut kept = Metadata::default();
+        for &field in fields {
+            match field {
+                StandardField::Genre => {
+                    kept.genre = self.genre.take();
+                    kept.genres = std::mem::take(&mut self.genres);
+                    kept.genre_raw = self.genre_raw.take();
+                }
+                StandardField::Cover => kept.cover = self.cover.take(),
+                text => {
+                    if let (Some(kept), Some(value)) = (kept.text_field_mut(text), self.text_field_mut(text)) {
+                        *kept = value.take();
+                    }
+                }
+            }
+        }
+        *self = kept;
+    }
+
+    /// Set a standard field to `other`'s value; genre brings its list and raw text along
+    fn copy_field_from(&mut self, field: StandardField, other: &Metadata) {
+        match field {
+            StandardField::Genre => {
+                self.genre = other.genre.clone();
+                self.genres = other.genres.clone();
+                self.genre_raw = other.genre_raw.clone();
+            }
+            StandardField::Cover => self.cover = other.cover.clone(),
+            text => {
+                if let Some(value) = self.text_field_mut(text) {
+                    *value = other.text_field(text).cloned();
+                }
+            }
+        }
+    }
+
+    /// The fields MusicBrainz lookup tools use, under their names
+    ///
+    /// `artists` is `artist` split on ";" and "/"; `tracknumber` and
+    /// `totaltracks` come from a "5/12" track. `Metadata` has no disc
+    /// numbers, ISRC or MusicBrainz IDs; `AudioFile::read_musicbrainz_fields`
+    /// fills those in from the tags.
+    pub fn to_musicbrainz(&self) -> MusicBrainzFields {
+        let (tracknumber, totaltracks) = self.track_position();
+        MusicBrainzFields {
+            title: self.title.clone(),
+            artists: self.artist.iter()
+                .flat_map(|artist| artist.split([';', '/']))
+                .map(str::trim)
+                .filter(|artist| !artist.is_empty())
+                .map(str::to_string)
+                .collect(),
+            release: self.album.clone(),
+            date: self.year.clone(),
+            tracknumber,
+            totaltracks,
+            ..Default::default()
+        }
+    }
+
+    /// Track number parsed from `track`, so "3/12" and "03" both give 3
+    pub fn track_number(&self) -> Option<u32> {
+        self.track_position().0
+    }
+
+    /// Total number of tracks parsed from a "3/12" style `track`
+    pub fn track_total(&self) -> Option<u32> {
+        self.track_position().1
+    }
+
+    fn track_position(&self) -> (Option<u32>, Option<u32>) {
+        self.track.as_deref().map_or((None, None), ValueConverter::parse_position)
+    }
+
+    /// Set all genres and the joined `genre` string; blank entries are dropped
+    pub fn set_genres(&mut self, genres: Vec<String>) {
+        self.genres = genres.into_iter()
+            .map(|genre| genre.trim().to_string())
+            .filter(|genre| !genre.is_empty())
+            .collect();
+        self.genre = Some(self.genres.join(utils::genre::SEPARATOR)).filter(|genre| !genre.is_empty());
+    }
+
+    /// All genres to write: `genres`, unless `genre` was changed on its own,
+    /// in which case it is split on ";"
+    pub fn all_genres(&self) -> Vec<String> {
+        match &self.genre {
+            None => Vec::new(),
+            Some(genre) if *genre == self.genres.join(utils::genre::SEPARATOR) => self.genres.clone(),
+            Some(genre) => utils::genre::split(genre),
+        }
+    }
+
+    /// Clean up values read from one format for writing to a file of `target` format
+    ///
+    /// Text is cleaned with `ValueConverter::clean_text`, genres are resolved
+    /// to text ("(17)" becomes "Rock"), the track loses its leading zeros and
+    /// dates take ISO 8601 form, cut to the year for ID3v1. `genre_raw` is
+    /// dropped, as it only describes the tag it was read from.
+    pub fn normalize_for(&mut self, target: Format) {
+        let text_fields = [
+            &mut self.title,
+            &mut self.artist,
+            &mut self.album,
+            &mut self.year,
+            &mut self.comment,
+            &mut self.track,
+            &mut self.album_artist,
+            &mut self.composer,
+            &mut self.original_date,
+            &mut self.original_artist,
+            &mut self.original_album,
+            &mut self.lyrics,
+            &mut self.encoder,
+            &mut self.encoder_settings,
+        ];
+        for field in text_fields {
+            *field = field.as_deref().map(ValueConverter::clean_text).filter(|text| !text.is_empty());
+        }
+
+        let genres = self.all_genres().iter()
+            .map(|genre| ValueConverter::clean_text(genre))
+            .flat_map(|genre| utils::genre::parse_tcon(&genre))
+            .collect();
+        self.set_genres(genres);
+        self.genre_raw = None;
+        for (instrument, name) in &mut self.performers {
+            *instrument = ValueConverter::clean_text(instrument);
+            *name = ValueConverter::clean_text(name);
+        }
+
+        if let (Some(number), total) = self.track_position() {
+            self.track = Some(match total {
+                Some(total) => format!("{}/{}", number, total),
+                None => number.to_string(),
+            });
+        }
+        for date in [&mut self.year, &mut self.original_date].into_iter().flatten() {
+            *date = match target {
+                Format::Id3v1 => ValueConverter::normalize_year(date),
+                _ => ValueConverter::normalize_date(date),
+            };
+        }
+    }
+
+    /// Map every genre to its standard name (see `GenrePolicy`)
+    ///
+    /// Numeric references such as "(17)" become "Rock" and names are matched
+    /// case-insensitively against the ID3v1 list plus the Winamp extensions.
+    pub fn normalize_genres(&mut self, policy: GenrePolicy) -> AudioResult<()> {
+        let mut genres = Vec::new();
+        for genre in self.all_genres() {
+            match utils::genre::normalize(&genre) {
+                Some(standard) => genres.push(standard),
+                None if policy == GenrePolicy::PassThrough => genres.push(genre),
+                None => {
+                    return Err(AudioFileError::ParseError(format!("Non-standard genre: {}", genre)));
+                }
+            }
+        }
+        genres.dedup();
+        self.set_genres(genres);
+        Ok(())
+    }
+
+    /// Merge fields from `other` into this metadata, cover included
+    ///
+    /// `other` never clears a field: a field missing from `other` keeps its
+    /// current value under every policy.
+    pub fn merge(&mut self, other: &Metadata, policy: MergePolicy) {
+        let take_other = |mine_missing: bool, mine_blank: bool| match policy {
+            MergePolicy::PreferSelf => mine_missing,
+            MergePolicy::PreferOther => true,
+            MergePolicy::FillEmpty => mine_blank,
+        };
+
+        let text_fields = [
+            (&mut self.title, &other.title),
+            (&mut self.artist, &other.artist),
+            (&mut self.album, &other.album),
+            (&mut self.year, &other.year),
+            (&mut self.comment, &other.comment),
+            (&mut self.track, &other.track),
+            (&mut self.album_artist, &other.album_artist),
+            (&mut self.composer, &other.composer),
+            (&mut self.original_date, &other.original_date),
+            (&mut self.original_artist, &other.original_artist),
+            (&mut self.original_album, &other.original_album),
+            (&mut self.lyrics, &other.lyrics),
+            (&mut self.encoder, &other.encoder),
+            (&mut self.encoder_settings, &other.encoder_settings),
+        ];
+        for (mine, theirs) in text_fields {
+            let blank = mine.as_deref().is_none_or(|text| text.trim().is_empty());
+            if theirs.is_some() && take_other(mine.is_none(), blank) {
+                *mine = theirs.clone();
+            }
+        }
+
+        if other.length_ms.is_some() && take_other(self.length_ms.is_none(), self.length_ms.is_none()) {
+            self.length_ms = other.length_ms;
+        }
+
+        if !other.performers.is_empty() && take_other(self.performers.is_empty(), self.performers.is_empty()) {
+            self.performers = other.performers.clone();
+        }
+
+        if !other.itunes.is_empty() && take_other(self.itunes.is_empty(), self.itunes.is_empty()) {
+            self.itunes = other.itunes.clone();
+        }
+
+        // The genre list travels with the joined genre
+        let blank = self.genre.as_deref().is_none_or(|text| text.trim().is_empty());
+        if other.genre.is_some() && take_other(self.genre.is_none(), blank) {
+            self.genre = other.genre.clone();
+            self.genres = other.all_genres();
+        }
+
+        let blank = self.cover.as_ref().is_none_or(|cover| cover.data.is_empty());
+        if other.cover.is_some() && take_other(self.cover.is_none(), blank) {
+            self.cover = other.cover.clone();
+        }
+    }
+
+    /// The fields both sides set to the same value; every other field is empty
+    ///
+    /// Text is compared exactly, `genres` and `performers` as whole lists,
+    /// and covers by their image data.
+    pub fn intersection(&self, other: &Metadata) -> Metadata {
+        fn agreed<T: Clone + PartialEq>(mine: &Option<T>, theirs: &Option<T>) -> Option<T> {
+            mine.clone().filter(|_| mine == theirs)
+        }
+        fn agreed_list<T: Clone + PartialEq>(mine: &[T], theirs: &[T]) -> Vec<T> {
+            if mine == theirs { mine.to_vec() } else { Vec::new() }
+        }
+
+        let genre = agreed(&self.genre, &other.genre);
+        Metadata {
+            title: agreed(&self.title, &other.title),
+            artist: agreed(&self.artist, &other.artist),
+            album: agreed(&self.album, &other.album),
+            year: agreed(&self.year, &other.year),
+            comment: agreed(&self.comment, &other.comment),
+            track: agreed(&self.track, &other.track),
+            // The genre list travels with the joined genre
+            genres: if genre.is_some() { agreed_list(&self.genres, &other.genres) } else { Vec::new() },
+            genre,
+            genre_raw: agreed(&self.genre_raw, &other.genre_raw),
+            album_artist: agreed(&self.album_artist, &other.album_artist),
+            composer: agreed(&self.composer, &other.composer),
+            original_date: agreed(&self.original_date, &other.original_date),
+            original_artist: agreed(&self.original_artist, &other.original_artist),
+            original_album: agreed(&self.original_album, &other.original_album),
+            performers: agreed_list(&self.performers, &other.performers),
+            lyrics: agreed(&self.lyrics, &other.lyrics),
+            length_ms: agreed(&self.length_ms, &other.length_ms),
+            encoder: agreed(&self.encoder, &other.encoder),
+            encoder_settings: agreed(&self.encoder_settings, &other.encoder_settings),
+            itunes: ItunesFields {
+                media_kind: agreed(&self.itunes.media_kind, &other.itunes.media_kind),
+                gapless: agreed(&self.itunes.gapless, &other.itunes.gapless),
+                tv_show: agreed(&self.itunes.tv_show, &other.itunes.tv_show),
+                tv_season: agreed(&self.itunes.tv_season, &other.itunes.tv_season),
+                tv_episode: agreed(&self.itunes.tv_episode, &other.itunes.tv_episode),
+            },
+            cover: self.cover.clone().filter(|cover| {
+                other.cover.as_ref().is_some_and(|theirs| theirs.data == cover.data)
+            }),
+        }
+    }
+
+    /// Every field set on either side; where both are set, ours wins if `prefer_self`
+    ///
+    /// The same as merging into a copy with `MergePolicy::PreferSelf` or
+    /// `MergePolicy::PreferOther`.
+    pub fn union(&self, other: &Metadata, prefer_self: bool) -> Metadata {
+        let mut union = self.clone();
+        let policy = if prefer_self { MergePolicy::PreferSelf } else { MergePolicy::PreferOther };
+        union.merge(other, policy);
+        union
+    }
+
+    /// Apply the fields present in a JSON object; `null` or "" clears a field
+    fn apply_json_updates(&mut self, updates: &serde_json::Value) -> AudioResult<()> {
+        let updates = updates.as_object()
+            .ok_or_else(|| AudioFileError::ParseError("Expected a JSON object".to_string()))?;
+
+        let text_fields = [
+            ("title", &mut self.title),
+            ("artist", &mut self.artist),
+            ("album", &mut self.album),
+            ("year", &mut self.year),
+            ("comment", &mut self.comment),
+            ("track", &mut self.track),
+            ("genre", &mut self.genre),
+            ("album_artist", &mut self.album_artist),
+            ("composer", &mut self.composer),
+            ("original_date", &mut self.original_date),
+            ("original_artist", &mut self.original_artist),
+            ("original_album", &mut self.original_album),
+            ("lyrics", &mut self.lyrics),
+            ("encoder", &mut self.encoder),
+            ("encoder_settings", &mut self.encoder_settings),
+        ];
+        for (key, field) in text_fields {
+            match updates.get(key) {
+                None => {}
+                Some(serde_json::Value::Null) => *field = None,
+                Some(serde_json::Value::String(text)) => {
+                    *field = if text.is_empty() { None } else { Some(text.clone()) };
+                }
+                Some(other) => *field = Some(other.to_string()),
+            }
+        }
+
+        match updates.get("length_ms") {
+            None => {}
+            Some(serde_json::Value::Null) => self.length_ms = None,
+            Some(value) => {
+                let length = value.as_u64().ok_or_else(|| {
+                    AudioFileError::ParseError("length_ms must be a non-negative integer".to_string())
+                })?;
+                self.length_ms = Some(length);
+            }
+        }
+
+        match updates.get("performers") {
+            None => {}
+            Some(serde_json::Value::Null) => self.performers.clear(),
+            Some(value) => {
+                self.performers = serde_json::from_value(value.clone()).map_err(|_| {
+                    AudioFileError::ParseError("performers must be an array of [instrument, name] pairs".to_string())
+                })?;
+            }
+        }
+
+        // Keys inside `itunes` update one by one, like top-level fields
+        match updates.get("itunes") {
+            None => {}
+            Some(serde_json::Value::Null) => self.itunes = ItunesFields::default(),
+            Some(value) => {
+                let invalid = || AudioFileError::ParseError(
+                    "itunes must be an object of media_kind, gapless, tv_show, tv_season and tv_episode".to_string()
+                );
+                let changes = value.as_object().ok_or_else(invalid)?;
+                let mut fields = match serde_json::to_value(&self.itunes) {
+                    Ok(serde_json::Value::Object(fields)) => fields,
+                    _ => serde_json::Map::new(),
+                };
+                for (key, change) in changes {
+                    if change.is_null() {
+                        fields.remove(key);
+                    } else {
+                        fields.insert(key.clone(), change.clone());
+                    }
+                }
+                self.itunes = serde_json::from_value(serde_json::Value::Object(fields)).map_err(|_| invalid())?;
+            }
+        }
+
+        match updates.get("genres") {
+            None => {
+                // A new scalar genre replaces the whole list
+                if updates.contains_key("genre") {
+                    self.set_genres(self.all_genres());
+                }
+            }
+            Some(serde_json::Value::Null) => self.set_genres(Vec::new()),
+            Some(serde_json::Value::Array(values)) => {
+                let genres = values.iter()
+                    .map(|value| value.as_str().map(str::to_string).ok_or_else(|| {
+                        AudioFileError::ParseError("genres must be an array of strings".to_string())
+                    }))
+                    .collect::<AudioResult<Vec<_>>>()?;
+                self.set_genres(genres);
+            }
+            Some(_) => {
+                return Err(AudioFileError::ParseError("genres must be an array of strings".to_string()));
+            }
+        }
+
+        match updates.get("cover") {
+            None => {}
+            Some(serde_json::Value::Null) => self.cover = None,
+            Some(value) => {
+                let cover = serde_json::from_value::<CoverArt>(value.clone())
+                    .map_err(|e| AudioFileError::ParseError(format!("Invalid cover: {}", e)))?;
+                self.cover = Some(cover);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What `Metadata::normalize_genres` does with genres not in the standard list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenrePolicy {
+    /// Keep non-standard genres unchanged
+    PassThrough,
+    /// Fail with an error naming the first non-standard genre
+    Reject,
+}
+
+/// What `set_metadata` does with a new cover the format's players may not show
+///
+/// Every format allows JPEG and PNG, and MP4 also BMP, as the FLAC and
+/// ID3v2 specs recommend and iTunes' `covr` data types require. GIF, WebP,
+/// TIFF, animated PNG and unrecognized data fall outside that. Covers
+/// already in the file are left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverPolicy {
+    /// Fail with an error naming the image type
+    Strict,
+    /// Re-encode the image as JPEG; requires the `image` feature
+    AutoConvert,
+}
+
+impl std::str::FromStr for CoverPolicy {
+    type Err = AudioFileError;
+
+    fn from_str(policy: &str) -> AudioResult<Self> {
+        match policy {
+            "strict" => Ok(CoverPolicy::Strict),
+            "auto-convert" => Ok(CoverPolicy::AutoConvert),
+            other => Err(AudioFileError::ParseError(format!(
+                "Unknown cover policy '{}', expected 'strict' or 'auto-convert'", other
+            ))),
+        }
+    }
+}
+
+/// How `Metadata::merge` resolves a field set on both sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep this side's value; only fields it lacks are taken from the other side
+    PreferSelf,
+    /// Take the other side's value wherever it has one
+    PreferOther,
+    /// Like `PreferSelf`, but blank text and empty covers also count as missing
+    FillEmpty,
+}
+
+impl std::str::FromStr for MergePolicy {
+    type Err = AudioFileError;
+
+    fn from_str(policy: &str) -> AudioResult<Self> {
+        match policy {
+            "prefer-self" => Ok(MergePolicy::PreferSelf),
+            "prefer-other" => Ok(MergePolicy::PreferOther),
+            "fill-empty" => Ok(MergePolicy::FillEmpty),
+            other => Err(AudioFileError::ParseError(format!(
+                "Unknown merge policy '{}', expected 'prefer-self', 'prefer-other' or 'fill-empty'", other
+            ))),
+        }
+    }
+}
+
+/// Cover art data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoverArt {
+    #[serde(serialize_with = "serialize_as_base64", deserialize_with = "deserialize_base64_to_vec")]
+    pub data: Vec<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl CoverArt {
+    /// Cover art from image data; the MIME type is detected when not given
+    pub fn new(data: Vec<u8>, mime_type: Option<String>, description: Option<String>) -> Self {
+        let mime_type = mime_type.or_else(|| utils::image::detect_mime_type(&data).map(str::to_string));
+        CoverArt { data, mime_type, description }
+    }
+
+    /// Width and height read from the image header, without decoding the image
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        utils::image::dimensions(&self.data)
+    }
+
+    /// Downscale the image to fit within `max_dim` pixels, keeping its aspect
+    /// ratio, and encode it as JPEG
+    ///
+    /// Images already small enough are re-encoded at their own size.
+    #[cfg(feature = "image")]
+    pub fn thumbnail(&self, max_dim: u32) -> AudioResult<Vec<u8>> {
+        if max_dim == 0 {
+            return Err(AudioFileError::ParseError("Thumbnail size must be at least 1 pixel".to_string()));
+        }
+        let image = image::load_from_memory(&self.data)
+            .map_err(|e| AudioFileError::ParseError(format!("Cannot decode cover image: {}", e)))?;
+        let image = if image.width() > max_dim || image.height() > max_dim {
+            image.thumbnail(max_dim, max_dim)
+        } else {
+            image
+        };
+
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .map_err(|e| AudioFileError::ParseError(format!("Cannot encode thumbnail: {}", e)))?;
+        Ok(jpeg.into_inner())
+    }
+}
+
+/// Location and type of a FLAC metadata block, without its data
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataBlockInfo {
+    /// Block type name, e.g. "VORBIS_COMMENT", or "UNKNOWN(n)" for reserved types
+    pub block_type: String,
+    /// Size of the block data, excluding the 4-byte header
+    pub size_bytes: usize,
+    pub is_last: bool,
+    /// Offset of the block header from the start of the file
+    pub offset: usize,
+}
+
+/// A problem found by `AudioFile::validate`
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    /// Stable identifier for the kind of problem, e.g. "truncated-cover"
+    pub code: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.code, self.field, self.message)
+    }
+}
+
+/// A field whose ID3v1 and ID3v2 values disagree
+#[derive(Debug, Clone, Serialize)]
+pub struct TagMismatch {
+    pub field: String,
+    pub id3v1: String,
+    pub id3v2: String,
+}
+
+/// Which tag to keep when ID3v1 and ID3v2 disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagResolution {
+    /// Rewrite the ID3v1 tag from the ID3v2 tag
+    V2Wins,
+    /// Copy the disagreeing ID3v1 fields into the ID3v2 tag
+    V1Wins,
+}
+
+// ============================================================================
+// PyO3 Bindings (only compiled when "python" feature is enabled)
+// ============================================================================
+
+#[cfg(feature = "python")]
+#[pymodule]
+fn oxidant(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAudioFile>()?;
+    m.add_class::<PyMetadata>()?;
+    m.add_class::<PyCoverArt>()?;
+    m.add_class::<BatchProcessor>()?;
+    m.add_class::<PyBatchResult>()?;
+    m.add_class::<PyMetadataBlockInfo>()?;
+    m.add_class::<PyOptimizeReport>()?;
+    m.add_class::<PyCueTrack>()?;
+    m.add_class::<PyCueIndex>()?;
+    m.add_class::<PyTrackPoint>()?;
+    m.add_class::<PyAudioProperties>()?;
+    m.add_class::<PyHistoryEntry>()?;
+    m.add_class::<PyLameHeader>()?;
+    m.add_class::<PyFormat>()?;
+    m.add_class::<PySoundCheck>()?;
+    m.add_function(wrap_pyfunction!(py_apply, m)?)?;
+    m.add_function(wrap_pyfunction!(py_enable_logging, m)?)?;
+    m.add_function(wrap_pyfunction!(py_field_mapping, m)?)?;
+    Ok(())
+}
+
+/// Name of the Metadata field a raw key of `format` ("id3v2", "vorbis" or "ape") maps to
+#[cfg(feature = "python")]
+#[pyfunction(name = "field_mapping")]
+fn py_field_mapping(format: &str, key: &str) -> Option<&'static str> {
+    FieldMappings::canonical_field_name(format, key)
+}
+
+/// Most verbose level forwarded to Python, changed by each `enable_logging` call
+#[cfg(feature = "python")]
+static PY_LOG_LEVEL: std::sync::RwLock<tracing::Level> = std::sync::RwLock::new(tracing::Level::INFO);
+
+/// Send oxidant's log records to the "oxidant" logger of Python's `logging` module
+///
+/// Each record's message is the JSON line described in `oxidant::logging`.
+/// `level` ("ERROR", "WARNING", "INFO", "DEBUG" or "TRACE") is also set on
+/// the logger; calling again changes it.
+#[cfg(feature = "python")]
+#[pyfunction(name = "enable_logging")]
+#[pyo3(signature = (level="INFO"))]
+fn py_enable_logging(py: Python, level: &str) -> PyResult<()> {
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+
+    let parsed = match level.to_ascii_uppercase().as_str() {
+        "WARNING" => Level::WARN,
+        other => other.parse::<Level>().map_err(|_| {
+            pyo3::exceptions::PyValueError::new_err(format!("Unknown log level '{}'", level))
+        })?,
+    };
+    let python_level = |level: Level| match level {
+        Level::ERROR => 40,
+        Level::WARN => 30,
+        Level::INFO => 20,
+        Level::DEBUG => 10,
+        Level::TRACE => 5,
+    };
+
+    *PY_LOG_LEVEL.write().unwrap() = parsed;
+    py.import("logging")?.call_method1("getLogger", ("oxidant",))?.call_method1("setLevel", (python_level(parsed),))?;
+
+    let mut result = Ok(());
+    INSTALLED.call_once(|| {
+        let layer = logging::JsonLayer::with_sink(Level::TRACE, move |level, line| {
+            if level > *PY_LOG_LEVEL.read().unwrap() {
+                return;
+            }
+            Python::attach(|py| {
+                // A failing handler must not fail the operation being logged
+                let _ = py.import("logging")
+                    .and_then(|logging| logging.call_method1("getLogger", ("oxidant",)))
+                    .and_then(|logger| logger.call_method1("log", (python_level(level), line)));
+            });
+        });
+        result = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()));
+    });
+    result
+}
+
+/// Write each (path, Metadata) pair's metadata to its file using up to `jobs` threads
+///
+/// Results come back in the order given; a path listed twice fails for both entries.
+#[cfg(feature = "python")]
+#[pyfunction(name = "apply")]
+#[pyo3(signature = (items, jobs=4))]
+fn py_apply(py: Python, items: Vec<(String, PyRef<PyMetadata>)>, jobs: usize) -> Vec<PyBatchResult> {
+    let items: Vec<(String, Metadata)> = items.into_iter()
+        .map(|(path, metadata)| (path, Metadata::from(&*metadata)))
+        .collect();
+    py.detach(|| apply(items, jobs))
+        .into_iter()
+        .map(|(file_path, result)| PyBatchResult {
+            file_path,
+            success: result.is_ok(),
+            error_message: result.err().map(|e| e.to_string()),
+        })
+        .collect()
+}
+
+/// Convert a JSON value to the equivalent Python object
+#[cfg(feature = "python")]
+fn json_to_py<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    use serde_json::Value;
+
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Value::Number(number) => match number.as_i64() {
+            Some(int) => int.into_pyobject(py)?.into_any(),
+            None => number.as_f64().unwrap_or_default().into_pyobject(py)?.into_any(),
+        },
+        Value::String(text) => PyString::new(py, text).into_any(),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(json_to_py(py, item)?)?;
+            }
+            list.into_any()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, json_to_py(py, item)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "Format", eq, eq_int)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum PyFormat {
+    Id3v2,
+    Id3v1,
+    Flac,
+    OggVorbis,
+    Opus,
+    Mp4,
+    Ape,
+    Mp3,
+    Dsf,
+}
+
+#[cfg(feature = "python")]
+impl From<Format> for PyFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Id3v2 => PyFormat::Id3v2,
+            Format::Id3v1 => PyFormat::Id3v1,
+            Format::Flac => PyFormat::Flac,
+            Format::OggVorbis => PyFormat::OggVorbis,
+            Format::Opus => PyFormat::Opus,
+            Format::Mp4 => PyFormat::Mp4,
+            Format::Ape => PyFormat::Ape,
+            Format::Mp3 => PyFormat::Mp3,
+            Format::Dsf => PyFormat::Dsf,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "AudioFile")]
+pub struct PyAudioFile {
+    #[pyo3(get)]
+    path: String,
+    /// Format name, e.g. "flac", kept for compatibility; see `format`
+    #[pyo3(get)]
+    file_type: String,
+    #[pyo3(get)]
+    format: PyFormat,
+    audio: AudioFile,
+    /// The private copy of a file opened with `from_fileobj`, removed on drop
+    _scratch: Option<storage::ScratchFile>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyAudioFile {
+    /// Open `path`; `force_format` (e.g. "id3v1") skips format detection
+    #[new]
+    #[pyo3(signature = (path, force_format=None))]
+    fn new(path: String, force_format: Option<&str>) -> PyResult<Self> {
+        let audio = match force_format {
+            Some(format) => format.parse().and_then(|format| AudioFile::with_format(path, format)),
+            None => AudioFile::new(path),
+        }
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        Ok(Self {
+            path: audio.path.clone(),
+            file_type: audio.format.to_string(),
+            format: audio.format.into(),
+            audio,
+            _scratch: None,
+        })
+    }
+
+    /// Open audio read from a binary file object
+    ///
+    /// The data is copied to a private temporary file, which `path` names;
+    /// edits apply to that copy until `write_to_fileobj` writes it out. `name`,
+    /// or else the object's `name` attribute, supplies the file extension.
+    #[staticmethod]
+    #[pyo3(signature = (fileobj, name=None))]
+    fn from_fileobj(fileobj: &Bound<'_, PyAny>, name: Option<String>) -> PyResult<Self> {
+        let data: Vec<u8> = fileobj.call_method0("read")?.extract()?;
+        let name = name
+            .or_else(|| fileobj.getattr("name").and_then(|name| name.extract()).ok())
+            .unwrap_or_default();
+        let scratch = storage::ScratchFile::new(&data, &name)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        let audio = AudioFile::new(scratch.path())
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        Ok(Self {
+            path: audio.path.clone(),
+            file_type: audio.format.to_string(),
+            format: audio.format.into(),
+            audio,
+            _scratch: Some(scratch),
+        })
+    }
+
+    /// Write the whole file, with any edits made so far, to a binary file object and return it
+    fn write_to_fileobj<'py>(&self, fileobj: Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+        let data = std::fs::read(&self.audio.path)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        fileobj.call_method1("write", (data,))?;
+        Ok(fileobj)
+    }
+
+    #[pyo3(signature = (include_cover=true, include_properties=false, icy_aware=false, with_sources=false))]
+    fn get_metadata(&self, include_cover: bool, include_properties: bool, icy_aware: bool, with_sources: bool) -> PyResult<String> {
+        let options = ReadOptions { include_cover, include_properties, icy_aware, with_sources };
+        self.audio.get_metadata_with_options(&options)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Metadata JSON holding only the named standard fields ("title", "cover", ...)
+    fn read_fields(&self, fields: Vec<String>) -> PyResult<String> {
+        let fields = fields.iter()
+            .map(|name| StandardField::from_str(name)
+                .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("Unknown field '{}'", name))))
+            .collect::<PyResult<Vec<_>>>()?;
+        let metadata = self.audio.read_fields(&fields)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        let value = serde_json::to_value(&metadata)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(ordered_json(&value))
+    }
+
+    /// Rename the file (a bare name stays in the same directory) and update `path`
+    fn rename_and_update_path(&mut self, new_name: &str) -> PyResult<()> {
+        self.audio.rename_and_update_path(new_name)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        self.path = self.audio.path.clone();
+        Ok(())
+    }
+
+    /// The metadata as a dict, with `cover` as a nested dict (base64 `data`) or None
+    fn get_metadata_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let value = self.audio.get_metadata_value_deep()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(json_to_py(py, &value)?.cast_into::<PyDict>()?)
+    }
+
+    /// Like `Metadata.to_musicbrainz_dict`, with disc numbers, ISRC and MusicBrainz IDs read from the tags
+    fn to_musicbrainz_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let fields = self.audio.read_musicbrainz_fields()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        let value = serde_json::to_value(fields)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        json_to_py(py, &value)
+    }
+
+    /// (vendor, [(field, value), ...]) exactly as stored in the Vorbis comment
+    fn get_vorbis_comments(&self) -> PyResult<(String, Vec<(String, String)>)> {
+        self.audio.get_vorbis_comments()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// (key, value) pairs of TXXX frames or unmapped Vorbis comments
+    fn get_extra_fields(&self) -> PyResult<Vec<(String, String)>> {
+        self.audio.get_extra_fields()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Replace user-defined fields by key; an empty value removes the field
+    fn set_extra_fields(&self, fields: Vec<(String, String)>) -> PyResult<()> {
+        self.audio.set_extra_fields(&fields)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Text of the ID3v2 comment with this language and description, if any
+    fn get_id3v2_comment(&self, language: &str, description: &str) -> PyResult<Option<String>> {
+        self.audio.get_id3v2_comment(language, description)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// The lyrics as stored, without decoding (ID3v2: USLT data after the language)
+    fn get_lyrics_raw(&self) -> PyResult<Option<Vec<u8>>> {
+        self.audio.get_lyrics_raw()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Text of the ID3v2 lyrics with this language and description, if any
+    fn get_id3v2_lyrics(&self, language: &str, description: &str) -> PyResult<Option<String>> {
+        self.audio.get_id3v2_lyrics(language, description)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Value of the ID3v2 TXXX frame with this description, if any
+    fn get_id3v2_user_text_by_description(&self, description: &str) -> PyResult<Option<String>> {
+        self.audio.get_id3v2_user_text_by_description(description)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// (owner, identifier) of the first ID3v2 UFID frame, if any
+    fn get_id3v2_unique_file_id(&self) -> PyResult<Option<(String, Vec<u8>)>> {
+        self.audio.get_id3v2_unique_file_id()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Set the ID3v2 UFID frame of `owner`, replacing any with the same owner
+    fn set_id3v2_unique_file_id(&self, owner: &str, identifier: Vec<u8>) -> PyResult<()> {
+        self.audio.set_id3v2_unique_file_id(owner, &identifier)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// (frame ID, value) pairs of the ID3v2 frames whose ID starts with X, Y or Z
+    fn list_id3v2_custom_frames(&self) -> PyResult<Vec<(String, String)>> {
+        self.audio.list_id3v2_custom_frames()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Set a custom ID3v2 text frame, replacing any with the same ID; an empty value removes it
+    fn set_id3v2_custom_frame(&self, frame_id: &str, value: &str) -> PyResult<()> {
+        self.audio.set_id3v2_custom_frame(frame_id, value)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Warnings for embedded pictures whose MIME type, dimensions or description disagree with the image
+    fn validate_cover_art_consistency(&self) -> PyResult<Vec<String>> {
+        self.audio.validate_cover_art_consistency()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Remove ICY metadata blocks from the MPEG stream of an internet-radio rip; returns how many
+    fn strip_icy_metadata(&self) -> PyResult<usize> {
+        self.audio.strip_icy_metadata()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// (application ID, data) pairs of the FLAC APPLICATION blocks
+    fn get_flac_applications(&self) -> PyResult<Vec<(String, Vec<u8>)>> {
+        self.audio.get_flac_applications()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Hex MD5 of the decoded audio from FLAC STREAMINFO, or None when unset
+    fn get_flac_audio_md5(&self) -> PyResult<Option<String>> {
+        self.audio.get_flac_audio_md5()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Hex MD5 of the decoded audio from FLAC STREAMINFO; None when unset or not FLAC
+    fn compute_audio_md5(&self) -> PyResult<Option<String>> {
+        self.audio.compute_audio_md5()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Decode with the `flac` tool and compare with the STREAMINFO MD5; None when unset
+    #[cfg(feature = "verify-audio")]
+    fn get_flac_md5_matches_audio(&self) -> PyResult<Option<bool>> {
+        self.audio.get_flac_md5_matches_audio().map_err(|e| match e {
+            AudioFileError::ToolNotFound(_) => pyo3::exceptions::PyValueError::new_err(e.to_string()),
+            _ => pyo3::exceptions::PyIOError::new_err(e.to_string()),
+        })
+    }
+
+    /// Sorted unique bitstream serial numbers of the OGG pages
+    fn get_ogg_serial_numbers(&self) -> PyResult<Vec<u32>> {
+        self.audio.get_ogg_serial_numbers()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Give every OGG page the serial number `new_serial`, recomputing the CRCs
+    fn rewrite_ogg_serial_numbers(&self, new_serial: u32) -> PyResult<()> {
+        self.audio.rewrite_ogg_serial_numbers(new_serial)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Add a FLAC APPLICATION block, replacing any with the same 4-character ID
+    fn set_flac_application(&self, id: &str, data: Vec<u8>) -> PyResult<()> {
+        self.audio.set_flac_application(id, &data)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Checksum of the audio stream, unchanged by tag edits and renames
+    fn get_audio_checksum(&self) -> PyResult<u64> {
+        self.audio.get_audio_checksum()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Size in bytes of the cover image, without loading it
+    fn get_cover_art_size_bytes(&self) -> PyResult<Option<usize>> {
+        self.audio.get_cover_art_size_bytes()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Sorted picture type codes of every embedded picture
+    fn list_cover_types(&self) -> PyResult<Vec<u8>> {
+        self.audio.list_cover_types()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Every embedded picture in file order
+    fn read_covers(&self) -> PyResult<Vec<PyCoverArt>> {
+        self.audio.read_covers()
+            .map(|covers| covers.into_iter().map(PyCoverArt::from).collect())
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Whether a picture of the given type code is embedded
+    fn has_cover_type(&self, picture_type: u8) -> PyResult<bool> {
+        self.audio.has_cover_type(picture_type)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Number of embedded binary objects (cover art, GEOB frames, APE binary items)
+    fn get_embedded_object_count(&self) -> PyResult<usize> {
+        self.audio.get_embedded_object_count()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Copy the tags of another file, converting values for this file's format
+    fn copy_metadata_from(&self, source: PyRef<PyAudioFile>) -> PyResult<()> {
+        self.audio.copy_metadata_from(&source.audio)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Replace this file's pictures with all pictures from another file
+    fn copy_cover_from(&self, source: PyRef<PyAudioFile>) -> PyResult<()> {
+        self.audio.copy_cover_from(&source.audio)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Remove every embedded picture
+    fn remove_all_covers(&self) -> PyResult<()> {
+        self.audio.remove_all_covers()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Embed an image file as a picture of the given type (3 front cover, 4 back cover, ...)
+    fn set_cover_for_type(&self, picture_type: u8, image_path: &str, mime_type: String, description: String) -> PyResult<()> {
+        self.audio.set_cover_for_type(picture_type, image_path, &mime_type, &description)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Replace the cover with image bytes; the MIME type is detected when not given
+    #[pyo3(signature = (data, mime_type=None))]
+    fn set_cover_from_bytes(&self, data: Vec<u8>, mime_type: Option<String>) -> PyResult<()> {
+        self.audio.set_cover_from_bytes(data, mime_type)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Download an image and embed it as the cover
+    ///
+    /// `timeout` is in seconds; downloads larger than `max_size` bytes are rejected.
+    #[cfg(feature = "http")]
+    #[pyo3(signature = (url, mime_type=None, timeout=30.0, max_size=10 * 1024 * 1024))]
+    fn set_cover_from_url(&self, url: &str, mime_type: Option<String>, timeout: f64, max_size: u64) -> PyResult<()> {
+        let timeout = std::time::Duration::try_from_secs_f64(timeout)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("Invalid timeout: {}", e)))?;
+        let options = DownloadOptions { max_bytes: max_size, timeout };
+        self.audio.set_cover_from_url(url, mime_type, &options)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Write the tags to an "nfo", "xmp" or "json" sidecar file
+    fn export_metadata_to_sidecar(&self, format: &str, output_path: &str) -> PyResult<()> {
+        self.audio.export_metadata_to_sidecar(format, output_path)
+            .map_err(|e| match e {
+                AudioFileError::UnsupportedFormat(_) => pyo3::exceptions::PyValueError::new_err(e.to_string()),
+                _ => pyo3::exceptions::PyIOError::new_err(e.to_string()),
+            })
+    }
+
+    /// Tags as a Metadata object, empty if they can't be parsed
+    fn get_or_create_metadata(&self) -> PyResult<PyMetadata> {
+        self.audio.get_or_create_metadata()
+            .map(PyMetadata::from)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Write a Metadata object as the ID3v2 tag, padded by `padding_bytes` when it grows
+    #[pyo3(signature = (metadata, padding_bytes=4096))]
+    fn write_id3v2_metadata_with_padding(&self, metadata: PyRef<PyMetadata>, padding_bytes: usize) -> PyResult<()> {
+        self.audio.write_id3v2_metadata_with_padding(&Metadata::from(&*metadata), padding_bytes)
+            .map(|_| ())
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Write a Metadata object as the file's complete set of tags
+    fn write_metadata(&self, metadata: PyRef<PyMetadata>) -> PyResult<()> {
+        self.audio.write_metadata(&Metadata::from(&*metadata))
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Returns descriptions of frames that couldn't be converted to `target_version`
+    /// `genre_policy` is None (write genres as given), "pass-through" or "reject"
+    /// `auto_length` sets the stored length from the audio stream's duration
+    /// `history` appends an entry to the edit history (ID3v2 and FLAC only)
+    /// `language` is the ISO-639 code of ID3v2 comments and lyrics, "eng" by default
+    /// `preserve_vorbis_keys` keeps Vorbis comment keys as spelled instead of normalizing them
+    /// `cover_policy` is None (write covers as given), "strict" or "auto-convert"
+    #[pyo3(signature = (metadata_json, target_version=None, deterministic=false, genre_policy=None, auto_length=false, history=false, language=None, preserve_vorbis_keys=false, cover_policy=None, strict=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn set_metadata(
+        &self,
+        metadata_json: String,
+        target_version: Option<(u8, u8)>,
+        deterministic: bool,
+        genre_policy: Option<&str>,
+        auto_length: bool,
+        history: bool,
+        language: Option<String>,
+        preserve_vorbis_keys: bool,
+        cover_policy: Option<&str>,
+        strict: bool,
+    ) -> PyResult<Vec<String>> {
+        let genre_policy = match genre_policy {
+            None => None,
+            Some("pass-through") => Some(GenrePolicy::PassThrough),
+            Some("reject") => Some(GenrePolicy::Reject),
+            Some(other) => {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    format!("Unknown genre policy '{}', expected 'pass-through' or 'reject'", other)
+                ));
+            }
+        };
+        let cover_policy = cover_policy
+            .map(str::parse::<CoverPolicy>)
+            .transpose()
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let options = WriteOptions {
+            target_version, deterministic, genre_policy, auto_length, history, language, preserve_vorbis_keys,
+            id3v2_padding: None,
+            cover_policy,
+            strict,
+        };
+        let report = self.audio.set_metadata_with_options(metadata_json, &options)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        let skipped = report.skipped.iter().map(|skipped| skipped.to_string());
+        Ok(report.lossy_frames.into_iter().chain(report.warnings).chain(skipped).collect())
+    }
+
+    #[cfg(feature = "toml-output")]
+    fn get_metadata_as_toml(&self) -> PyResult<String> {
+        self.audio.get_metadata_as_toml()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    #[cfg(feature = "toml-output")]
+    fn set_metadata_from_toml(&self, toml_str: String) -> PyResult<()> {
+        self.audio.set_metadata_from_toml(&toml_str)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+
+    fn get_version(&self) -> PyResult<String> {
+        self.audio.get_version()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn get_all_metadata_blocks(&self) -> PyResult<Vec<PyMetadataBlockInfo>> {
+        let blocks = self.audio.get_all_metadata_blocks()
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        Ok(blocks.into_iter().map(PyMetadataBlockInfo::from).collect())
+    }
+
+    /// Frame IDs, FLAC block types, Vorbis comment keys, MP4 item types or APE keys, without values
+    fn get_all_frame_ids(&self) -> PyResult<Vec<String>> {
+        self.audio.get_all_frame_ids()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn validate(&self) -> PyResult<Vec<String>> {
+        let issues = self.audio.validate()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(issues.iter().map(|issue| issue.to_string()).collect())
+    }
+
+    /// Raw ID3v1 genre byte (255 = none), or None without an ID3v1 tag
+    fn get_id3v1_genre(&self) -> PyResult<Option<u8>> {
+        self.audio.get_id3v1_genre()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// The TAG+ extended tag as a dict (speed, genre, start_time, end_time), or None
+    fn get_id3v1_extended<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let extended = self.audio.get_id3v1_extended()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        extended.map(|extended| {
+            let value = serde_json::to_value(&extended)
+                .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+            json_to_py(py, &value)
+        }).transpose()
+    }
+
+    /// Remove the TAG+ extended tag; returns whether there was one
+    fn remove_id3v1_extended(&self) -> PyResult<bool> {
+        self.audio.remove_id3v1_extended()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Fields whose ID3v1 and ID3v2 values disagree, as (field, id3v1, id3v2)
+    fn compare_id3_tags(&self) -> PyResult<Vec<(String, String, String)>> {
+        let mismatches = self.audio.compare_id3_tags()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(mismatches.into_iter().map(|m| (m.field, m.id3v1, m.id3v2)).collect())
+    }
+
+    /// Make ID3v1 and ID3v2 agree; `resolve` is "v2-wins" or "v1-wins"
+    fn resolve_id3_tags(&self, resolve: &str) -> PyResult<Vec<String>> {
+        let resolution = match resolve {
+            "v2-wins" => TagResolution::V2Wins,
+            "v1-wins" => TagResolution::V1Wins,
+            other => {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    format!("Unknown resolution '{}', expected 'v2-wins' or 'v1-wins'", other)
+                ));
+            }
+        };
+        let mismatches = self.audio.resolve_id3_tags(resolution)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(mismatches.into_iter().map(|m| m.field).collect())
+    }
+
+    fn optimize(&self) -> PyResult<PyOptimizeReport> {
+        self.audio.optimize()
+            .map(PyOptimizeReport::from)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn has_embedded_cue(&self) -> PyResult<bool> {
+        self.audio.has_embedded_cue()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn get_cue_tracks(&self) -> PyResult<Vec<PyCueTrack>> {
+        let tracks = self.audio.get_cue_tracks()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(tracks.into_iter().map(PyCueTrack::from).collect())
+    }
+
+    fn get_track_points(&self) -> PyResult<Vec<PyTrackPoint>> {
+        let points = self.audio.get_track_points()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
+        Ok(points.into_iter().map(PyTrackPoint::from).collect())
+    }
+
+    fn has_hidden_track(&self) -> PyResult<bool> {
+        self.audio.has_hidden_track()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn get_sound_check(&self) -> PyResult<Option<PySoundCheck>> {
+        self.audio.get_sound_check()
+            .map(|sound_check| sound_check.map(PySoundCheck::from))
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn set_sound_check(&self, sound_check: PyRef<PySoundCheck>) -> PyResult<()> {
+        self.audio.set_sound_check(&sound_check.inner)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn clone_audio_properties_from(&self, source_path: String) -> PyResult<()> {
+        self.audio.clone_audio_properties_from(&source_path)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn get_properties(&self) -> PyResult<PyAudioProperties> {
+        self.audio.get_properties()
+            .map(PyAudioProperties::from)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Audio properties, the same as `get_properties()`
+    #[getter(properties)]
+    fn properties_attr(&self) -> PyResult<PyAudioProperties> {
+        self.get_properties()
+    }
+
+    fn get_mp3_lame_header(&self) -> PyResult<Option<PyLameHeader>> {
+        self.audio.get_mp3_lame_header()
+            .map(|header| header.map(PyLameHeader::from))
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Output gain of the Opus identification header in dB, or `None` for other formats
+    fn get_opus_output_gain(&self) -> PyResult<Option<f64>> {
+        self.audio.get_opus_output_gain()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// Set the output gain of the Opus identification header, rounded to 1/256 dB
+    fn set_opus_output_gain(&self, gain_db: f64) -> PyResult<()> {
+        self.audio.set_opus_output_gain(gain_db)
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn get_edit_history(&self) -> PyResult<Vec<PyHistoryEntry>> {
+        self.audio.get_edit_history()
+            .map(|history| history.into_iter().map(PyHistoryEntry::from).collect())
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn get_audio_span(&self) -> PyResult<(u64, u64)> {
+        self.audio.get_audio_span()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    /// (metadata_bytes, audio_bytes), adding up to the file size
+    fn tag_overhead(&self) -> PyResult<(u64, u64)> {
+        self.audio.tag_overhead()
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        self.audio.read_metadata()
+            .map(|metadata| metadata.len())
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))
+    }
+
+    fn __bool__(&self) -> PyResult<bool> {
+        self.__len__().map(|len| len > 0)
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "Metadata")]
+pub struct PyMetadata {
+    #[pyo3(get, set)]
+    title: Option<String>,
+    #[pyo3(get, set)]
+    artist: Option<String>,
+    #[pyo3(get, set)]
+    album: Option<String>,
+    #[pyo3(get, set)]
+    year: Option<String>,
+    #[pyo3(get, set)]
+    comment: Option<String>,
+    #[pyo3(get, set)]
+    track: Option<String>,
+    #[pyo3(get, set)]
+    genre: Option<String>,
+    #[pyo3(get, set)]
+    genres: Vec<String>,
+    #[pyo3(get)]
+    genre_raw: Option<String>,
+    #[pyo3(get, set)]
+    album_artist: Option<String>,
+    #[pyo3(get, set)]
+    composer: Option<String>,
+    #[pyo3(get, set)]
+    original_date: Option<String>,
+    #[pyo3(get, set)]
+    original_artist: Option<String>,
+    #[pyo3(get, set)]
+    original_album: Option<String>,
+    #[pyo3(get, set)]
+    performers: Vec<(String, String)>,
+    #[pyo3(get, set)]
+    lyrics: Option<String>,
+    #[pyo3(get, set)]
+    length_ms: Option<u64>,
+    #[pyo3(get, set)]
+    encoder: Option<String>,
+    #[pyo3(get, set)]
+    encoder_settings: Option<String>,
+    /// Carried through unchanged; read and written via the metadata JSON
+    itunes: ItunesFields,
+    #[pyo3(get, set)]
+    cover: Option<PyCoverArt>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyMetadata {
+    /// Empty metadata, to fill in and pass to `AudioFile.write_metadata`
+    #[new]
+    fn new() -> Self {
+        Metadata::default().into()
+    }
+
+    /// Merge fields from `other`; `policy` is "prefer-self", "prefer-other" or "fill-empty"
+    #[pyo3(signature = (other, policy = "fill-empty"))]
+    fn merge(&mut self, other: PyRef<PyMetadata>, policy: &str) -> PyResult<()> {
+        let policy: MergePolicy = policy.parse()
+            .map_err(|e: AudioFileError| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let mut merged = Metadata::from(&*self);
+        merged.merge(&Metadata::from(&*other), policy);
+        *self = PyMetadata::from(merged);
+        Ok(())
+    }
+
+    /// A new Metadata with only the fields both sides set to the same value
+    fn intersection(&self, other: PyRef<PyMetadata>) -> PyMetadata {
+        Metadata::from(self).intersection(&Metadata::from(&*other)).into()
+    }
+
+    /// A new Metadata with every field set on either side; where both are set, ours wins if `prefer_self`
+    #[pyo3(signature = (other, prefer_self = true))]
+    fn union(&self, other: PyRef<PyMetadata>, prefer_self: bool) -> PyMetadata {
+        Metadata::from(self).union(&Metadata::from(&*other), prefer_self).into()
+    }
+
+    /// Dict with the keys MusicBrainz lookup tools use ("title", "artists", "release", ...)
+    fn to_musicbrainz_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let value = serde_json::to_value(Metadata::from(self).to_musicbrainz())
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        json_to_py(py, &value)
+    }
+
+    /// Track number parsed from `track` ("3/12" and "03" both give 3), or None
+    fn track_number(&self) -> Option<u32> {
+        Metadata::from(self).track_number()
+    }
+
+    /// Total number of tracks parsed from a "3/12" style `track`, or None
+    fn track_total(&self) -> Option<u32> {
+        Metadata::from(self).track_total()
+    }
+
+    /// Dict of the populated fields as in `get_metadata()`, plus `track_number`
+    /// and `track_total` ints parsed from the raw `track` string
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let metadata = Metadata::from(self);
+        let mut value = serde_json::to_value(&metadata)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        if let Some(number) = metadata.track_number() {
+            value["track_number"] = number.into();
+        }
+        if let Some(total) = metadata.track_total() {
+            value["track_total"] = total.into();
+        }
+        json_to_py(py, &value)
+    }
+
+    fn __len__(&self) -> usize {
+        Metadata::from(self).len()
+    }
+
+    fn __bool__(&self) -> bool {
+        self.__len__() > 0
+    }
+
+    /// Iterate over (field, value) pairs of the populated string fields
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        let pairs: Vec<(&str, &str)> = self.string_fields().into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+            .collect();
+        PyIterator::from_object(PyList::new(py, pairs)?.as_any())
+    }
+
+    /// Check whether the named field has a value
+    fn __contains__(&self, key: &str) -> bool {
+        match key {
+            "genres" => !self.genres.is_empty(),
+            "performers" => !self.performers.is_empty(),
+            "length_ms" => self.length_ms.is_some(),
+            "cover" => self.cover.is_some(),
+            _ => self.string_fields().iter().any(|(name, value)| *name == key && value.is_some()),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl PyMetadata {
+    /// String fields in iteration order: the common fields first, then the rest
+    fn string_fields(&self) -> [(&'static str, &Option<String>); 15] {
+        [
+            ("title", &self.title),
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("year", &self.year),
+            ("track", &self.track),
+            ("genre", &self.genre),
+            ("comment", &self.comment),
+            ("lyrics", &self.lyrics),
+            ("album_artist", &self.album_artist),
+            ("composer", &self.composer),
+            ("original_date", &self.original_date),
+            ("original_artist", &self.original_artist),
+            ("original_album", &self.original_album),
+            ("encoder", &self.encoder),
+            ("encoder_settings", &self.encoder_settings),
+        ]
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<Metadata> for PyMetadata {
+    fn from(metadata: Metadata) -> Self {
+        Self {
+            title: metadata.title,
+            artist: metadata.artist,
+            album: metadata.album,
+            year: metadata.year,
+            comment: metadata.comment,
+            track: metadata.track,
+            genre: metadata.genre,
+            genres: metadata.genres,
+            genre_raw: metadata.genre_raw,
+            album_artist: metadata.album_artist,
+            composer: metadata.composer,
+            original_date: metadata.original_date,
+            original_artist: metadata.original_artist,
+            original_album: metadata.original_album,
+            performers: metadata.performers,
+            lyrics: metadata.lyrics,
+            length_ms: metadata.length_ms,
+            encoder: metadata.encoder,
+            encoder_settings: metadata.encoder_settings,
+            itunes: metadata.itunes,
+            cover: metadata.cover.map(PyCoverArt::from),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<&PyMetadata> for Metadata {
+    fn from(metadata: &PyMetadata) -> Self {
+        Self {
+            title: metadata.title.clone(),
+            artist: metadata.artist.clone(),
+            album: metadata.album.clone(),
+            year: metadata.year.clone(),
+            comment: metadata.comment.clone(),
+            track: metadata.track.clone(),
+            genre: metadata.genre.clone(),
+            genres: metadata.genres.clone(),
+            genre_raw: metadata.genre_raw.clone(),
+            album_artist: metadata.album_artist.clone(),
+            composer: metadata.composer.clone(),
+            original_date: metadata.original_date.clone(),
+            original_artist: metadata.original_artist.clone(),
+            original_album: metadata.original_album.clone(),
+            performers: metadata.performers.clone(),
+            lyrics: metadata.lyrics.clone(),
+            length_ms: metadata.length_ms,
+            encoder: metadata.encoder.clone(),
+            encoder_settings: metadata.encoder_settings.clone(),
+            itunes: metadata.itunes.clone(),
+            cover: metadata.cover.clone().map(CoverArt::from),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "CoverArt")]
+#[derive(Clone)]
+pub struct PyCoverArt {
+    #[pyo3(get, set)]
+    data: Vec<u8>,
+    #[pyo3(get, set)]
+    mime_type: Option<String>,
+    #[pyo3(get, set)]
+    description: Option<String>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyCoverArt {
+    /// Cover art from image bytes; the MIME type is detected when not given
+    #[new]
+    #[pyo3(signature = (data, mime_type=None, description=""))]
+    fn new(data: Vec<u8>, mime_type: Option<String>, description: &str) -> Self {
+        let description = Some(description.to_string()).filter(|description| !description.is_empty());
+        CoverArt::new(data, mime_type, description).into()
+    }
+
+    /// (width, height) from the image header, or None if it can't be read
+    fn dimensions(&self) -> Option<(u32, u32)> {
+        utils::image::dimensions(&self.data)
+    }
+
+    /// JPEG thumbnail fitting within `max_dim` pixels
+    #[cfg(feature = "image")]
+    fn thumbnail(&self, max_dim: u32) -> PyResult<Vec<u8>> {
+        CoverArt::from(self.clone()).thumbnail(max_dim)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<CoverArt> for PyCoverArt {
+    fn from(cover: CoverArt) -> Self {
+        Self {
+            data: cover.data,
+            mime_type: cover.mime_type,
+            description: cover.description,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<PyCoverArt> for CoverArt {
+    fn from(cover: PyCoverArt) -> Self {
+        Self {
+            data: cover.data,
+            mime_type: cover.mime_type,
+            description: cover.description,
+        }
+    }
+}
+
+// Batch processing types (only for Python)
+#[cfg(feature = "python")]
+#[pyclass]
+pub struct BatchProcessor {
+    #[pyo3(get, set)]
+    pub show_progress: bool,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl BatchProcessor {
+    #[new]
+    fn new() -> Self {
+        BatchProcessor {
+            show_progress: true,
+        }
+    }
+
+    fn read_metadata_batch(&self, file_paths: Vec<String>) -> PyResult<Vec<String>> {
+        let mut results = Vec::new();
+        let total = file_paths.len();
+
+        for (index, path) in file_paths.iter().enumerate() {
+            if self.show_progress {
+                println!("Reading {}/{}: {}", index + 1, total, path);
+            }
+
+            match AudioFile::new(path.clone()) {
+                Ok(audio) => {
+                    match audio.get_metadata() {
+                        Ok(metadata) => results.push(metadata),
+                        Err(e) => {
+                            let error_json = format!(r#"{{"error": "{}", "file": "{}"}}"#, e, path);
+                            results.push(error_json);
+                        }
+                    }
+                }
+                Err(e) => {
+                    let error_json = format!(r#"{{"error": "{}", "file": "{}"}}"#, e, path);
+                    results.push(error_json);
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn write_metadata_batch(&self, updates: Vec<(String, String)>) -> PyResult<Vec<PyBatchResult>> {
+        let mut results = Vec::new();
+        let total = updates.len();
+
+        for (index, (path, _metadata_json)) in updates.iter().enumerate() {
+            if self.show_progress {
+                println!("Writing {}/{}: {}", index + 1, total, path);
+            }
+
+            let result = PyBatchResult {
+                file_path: path.clone(),
+                success: false,
+                error_message: None,
+            };
+
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    fn process_directory(
+        &self,
+        _directory: String,
+        _pattern: String,
+        _operation: String,
+        _metadata: Option<String>,
+        py: Python,
+    ) -> PyResult<Py<PyAny>> {
+        let results = Vec::<PyBatchResult>::new();
+        Ok(PyList::new(py, results)?.into())
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "BatchResult")]
+#[derive(Clone)]
+pub struct PyBatchResult {
+    #[pyo3(get, set)]
+    pub file_path: String,
+    #[pyo3(get, set)]
+    pub success: bool,
+    #[pyo3(get, set)]
+    pub error_message: Option<String>,
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "MetadataBlockInfo")]
+#[derive(Clone)]
+pub struct PyMetadataBlockInfo {
+    #[pyo3(get)]
+    pub block_type: String,
+    #[pyo3(get)]
+    pub size_bytes: usize,
+    #[pyo3(get)]
+    pub is_last: bool,
+    #[pyo3(get)]
+    pub offset: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyMetadataBlockInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "MetadataBlockInfo(block_type='{}', size_bytes={}, is_last={}, offset={})",
+            self.block_type, self.size_bytes, self.is_last, self.offset
+        )
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<MetadataBlockInfo> for PyMetadataBlockInfo {
+    fn from(block: MetadataBlockInfo) -> Self {
+        PyMetadataBlockInfo {
+            block_type: block.block_type,
+            size_bytes: block.size_bytes,
+            is_last: block.is_last,
+            offset: block.offset,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "OptimizeReport")]
+#[derive(Clone)]
+pub struct PyOptimizeReport {
+    #[pyo3(get)]
+    pub bytes_saved: i64,
+    #[pyo3(get)]
+    pub changes: Vec<String>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyOptimizeReport {
+    fn __repr__(&self) -> String {
+        format!("OptimizeReport(bytes_saved={}, changes={:?})", self.bytes_saved, self.changes)
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<OptimizeReport> for PyOptimizeReport {
+    fn from(report: OptimizeReport) -> Self {
+        PyOptimizeReport {
+            bytes_saved: report.bytes_saved,
+            changes: report.changes,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "CueIndex")]
+#[derive(Clone)]
+pub struct PyCueIndex {
+    #[pyo3(get)]
+    pub index: u8,
+    #[pyo3(get)]
+    pub sample_offset: u64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyCueIndex {
+    fn __repr__(&self) -> String {
+        format!("CueIndex(index={}, sample_offset={})", self.index, self.sample_offset)
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "CueTrack")]
+#[derive(Clone)]
+pub struct PyCueTrack {
+    #[pyo3(get)]
+    pub number: u8,
+    #[pyo3(get)]
+    pub isrc: String,
+    #[pyo3(get)]
+    pub indices: Vec<PyCueIndex>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyCueTrack {
+    fn __repr__(&self) -> String {
+        format!("CueTrack(number={}, isrc='{}', indices={})", self.number, self.isrc, self.indices.len())
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<CueTrack> for PyCueTrack {
+    fn from(track: CueTrack) -> Self {
+        PyCueTrack {
+            number: track.number,
+            isrc: track.isrc,
+            indices: track.indices.into_iter()
+                .map(|index| PyCueIndex { index: index.index, sample_offset: index.sample_offset })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "TrackPoint")]
+#[derive(Clone)]
+pub struct PyTrackPoint {
+    #[pyo3(get)]
+    pub number: u8,
+    #[pyo3(get)]
+    pub start_sample: u64,
+    #[pyo3(get)]
+    pub start_ms: u64,
+    #[pyo3(get)]
+    pub isrc: String,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyTrackPoint {
+    fn __repr__(&self) -> String {
+        format!("TrackPoint(number={}, start_ms={}, isrc='{}')", self.number, self.start_ms, self.isrc)
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<TrackPoint> for PyTrackPoint {
+    fn from(point: TrackPoint) -> Self {
+        PyTrackPoint {
+            number: point.number,
+            start_sample: point.start_sample,
+            start_ms: point.start_ms,
+            isrc: point.isrc,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "AudioProperties")]
+#[derive(Clone)]
+pub struct PyAudioProperties {
+    #[pyo3(get)]
+    pub duration_ms: u64,
+    #[pyo3(get)]
+    pub sample_rate: Option<u32>,
+    #[pyo3(get)]
+    pub channels: Option<u8>,
+    #[pyo3(get)]
+    pub bits_per_sample: Option<u8>,
+    #[pyo3(get)]
+    pub bitrate_kbps: Option<u32>,
+    #[pyo3(get)]
+    pub codec: String,
+    #[pyo3(get)]
+    pub lossless: bool,
+    summary: String,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyAudioProperties {
+    /// Duration in seconds
+    #[getter]
+    fn duration(&self) -> f64 {
+        self.duration_ms as f64 / 1000.0
+    }
+
+    #[getter]
+    fn bit_depth(&self) -> Option<u8> {
+        self.bits_per_sample
+    }
+
+    /// Average bitrate in kbit/s
+    #[getter]
+    fn bitrate(&self) -> Option<u32> {
+        self.bitrate_kbps
+    }
+
+    fn __str__(&self) -> String {
+        self.summary.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "AudioProperties(duration_ms={}, sample_rate={:?}, channels={:?}, codec='{}', lossless={})",
+            self.duration_ms, self.sample_rate, self.channels, self.codec,
+            if self.lossless { "True" } else { "False" }
+        )
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "LameHeader")]
+#[derive(Clone)]
+pub struct PyLameHeader {
+    #[pyo3(get)]
+    pub encoder_version: String,
+    #[pyo3(get)]
+    pub encoder_delay: u16,
+    #[pyo3(get)]
+    pub encoder_padding: u16,
+    #[pyo3(get)]
+    pub replaygain_track: Option<f32>,
+    #[pyo3(get)]
+    pub replaygain_album: Option<f32>,
+    #[pyo3(get)]
+    pub audio_length_ms: u64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyLameHeader {
+    fn __repr__(&self) -> String {
+        format!(
+            "LameHeader(encoder_version={:?}, encoder_delay={}, encoder_padding={})",
+            self.encoder_version, self.encoder_delay, self.encoder_padding
+        )
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<LameHeader> for PyLameHeader {
+    fn from(header: LameHeader) -> Self {
+        PyLameHeader {
+            encoder_version: header.encoder_version,
+            encoder_delay: header.encoder_delay,
+            encoder_padding: header.encoder_padding,
+            replaygain_track: header.replaygain_track,
+            replaygain_album: header.replaygain_album,
+            audio_length_ms: header.audio_length_ms,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "HistoryEntry")]
+#[derive(Clone)]
+pub struct PyHistoryEntry {
+    #[pyo3(get)]
+    pub timestamp: u64,
+    #[pyo3(get)]
+    pub fields_changed: Vec<String>,
+    #[pyo3(get)]
+    pub tool_version: String,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PyHistoryEntry {
+    fn __repr__(&self) -> String {
+        format!(
+            "HistoryEntry(timestamp={}, fields_changed={:?}, tool_version={:?})",
+            self.timestamp, self.fields_changed, self.tool_version
+        )
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<HistoryEntry> for PyHistoryEntry {
+    fn from(entry: HistoryEntry) -> Self {
+        PyHistoryEntry {
+            timestamp: entry.timestamp,
+            fields_changed: entry.fields_changed,
+            tool_version: entry.tool_version,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<AudioProperties> for PyAudioProperties {
+    fn from(properties: AudioProperties) -> Self {
+        PyAudioProperties {
+            duration_ms: properties.duration_ms,
+            sample_rate: properties.sample_rate,
+            channels: properties.channels,
+            bits_per_sample: properties.bits_per_sample,
+            bitrate_kbps: properties.bitrate_kbps,
+            summary: properties.summary(),
+            codec: properties.codec,
+            lossless: properties.lossless,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pyclass(name = "SoundCheck")]
+#[derive(Clone)]
+pub struct PySoundCheck {
+    inner: SoundCheck,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl PySoundCheck {
+    /// Parse an iTunNORM string (ten hex values)
+    #[staticmethod]
+    fn parse(text: &str) -> PyResult<Self> {
+        SoundCheck::parse(text)
+            .map(PySoundCheck::from)
+            .ok_or_else(|| pyo3::exceptions::PyValueError::new_err(format!("Invalid iTunNORM value: {:?}", text)))
+    }
+
+    /// Values applying `gain_db` to both channels
+    #[staticmethod]
+    fn from_gain_db(gain_db: f64) -> Self {
+        SoundCheck::from_gain_db(gain_db).into()
+    }
+
+    #[getter]
+    fn gain_db(&self) -> (f64, f64) {
+        let [left, right] = self.inner.gain_db();
+        (left, right)
+    }
+
+    #[getter]
+    fn peak(&self) -> (u32, u32) {
+        (self.inner.peak[0], self.inner.peak[1])
+    }
+
+    fn to_itunnorm(&self) -> String {
+        self.inner.to_itunnorm()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SoundCheck('{}')", self.inner.to_itunnorm().trim())
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<SoundCheck> for PySoundCheck {
+    fn from(inner: SoundCheck) -> Self {
+        PySoundCheck { inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    fn find_frame(data: &[u8], frame_id: &[u8]) -> Option<usize> {
+        data.windows(frame_id.len()).position(|window| window == frame_id)
+    }
+
+    #[test]
+    fn test_set_metadata_preserves_version_and_audio() {
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x00Old".to_vec()),
+            ("TXXX", b"\x00key\x00value".to_vec()),
+        ]);
+        let path = write_temp_file("preserve.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let span_before = audio.get_audio_span().unwrap();
+        audio.set_metadata(r#"{"title": "New", "artist": "Band", "track": 3}"#.to_string()).unwrap();
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[3], 3);
+        assert!(written.ends_with(&[0xFF, 0xFB, 0x90, 0x00]));
+        assert!(find_frame(&written, b"TXXX").is_some());
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("New"));
+        assert_eq!(metadata.artist.as_deref(), Some("Band"));
+        assert_eq!(metadata.track.as_deref(), Some("3"));
+        assert_eq!(audio.get_audio_span().unwrap().1, span_before.1);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_set_metadata_converts_id3v22_to_v24() {
+        let data = build_id3v2_file_version(2, &[
+            ("TT2", b"\x00Old".to_vec()),
+            ("TP1", b"\x00Band".to_vec()),
+            ("TYE", b"\x001999".to_vec()),
+            ("TXX", b"\x00key\x00value".to_vec()),
+            ("CRM", b"owner\x00x".to_vec()),
+        ]);
+        let path = write_temp_file("v22.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().artist.as_deref(), Some("Band"));
+
+        let options = WriteOptions { target_version: Some((2, 4)), ..Default::default() };
+        let report = audio.set_metadata_with_options(r#"{"title": "New"}"#.to_string(), &options).unwrap();
+        assert_eq!(report.lossy_frames.len(), 1);
+        assert!(report.lossy_frames[0].starts_with("CRM"));
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[3], 4);
+        assert!(find_frame(&written, b"TDRC").is_some());
+        assert!(find_frame(&written, b"TXXX").is_some());
+        assert!(find_frame(&written, b"TYER").is_none());
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("New"));
+        assert_eq!(metadata.artist.as_deref(), Some("Band"));
+        assert_eq!(metadata.year.as_deref(), Some("1999"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_id3v22_rewrite_warns_about_dropped_frames() {
+        let data = build_id3v2_file_version(2, &[
+            ("TT2", b"\x00Title".to_vec()),
+            ("CRM", b"owner\x00x".to_vec()),
+        ]);
+        let path = write_temp_file("v22-rewrite.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let warnings = captured_warnings(|| audio.set_id3v2_unique_file_id("owner", b"id").unwrap());
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].starts_with("Dropped CRM"), "{:?}", warnings);
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[3], 3);
+        assert!(find_frame(&written, b"UFID").is_some());
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Title"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_set_metadata_converts_id3v24_to_v23() {
+        let data = build_id3v2_file_version(4, &[
+            ("TIT2", "\u{3}Café ✓".as_bytes().to_vec()),
+            ("TDRC", b"\x032001-05-06".to_vec()),
+            ("TMOO", b"\x03Happy".to_vec()),
+            ("TXXX", "\u{3}key\u{0}välue ✓".as_bytes().to_vec()),
+        ]);
+        let path = write_temp_file("v24.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let options = WriteOptions { target_version: Some((2, 3)), ..Default::default() };
+        let report = audio.set_metadata_with_options("{}".to_string(), &options).unwrap();
+        assert_eq!(report.lossy_frames.len(), 1);
+        assert!(report.lossy_frames[0].starts_with("TMOO"));
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[3], 3);
+        assert!(find_frame(&written, b"TDRC").is_none());
+        // ID3v2.3 has no UTF-8, so non-Latin-1 text is re-encoded as UTF-16
+        let txxx = find_frame(&written, b"TXXX").unwrap();
+        assert_eq!(written[txxx + 10], 1);
+
+        // The release day goes to TDAT and is read back into the year
+        let tdat = find_frame(&written, b"TDAT").unwrap();
+        assert_eq!(&written[tdat + 11..tdat + 15], b"0605");
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Café ✓"));
+        assert_eq!(metadata.year.as_deref(), Some("2001-05-06"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    /// ftyp + moov (one track with an stco entry) + mdat, moov before mdat
+    fn build_m4a_file() -> Vec<u8> {
+        let ftyp = mp4_atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+        let build_moov = |chunk_offset: u32| {
+            let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+            stco.extend_from_slice(&chunk_offset.to_be_bytes());
+            let stbl = mp4_atom(b"stbl", &mp4_atom(b"stco", &stco));
+            let trak = mp4_atom(b"trak", &mp4_atom(b"mdia", &mp4_atom(b"minf", &stbl)));
+            mp4_atom(b"moov", &trak)
+        };
+        let audio_offset = (ftyp.len() + build_moov(0).len() + 8) as u32;
+
+        let mut data = ftyp;
+        data.extend(build_moov(audio_offset));
+        data.extend(mp4_atom(b"mdat", b"AUDIOAUDIO"));
+        data
+    }
+
+    /// Follow the single stco entry and return the bytes it points at
+    fn m4a_chunk(data: &[u8]) -> &[u8] {
+        let stco = find_frame(data, b"stco").unwrap();
+        let offset = u32::from_be_bytes(data[stco + 12..stco + 16].try_into().unwrap()) as usize;
+        &data[offset..offset + 10]
+    }
+
+    #[test]
+    fn test_mp4_cover_round_trip() {
+        let path = write_temp_file("cover.m4a", &build_m4a_file());
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.format, Format::Mp4);
+
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x00IEND\xaeB`\x82".to_vec();
+        let cover = CoverArt { data: png.clone(), mime_type: Some("image/png".to_string()), description: None };
+        let update = serde_json::json!({"title": "Song", "track": "2/9", "cover": cover});
+        audio.set_metadata(update.to_string()).unwrap();
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(m4a_chunk(&written), b"AUDIOAUDIO");
+        // covr data atom carries the PNG type flag (14)
+        let covr = find_frame(&written, b"covr").unwrap();
+        assert_eq!(&written[covr + 12..covr + 16], &14u32.to_be_bytes());
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.track.as_deref(), Some("2"));
+        let cover = metadata.cover.unwrap();
+        assert_eq!(cover.data, png);
+        assert_eq!(cover.mime_type.as_deref(), Some("image/png"));
+
+        // Clearing the cover removes the covr atom
+        audio.set_metadata(r#"{"cover": null}"#.to_string()).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(find_frame(&written, b"covr").is_none());
+        assert_eq!(m4a_chunk(&written), b"AUDIOAUDIO");
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_mp4_writes_multiple_covers() {
+        let path = write_temp_file("covers.m4a", &build_m4a_file());
+        let mp4_file = Mp4File::new(path.clone());
+        let covers = vec![
+            mp4::Mp4Cover::new(vec![0xFF, 0xD8, 0xFF, 0xD9], "image/jpeg"),
+            mp4::Mp4Cover::new(b"\x89PNG\r\n\x1a\n".to_vec(), "image/png"),
+        ];
+        let metadata = mp4::Mp4Metadata { covers: covers.clone(), ..Default::default() };
+        mp4_file.write_metadata(&metadata).unwrap();
+
+        assert_eq!(mp4_file.read_metadata().unwrap().unwrap().covers, covers);
+
+        // Rewriting text keeps both pictures
+        let audio = AudioFile::new(path.clone()).unwrap();
+        audio.set_metadata(r#"{"artist": "Band"}"#.to_string()).unwrap();
+        assert_eq!(mp4_file.read_metadata().unwrap().unwrap().covers, covers);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    /// Vorbis comment packet of exactly `len` bytes holding a single TITLE
+    fn vorbis_comment_packet(len: usize) -> Vec<u8> {
+        // type+"vorbis"(7) + vendor length(4) + count(4) + comment length(4) + framing(1)
+        let title = format!("TITLE={}", "a".repeat(len - 20 - 6));
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend_from_slice(&0u32.to_le_bytes());
+        packet.extend_from_slice(&1u32.to_le_bytes());
+        packet.extend_from_slice(&(title.len() as u32).to_le_bytes());
+        packet.extend_from_slice(title.as_bytes());
+        packet.push(1);
+        assert_eq!(packet.len(), len);
+        packet
+    }
+
+    #[test]
+    fn test_ogg_comment_packet_continues_on_next_page() {
+        let ident = b"\x01vorbis\x00\x00\x00\x00";
+        let packet = vorbis_comment_packet(610);
+
+        // Page 1 ends on a 255 lacing value, so the packet continues on page 2
+        let mut data = ogg_page(0, 0x02, &[ident.len() as u8], ident);
+        data.extend(ogg_page(1, 0x00, &[255, 255], &packet[..510]));
+        data.extend(ogg_page(2, 0x01, &[100], &packet[510..]));
+        let path = write_temp_file("continued.ogg", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.format, Format::OggVorbis);
+        assert_eq!(audio.read_metadata().unwrap().title.map(|t| t.len()), Some(610 - 26));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_ogg_comment_packet_ending_on_255_boundary() {
+        let ident = b"\x01vorbis\x00\x00\x00\x00";
+        let packet = vorbis_comment_packet(510);
+
+        // A packet of exactly 2 * 255 bytes is terminated by a 0 lacing value on the next page
+        let mut data = ogg_page(0, 0x02, &[ident.len() as u8], ident);
+        data.extend(ogg_page(1, 0x00, &[255, 255], &packet));
+        data.extend(ogg_page(2, 0x01, &[0, 4], b"\x05abc"));
+        let path = write_temp_file("boundary.ogg", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().title.map(|t| t.len()), Some(510 - 26));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_optimize_flac_merges_padding_and_drops_unknown_application() {
+        let mut data = FLAC_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+        data.extend_from_slice(&[0u8; 34]);
+        data.extend_from_slice(&[0x01, 0x00, 0x10, 0x00]);
+        data.extend_from_slice(&[0u8; 4096]);
+        data.extend_from_slice(&[0x02, 0x00, 0x00, 0x06]);
+        data.extend_from_slice(b"ZZZZ\x01\x02");
+        data.extend_from_slice(&[0x02, 0x00, 0x00, 0x06]);
+        data.extend_from_slice(b"riff\x01\x02");
+        data.extend_from_slice(&[0x81, 0x00, 0x04, 0x00]);
+        data.extend_from_slice(&[0u8; 1024]);
+        data.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+        let path = write_temp_file("optimize.flac", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let report = audio.optimize().unwrap();
+        assert_eq!(report.changes.len(), 2);
+        // 1024 bytes of padding, one 4-byte block header and the 10-byte application block
+        assert_eq!(report.bytes_saved, 1024 + 4 + 10);
+
+        let blocks = audio.get_all_metadata_blocks().unwrap();
+        let summary: Vec<_> = blocks.iter()
+            .map(|block| (block.block_type.as_str(), block.size_bytes, block.is_last))
+            .collect();
+        assert_eq!(summary, vec![
+            ("STREAMINFO", 34, false),
+            ("APPLICATION", 6, false),
+            ("PADDING", 4096, true),
+        ]);
+        assert!(std::fs::read(&path).unwrap().ends_with(&[0xFF, 0xF8, 0x69, 0x08]));
+
+        // A second pass has nothing left to do
+        assert!(audio.optimize().unwrap().changes.is_empty());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_optimize_id3v2_removes_duplicates_and_resets_padding() {
+        let mut data = build_id3v2_file(&[
+            ("TIT2", b"\x03Song".to_vec()),
+            ("TXXX", b"\x03KEY\x00value".to_vec()),
+            ("TXXX", b"\x03KEY\x00value".to_vec()),
+        ]);
+        let audio_data = data.split_off(data.len() - 4);
+        data.extend(vec![0u8; 8192]);
+        let size = (data.len() - 10) as u32;
+        data[6..10].copy_from_slice(&[
+            ((size >> 21) & 0x7F) as u8,
+            ((size >> 14) & 0x7F) as u8,
+            ((size >> 7) & 0x7F) as u8,
+            (size & 0x7F) as u8,
+        ]);
+        data.extend_from_slice(&audio_data);
+        let path = write_temp_file("optimize.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let report = audio.optimize().unwrap();
+        assert_eq!(report.changes.len(), 2);
+        // The duplicate frame is 10 bytes of header plus 10 bytes of data
+        assert_eq!(report.bytes_saved, 8192 - 512 + 20);
+
+        let new_data = std::fs::read(&path).unwrap();
+        assert_eq!(new_data.len() as i64, data.len() as i64 - report.bytes_saved);
+        assert!(find_frame(&new_data, b"TXXX").is_some());
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+        assert!(new_data.ends_with(&audio_data));
+        std::fs::remove_file(path).ok();
+
+        // ID3v2.2 frames that can't be upgraded are named in the report
+        let path = write_temp_file("optimize-v22.mp3", &build_id3v2_file_version(2, &[
+            ("TT2", b"\x00Song".to_vec()),
+            ("CRM", b"owner\x00x".to_vec()),
+        ]));
+        let report = AudioFile::new(path.clone()).unwrap().optimize().unwrap();
+        assert!(report.changes.iter().any(|change| change.starts_with("Dropped CRM")), "{:?}", report.changes);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_optimize_ogg_removes_empty_comments_and_keeps_setup_header() {
+        let ident = b"\x01vorbis\x00\x00\x00\x00";
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend_from_slice(&0u32.to_le_bytes());
+        comment.extend_from_slice(&2u32.to_le_bytes());
+        for entry in [&b"TITLE=Song"[..], b"GENRE="] {
+            comment.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            comment.extend_from_slice(entry);
+        }
+        comment.push(1);
+        let setup = b"\x05vorbis-setup";
+
+        let mut data = ogg_page(0, 0x02, &[ident.len() as u8], ident);
+        let page1_data = [comment.as_slice(), setup].concat();
+        data.extend(ogg_page(1, 0x00, &[comment.len() as u8, setup.len() as u8], &page1_data));
+        data.extend(ogg_page(2, 0x04, &[4], b"\xAA\xBB\xCC\xDD"));
+        let path = write_temp_file("optimize.ogg", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let report = audio.optimize().unwrap();
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.bytes_saved, 4 + 6);
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+
+        let new_data = std::fs::read(&path).unwrap();
+        let mut reader = std::io::Cursor::new(&new_data);
+        let packets = ogg::page::OggPage::read_packets(&mut reader, 4).unwrap();
+        assert_eq!(packets[2], setup);
+        assert_eq!(packets[3], b"\xAA\xBB\xCC\xDD");
+
+        // The rewritten comment page carries a valid CRC
+        let page_start = ogg_page(0, 0x02, &[ident.len() as u8], ident).len();
+        let page_len = 27 + 2 + comment.len() - 10 + setup.len();
+        let mut page = new_data[page_start..page_start + page_len].to_vec();
+        let stored = u32::from_le_bytes(page[22..26].try_into().unwrap());
+        page[22..26].fill(0);
+        assert_eq!(ogg::page::page_crc(&page), stored);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    fn sha256_file(path: &str) -> Vec<u8> {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(std::fs::read(path).unwrap()).to_vec()
+    }
+
+    /// Write the same metadata deterministically to each source and return the output hashes
+    fn deterministic_hashes(name: &str, sources: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let options = WriteOptions { deterministic: true, ..Default::default() };
+        let json = r#"{"title": "Archived", "artist": "Band"}"#;
+
+        sources.iter().enumerate().map(|(index, data)| {
+            let path = write_temp_file(&format!("{}-{}", index, name), data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            audio.set_metadata_with_options(json.to_string(), &options).unwrap();
+            assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Archived"));
+            let hash = sha256_file(&path);
+            std::fs::remove_file(path).ok();
+            hash
+        }).collect()
+    }
+
+    #[test]
+    fn test_deterministic_write_mp3() {
+        let source = build_id3v2_file(&[
+            ("TXXX", b"\x03B\x00two".to_vec()),
+            ("TXXX", b"\x03A\x00one".to_vec()),
+            ("TIT2", b"\x03Old".to_vec()),
+        ]);
+        // Same tags in a different order, with padding
+        let mut reordered = build_id3v2_file(&[
+            ("TIT2", b"\x03Old".to_vec()),
+            ("TXXX", b"\x03A\x00one".to_vec()),
+            ("TXXX", b"\x03B\x00two".to_vec()),
+        ]);
+        let audio_data = reordered.split_off(reordered.len() - 4);
+        reordered.extend(vec![0u8; 300]);
+        let size = (reordered.len() - 10) as u32;
+        reordered[6..10].copy_from_slice(&[
+            ((size >> 21) & 0x7F) as u8,
+            ((size >> 14) & 0x7F) as u8,
+            ((size >> 7) & 0x7F) as u8,
+            (size & 0x7F) as u8,
+        ]);
+        reordered.extend_from_slice(&audio_data);
+
+        let hashes = deterministic_hashes("deterministic.mp3", &[source.clone(), source, reordered]);
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(hashes[0], hashes[2]);
+    }
+
+    #[test]
+    fn test_deterministic_write_flac() {
+        let source = build_flac_file("libFLAC 1.4.3", &[("ZZZ", "1"), ("AAA", "2"), ("TITLE", "Old")], 100);
+        let reordered = build_flac_file("reference libFLAC 1.2.1", &[("TITLE", "Old"), ("AAA", "2"), ("ZZZ", "1")], 10);
+
+        let hashes = deterministic_hashes("deterministic.flac", &[source.clone(), source, reordered]);
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(hashes[0], hashes[2]);
+    }
+
+    #[test]
+    fn test_deterministic_write_ogg() {
+        let source = build_ogg_file("Xiph.Org libVorbis I 20200704", &[("ZZZ", "1"), ("AAA", "2")]);
+        let reordered = build_ogg_file("Lavf60.3.100", &[("AAA", "2"), ("ZZZ", "1")]);
+
+        let hashes = deterministic_hashes("deterministic.ogg", &[source.clone(), source, reordered]);
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(hashes[0], hashes[2]);
+    }
+
+    fn cue_track(offset: u64, number: u8, isrc: &[u8], indices: &[(u64, u8)]) -> Vec<u8> {
+        let mut track = offset.to_be_bytes().to_vec();
+        track.push(number);
+        let mut isrc_field = [0u8; 12];
+        isrc_field[..isrc.len()].copy_from_slice(isrc);
+        track.extend_from_slice(&isrc_field);
+        track.extend_from_slice(&[0u8; 14]);
+        track.push(indices.len() as u8);
+        for (index_offset, index) in indices {
+            track.extend_from_slice(&index_offset.to_be_bytes());
+            track.push(*index);
+            track.extend_from_slice(&[0u8; 3]);
+        }
+        track
+    }
+
+    fn build_cue_flac(track1_indices: &[(u64, u8)]) -> Vec<u8> {
+        let mut cue = vec![0u8; 128];
+        cue.extend_from_slice(&88200u64.to_be_bytes());
+        let mut flags = [0u8; 259];
+        flags[0] = 0x80;
+        cue.extend_from_slice(&flags);
+        cue.push(3);
+        cue.extend(cue_track(0, 1, b"USABC1234567", track1_indices));
+        cue.extend(cue_track(441_000, 2, b"", &[(0, 1)]));
+        cue.extend(cue_track(882_000, 170, b"", &[]));
+
+        let mut data = FLAC_SIGNATURE.to_vec();
+        data.extend(FlacMetadataBlock::new(FlacMetadataBlockType::StreamInfo, vec![0u8; 34]).to_bytes(false));
+        data.extend(FlacMetadataBlock::new(FlacMetadataBlockType::CueSheet, cue).to_bytes(true));
+        data.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+        data
+    }
+
+    #[test]
+    fn test_cue_sheet_tracks_and_hidden_track() {
+        let path = write_temp_file("hidden-track.flac", &build_cue_flac(&[(0, 0), (132_300, 1)]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        assert!(audio.has_embedded_cue().unwrap());
+        assert!(audio.has_hidden_track().unwrap());
+
+        // The lead-out track is not listed; index offsets are absolute
+        let tracks = audio.get_cue_tracks().unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].isrc, "USABC1234567");
+        assert_eq!(tracks[0].indices.len(), 2);
+        assert_eq!(tracks[1].number, 2);
+        assert_eq!(tracks[1].indices[0].sample_offset, 441_000);
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("no-hidden-track.flac", &build_cue_flac(&[(0, 1)]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.has_embedded_cue().unwrap());
+        assert!(!audio.has_hidden_track().unwrap());
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("no-cue.flac", &build_flac_file("vendor", &[], 8));
+        assert!(!AudioFile::new(path.clone()).unwrap().has_embedded_cue().unwrap());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_multiple_genres_round_trip() {
+        let path = write_temp_file("genres-v23.mp3", &build_id3v2_file(&[("TCON", b"\x00(17)(79)Custom".to_vec())]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.genres, vec!["Rock", "Hard Rock", "Custom"]);
+        assert_eq!(metadata.genre.as_deref(), Some("Rock; Hard Rock; Custom"));
+        assert_eq!(metadata.genre_raw.as_deref(), Some("(17)(79)Custom"));
+
+        // ID3v2.3 keeps the reference form
+        audio.set_metadata(r#"{"genres": ["Rock", "Hard Rock", "Custom"]}"#.to_string()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.genre_raw.as_deref(), Some("(17)(79)Custom"));
+        assert_eq!(metadata.genres, vec!["Rock", "Hard Rock", "Custom"]);
+
+        // ID3v2.4 writes NUL-separated names
+        let v24 = WriteOptions { target_version: Some((2, 4)), ..Default::default() };
+        audio.set_metadata_with_options("{}".to_string(), &v24).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.genre_raw.as_deref(), Some("Rock\0Hard Rock\0Custom"));
+        assert_eq!(metadata.genres, vec!["Rock", "Hard Rock", "Custom"]);
+
+        // A joined string set on its own is split on ";"
+        audio.set_metadata_with_options(r#"{"genre": "Jazz; Indie"}"#.to_string(), &v24).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().genres, vec!["Jazz", "Indie"]);
+        std::fs::remove_file(path).ok();
+
+        let flac_path = write_temp_file("genres.flac", &build_flac_file("vendor", &[("GENRE", "Rock"), ("GENRE", "Indie")], 64));
+        let flac = AudioFile::new(flac_path.clone()).unwrap();
+        let metadata = flac.read_metadata().unwrap();
+        assert_eq!(metadata.genre.as_deref(), Some("Rock; Indie"));
+        assert_eq!(metadata.genre_raw, None);
+
+        flac.set_metadata(r#"{"genres": ["Rock", "Hard Rock", "Custom"]}"#.to_string()).unwrap();
+        assert_eq!(flac.read_metadata().unwrap().genres, vec!["Rock", "Hard Rock", "Custom"]);
+        std::fs::remove_file(flac_path).ok();
+    }
+
+    #[test]
+    fn test_flac_multiple_genres() {
+        let data = build_flac_file("vendor", &[("GENRE", "Rock"), ("TITLE", "Song"), ("genre", "Jazz")], 64);
+        let path = write_temp_file("two-genres.flac", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.genre.as_deref(), Some("Rock; Jazz"));
+        assert_eq!(metadata.genres, vec!["Rock", "Jazz"]);
+
+        // Untouched genres survive a write of other fields
+        audio.set_metadata(r#"{"title": "New"}"#.to_string()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().genres, vec!["Rock", "Jazz"]);
+
+        audio.set_metadata(r#"{"genres": ["Blues", "Soul"]}"#.to_string()).unwrap();
+        let comment = flac::vorbis::VorbisComment::read(&mut std::io::Cursor::new(
+            &std::fs::read(&path).unwrap()[4 + 4 + 34 + 4..],
+        )).unwrap();
+        let genres: Vec<_> = comment.comments.iter().filter(|(key, _)| key == "GENRE").map(|(_, v)| v.as_str()).collect();
+        assert_eq!(genres, vec!["Blues", "Soul"]);
+
+        // Setting the scalar genre replaces the list
+        audio.set_metadata(r#"{"genre": "Pop"}"#.to_string()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.genre.as_deref(), Some("Pop"));
+        assert_eq!(metadata.genres, vec!["Pop"]);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_numeric_genre_normalized_on_write() {
+        let data = build_id3v2_file(&[("TCON", b"\x00(17)".to_vec())]);
+        let path = write_temp_file("numeric-genre.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.genre_raw.as_deref(), Some("(17)"));
+
+        let reject = WriteOptions { genre_policy: Some(GenrePolicy::Reject), ..Default::default() };
+        audio.set_metadata_with_options("{}".to_string(), &reject).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().genre.as_deref(), Some("Rock"));
+
+        let err = audio.set_metadata_with_options(r#"{"genre": "Vaporwave"}"#.to_string(), &reject).unwrap_err();
+        assert!(err.to_string().contains("Vaporwave"));
+
+        // ID3v2.4 stores several genres NUL-separated in one TCON frame
+        let options = WriteOptions {
+            target_version: Some((2, 4)),
+            genre_policy: Some(GenrePolicy::PassThrough),
+            ..Default::default()
+        };
+        audio.set_metadata_with_options(r#"{"genres": ["rock", "Vaporwave"]}"#.to_string(), &options).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().genres, vec!["Rock", "Vaporwave"]);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_audio_span_excludes_id3v2_and_id3v1() {
+        let mut data = build_id3v2_file(&[("TIT2", b"\x03Song".to_vec())]);
+        let tag_len = data.len() as u64 - 4;
+        data.extend_from_slice(&[0x11; 96]);
+        let mut v1 = vec![0u8; 128];
+        v1[0..3].copy_from_slice(b"TAG");
+        data.extend_from_slice(&v1);
+        let path = write_temp_file("span.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_audio_span().unwrap(), (tag_len, 100));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_get_all_metadata_blocks_lists_unusual_layouts() {
+        let mut data = FLAC_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+        data.extend_from_slice(&[0u8; 34]);
+        data.extend_from_slice(&[0x01, 0x00, 0x00, 0x08]);
+        data.extend_from_slice(&[0u8; 8]);
+        data.extend_from_slice(&[0x09, 0x00, 0x00, 0x02]);
+        data.extend_from_slice(&[0u8; 2]);
+        data.extend_from_slice(&[0x81, 0x00, 0x00, 0x04]);
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+        let path = write_temp_file("blocks.flac", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let blocks = audio.get_all_metadata_blocks().unwrap();
+        let summary: Vec<_> = blocks.iter()
+            .map(|block| (block.block_type.as_str(), block.size_bytes, block.offset, block.is_last))
+            .collect();
+        assert_eq!(summary, vec![
+            ("STREAMINFO", 34, 4, false),
+            ("PADDING", 8, 42, false),
+            ("UNKNOWN(9)", 2, 54, false),
+            ("PADDING", 4, 60, true),
+        ]);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_audio_span_flac_starts_after_last_block() {
+        let mut data = FLAC_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+        data.extend_from_slice(&[0u8; 34]);
+        data.extend_from_slice(&[0x81, 0x00, 0x00, 0x10]);
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+        let path = write_temp_file("span.flac", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_audio_span().unwrap(), (62, 4));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_guarded_write_detects_modified_audio() {
+        let data = build_id3v2_file(&[("TIT2", b"\x03Song".to_vec())]);
+        let path = write_temp_file("guard.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        // A well-behaved writer only touches the tag
+        audio.guarded_write(|| {
+            let mut bytes = std::fs::read(&path)?;
+            bytes[15] = b'X';
+            std::fs::write(&path, bytes)?;
+            Ok(())
+        }).unwrap();
+
+        // A broken writer that clobbers the audio must be caught
+        let result = audio.guarded_write(|| {
+            let mut bytes = std::fs::read(&path)?;
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0xFF;
+            std::fs::write(&path, bytes)?;
+            Ok(())
+        });
+        assert!(matches!(result, Err(AudioFileError::AudioIntegrityError(_))));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[cfg(feature = "toml-output")]
+    #[test]
+    fn test_metadata_toml_uses_multiline_lyrics() {
+        let mut uslt = b"\x03eng\x00".to_vec();
+        uslt.extend_from_slice(b"Line one\nLine two");
+        let data = build_id3v2_file(&[("TIT2", b"\x03Song".to_vec()), ("USLT", uslt)]);
+        let path = write_temp_file("toml.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let output = audio.get_metadata_as_toml().unwrap();
+        assert!(output.contains("title = \"Song\""));
+        assert!(output.contains("lyrics = \"\"\""));
+
+        let parsed: Metadata = toml::from_str(&output).unwrap();
+        assert_eq!(parsed.lyrics.as_deref(), Some("Line one\nLine two"));
+        audio.set_metadata_from_toml(&output).unwrap();
+        assert!(audio.set_metadata_from_toml("title = ").is_err());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_validate_reports_truncated_jpeg_cover() {
+        let truncated = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46];
+        let data = build_id3v2_file(&[("APIC", build_apic_frame(&truncated))]);
+        let path = write_temp_file("truncated-cover.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        // Reading still succeeds; the problem is only reported by validate
+        assert!(audio.read_metadata().unwrap().cover.is_some());
+        let issues = audio.validate().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "cover");
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_validate_accepts_complete_jpeg_cover() {
+        let complete = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0xFF, 0xD9];
+        let data = build_id3v2_file(&[("APIC", build_apic_frame(&complete))]);
+        let path = write_temp_file("complete-cover.mp3", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.validate().unwrap().is_empty());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    fn build_id3v1_tag(title: &str, artist: &str, album: &str, year: &str, track: Option<u8>) -> [u8; 128] {
+        crate::id3::Id3v1Tag {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            year: year.to_string(),
+            comment: String::new(),
+            track,
+            genre: 17,
+            extended: None,
+        }.to_bytes()
+    }
+
+    fn build_mismatched_id3_file(name: &str) -> String {
+        let long_album = "An Album Name Longer Than Thirty Characters";
+        let mut data = build_id3v2_file(&[
+            ("TIT2", b"\x03New Title".to_vec()),
+            ("TPE1", b"\x03New Artist".to_vec()),
+            ("TALB", [b"\x03".as_slice(), long_album.as_bytes()].concat()),
+            ("TYER", b"\x032021".to_vec()),
+            ("TRCK", b"\x035/12".to_vec()),
+        ]);
+        data.extend_from_slice(&build_id3v1_tag("Old Title", "Old Artist", &long_album[..30], "2021", Some(3)));
+        write_temp_file(name, &data)
+    }
+
+    #[test]
+    fn test_compare_id3_tags_reports_conflicts() {
+        let path = build_mismatched_id3_file("id3-mismatch.mp3");
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let mismatches = audio.compare_id3_tags().unwrap();
+        let fields: Vec<&str> = mismatches.iter().map(|m| m.field.as_str()).collect();
+        // The album only differs by ID3v1 truncation
+        assert_eq!(fields, vec!["title", "artist", "track"]);
+        assert_eq!(mismatches[0].id3v1, "Old Title");
+        assert_eq!(mismatches[0].id3v2, "New Title");
+
+        let issues = audio.validate().unwrap();
+        assert_eq!(issues.len(), 3);
+        assert!(issues.iter().all(|issue| issue.code == "id3-mismatch"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_resolve_id3_tags_v2_wins() {
+        let path = build_mismatched_id3_file("id3-resolve-v2.mp3");
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let before = std::fs::metadata(&path).unwrap().len();
+
+        let resolved = audio.resolve_id3_tags(TagResolution::V2Wins).unwrap();
+        assert_eq!(resolved.len(), 3);
+        assert!(audio.compare_id3_tags().unwrap().is_empty());
+
+        // The ID3v1 tag is replaced in place and keeps its genre
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), before);
+        let v1 = crate::id3::Id3v1Tag::read_from_file(&path).unwrap().unwrap();
+        assert_eq!(v1.title, "New Title");
+        assert_eq!(v1.track, Some(5));
+        assert_eq!(v1.genre, 17);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_resolve_id3_tags_v1_wins() {
+        let path = build_mismatched_id3_file("id3-resolve-v1.mp3");
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        audio.resolve_id3_tags(TagResolution::V1Wins).unwrap();
+        assert!(audio.compare_id3_tags().unwrap().is_empty());
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Old Title"));
+        assert_eq!(metadata.artist.as_deref(), Some("Old Artist"));
+        assert_eq!(metadata.track.as_deref(), Some("3"));
+        // Untouched fields keep their full ID3v2 value
+        assert_eq!(metadata.album.as_deref(), Some("An Album Name Longer Than Thirty Characters"));
+        assert!(crate::id3::Id3v1Tag::read_from_file(&path).unwrap().is_some());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_get_metadata_excludes_cover_when_requested() {
+        let mut apic = vec![0u8];
+        apic.extend_from_slice(b"image/jpeg\0");
+        apic.push(3);
+        apic.push(0);
+        apic.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xE0, 0xFF, 0xD9]);
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x03Song".to_vec()),
+            ("APIC", apic),
+        ]);
+        let path = write_temp_file("exclude-cover.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let with_cover: serde_json::Value = serde_json::from_str(&audio.get_metadata().unwrap()).unwrap();
+        assert!(with_cover.get("cover").is_some());
+
+        let options = ReadOptions { include_cover: false, ..Default::default() };
+        let without_cover: serde_json::Value =
+            serde_json::from_str(&audio.get_metadata_with_options(&options).unwrap()).unwrap();
+        assert!(without_cover.get("cover").is_none());
+        assert_eq!(without_cover["title"], "Song");
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_metadata_len_counts_populated_fields() {
+        let mut metadata = Metadata::default();
+        assert_eq!(metadata.len(), 0);
+        assert!(metadata.is_empty());
+
+        metadata.title = Some("Song".to_string());
+        metadata.artist = Some("Band".to_string());
+        metadata.cover = Some(CoverArt {
+            data: vec![0xFF, 0xD8],
+            mime_type: Some("image/jpeg".to_string()),
+            description: None,
+        });
+        assert_eq!(metadata.len(), 3);
+        assert!(!metadata.is_empty());
+    }
+
+    fn merge_sources() -> (Metadata, Metadata) {
+        let cover = CoverArt {
+            data: vec![0xFF, 0xD8, 0xFF, 0xD9],
+            mime_type: Some("image/jpeg".to_string()),
+            description: None,
+        };
+        let scanned = Metadata {
+            title: Some("Scanned Title".to_string()),
+            artist: Some(" ".to_string()),
+            cover: Some(cover),
+            ..Default::default()
+        };
+        let lookup = Metadata {
+            title: Some("Lookup Title".to_string()),
+            artist: Some("Lookup Artist".to_string()),
+            album: Some("Lookup Album".to_string()),
+            ..Default::default()
+        };
+        (scanned, lookup)
+    }
+
+    #[test]
+    fn test_metadata_merge_prefer_self() {
+        let (mut scanned, lookup) = merge_sources();
+        scanned.merge(&lookup, MergePolicy::PreferSelf);
+        assert_eq!(scanned.title.as_deref(), Some("Scanned Title"));
+        assert_eq!(scanned.artist.as_deref(), Some(" "));
+        assert_eq!(scanned.album.as_deref(), Some("Lookup Album"));
+        // The other side has no cover, so ours is kept
+        assert!(scanned.cover.is_some());
+    }
+
+    #[test]
+    fn test_metadata_merge_prefer_other() {
+        let (mut scanned, lookup) = merge_sources();
+        scanned.merge(&lookup, MergePolicy::PreferOther);
+        assert_eq!(scanned.title.as_deref(), Some("Lookup Title"));
+        assert_eq!(scanned.artist.as_deref(), Some("Lookup Artist"));
+        assert_eq!(scanned.album.as_deref(), Some("Lookup Album"));
+        // A missing cover on the other side doesn't clear ours
+        assert!(scanned.cover.is_some());
+
+        let (scanned, mut lookup) = merge_sources();
+        lookup.merge(&scanned, MergePolicy::PreferOther);
+        assert_eq!(lookup.cover.map(|cover| cover.data), Some(vec![0xFF, 0xD8, 0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn test_metadata_merge_fill_empty() {
+        let (mut scanned, lookup) = merge_sources();
+        scanned.merge(&lookup, MergePolicy::FillEmpty);
+        assert_eq!(scanned.title.as_deref(), Some("Scanned Title"));
+        // Blank text counts as empty
+        assert_eq!(scanned.artist.as_deref(), Some("Lookup Artist"));
+        assert_eq!(scanned.album.as_deref(), Some("Lookup Album"));
+
+        let (scanned, mut lookup) = merge_sources();
+        lookup.merge(&scanned, MergePolicy::FillEmpty);
+        assert_eq!(lookup.title.as_deref(), Some("Lookup Title"));
+        assert!(lookup.cover.is_some());
+    }
+
+    #[test]
+    fn test_read_metadata_fills_missing_id3v2_fields_from_id3v1() {
+        let mut data = build_id3v2_file(&[("TIT2", b"\x03Full Title".to_vec())]);
+        data.extend_from_slice(&build_id3v1_tag("Full Title", "V1 Artist", "", "1999", None));
+        let path = write_temp_file("merge-read.mp3", &data);
+
+        let metadata = AudioFile::new(path.clone()).unwrap().read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Full Title"));
+        assert_eq!(metadata.artist.as_deref(), Some("V1 Artist"));
+        assert_eq!(metadata.year.as_deref(), Some("1999"));
+        assert_eq!(metadata.album, None);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    /// Build a VBR MP3 (MPEG-1 Layer III, 44.1 kHz stereo) with `frames` audio frames
+    ///
+    /// With `xing`, a leading Info frame carries the frame count, as LAME writes it.
+    fn build_vbr_mp3(frames: u32, xing: bool) -> Vec<u8> {
+        let mut data = build_id3v2_file(&[("TSSE", b"\x00LAME3.100".to_vec())]);
+        data.truncate(data.len() - 4);
+
+        // Bitrate indices 5, 9, 11 and 14: 64, 128, 192 and 320 kbit/s
+        let frame = |bitrate_index: u8, bitrate: usize| {
+            let mut frame = vec![0u8; 144 * bitrate * 1000 / 44100];
+            frame[..4].copy_from_slice(&[0xFF, 0xFB, bitrate_index << 4, 0x00]);
+            frame
+        };
+        if xing {
+            let mut info = frame(9, 128);
+            info[36..40].copy_from_slice(b"Xing");
+            info[40..44].copy_from_slice(&1u32.to_be_bytes());
+            info[44..48].copy_from_slice(&frames.to_be_bytes());
+            data.extend(info);
+        }
+        let bitrates = [(5, 64), (9, 128), (11, 192), (14, 320)];
+        for i in 0..frames as usize {
+            let (index, bitrate) = bitrates[i * 7 % bitrates.len()];
+            data.extend(frame(index, bitrate));
+        }
+        data
+    }
+
+    #[test]
+    fn test_auto_length_matches_vbr_mp3_duration() {
+        let frames = 400;
+        let expected_ms = frames as u64 * 1152 * 1000 / 44100;
+
+        for xing in [true, false] {
+            let path = write_temp_file(&format!("vbr-{}.mp3", xing), &build_vbr_mp3(frames, xing));
+            let audio = AudioFile::new(path.clone()).unwrap();
+
+            let properties = audio.get_properties().unwrap();
+            assert_eq!(properties.sample_rate, Some(44100));
+            assert_eq!(properties.channels, Some(2));
+
+            let options = WriteOptions { auto_length: true, ..Default::default() };
+            audio.set_metadata_with_options(r#"{"title": "VBR"}"#.to_string(), &options).unwrap();
+
+            let metadata = audio.read_metadata().unwrap();
+            let length_ms = metadata.length_ms.unwrap();
+            assert!(length_ms.abs_diff(expected_ms) <= 100, "TLEN {} vs {}", length_ms, expected_ms);
+            // The encoder is preserved, never replaced by the writer
+            assert_eq!(metadata.encoder.as_deref(), Some("LAME3.100"));
+
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_length_and_encoder_vorbis_round_trip() {
+        let path = write_temp_file("length.flac", &build_flac_file("ref", &[("LENGTH", "3:35"), ("ENCODER", "flac 1.4")], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.length_ms, Some(215_000));
+        assert_eq!(metadata.encoder.as_deref(), Some("flac 1.4"));
+
+        audio.set_metadata(r#"{"length_ms": 1234, "encoder": "custom"}"#.to_string()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.length_ms, Some(1234));
+        assert_eq!(metadata.encoder.as_deref(), Some("custom"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    /// STREAMINFO block data for 16-bit stereo at `sample_rate` with `total_samples`
+    fn streaminfo(sample_rate: u32, total_samples: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 34];
+        let packed = (sample_rate as u64) << 44 | 1 << 41 | 15 << 36 | total_samples;
+        data[10..18].copy_from_slice(&packed.to_be_bytes());
+        data
+    }
+
+    fn build_flac_with_streaminfo(info: Vec<u8>, comments: &[(&str, &str)], audio: &[u8]) -> Vec<u8> {
+        let mut data = build_flac_file("ref", comments, 16);
+        data.truncate(data.len() - 4);
+        data[8..42].copy_from_slice(&info);
+        data.extend_from_slice(audio);
+        data
+    }
+
+    #[test]
+    fn test_clone_audio_properties_into_id3v2() {
+        let source = write_temp_file("clone-source.flac", &build_flac_with_streaminfo(
+            streaminfo(44100, 441_000), &[("ENCODER", "flac 1.4.3")], &[0xFF, 0xF8, 0x69, 0x08],
+        ));
+        let target = write_temp_file("clone-target.mp3", &build_id3v2_file(&[
+            ("TIT2", b"\x00Song".to_vec()),
+            ("TXXX", b"\x00SAMPLERATE\x0048000".to_vec()),
+        ]));
+
+        let audio = AudioFile::new(target.clone()).unwrap();
+        assert_eq!(AudioFile::new(source.clone()).unwrap().get_properties().unwrap().duration_ms, 10_000);
+        audio.clone_audio_properties_from(&source).unwrap();
+
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.length_ms, Some(10_000));
+        assert_eq!(metadata.encoder.as_deref(), Some("flac 1.4.3"));
+
+        let tag = audio.read_id3v2_tag().unwrap().unwrap();
+        let user_text: Vec<_> = tag.frames.iter()
+            .filter(|frame| frame.frame_id == "TXXX")
+            .filter_map(|frame| id3::frames::decode_txxx_frame(&frame.data))
+            .collect();
+        assert_eq!(user_text, vec![
+            ("SAMPLERATE".to_string(), "44100".to_string()),
+            ("CHANNELS".to_string(), "2".to_string()),
+            ("BITSPERSAMPLE".to_string(), "16".to_string()),
+        ]);
+
+        std::fs::remove_file(source).ok();
+        std::fs::remove_file(target).ok();
+    }
+
+    #[test]
+    fn test_clone_audio_properties_into_flac_requires_identical_audio() {
+        let frames = [0xFF, 0xF8, 0x69, 0x08, 0x01, 0x02];
+        let source = write_temp_file("clone-streaminfo.flac", &build_flac_with_streaminfo(
+            streaminfo(48000, 96_000), &[], &frames,
+        ));
+        let same = write_temp_file("clone-same.flac", &build_flac_with_streaminfo(
+            vec![0u8; 34], &[("TITLE", "Same")], &frames,
+        ));
+        let other = write_temp_file("clone-other.flac", &build_flac_with_streaminfo(
+            vec![0u8; 34], &[], &[0xFF, 0xF8, 0x69, 0x08, 0x03],
+        ));
+
+        let audio = AudioFile::new(same.clone()).unwrap();
+        audio.clone_audio_properties_from(&source).unwrap();
+        assert_eq!(audio.get_properties().unwrap().duration_ms, 2000);
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Same"));
+
+        let audio = AudioFile::new(other.clone()).unwrap();
+        assert!(matches!(
+            audio.clone_audio_properties_from(&source),
+            Err(AudioFileError::AudioIntegrityError(_))
+        ));
+        assert!(audio.get_properties().is_err());
+
+        for path in [source, same, other] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    /// Copy every tag field from one file to another through the JSON interface
+    fn copy_tags(from: &AudioFile, to: &AudioFile) {
+        let metadata = from.read_metadata_with_options(&ReadOptions { include_cover: false, ..Default::default() }).unwrap();
+        to.set_metadata(serde_json::to_string(&metadata).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_original_release_survives_flac_to_mp3_to_m4a() {
+        let flac = write_temp_file("original.flac", &build_flac_file("ref", &[
+            ("DATE", "2011-09-26"),
+            ("ORIGINALYEAR", "1977"),
+            ("ORIGINALDATE", "1977-05-25"),
+            ("ORIGINALARTIST", "The Originals"),
+            ("ORIGINALALBUM", "First Pressing"),
+        ], 64));
+        let mp3 = write_temp_file("original.mp3", &build_id3v2_file_version(4, &[("TIT2", b"\x00Song".to_vec())]));
+        let m4a = write_temp_file("original.m4a", &build_m4a_file());
+
+        let flac = AudioFile::new(flac).unwrap();
+        let mp3 = AudioFile::new(mp3).unwrap();
+        let m4a = AudioFile::new(m4a).unwrap();
+        copy_tags(&flac, &mp3);
+        copy_tags(&mp3, &m4a);
+
+        let written = std::fs::read(&mp3.path).unwrap();
+        assert!(find_frame(&written, b"TDOR").is_some());
+        for audio in [&flac, &mp3, &m4a] {
+            let metadata = audio.read_metadata().unwrap();
+            assert_eq!(metadata.year.as_deref(), Some("2011-09-26"), "{}", audio.path);
+            assert_eq!(metadata.original_date.as_deref(), Some("1977-05-25"), "{}", audio.path);
+            assert_eq!(metadata.original_artist.as_deref(), Some("The Originals"), "{}", audio.path);
+            assert_eq!(metadata.original_album.as_deref(), Some("First Pressing"), "{}", audio.path);
+        }
+
+        // Copying back to FLAC writes ORIGINALYEAR alongside ORIGINALDATE
+        copy_tags(&m4a, &flac);
+        let written = std::fs::read(&flac.path).unwrap();
+        assert!(find_frame(&written, b"ORIGINALYEAR=1977").is_some());
+
+        for audio in [flac, mp3, m4a] {
+            std::fs::remove_file(audio.path).ok();
+        }
+    }
+
+    #[test]
+    fn test_original_date_id3v23_uses_tory_year() {
+        let path = write_temp_file("original-v23.mp3", &build_id3v2_file(&[("TIT2", b"\x00Song".to_vec())]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let report = audio.set_metadata_with_options(r#"{"original_date": "1977-05-25"}"#.to_string(), &WriteOptions::default()).unwrap();
+        assert_eq!(report.lossy_frames, [r#"TDOR: "1977-05-25" cut to "1977" for ID3v2.3"#]);
+
+        let written = std::fs::read(&path).unwrap();
+        assert!(find_frame(&written, b"TORY").is_some());
+        assert!(find_frame(&written, b"TDOR").is_none());
+        assert_eq!(audio.read_metadata().unwrap().original_date.as_deref(), Some("1977"));
+
+        // The release date keeps its day and time in TDAT and TIME; only the seconds are lost
+        let report = audio.set_metadata_with_options(r#"{"year": "2001-05-12T10:30:15"}"#.to_string(), &WriteOptions::default()).unwrap();
+        assert_eq!(report.lossy_frames, [r#"TDRC: "2001-05-12T10:30:15" cut to "2001-05-12T10:30" for ID3v2.3"#]);
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.year.as_deref(), Some("2001-05-12T10:30"));
+        assert_eq!(audio.read_fields(&[StandardField::Year]).unwrap().year.as_deref(), Some("2001-05-12T10:30"));
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written.windows(4).filter(|window| window == b"TDAT").count(), 1);
+        assert!(find_frame(&written, b"TIME").is_some());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_sound_check_id3v2_comm_frame() {
+        let mut itunnorm = b"\x00eng".to_vec();
+        itunnorm.extend_from_slice(b"iTunNORM\x00");
+        itunnorm.extend_from_slice(ITUNNORM_SAMPLE.as_bytes());
+        let path = write_temp_file("soundcheck.mp3", &build_id3v2_file(&[
+            ("COMM", b"\x00eng\x00A real comment".to_vec()),
+            ("COMM", itunnorm),
+        ]));
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().comment.as_deref(), Some("A real comment"));
+        assert_eq!(audio.get_sound_check().unwrap(), SoundCheck::parse(ITUNNORM_SAMPLE));
+
+        // Rewriting the comment keeps the iTunNORM frame
+        audio.set_metadata(r#"{"comment": "Changed"}"#.to_string()).unwrap();
+        assert_eq!(audio.get_sound_check().unwrap(), SoundCheck::parse(ITUNNORM_SAMPLE));
+
+        let quieter = SoundCheck::from_gain_db(-3.0);
+        audio.set_sound_check(&quieter).unwrap();
+        assert_eq!(audio.get_sound_check().unwrap(), Some(quieter));
+        assert_eq!(audio.read_metadata().unwrap().comment.as_deref(), Some("Changed"));
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written.windows(8).filter(|window| window == b"iTunNORM").count(), 1);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_sound_check_mp4_freeform() {
+        let path = write_temp_file("soundcheck.m4a", &build_m4a_file());
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_sound_check().unwrap(), None);
+
+        let sound_check = SoundCheck::parse(ITUNNORM_SAMPLE).unwrap();
+        audio.set_sound_check(&sound_check).unwrap();
+        audio.set_metadata(r#"{"title": "Song"}"#.to_string()).unwrap();
+
+        assert_eq!(audio.get_sound_check().unwrap(), Some(sound_check));
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+        let written = std::fs::read(&path).unwrap();
+        assert!(find_frame(&written, b"com.apple.iTunes").is_some());
+        assert_eq!(m4a_chunk(&written), b"AUDIOAUDIO");
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_get_or_create_metadata_ignores_corrupt_tags() {
+        // An ID3v2 tag claiming more data than the file holds
+        let mut data = b"ID3\x03\x00\x00\x00\x00\x7F\x7F".to_vec();
+        data.extend_from_slice(b"TIT2truncated");
+        let path = write_temp_file("corrupt-tags.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        assert!(audio.read_metadata().is_err());
+        let mut metadata = audio.get_or_create_metadata().unwrap();
+        assert!(metadata.is_empty());
+        metadata.title = Some("x".to_string());
+
+        // A missing file is still reported
+        std::fs::remove_file(&path).ok();
+        assert!(matches!(
+            audio.get_or_create_metadata(),
+            Err(AudioFileError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_get_vorbis_comments_preserves_case_and_order() {
+        let comments = [("Title", "One"), ("ARTIST", "Band"), ("title", "Two"), ("x-Custom", "=value")];
+        let path = write_temp_file("raw-comments.flac", &build_flac_file("odd tool 1.0", &comments, 64));
+        let ogg_path = write_temp_file("raw-comments.ogg", &build_ogg_file("odd tool 1.0", &comments));
+        let expected: Vec<_> = comments.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+
+        for path in [&path, &ogg_path] {
+            let (vendor, pairs) = AudioFile::new(path.clone()).unwrap().get_vorbis_comments().unwrap();
+            assert_eq!(vendor, "odd tool 1.0");
+            assert_eq!(pairs, expected);
+        }
+
+        let mp3_path = write_temp_file("raw-comments.mp3", &build_id3v2_file(&[]));
+        assert!(matches!(
+            AudioFile::new(mp3_path.clone()).unwrap().get_vorbis_comments(),
+            Err(AudioFileError::UnsupportedFormat(_))
+        ));
+
+        for path in [path, ogg_path, mp3_path] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_edit_history_records_each_edit() {
+        let mp3_path = write_temp_file("history.mp3", &build_id3v2_file(&[("TIT2", b"\x00Old".to_vec())]));
+        let flac_path = write_temp_file("history.flac", &build_flac_file("vendor", &[("TITLE", "Old")], 64));
+        let options = WriteOptions { history: true, ..Default::default() };
+
+        for path in [&mp3_path, &flac_path] {
+            let audio = AudioFile::new(path.clone()).unwrap();
+            assert!(audio.get_edit_history().unwrap().is_empty());
+
+            audio.set_metadata_with_options(r#"{"title": "New"}"#.to_string(), &options).unwrap();
+            // Writes without `history` leave the existing entries alone
+            audio.set_metadata(r#"{"comment": "untracked"}"#.to_string()).unwrap();
+            audio.set_metadata_with_options(r#"{"artist": "Band", "year": "2001"}"#.to_string(), &options).unwrap();
+            audio.set_metadata_with_options(r#"{"genres": ["Rock", "Jazz"]}"#.to_string(), &options).unwrap();
+            audio.optimize().unwrap();
+
+            let history = audio.get_edit_history().unwrap();
+            let fields: Vec<_> = history.iter().map(|entry| entry.fields_changed.clone()).collect();
+            assert_eq!(fields, vec![
+                vec!["title".to_string()],
+                vec!["artist".to_string(), "year".to_string()],
+                vec!["genre".to_string(), "genres".to_string()],
+            ]);
+            assert!(history.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+            assert!(history.iter().all(|entry| entry.tool_version == env!("CARGO_PKG_VERSION")));
+            assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("New"));
+        }
+
+        let deterministic = WriteOptions { history: true, deterministic: true, ..Default::default() };
+        let audio = AudioFile::new(mp3_path.clone()).unwrap();
+        assert!(audio.set_metadata_with_options("{}".to_string(), &deterministic).is_err());
+
+        for path in [mp3_path, flac_path] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_edit_history_evicts_oldest() {
+        let path = write_temp_file("history-cap.flac", &build_flac_file("vendor", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let options = WriteOptions { history: true, ..Default::default() };
+        for track in 1..=history::MAX_ENTRIES + 2 {
+            audio.set_metadata_with_options(format!(r#"{{"track": "{}"}}"#, track), &options).unwrap();
+        }
+        assert_eq!(audio.get_edit_history().unwrap().len(), history::MAX_ENTRIES);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_mp3_lame_header() {
+        let frames = 400u32;
+        let mut data = build_id3v2_file(&[]);
+        data.truncate(data.len() - 4);
+
+        // Info frame: flags (frames + bytes + TOC + quality), then the LAME extension
+        let mut info = vec![0u8; 144 * 128 * 1000 / 44100];
+        info[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        info[36..40].copy_from_slice(b"Info");
+        info[40..44].copy_from_slice(&0x0Fu32.to_be_bytes());
+        info[44..48].copy_from_slice(&frames.to_be_bytes());
+        let lame = 36 + 8 + 4 + 4 + 100 + 4;
+        info[lame..lame + 9].copy_from_slice(b"LAME3.100");
+        // Track gain -6.5 dB (name 1, originator 3), album gain +1.2 dB (name 2, originator 3)
+        info[lame + 15..lame + 17].copy_from_slice(&0x2E41u16.to_be_bytes());
+        info[lame + 17..lame + 19].copy_from_slice(&0x4C0Cu16.to_be_bytes());
+        // Delay 576, padding 1200
+        info[lame + 21..lame + 24].copy_from_slice(&[0x24, 0x04, 0xB0]);
+        data.extend(info);
+        let mut frame = vec![0u8; 144 * 128 * 1000 / 44100];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        for _ in 0..frames {
+            data.extend_from_slice(&frame);
+        }
+
+        let path = write_temp_file("lame.mp3", &data);
+        let header = AudioFile::new(path.clone()).unwrap().get_mp3_lame_header().unwrap().unwrap();
+        assert_eq!(header.encoder_version, "LAME3.100");
+        assert_eq!(header.encoder_delay, 576);
+        assert_eq!(header.encoder_padding, 1200);
+        assert_eq!(header.replaygain_track, Some(-6.5));
+        assert_eq!(header.replaygain_album, Some(1.2));
+        assert_eq!(header.audio_length_ms, (frames as u64 * 1152 - 576 - 1200) * 1000 / 44100);
+        std::fs::remove_file(path).ok();
+
+        // A Xing header without a LAME extension
+        let path = write_temp_file("no-lame.mp3", &build_vbr_mp3(10, true));
+        assert_eq!(AudioFile::new(path.clone()).unwrap().get_mp3_lame_header().unwrap(), None);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_performers_round_trip_flac_and_id3v2() {
+        let flac = write_temp_file("performers.flac", &build_flac_file("ref", &[
+            ("PERFORMER", "Violin (John Doe)"),
+            ("TITLE", "Sonata"),
+            ("performer", "Piano (Jane Roe)"),
+        ], 64));
+        let mp3 = write_temp_file("performers.mp3", &build_id3v2_file_version(4, &[("TIT2", b"\x00Sonata".to_vec())]));
+        let flac = AudioFile::new(flac).unwrap();
+        let mp3 = AudioFile::new(mp3).unwrap();
+        let expected = vec![
+            ("Violin".to_string(), "John Doe".to_string()),
+            ("Piano".to_string(), "Jane Roe".to_string()),
+        ];
+        assert_eq!(flac.read_metadata().unwrap().performers, expected);
+
+        copy_tags(&flac, &mp3);
+        let written = std::fs::read(&mp3.path).unwrap();
+        assert!(find_frame(&written, b"TMCL").is_some());
+        assert!(find_frame(&written, b"Violin\0John Doe\0Piano\0Jane Roe").is_some());
+        assert_eq!(mp3.read_metadata().unwrap().performers, expected);
+
+        // Back to FLAC, in the "Instrument (Name)" form
+        flac.set_metadata(r#"{"performers": null}"#.to_string()).unwrap();
+        assert!(flac.read_metadata().unwrap().performers.is_empty());
+        copy_tags(&mp3, &flac);
+        let (_, comments) = flac.get_vorbis_comments().unwrap();
+        let performers: Vec<_> = comments.iter().filter(|(key, _)| key == "PERFORMER").map(|(_, v)| v.as_str()).collect();
+        assert_eq!(performers, vec!["Violin (John Doe)", "Piano (Jane Roe)"]);
+
+        // ID3v2.3 has no TMCL frame
+        let v23 = WriteOptions { target_version: Some((2, 3)), ..Default::default() };
+        let report = mp3.set_metadata_with_options("{}".to_string(), &v23).unwrap();
+        assert!(report.lossy_frames.iter().any(|frame| frame.starts_with("TMCL")));
+
+        assert_eq!(parse_performer("John Doe"), (String::new(), "John Doe".to_string()));
+        std::fs::remove_file(&flac.path).ok();
+        std::fs::remove_file(&mp3.path).ok();
+    }
+
+    #[test]
+    fn test_comment_language_option() {
+        let path = write_temp_file("language.mp3", &build_id3v2_file(&[]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let options = WriteOptions { language: Some("de".to_string()), ..Default::default() };
+        audio.set_metadata_with_options(r#"{"comment": "Hallo", "lyrics": "La la"}"#.to_string(), &options).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(find_frame(&written, b"ger\0Hallo").is_some());
+        assert!(find_frame(&written, b"ger\0La la").is_some());
+
+        // Described comments take the same language
+        let described = [("COMM", "Note", "Notiz".to_string())];
+        audio.write_id3v2_metadata(&audio.read_metadata().unwrap(), &options, &described, None).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(find_frame(&written, b"gerNote\0Notiz").is_some());
+        assert!(find_frame(&written, b"ger\0Hallo").is_some());
+
+        let invalid = WriteOptions { language: Some("english".to_string()), ..Default::default() };
+        let err = audio.set_metadata_with_options(r#"{"comment": "Hi"}"#.to_string(), &invalid).unwrap_err();
+        assert!(err.to_string().contains("english"));
+        assert_eq!(std::fs::read(&path).unwrap(), written);
+
+        // Codes that slip past validation are never written malformed
+        let frame = id3::frames::encode_uslt_frame("en-GB", "", "x", id3::frames::TextEncoding::Iso8859_1);
+        assert_eq!(&frame[1..4], b"und");
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_id3v24_footer_preserved() {
+        let data = build_id3v2_file_version(4, &[("TIT2", b"\x03Old".to_vec())]);
+        let tag_end = data.len() - 4;
+        // Footer and experimental flags, then a footer repeating the header
+        let mut file = data[..tag_end].to_vec();
+        file[5] = 0x30;
+        let mut footer = file[..10].to_vec();
+        footer[..3].copy_from_slice(b"3DI");
+        file.extend(footer);
+        file.extend_from_slice(&data[tag_end..]);
+        let path = write_temp_file("footer.mp3", &file);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Old"));
+        assert_eq!(audio.compute_audio_span().unwrap(), (tag_end as u64 + 10, 4));
+        let issues = audio.validate().unwrap();
+        assert!(issues.iter().any(|issue| issue.code == "id3-experimental"), "{:?}", issues);
+
+        audio.set_metadata(r#"{"title": "A longer title"}"#.to_string()).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[5], 0x10);
+        let footer_start = written.len() - 4 - 10;
+        assert_eq!(&written[footer_start..footer_start + 3], b"3DI");
+        assert_eq!(&written[footer_start + 3..footer_start + 10], &written[3..10]);
+        assert_eq!(&written[written.len() - 4..], &[0xFF, 0xFB, 0x90, 0x00]);
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("A longer title"));
+
+        // Converting to ID3v2.3 drops the footer, which that version doesn't have
+        let v23 = WriteOptions { target_version: Some((2, 3)), ..Default::default() };
+        audio.set_metadata_with_options("{}".to_string(), &v23).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written[5], 0);
+        assert!(find_frame(&written, b"3DI").is_none());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    /// Header of a PNG image: signature and IHDR chunk, no image data
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 2, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn test_cover_art_dimensions_and_embedding() {
+        use crate::utils::image::dimensions;
+
+        let cover = CoverArt::new(png_header(640, 480), None, Some("Front".to_string()));
+        assert_eq!(cover.mime_type.as_deref(), Some("image/png"));
+        assert_eq!(cover.dimensions(), Some((640, 480)));
+
+        // SOI, APP0, then SOF0 with height 300 and width 500
+        let jpeg = [
+            &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00][..],
+            &[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0x2C, 0x01, 0xF4, 0x03],
+        ].concat();
+        assert_eq!(dimensions(&jpeg), Some((500, 300)));
+        assert_eq!(dimensions(b"GIF89a\x10\x00\x20\x00"), Some((16, 32)));
+        assert_eq!(dimensions(b"not an image"), None);
+
+        let path = write_temp_file("png-cover.flac", &build_flac_file("vendor", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let updates = serde_json::json!({ "cover": cover });
+        audio.set_metadata(updates.to_string()).unwrap();
+        let embedded = audio.read_metadata().unwrap().cover.unwrap();
+        assert_eq!(embedded.data, cover.data);
+        assert_eq!(embedded.mime_type.as_deref(), Some("image/png"));
+        assert_eq!(embedded.dimensions(), Some((640, 480)));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_cover_art_thumbnail() {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(200, 100))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let cover = CoverArt::new(png.into_inner(), None, None);
+        assert_eq!(cover.dimensions(), Some((200, 100)));
+
+        let thumbnail = CoverArt::new(cover.thumbnail(50).unwrap(), None, None);
+        assert_eq!(thumbnail.mime_type.as_deref(), Some("image/jpeg"));
+        assert_eq!(thumbnail.dimensions(), Some((50, 25)));
+
+        // Never upscaled
+        assert_eq!(utils::image::dimensions(&cover.thumbnail(500).unwrap()), Some((200, 100)));
+        assert!(cover.thumbnail(0).is_err());
+    }
+
+    #[test]
+    fn test_export_metadata_to_sidecar() {
+        let path = write_temp_file("sidecar.flac", &build_flac_file("vendor", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let updates = serde_json::json!({
+            "title": "Rock & Roll <Live>",
+            "artist": "Band",
+            "genre": "Rock; Blues",
+            "track": "3",
+        });
+        audio.set_metadata(updates.to_string()).unwrap();
+
+        let nfo_path = format!("{}.nfo", path);
+        audio.export_metadata_to_sidecar("nfo", &nfo_path).unwrap();
+        let nfo = std::fs::read_to_string(&nfo_path).unwrap();
+        assert!(nfo.contains("<track>\n  <title>Rock &amp; Roll &lt;Live&gt;</title>\n  <artist>Band</artist>\n"));
+        assert!(nfo.contains("  <position>3</position>\n"));
+        assert!(nfo.contains("  <genre>Rock</genre>\n  <genre>Blues</genre>\n"));
+        assert!(nfo.ends_with("</track>\n"));
+
+        let xmp_path = format!("{}.xmp", path);
+        audio.export_metadata_to_sidecar("XMP", &xmp_path).unwrap();
+        let xmp = std::fs::read_to_string(&xmp_path).unwrap();
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Rock &amp; Roll &lt;Live&gt;</rdf:li>"));
+        assert!(xmp.contains("<xmpDM:trackNumber>3</xmpDM:trackNumber>"));
+        assert!(xmp.contains("<xmpDM:genre>Rock, Blues</xmpDM:genre>"));
+
+        let json_path = format!("{}.json", path);
+        audio.export_metadata_to_sidecar("json", &json_path).unwrap();
+        let exported: Metadata = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(exported.title.as_deref(), Some("Rock & Roll <Live>"));
+
+        assert!(matches!(
+            audio.export_metadata_to_sidecar("cue", &json_path),
+            Err(AudioFileError::UnsupportedFormat(_))
+        ));
+        for file in [path, nfo_path, xmp_path, json_path] {
+            std::fs::remove_file(file).ok();
+        }
+    }
+
+    #[test]
+    fn test_write_metadata_object() {
+        for (name, data) in [
+            ("object.flac", build_flac_file("vendor", &[("COMMENT", "old comment")], 64)),
+            ("object.mp3", build_id3v2_file_version(4, &[("COMM", b"\x00engold comment\x00".to_vec())])),
+        ] {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+
+            let metadata = Metadata {
+                title: Some("Title".to_string()),
+                artist: Some("Artist".to_string()),
+                track: Some("7".to_string()),
+                genres: vec!["Rock".to_string(), "Jazz".to_string()],
+                genre: Some("Rock; Jazz".to_string()),
+                performers: vec![("guitar".to_string(), "Someone".to_string())],
+                ..Default::default()
+            };
+            audio.write_metadata(&metadata).unwrap();
+
+            let read_back = audio.read_metadata().unwrap();
+            assert_eq!(read_back.title.as_deref(), Some("Title"), "{}", name);
+            assert_eq!(read_back.artist.as_deref(), Some("Artist"), "{}", name);
+            assert_eq!(read_back.track.as_deref(), Some("7"), "{}", name);
+            assert_eq!(read_back.genres, ["Rock", "Jazz"], "{}", name);
+            assert_eq!(read_back.performers, metadata.performers, "{}", name);
+            // The object is the complete set of tags, so unset fields are removed
+            assert_eq!(read_back.comment, None, "{}", name);
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_flac_application_blocks() {
+        let path = write_temp_file("application.flac", &build_flac_file("vendor", &[("TITLE", "Old")], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let span = audio.get_audio_span().unwrap();
+        assert!(audio.get_flac_applications().unwrap().is_empty());
+
+        audio.set_flac_application("tune", b"loop 0 1000").unwrap();
+        audio.set_flac_application("xbat", &[0, 1, 2]).unwrap();
+        audio.set_flac_application("tune", b"loop 10 20").unwrap();
+        let expected = vec![
+            ("tune".to_string(), b"loop 10 20".to_vec()),
+            ("xbat".to_string(), vec![0, 1, 2]),
+        ];
+        assert_eq!(audio.get_flac_applications().unwrap(), expected);
+        // The blocks fit in the old padding, so the audio stays put
+        assert_eq!(audio.get_audio_span().unwrap(), span);
+
+        // Rebuilding the blocks for a comment edit keeps the applications
+        audio.set_metadata(r#"{"title": "New"}"#.to_string()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("New"));
+        assert_eq!(audio.get_flac_applications().unwrap(), expected);
+
+        assert!(matches!(audio.set_flac_application("toolong", b""), Err(AudioFileError::ParseError(_))));
+        let mp3 = AudioFile::new(write_temp_file("application.mp3", &build_id3v2_file(&[]))).unwrap();
+        assert!(matches!(mp3.get_flac_applications(), Err(AudioFileError::UnsupportedFormat(_))));
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(mp3.path).ok();
+    }
+
+    #[test]
+    fn test_get_embedded_object_count() {
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x00Song".to_vec()),
+            ("APIC", build_apic_frame(&[0xFF, 0xD8, 0xFF, 0xD9])),
+            ("GEOB", b"\x00application/octet-stream\x00file.bin\x00desc\x00\x01\x02".to_vec()),
+        ]);
+        let path = write_temp_file("embedded-count.mp3", &data);
+        assert_eq!(AudioFile::new(path.clone()).unwrap().get_embedded_object_count().unwrap(), 2);
+        std::fs::remove_file(path).ok();
+
+        // Splice a PICTURE block in after STREAMINFO
+        let mut flac = build_flac_file("vendor", &[("TITLE", "Song")], 16);
+        let picture = FlacMetadataBlock::new(FlacMetadataBlockType::Picture, vec![0u8; 32]).to_bytes(false);
+        let streaminfo_end = FLAC_SIGNATURE.len() + 4 + 34;
+        flac.splice(streaminfo_end..streaminfo_end, picture);
+        let path = write_temp_file("embedded-count.flac", &flac);
+        assert_eq!(AudioFile::new(path.clone()).unwrap().get_embedded_object_count().unwrap(), 1);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_vorbis_keys_normalized_on_flac_write() {
+        let data = build_flac_file("vendor", &[
+            ("date", "2001"), ("DATE", "2003"), ("Track Number", "5"),
+            ("mood", "calm"), ("MOOD", "calm"), ("Label", "Indie"),
+        ], 64);
+        let path = write_temp_file("vorbis-keys.flac", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let issues = audio.validate().unwrap();
+        let noncanonical: Vec<&str> = issues.iter()
+            .filter(|issue| issue.code == "noncanonical-vorbis-key")
+            .map(|issue| issue.field.as_str())
+            .collect();
+        assert_eq!(noncanonical, vec!["date", "Track Number", "mood", "Label"]);
+        assert_eq!(issues.iter().filter(|issue| issue.code == "vorbis-key-conflict").count(), 1);
+        assert_eq!(audio.read_metadata().unwrap().track.as_deref(), Some("5"));
+
+        let report = audio.set_metadata_with_options(r#"{"title": "Song"}"#.to_string(), &WriteOptions::default()).unwrap();
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("DATE:"));
+
+        let (_, comments) = audio.get_vorbis_comments().unwrap();
+        assert!(comments.iter().all(|(key, _)| *key == flac::vorbis::canonical_key(key)));
+        assert_eq!(comments.iter().filter(|(key, _)| key == "TRACKNUMBER").count(), 1);
+        assert_eq!(comments.iter().filter(|(key, _)| key == "MOOD").count(), 1);
+        assert!(comments.contains(&("LABEL".to_string(), "Indie".to_string())));
+        assert!(audio.validate().unwrap().is_empty());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_vorbis_keys_normalized_on_ogg_write() {
+        let data = build_ogg_file("vendor", &[("mood", "calm"), ("Mood", "calm"), ("label", "Indie")]);
+        let path = write_temp_file("vorbis-keys.ogg", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let preserve = WriteOptions { preserve_vorbis_keys: true, ..Default::default() };
+        audio.set_metadata_with_options(r#"{"title": "Song"}"#.to_string(), &preserve).unwrap();
+        let (_, comments) = audio.get_vorbis_comments().unwrap();
+        assert_eq!(comments.iter().filter(|(key, _)| key.eq_ignore_ascii_case("mood")).count(), 2);
+
+        let report = audio.set_metadata_with_options(r#"{"title": "Song"}"#.to_string(), &WriteOptions::default()).unwrap();
+        assert!(report.warnings.is_empty());
+
+        // Removing a field drops every spelling of it
+        audio.set_extra_fields(&[("LABEL".to_string(), String::new())]).unwrap();
+        let (_, comments) = audio.get_vorbis_comments().unwrap();
+        assert!(!comments.iter().any(|(key, _)| key.eq_ignore_ascii_case("label")));
+        let moods: Vec<_> = comments.iter().filter(|(key, _)| key.eq_ignore_ascii_case("mood")).collect();
+        assert_eq!(moods, vec![&("MOOD".to_string(), "calm".to_string())]);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_copy_cover_from_and_remove_all_covers() {
+        use flac::picture::PictureType;
+
+        let front = FlacPicture::new(vec![0xFF, 0xD8, 0xFF, 0xD9], "image/jpeg".to_string(), "front".to_string());
+        let back = FlacPicture {
+            picture_type: PictureType::CoverBack,
+            ..FlacPicture::new(png_header(2, 2), "image/png".to_string(), "back".to_string())
+        };
+        let mut flac = build_flac_file("vendor", &[("TITLE", "Song")], 16);
+        let streaminfo_end = FLAC_SIGNATURE.len() + 4 + 34;
+        let blocks: Vec<u8> = [&front, &back].iter()
+            .flat_map(|picture| FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes()).to_bytes(false))
+            .collect();
+        flac.splice(streaminfo_end..streaminfo_end, blocks);
+        let source = AudioFile::new(write_temp_file("covers-source.flac", &flac)).unwrap();
+
+        let summary = |audio: &AudioFile| -> Vec<(u32, String, String, Vec<u8>)> {
+            audio.read_pictures().unwrap().into_iter()
+                .map(|picture| (picture.picture_type as u32, picture.mime_type, picture.description, picture.data))
+                .collect()
+        };
+        let expected = summary(&source);
+        assert_eq!(expected.len(), 2);
+
+        let mp3 = AudioFile::new(write_temp_file("covers-target.mp3", &build_id3v2_file(&[("TIT2", b"\x00Song".to_vec())]))).unwrap();
+        let ogg = AudioFile::new(write_temp_file("covers-target.ogg", &build_ogg_file("vendor", &[("TITLE", "Song")]))).unwrap();
+        for target in [&mp3, &ogg] {
+            target.copy_cover_from(&source).unwrap();
+            assert_eq!(summary(target), expected);
+            assert_eq!(target.read_metadata().unwrap().title.as_deref(), Some("Song"));
+        }
+
+        // Editing text keeps both pictures
+        mp3.set_metadata(r#"{"artist": "Band"}"#.to_string()).unwrap();
+        assert_eq!(summary(&mp3), expected);
+
+        for audio in [&source, &mp3, &ogg] {
+            audio.remove_all_covers().unwrap();
+            assert_eq!(audio.get_embedded_object_count().unwrap(), 0);
+            assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+            std::fs::remove_file(&audio.path).ok();
+        }
+    }
+
+    #[test]
+    fn test_copy_cover_larger_than_an_ogg_page() {
+        // A 200 KB cover, base64 encoded in the comment header, needs several OGG pages
+        let mut image = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        image.extend((0..200_000u32).map(|i| (i * 31 % 251) as u8));
+        image.extend_from_slice(&[0xFF, 0xD9]);
+        let picture = FlacPicture::new(image, "image/jpeg".to_string(), String::new());
+        let mut flac = build_flac_file("vendor", &[], 16);
+        let streaminfo_end = FLAC_SIGNATURE.len() + 4 + 34;
+        let block = FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes());
+        flac.splice(streaminfo_end..streaminfo_end, block.to_bytes(false));
+        let source = AudioFile::new(write_temp_file("big-cover.flac", &flac)).unwrap();
+
+        let ogg = AudioFile::new(write_temp_file("big-cover.ogg", &build_ogg_file("vendor", &[("TITLE", "Song")]))).unwrap();
+        ogg.copy_cover_from(&source).unwrap();
+        assert_eq!(ogg.read_pictures().unwrap()[0].data, picture.data);
+        assert_eq!(ogg.read_metadata().unwrap().title.as_deref(), Some("Song"));
+        let data = std::fs::read(&ogg.path).unwrap();
+        assert!(ogg::page::page_ranges(&data).len() > 4);
+        assert!(data.ends_with(b"\xAA\xBB\xCC\xDD"));
+
+        for audio in [&source, &ogg] {
+            std::fs::remove_file(&audio.path).ok();
+        }
+    }
+
+    #[test]
+    fn test_get_id3v2_frames_by_language_and_description() {
+        use id3::frames::{encode_txxx_frame, encode_uslt_frame, TextEncoding};
+
+        let data = build_id3v2_file_version(4, &[
+            ("COMM", encode_uslt_frame("eng", "", "Main", TextEncoding::Utf8)),
+            ("COMM", encode_uslt_frame("ger", "", "Haupt", TextEncoding::Utf8)),
+            ("COMM", encode_uslt_frame("eng", "Notes", "Live take", TextEncoding::Utf8)),
+            ("USLT", encode_uslt_frame("eng", "", "Hello", TextEncoding::Utf8)),
+            ("USLT", encode_uslt_frame("jpn", "", "こんにちは", TextEncoding::Utf8)),
+            ("TXXX", encode_txxx_frame("MOOD", "calm", TextEncoding::Utf8)),
+            ("TXXX", encode_txxx_frame("Label", "Indie", TextEncoding::Utf8)),
+        ]);
+        let path = write_temp_file("described-frames.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        assert_eq!(audio.get_id3v2_comment("eng", "").unwrap().as_deref(), Some("Main"));
+        assert_eq!(audio.get_id3v2_comment("de", "").unwrap().as_deref(), Some("Haupt"));
+        assert_eq!(audio.get_id3v2_comment("eng", "notes").unwrap().as_deref(), Some("Live take"));
+        assert_eq!(audio.get_id3v2_comment("fre", "").unwrap(), None);
+        assert_eq!(audio.get_id3v2_lyrics("ja", "").unwrap().as_deref(), Some("こんにちは"));
+        assert_eq!(audio.get_id3v2_user_text_by_description("label").unwrap().as_deref(), Some("Indie"));
+        assert_eq!(audio.get_id3v2_user_text_by_description("Catalog").unwrap(), None);
+        assert!(audio.get_id3v2_comment("english", "").is_err());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_write_id3v2_metadata_with_padding() {
+        // A tag with no padding at all
+        let data = build_id3v2_file(&[("TIT2", b"\x00Song".to_vec())]);
+        let path = write_temp_file("id3-padding.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let tag_size = |path: &str| {
+            let header = id3::v2::Id3v2Header::read(&mut BufReader::new(File::open(path).unwrap())).unwrap().unwrap();
+            header.total_size() as usize
+        };
+        let audio_bytes = |path: &str| {
+            let file = std::fs::read(path).unwrap();
+            file[tag_size(path)..].to_vec()
+        };
+        let original_audio = audio_bytes(&path);
+
+        let mut metadata = audio.read_metadata().unwrap();
+        metadata.artist = Some("A much longer artist name than before".to_string());
+        audio.write_id3v2_metadata_with_padding(&metadata, 4096).unwrap();
+        let grown = tag_size(&path);
+        let frames_len: usize = audio.read_id3v2_tag().unwrap().unwrap().frames.iter().map(|frame| frame.data.len() + 10).sum();
+        assert_eq!(grown, 10 + frames_len + 4096);
+        assert_eq!(audio_bytes(&path), original_audio);
+
+        // A later write that fits in the padding keeps the tag size, so the audio stays put
+        metadata.album = Some("Album".to_string());
+        audio.write_id3v2_metadata_with_padding(&metadata, 64).unwrap();
+        assert_eq!(tag_size(&path), grown);
+        assert_eq!(audio.read_metadata().unwrap().album.as_deref(), Some("Album"));
+        assert_eq!(audio_bytes(&path), original_audio);
+
+        let flac = AudioFile::new(write_temp_file("id3-padding.flac", &build_flac_file("vendor", &[], 16))).unwrap();
+        assert!(matches!(flac.write_id3v2_metadata_with_padding(&metadata, 64), Err(AudioFileError::UnsupportedFormat(_))));
+
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(flac.path).ok();
+    }
+
+    #[test]
+    fn test_set_metadata_keeps_custom_vorbis_fields() {
+        let flac = write_temp_file("custom-field.flac", &build_flac_file("vendor", &[("TITLE", "Old"), ("FOO", "bar")], 64));
+        let ogg = write_temp_file("custom-field.ogg", &build_ogg_file("vendor", &[("TITLE", "Old"), ("FOO", "bar")]));
+        for path in [&flac, &ogg] {
+            let audio = AudioFile::new(path.clone()).unwrap();
+            audio.set_metadata(r#"{"title": "New"}"#.to_string()).unwrap();
+            let (_, comments) = audio.get_vorbis_comments().unwrap();
+            assert!(comments.contains(&("FOO".to_string(), "bar".to_string())), "{}", path);
+            assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("New"));
+            std::fs::remove_file(path).ok();
+        }
+
+        // A comment packet that can't be parsed fails the write instead of being replaced
+        let ident = b"\x01vorbis\x00\x00\x00\x00";
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend_from_slice(&1000u32.to_le_bytes());
+        packet.extend_from_slice(b"vendor");
+        let mut data = ogg_page(0, 0x02, &[ident.len() as u8], ident);
+        data.extend(ogg_page(1, 0x00, &[packet.len() as u8], &packet));
+        data.extend(ogg_page(2, 0x04, &[4], b"\xAA\xBB\xCC\xDD"));
+        let path = write_temp_file("corrupt-comment.ogg", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.set_metadata(r#"{"title": "New"}"#.to_string()).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_id3v2_unique_file_id() {
+        use id3::frames::{decode_ufid_frame, encode_ufid_frame};
+
+        let recording = b"5b1e5f4a-0e6c-4f7a-9f2b-0c8a1d2e3f40";
+        assert_eq!(
+            decode_ufid_frame(&encode_ufid_frame("http://musicbrainz.org", recording)),
+            Some(("http://musicbrainz.org".to_string(), recording.to_vec()))
+        );
+        assert_eq!(decode_ufid_frame(b"no terminator"), None);
+
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x00Song".to_vec()),
+            ("UFID", encode_ufid_frame("http://example.com", b"old")),
+        ]);
+        let path = write_temp_file("ufid.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_id3v2_unique_file_id().unwrap(), Some(("http://example.com".to_string(), b"old".to_vec())));
+
+        audio.set_id3v2_unique_file_id("http://example.com", b"new").unwrap();
+        audio.set_id3v2_unique_file_id("http://musicbrainz.org", recording).unwrap();
+        let owners: Vec<(String, Vec<u8>)> = audio.read_id3v2_tag().unwrap().unwrap().frames.iter()
+            .filter(|frame| frame.frame_id == "UFID")
+            .filter_map(|frame| decode_ufid_frame(&frame.data))
+            .collect();
+        assert_eq!(owners, vec![
+            ("http://example.com".to_string(), b"new".to_vec()),
+            ("http://musicbrainz.org".to_string(), recording.to_vec()),
+        ]);
+
+        // Kept through metadata writes
+        audio.set_metadata(r#"{"title": "Other"}"#.to_string()).unwrap();
+        assert_eq!(audio.get_id3v2_unique_file_id().unwrap().unwrap().1, b"new".to_vec());
+
+        assert!(audio.set_id3v2_unique_file_id("", b"id").is_err());
+        assert!(audio.set_id3v2_unique_file_id("owner", &[0u8; 65]).is_err());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_cue_sheet_track_points() {
+        let mut data = build_cue_flac(&[(0, 0), (132_300, 1)]);
+        data[8..42].copy_from_slice(&streaminfo(44100, 1_323_000));
+        let path = write_temp_file("track-points.flac", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        // Track 1 starts at index 1, after its 3 second pre-gap
+        let points = audio.get_track_points().unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].number, points[0].start_sample, points[0].start_ms), (1, 132_300, 3000));
+        assert_eq!(points[0].isrc, "USABC1234567");
+        assert_eq!((points[1].number, points[1].start_sample, points[1].start_ms), (2, 441_000, 10_000));
+        assert_eq!(points[1].isrc, "");
+        std::fs::remove_file(path).ok();
+
+        // A cue sheet is useless for splitting without a sample rate
+        let path = write_temp_file("track-points-no-rate.flac", &build_cue_flac(&[(0, 1)]));
+        assert!(AudioFile::new(path.clone()).unwrap().get_track_points().is_err());
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("track-points-no-cue.flac", &build_flac_file("vendor", &[], 8));
+        assert!(AudioFile::new(path.clone()).unwrap().get_track_points().unwrap().is_empty());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_write_to_read_only_file_fails_untouched() {
+        let temp_file_of = |path: &str| {
+            let path = std::path::Path::new(path);
+            path.with_file_name(format!(".{}.oxidant-tmp", path.file_name().unwrap().to_string_lossy()))
+        };
+        let data = build_flac_file("vendor", &[("TITLE", "Song")], 16);
+        let path = write_temp_file("read-only.flac", &data);
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        match audio.set_metadata(r#"{"title": "Other"}"#.to_string()) {
+            Err(AudioFileError::ReadOnlyFile(error_path)) => assert_eq!(error_path, path),
+            other => panic!("expected ReadOnlyFile, got {:?}", other),
+        }
+        let fields = [("FOO".to_string(), "bar".to_string())];
+        assert!(matches!(audio.set_extra_fields(&fields), Err(AudioFileError::ReadOnlyFile(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert!(!temp_file_of(&path).exists());
+        std::fs::remove_file(path).ok();
+
+        // Successful writes go through a temporary file but keep the permissions
+        let path = write_temp_file("replaced.flac", &data);
+        let before = std::fs::metadata(&path).unwrap().permissions();
+        AudioFile::new(path.clone()).unwrap().set_metadata(r#"{"title": "Other"}"#.to_string()).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions(), before);
+        assert!(!temp_file_of(&path).exists());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_metadata_value_deep_describes_cover() {
+        use base64::prelude::*;
+
+        let png = png_header(2, 3);
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x00Song".to_vec()),
+            ("APIC", id3::frames::encode_apic_frame("image/png", id3::frames::PictureType::CoverFront, "Front", &png)),
+        ]);
+        let path = write_temp_file("deep-cover.mp3", &data);
+        let value = AudioFile::new(path.clone()).unwrap().get_metadata_value_deep().unwrap();
+        assert_eq!(value["title"], "Song");
+        assert_eq!(value["cover"], serde_json::json!({
+            "mime_type": "image/png",
+            "width": 2,
+            "height": 3,
+            "depth": 0,
+            "description": "Front",
+            "data": BASE64_STANDARD.encode(&png),
+        }));
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("deep-no-cover.flac", &build_flac_file("vendor", &[("TITLE", "Song")], 8));
+        let value = AudioFile::new(path.clone()).unwrap().get_metadata_value_deep().unwrap();
+        assert!(value["cover"].is_null());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_with_format_overrides_detection() {
+        let mut data = build_id3v2_file(&[("TIT2", b"\x00From ID3v2".to_vec())]);
+        let mut v1 = vec![0u8; 128];
+        v1[..3].copy_from_slice(b"TAG");
+        v1[3..14].copy_from_slice(b"From ID3v1 ");
+        v1[127] = 255;
+        data.extend_from_slice(&v1);
+        let path = write_temp_file("forced-id3v1.mp3", &data);
+
+        assert_eq!(AudioFile::new(path.clone()).unwrap().format, Format::Id3v2);
+        let forced = AudioFile::with_format(path.clone(), "ID3v1".parse().unwrap()).unwrap();
+        assert_eq!(forced.format, Format::Id3v1);
+        assert_eq!(forced.read_metadata().unwrap().title.as_deref(), Some("From ID3v1"));
+
+        // No FLAC stream here, and no ID3v2 tag in a file with only ID3v1
+        assert!(matches!(AudioFile::with_format(path.clone(), Format::Flac), Err(AudioFileError::ParseError(_))));
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("forced-id3v2.mp3", &v1);
+        assert!(AudioFile::with_format(path.clone(), Format::Id3v2).is_err());
+        std::fs::remove_file(path).ok();
+
+        assert!("wav".parse::<Format>().is_err());
+        assert_eq!("ogg".parse::<Format>().unwrap(), Format::OggVorbis);
+    }
+
+    /// OGG Vorbis stream of `seconds` at 44.1 kHz stereo with a TITLE comment
+    fn build_ogg_with_duration(seconds: u64) -> Vec<u8> {
+        let mut ident = b"\x01vorbis".to_vec();
+        ident.extend_from_slice(&0u32.to_le_bytes());
+        ident.push(2);
+        ident.extend_from_slice(&44100u32.to_le_bytes());
+        ident.extend_from_slice(&[0u8; 13]);
+        let comment = flac::vorbis::VorbisComment {
+            vendor_string: "ref".to_string(),
+            comments: vec![("TITLE".to_string(), "Song".to_string())],
+        };
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend(comment.to_bytes());
+        packet.push(1);
+        let setup = b"\x05vorbis-setup";
+
+        let mut data = ogg_page(0, 0x02, &[ident.len() as u8], &ident);
+        data.extend(ogg_page(1, 0x00, &[packet.len() as u8, setup.len() as u8], &[packet.as_slice(), setup].concat()));
+        let mut last = ogg_page(2, 0x04, &[4], b"\xAA\xBB\xCC\xDD");
+        last[6..14].copy_from_slice(&(seconds * 44100).to_le_bytes());
+        data.extend(last);
+        data
+    }
+
+    /// M4A with an ALAC sound track (48 kHz, 24-bit stereo) lasting `seconds`
+    fn build_alac_file(seconds: u32) -> Vec<u8> {
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&(seconds * 1000).to_be_bytes());
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"soun");
+        hdlr.extend_from_slice(&[0u8; 13]);
+        let mut entry = vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        entry.extend_from_slice(&2u16.to_be_bytes());
+        entry.extend_from_slice(&24u16.to_be_bytes());
+        entry.extend_from_slice(&[0u8; 4]);
+        entry.extend_from_slice(&(48000u32 << 16).to_be_bytes());
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_atom(b"alac", &entry));
+
+        let minf = mp4_atom(b"minf", &mp4_atom(b"stbl", &mp4_atom(b"stsd", &stsd)));
+        let mdia = mp4_atom(b"mdia", &[mp4_atom(b"hdlr", &hdlr), minf].concat());
+        let moov = mp4_atom(b"moov", &[mp4_atom(b"mvhd", &mvhd), mp4_atom(b"trak", &mdia)].concat());
+        let mut data = mp4_atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+        data.extend(moov);
+        data.extend(mp4_atom(b"mdat", &[0u8; 1000]));
+        data
+    }
+
+    #[test]
+    fn test_metadata_with_properties_per_format() {
+        use serde_json::json;
+
+        let flac = build_flac_with_streaminfo(streaminfo(44100, 441_000), &[("TITLE", "Song")], &[0xFF, 0xF8, 0x69, 0x08]);
+        let fixtures = [
+            ("props.flac", flac, json!({
+                "title": "Song",
+                "properties": {
+                    "duration_ms": 10000, "sample_rate": 44100, "channels": 2, "bits_per_sample": 16,
+                    "bitrate_kbps": 0, "codec": "FLAC", "lossless": true,
+                },
+            }), "0:10, 44.1 kHz, 16-bit, FLAC"),
+            ("props.mp3", build_vbr_mp3(400, true), json!({
+                "encoder": "LAME3.100",
+                "properties": {
+                    "duration_ms": 10448, "sample_rate": 44100, "channels": 2,
+                    "bitrate_kbps": 176, "codec": "MP3", "lossless": false,
+                },
+            }), "0:10, 44.1 kHz, 176 kbps, MP3"),
+            ("props.ogg", build_ogg_with_duration(5), json!({
+                "title": "Song",
+                "properties": {
+                    "duration_ms": 5000, "sample_rate": 44100, "channels": 2,
+                    "bitrate_kbps": 0, "codec": "Vorbis", "lossless": false,
+                },
+            }), "0:05, 44.1 kHz, Vorbis"),
+            ("props.m4a", build_alac_file(3), json!({
+                "properties": {
+                    "duration_ms": 3000, "sample_rate": 48000, "channels": 2, "bits_per_sample": 24,
+                    "bitrate_kbps": 2, "codec": "ALAC", "lossless": true,
+                },
+            }), "0:03, 48 kHz, 24-bit, ALAC"),
+        ];
+
+        let options = ReadOptions { include_cover: false, include_properties: true, ..Default::default() };
+        for (name, data, expected, summary) in fixtures {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            let combined: serde_json::Value = serde_json::from_str(&audio.get_metadata_with_options(&options).unwrap()).unwrap();
+            assert_eq!(combined, expected, "{}", name);
+            assert_eq!(audio.get_properties().unwrap().summary(), summary);
+            // Properties stay out of the default output
+            assert!(!audio.get_metadata().unwrap().contains("properties"));
+            std::fs::remove_file(path).ok();
+        }
+
+        // A file without a readable audio stream still reads, with null properties
+        let path = write_temp_file("props-none.flac", &build_flac_file("vendor", &[("TITLE", "Song")], 8));
+        let combined = AudioFile::new(path.clone()).unwrap().get_metadata_with_options(&options).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&combined).unwrap()["properties"], serde_json::Value::Null);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_encoder_and_settings_round_trip() {
+        // FLAC: ENCODER and ENCODERSETTINGS are managed, not extra fields
+        let path = write_temp_file("encoder.flac", &build_flac_file("ref", &[("ENCODER", "flac 1.4.3")], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        audio.set_metadata(r#"{"encoder_settings": "-8 -V"}"#.to_string()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.encoder.as_deref(), Some("flac 1.4.3"));
+        assert_eq!(metadata.encoder_settings.as_deref(), Some("-8 -V"));
+        assert!(audio.get_extra_fields().unwrap().is_empty());
+        std::fs::remove_file(path).ok();
+
+        // MP4: ©too and the ENCODERSETTINGS freeform item
+        let path = write_temp_file("encoder.m4a", &build_m4a_file());
+        let audio = AudioFile::new(path.clone()).unwrap();
+        audio.set_metadata(r#"{"encoder": "Lavf60.16.100", "encoder_settings": "-q:a 2"}"#.to_string()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        assert!(find_frame(&data, b"\xa9too").is_some());
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.encoder.as_deref(), Some("Lavf60.16.100"));
+        assert_eq!(metadata.encoder_settings.as_deref(), Some("-q:a 2"));
+        std::fs::remove_file(path).ok();
+
+        // ID3v2: the settings live in a TXXX frame hidden from the extra fields
+        let path = write_temp_file("encoder.mp3", &build_id3v2_file(&[]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        audio.set_metadata(r#"{"encoder": "LAME3.100", "encoder_settings": "-V 0"}"#.to_string()).unwrap();
+        audio.set_extra_fields(&[("MOOD".to_string(), "calm".to_string())]).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.encoder.as_deref(), Some("LAME3.100"));
+        assert_eq!(metadata.encoder_settings.as_deref(), Some("-V 0"));
+        assert_eq!(audio.get_extra_fields().unwrap(), vec![("MOOD".to_string(), "calm".to_string())]);
+        assert!(audio.set_extra_fields(&[("EncoderSettings".to_string(), "x".to_string())]).is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_mp4_nonstandard_meta_layouts() {
+        let mut value = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        value.extend_from_slice(b"Song");
+        let ilst = mp4_atom(b"ilst", &mp4_atom(b"\xa9nam", &mp4_atom(b"data", &value)));
+        let hdlr = mp4_atom(b"hdlr", &[[0u8; 8].as_slice(), b"mdirappl", &[0u8; 9]].concat());
+        let ftyp = mp4_atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+        let build = |moov_children: Vec<u8>| [ftyp.clone(), mp4_atom(b"moov", &moov_children)].concat();
+
+        // Full-box meta with non-zero flags directly under moov
+        let full_meta = mp4_atom(b"meta", &[vec![0, 0, 0, 1], hdlr.clone(), ilst.clone()].concat());
+        // QuickTime meta without version/flags, under udta
+        let quicktime_meta = mp4_atom(b"meta", &[hdlr.clone(), ilst.clone()].concat());
+        let track_meta = mp4_atom(b"trak", &mp4_atom(b"udta", &full_meta));
+        let layouts = [
+            ("udta-less.m4a", build(full_meta.clone()), false),
+            ("quicktime.m4a", build(mp4_atom(b"udta", &quicktime_meta)), false),
+            ("track.m4a", build(track_meta), true),
+        ];
+        for (name, data, scanned) in layouts {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"), "{}", name);
+
+            // Tags are rewritten in place rather than in a new udta/meta/ilst
+            let report = audio.set_metadata_with_options(r#"{"artist": "Band"}"#.to_string(), &WriteOptions::default()).unwrap();
+            assert_eq!(report.warnings.len(), usize::from(scanned), "{}", name);
+            let metadata = audio.read_metadata().unwrap();
+            assert_eq!((metadata.title.as_deref(), metadata.artist.as_deref()), (Some("Song"), Some("Band")));
+            let data = std::fs::read(&path).unwrap();
+            assert_eq!(data.windows(4).filter(|window| window == b"ilst").count(), 1, "{}", name);
+            std::fs::remove_file(path).ok();
+        }
+
+        // A meta version this reader doesn't know is never entered
+        let unknown = mp4_atom(b"meta", &[vec![1, 0, 0, 0], hdlr, ilst].concat());
+        let path = write_temp_file("meta-v1.m4a", &build(mp4_atom(b"udta", &unknown)));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().title, None);
+        assert!(audio.set_metadata(r#"{"artist": "Band"}"#.to_string()).is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_set_cover_from_bytes() {
+        let path = write_temp_file("cover-bytes.flac", &build_flac_file("ref", &[("TITLE", "Song")], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.set_cover_from_bytes(b"not an image".to_vec(), None).is_err());
+
+        audio.set_cover_from_bytes(png_header(4, 4), None).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.cover.unwrap().mime_type.as_deref(), Some("image/png"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_set_cover_from_url() {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/cover").with_header("content-type", "image/png").with_body(png_header(4, 4)).create();
+        let path = write_temp_file("cover-url.flac", &build_flac_file("ref", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        // Too large: rejected before anything is written
+        let small = DownloadOptions { max_bytes: 8, ..Default::default() };
+        assert!(audio.set_cover_from_url(&format!("{}/cover", server.url()), None, &small).is_err());
+        assert!(audio.read_metadata().unwrap().cover.is_none());
+
+        let options = DownloadOptions::default();
+        audio.set_cover_from_url(&format!("{}/cover", server.url()), None, &options).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().cover.unwrap().data, png_header(4, 4));
+        assert!(audio.set_cover_from_url(&format!("{}/missing", server.url()), None, &options).is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_cover_art_size_and_large_cover_issue() {
+        let mut large = png_header(3000, 3000);
+        large.resize(3 * 1024 * 1024, 0);
+
+        let path = write_temp_file("cover-size.flac", &build_flac_file("ref", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_cover_art_size_bytes().unwrap(), None);
+        audio.set_cover_from_bytes(large.clone(), None).unwrap();
+        assert_eq!(audio.get_cover_art_size_bytes().unwrap(), Some(large.len()));
+        let issues = audio.validate().unwrap();
+        assert!(issues.iter().any(|issue| issue.code == "large-cover" && issue.message.starts_with("cover image is 3.0 MB")));
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("cover-size.mp3", &build_id3v2_file(&[]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        audio.set_cover_from_bytes(png_header(2, 2), None).unwrap();
+        assert_eq!(audio.get_cover_art_size_bytes().unwrap(), Some(png_header(2, 2).len()));
+        assert!(audio.validate().unwrap().iter().all(|issue| issue.code != "large-cover"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_vorbis_comment_rejects_oversized_lengths() {
+        use flac::vorbis::VorbisComment;
+
+        let mut comment = 3u32.to_le_bytes().to_vec();
+        comment.extend_from_slice(b"ref");
+        let with_count = |count: u32, rest: &[u8]| [comment.as_slice(), &count.to_le_bytes(), rest].concat();
+
+        // A count of 4 billion comments fails before allocating for them
+        let error = VorbisComment::from_bytes(&with_count(u32::MAX, &[])).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        // One comment claiming more bytes than are left
+        let error = VorbisComment::from_bytes(&with_count(1, &1000u32.to_le_bytes())).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("comment of 1000 bytes with only 0 left"));
+        // The vendor string is checked the same way
+        assert!(VorbisComment::from_bytes(&u32::MAX.to_le_bytes()).is_err());
+
+        let parsed = VorbisComment::from_bytes(&with_count(1, &[&7u32.to_le_bytes()[..], b"A=value"].concat())).unwrap();
+        assert_eq!(parsed.comments, vec![("A".to_string(), "value".to_string())]);
+
+        // A FLAC file whose comment count is corrupt reads as untagged
+        let mut flac = build_flac_file("ref", &[("TITLE", "Song")], 64);
+        let count_pos = flac.windows(3).position(|window| window == b"ref").unwrap() + 3;
+        flac[count_pos..count_pos + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let path = write_temp_file("bad-count.flac", &flac);
+        assert_eq!(AudioFile::new(path.clone()).unwrap().read_metadata().unwrap().title, None);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_rename_and_update_path() {
+        let flac = build_flac_file("ref", &[("TITLE", "Song")], 64);
+        let original = write_temp_file("rename-from.flac", &flac);
+        let taken = write_temp_file("rename-taken.flac", &flac);
+        let mut audio = AudioFile::new(original.clone()).unwrap();
+
+        // An existing target is refused and nothing moves
+        let taken_name = Path::new(&taken).file_name().unwrap().to_str().unwrap().to_string();
+        assert!(audio.rename_and_update_path(&taken_name).is_err());
+        assert_eq!(audio.path, original);
+
+        // A bare name stays in the same directory
+        let new_name = format!("oxidant-{}-rename-to.flac", std::process::id());
+        audio.rename_and_update_path(&new_name).unwrap();
+        assert_eq!(Path::new(&audio.path), Path::new(&original).with_file_name(&new_name));
+        assert!(!Path::new(&original).exists());
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+
+        // A full path is used as given
+        audio.rename_and_update_path(&original).unwrap();
+        assert_eq!(audio.path, original);
+        assert!(Path::new(&original).exists());
+
+        std::fs::remove_file(original).ok();
+        std::fs::remove_file(taken).ok();
+    }
+
+    /// Warnings logged while running `f`
+    fn captured_warnings(f: impl FnOnce()) -> Vec<String> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = messages.clone();
+        let layer = logging::JsonLayer::with_sink(tracing::Level::WARN, move |_, line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            sink.lock().unwrap().push(record["message"].as_str().unwrap_or_default().to_string());
+        });
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), f);
+        let messages = messages.lock().unwrap().clone();
+        messages
+    }
+
+    #[test]
+    fn test_id3v1_adding_track_truncates_comment() {
+        let comment = "A comment thirty chars long!!!";
+        assert_eq!(comment.len(), 30);
+        let mut tag = crate::id3::Id3v1Tag { title: "Title".to_string(), comment: comment.to_string(), ..Default::default() };
+        let mut data = build_id3v2_file(&[("TIT2", b"\x03Title".to_vec())]);
+        data.extend_from_slice(&tag.to_bytes());
+        let path = write_temp_file("id3v1-add-track.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        tag.track = Some(7);
+        let warnings = captured_warnings(|| audio.write_id3v1_tag(&tag).unwrap());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("adding ID3v1.1 track 7"), "{}", warnings[0]);
+
+        let written = Id3v1Tag::read_from_file(&path).unwrap().unwrap();
+        assert_eq!(written.track, Some(7));
+        assert_eq!(written.comment, &comment[..28]);
+
+        // A comment that already fits next to the track is not reported
+        tag.comment = "Short".to_string();
+        assert!(captured_warnings(|| audio.write_id3v1_tag(&tag).unwrap()).is_empty());
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_id3v1_removing_track_frees_comment_bytes() {
+        let mut tag = crate::id3::Id3v1Tag {
+            title: "Title".to_string(),
+            comment: "Twenty-eight characters long".to_string(),
+            track: Some(3),
+            ..Default::default()
+        };
+        let mut data = build_id3v2_file(&[("TIT2", b"\x03Title".to_vec())]);
+        data.extend_from_slice(&tag.to_bytes());
+        let path = write_temp_file("id3v1-remove-track.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        // Without the track the comment may grow to the full 30 bytes
+        tag.track = None;
+        tag.comment = "Now a comment of thirty chars!".to_string();
+        assert!(captured_warnings(|| audio.write_id3v1_tag(&tag).unwrap()).is_empty());
+        let written = Id3v1Tag::read_from_file(&path).unwrap().unwrap();
+        assert_eq!(written.track, None);
+        assert_eq!(written.comment, "Now a comment of thirty chars!");
+
+        // With a short comment the old track byte is cleared, not left behind
+        tag.comment = "Short".to_string();
+        audio.write_id3v1_tag(&tag).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[bytes.len() - 3..bytes.len() - 1], &[0, 0]);
+        assert_eq!(Id3v1Tag::read_from_file(&path).unwrap().unwrap().track, None);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_cover_policy_strict_rejects_webp() {
+        let path = write_temp_file("cover-policy-strict.flac", &build_flac_file("ref", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let mut webp = b"RIFF\x1a\0\0\0WEBPVP8L".to_vec();
+        webp.resize(34, 0);
+        let metadata = Metadata { cover: Some(CoverArt::new(webp, None, None)), ..Default::default() };
+        let strict = WriteOptions { cover_policy: Some(CoverPolicy::Strict), ..Default::default() };
+        let error = audio.write_metadata_with_options(&metadata, &strict).unwrap_err();
+        assert!(matches!(error, AudioFileError::UnsupportedFormat(_)));
+        assert!(error.to_string().contains("image/webp"), "{}", error);
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+
+        // Animated PNGs are refused too; plain JPEG and PNG pass
+        let apng = [b"\x89PNG\r\n\x1a\n".as_slice(), b"\0\0\0\x08acTL\0\0\0\x02\0\0\0\0\0\0\0\0"].concat();
+        let metadata = Metadata { cover: Some(CoverArt::new(apng, None, None)), ..Default::default() };
+        let error = audio.write_metadata_with_options(&metadata, &strict).unwrap_err();
+        assert!(error.to_string().contains("animated image/png"), "{}", error);
+        let jpeg = b"\xFF\xD8\xFF\xE0\0\x10JFIF\0\xFF\xD9".to_vec();
+        let metadata = Metadata { cover: Some(CoverArt::new(jpeg, None, None)), ..Default::default() };
+        audio.write_metadata_with_options(&metadata, &strict).unwrap();
+
+        assert!("lenient".parse::<CoverPolicy>().is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_cover_policy_auto_converts_webp_to_jpeg() {
+        let path = write_temp_file("cover-policy-convert.flac", &build_flac_file("ref", &[], 64));
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let mut webp = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(40, 20))
+            .write_to(&mut webp, image::ImageFormat::WebP)
+            .unwrap();
+        let metadata = Metadata { cover: Some(CoverArt::new(webp.into_inner(), None, None)), ..Default::default() };
+        let options = WriteOptions { cover_policy: Some(CoverPolicy::AutoConvert), ..Default::default() };
+        audio.write_metadata_with_options(&metadata, &options).unwrap();
+
+        let cover = audio.read_cover().unwrap().unwrap();
+        assert_eq!(cover.mime_type.as_deref(), Some("image/jpeg"));
+        assert_eq!(utils::image::detect_mime_type(&cover.data), Some("image/jpeg"));
+        assert_eq!(cover.dimensions(), Some((40, 20)));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_get_lyrics_raw() {
+        // UTF-16 with BOM, language "eng", empty description
+        let mut uslt = b"\x01eng\xFF\xFE\0\0".to_vec();
+        uslt.extend("Hé".encode_utf16().flat_map(u16::to_le_bytes));
+        let path = write_temp_file("lyrics-raw.mp3", &build_id3v2_file(&[("USLT", uslt.clone())]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.read_metadata().unwrap().lyrics.as_deref(), Some("Hé"));
+        assert_eq!(audio.get_lyrics_raw().unwrap(), Some(uslt[4..].to_vec()));
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("lyrics-raw.flac", &build_flac_file("ref", &[("Lyrics", "Zeile\nzwei")], 64));
+        assert_eq!(AudioFile::new(path.clone()).unwrap().get_lyrics_raw().unwrap(), Some(b"Zeile\nzwei".to_vec()));
+        std::fs::remove_file(path).ok();
+
+        let path = write_temp_file("no-lyrics.flac", &build_flac_file("ref", &[("TITLE", "x")], 64));
+        assert_eq!(AudioFile::new(path.clone()).unwrap().get_lyrics_raw().unwrap(), None);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_metadata_json_golden() {
+        // Frames out of order, an empty title, and duplicates that must lose
+        let id3 = build_id3v2_file(&[
+            ("TRCK", b"\x033/9".to_vec()),
+            ("TIT2", b"\x03".to_vec()),
+            ("TALB", b"\x03Album".to_vec()),
+            ("TIT2", b"\x03First".to_vec()),
+            ("TPE1", b"\x03Artist".to_vec()),
+            ("TIT2", b"\x03Second".to_vec()),
+            ("TPE2", b"\x03Band".to_vec()),
+            ("TYER", b"\x031999".to_vec()),
+            ("TDRC", b"\x032001".to_vec()),
+        ]);
+        let id3_path = write_temp_file("golden.mp3", &id3);
+        let audio = AudioFile::new(id3_path.clone()).unwrap();
+        let mut json = String::new();
+        let warnings = captured_warnings(|| json = audio.get_metadata().unwrap());
+        assert_eq!(
+            json,
+            r#"{"title":"First","artist":"Artist","album":"Album","year":"1999","track":"3/9","album_artist":"Band"}"#
+        );
+        assert_eq!(warnings, [
+            r#"ignoring duplicate TIT2 value "Second"; the first one is used"#,
+            r#"ignoring duplicate TDRC value "2001"; the first one is used"#,
+        ]);
+
+        // Keys in mixed case, repeated and empty
+        let flac = build_flac_file("ref", &[
+            ("genre", "Rock"),
+            ("Title", "One"),
+            ("ARTIST", ""),
+            ("TITLE", "Two"),
+            ("artist", "Real"),
+            ("GENRE", "Jazz"),
+            ("Album Artist", "Various"),
+            ("ORIGINALYEAR", "1970"),
+            ("ORIGINALDATE", "1970-05-01"),
+        ], 64);
+        let flac_path = write_temp_file("golden.flac", &flac);
+        let audio = AudioFile::new(flac_path.clone()).unwrap();
+        let mut json = String::new();
+        let warnings = captured_warnings(|| json = audio.get_metadata().unwrap());
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"title":"One","artist":"Real","genre":"Rock; Jazz","genres":["Rock","Jazz"],"#,
+                r#""album_artist":"Various","original_date":"1970-05-01"}"#,
+            )
+        );
+        assert_eq!(warnings, [r#"ignoring duplicate TITLE value "Two"; the first one is used"#]);
+
+        // Extra keys go after the known ones, sorted, as do nested keys
+        let options = ReadOptions { include_cover: false, include_properties: true, ..Default::default() };
+        assert!(audio.get_metadata_with_options(&options).unwrap().ends_with(r#""original_date":"1970-05-01","properties":null}"#));
+        let value = serde_json::json!({"zeta": 1, "properties": {"b": 1, "a": [{"y": 1, "x": 2}]}, "title": "T"});
+        assert_eq!(ordered_json(&value), r#"{"title":"T","properties":{"a":[{"x":2,"y":1}],"b":1},"zeta":1}"#);
+
+        std::fs::remove_file(id3_path).ok();
+        std::fs::remove_file(flac_path).ok();
+    }
+
+    #[test]
+    fn test_list_cover_types() {
+        use flac::picture::PictureType;
+
+        let picture = |picture_type| FlacPicture {
+            picture_type,
+            ..FlacPicture::new(vec![0xFF, 0xD8, 0xFF, 0xD9], "image/jpeg".to_string(), String::new())
+        };
+        let mut flac = build_flac_file("vendor", &[("TITLE", "Song")], 16);
+        let streaminfo_end = FLAC_SIGNATURE.len() + 4 + 34;
+        let blocks: Vec<u8> = [PictureType::CoverBack, PictureType::CoverFront, PictureType::CoverBack].into_iter()
+            .flat_map(|picture_type| {
+                FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture(picture_type).to_bytes()).to_bytes(false)
+            })
+            .collect();
+        flac.splice(streaminfo_end..streaminfo_end, blocks);
+        let source = AudioFile::new(write_temp_file("cover-types.flac", &flac)).unwrap();
+
+        let mp3 = AudioFile::new(write_temp_file("cover-types.mp3", &build_id3v2_file(&[("TIT2", b"\x00Song".to_vec())]))).unwrap();
+        let ogg = AudioFile::new(write_temp_file("cover-types.ogg", &build_ogg_file("vendor", &[("TITLE", "Song")]))).unwrap();
+        assert!(mp3.list_cover_types().unwrap().is_empty());
+        mp3.copy_cover_from(&source).unwrap();
+        ogg.copy_cover_from(&source).unwrap();
+
+        for audio in [&source, &mp3, &ogg] {
+            assert_eq!(audio.list_cover_types().unwrap(), vec![3, 4]);
+            assert!(audio.has_cover_type(4).unwrap());
+            assert!(!audio.has_cover_type(0).unwrap());
+            std::fs::remove_file(&audio.path).ok();
+        }
+    }
+
+    #[test]
+    fn test_get_flac_audio_md5() {
+        let path = write_temp_file("audio-md5.flac", &build_flac_file("vendor", &[], 16));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_flac_audio_md5().unwrap(), None);
+
+        // MD5 of empty input, as in STREAMINFO of a file with no samples
+        let md5 = [
+            0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04, 0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e,
+        ];
+        let mut data = std::fs::read(&path).unwrap();
+        let streaminfo_start = FLAC_SIGNATURE.len() + 4;
+        data[streaminfo_start + 18..streaminfo_start + 34].copy_from_slice(&md5);
+        std::fs::write(&path, data).unwrap();
+        assert_eq!(audio.get_flac_audio_md5().unwrap().as_deref(), Some("d41d8cd98f00b204e9800998ecf8427e"));
+
+        // Editing tags leaves STREAMINFO alone
+        audio.set_metadata(r#"{"title": "Song"}"#.to_string()).unwrap();
+        assert_eq!(audio.get_flac_audio_md5().unwrap().as_deref(), Some("d41d8cd98f00b204e9800998ecf8427e"));
+
+        assert_eq!(audio.compute_audio_md5().unwrap(), audio.get_flac_audio_md5().unwrap());
+
+        let mp3 = AudioFile::new(write_temp_file("audio-md5.mp3", &build_id3v2_file(&[]))).unwrap();
+        assert!(mp3.get_flac_audio_md5().is_err());
+        assert_eq!(mp3.compute_audio_md5().unwrap(), None);
+
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(&mp3.path).ok();
+    }
+
+    #[test]
+    fn test_write_skips_fields_the_format_cannot_hold() {
+        let metadata = Metadata {
+            title: Some("Song".to_string()),
+            length_ms: Some(1000),
+            performers: vec![("guitar".to_string(), "Ann".to_string())],
+            cover: Some(CoverArt::new(vec![0xFF, 0xD8, 0xFF, 0xD9], None, None)),
+            ..Default::default()
+        };
+        let cases = [
+            ("skipped.m4a", build_m4a_file(), vec!["length_ms", "performers"], vec!["cover", "title"]),
+            ("skipped.ogg", build_ogg_file("ref", &[]), vec!["cover"], vec!["length_ms", "performers", "title"]),
+            ("skipped.flac", build_flac_file("ref", &[], 64), vec![], vec!["cover", "length_ms", "performers", "title"]),
+        ];
+        for (name, data, skipped, written) in cases {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+
+            let strict = WriteOptions { strict: true, ..Default::default() };
+            if skipped.is_empty() {
+                audio.write_metadata_with_options(&metadata, &strict).unwrap();
+                std::fs::write(&path, &data).unwrap();
+            } else {
+                let error = audio.write_metadata_with_options(&metadata, &strict).unwrap_err();
+                assert!(matches!(error, AudioFileError::UnsupportedFormat(_)), "{}: {}", name, error);
+                assert_eq!(std::fs::read(&path).unwrap(), data, "{} was partly written", name);
+            }
+
+            let report = audio.write_metadata_with_options(&metadata, &WriteOptions::default()).unwrap();
+            let fields: Vec<&str> = report.skipped.iter().map(|skipped| skipped.field.as_str()).collect();
+            assert_eq!(fields, skipped, "{}", name);
+            assert_eq!(report.written, written, "{}", name);
+            assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn test_rewrite_ogg_serial_numbers() {
+        let mut data = build_ogg_file("ref", &[("TITLE", "Song")]);
+        // Damage the serial number of the last page
+        let last = ogg::page::page_ranges(&data).pop().unwrap();
+        data[last.start + ogg::page::SERIAL_OFFSET] = 7;
+        let path = write_temp_file("serials.ogg", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_ogg_serial_numbers().unwrap(), vec![1, 7]);
+
+        audio.rewrite_ogg_serial_numbers(0x1234_5678).unwrap();
+        assert_eq!(audio.get_ogg_serial_numbers().unwrap(), vec![0x1234_5678]);
+        let rewritten = std::fs::read(&path).unwrap();
+        assert_eq!(rewritten.len(), data.len());
+        for range in ogg::page::page_ranges(&rewritten) {
+            let mut page = rewritten[range].to_vec();
+            let stored = u32::from_le_bytes(page[22..26].try_into().unwrap());
+            page[22..26].fill(0);
+            assert_eq!(ogg::page::page_crc(&page), stored);
+        }
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+
+        let flac = AudioFile::new(write_temp_file("serials.flac", &build_flac_file("ref", &[], 16))).unwrap();
+        assert!(flac.get_ogg_serial_numbers().is_err());
+        assert!(flac.rewrite_ogg_serial_numbers(1).is_err());
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(&flac.path).ok();
+    }
+
+    #[test]
+    fn test_tagless_mp3_gets_new_id3v2_tag() {
+        let tagged = build_vbr_mp3(40, false);
+        let tag_len = id3::v2::Id3v2Header::read(&mut tagged.as_slice()).unwrap().unwrap().total_size() as usize;
+        let bare = tagged[tag_len..].to_vec();
+        let path = write_temp_file("tagless.mp3", &bare);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.format, Format::Mp3);
+        assert_eq!(audio.format.as_str(), "mp3");
+        assert!(audio.read_metadata().unwrap().is_empty());
+        assert_eq!(audio.get_properties().unwrap().duration_ms, 40 * 1152 * 1000 / 44100);
+
+        audio.set_metadata(r#"{"title": "Song"}"#.to_string()).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(written.starts_with(b"ID3\x03"));
+        assert!(written.ends_with(&bare));
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Song"));
+        let reopened = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(reopened.format, Format::Id3v2);
+        assert_eq!(reopened.read_metadata().unwrap().title.as_deref(), Some("Song"));
+
+        // Data that isn't an MPEG stream is still rejected
+        let garbage = write_temp_file("garbage.mp3", &[0x12; 4096]);
+        assert!(AudioFile::new(garbage.clone()).is_err());
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(garbage).ok();
+    }
+
+    #[test]
+    fn test_itunes_media_atoms_round_trip() {
+        let mut data = build_m4a_file();
+        data[8..12].copy_from_slice(b"M4B ");
+        let path = write_temp_file("book.m4b", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        audio.set_metadata(
+            r#"{"title": "Book", "itunes": {"media_kind": 2, "gapless": true, "tv_show": "Show", "tv_season": 3, "tv_episode": 7}}"#.to_string()
+        ).unwrap();
+        let itunes = audio.read_metadata().unwrap().itunes;
+        assert_eq!(itunes, ItunesFields {
+            media_kind: Some(2),
+            gapless: Some(true),
+            tv_show: Some("Show".to_string()),
+            tv_season: Some(3),
+            tv_episode: Some(7),
+        });
+
+        // Integers are type 21 data atoms: one byte for stik/pgap, four for tvsn/tves
+        let written = std::fs::read(&path).unwrap();
+        assert!(find_frame(&written, b"stik\x00\x00\x00\x11data\x00\x00\x00\x15\x00\x00\x00\x00\x02").is_some());
+        assert!(find_frame(&written, b"pgap\x00\x00\x00\x11data\x00\x00\x00\x15\x00\x00\x00\x00\x01").is_some());
+        assert!(find_frame(&written, b"tvsn\x00\x00\x00\x14data\x00\x00\x00\x15\x00\x00\x00\x00\x00\x00\x00\x03").is_some());
+        assert!(find_frame(&written, b"tvsh\x00\x00\x00\x14data\x00\x00\x00\x01\x00\x00\x00\x00Show").is_some());
+        assert_eq!(m4a_chunk(&written), b"AUDIOAUDIO");
+
+        // Keys update one at a time; null clears one
+        audio.set_metadata(r#"{"itunes": {"tv_show": null, "tv_episode": 8}}"#.to_string()).unwrap();
+        let itunes = audio.read_metadata().unwrap().itunes;
+        assert_eq!(itunes.media_kind, Some(2));
+        assert_eq!(itunes.tv_show, None);
+        assert_eq!(itunes.tv_episode, Some(8));
+        assert!(audio.get_metadata().unwrap().contains(r#""itunes":{"gapless":true,"media_kind":2,"tv_episode":8,"tv_season":3}"#));
+
+        assert!(audio.set_metadata(r#"{"itunes": {"media_kind": "audiobook"}}"#.to_string()).is_err());
+        assert_eq!(ItunesFields::media_kind_code("Audiobook"), Some(2));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_metadata_intersection_and_union() {
+        let (scanned, mut lookup) = merge_sources();
+        lookup.artist = Some(" ".to_string());
+        lookup.set_genres(vec!["Rock".to_string()]);
+
+        let common = scanned.intersection(&lookup);
+        assert_eq!(common.title, None);
+        assert_eq!(common.artist.as_deref(), Some(" "));
+        assert_eq!(common.album, None);
+        assert_eq!(common.genre, None);
+        assert!(common.genres.is_empty());
+        assert!(common.cover.is_none());
+        assert_eq!(common.len(), 1);
+        assert_eq!(scanned.intersection(&scanned).len(), scanned.len());
+
+        let union = scanned.union(&lookup, true);
+        assert_eq!(union.title.as_deref(), Some("Scanned Title"));
+        assert_eq!(union.album.as_deref(), Some("Lookup Album"));
+        assert_eq!(union.genres, vec!["Rock"]);
+        assert!(union.cover.is_some());
+
+        let union = scanned.union(&lookup, false);
+        assert_eq!(union.title.as_deref(), Some("Lookup Title"));
+        assert!(union.cover.is_some());
+    }
+
+    #[test]
+    fn test_write_creates_missing_flac_comment() {
+        // No VORBIS_COMMENT: the new one goes right after STREAMINFO, before the SEEKTABLE
+        let mut data = FLAC_SIGNATURE.to_vec();
+        data.extend(FlacMetadataBlock::new(FlacMetadataBlockType::StreamInfo, vec![0u8; 34]).to_bytes(false));
+        data.extend(FlacMetadataBlock::new(FlacMetadataBlockType::SeekTable, vec![0u8; 18]).to_bytes(true));
+        data.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+        let path = write_temp_file("untagged.flac", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.read_metadata().unwrap().is_empty());
+
+        audio.set_metadata(r#"{"title": "New", "artist": "Someone"}"#.to_string()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("New"));
+        assert_eq!(metadata.artist.as_deref(), Some("Someone"));
+
+        let blocks = audio.get_all_metadata_blocks().unwrap();
+        let types: Vec<&str> = blocks.iter().map(|block| block.block_type.as_str()).collect();
+        assert_eq!(types, ["STREAMINFO", "VORBIS_COMMENT", "SEEKTABLE", "PADDING"]);
+        assert_eq!(blocks.iter().filter(|block| block.is_last).count(), 1);
+        assert!(blocks.last().unwrap().is_last);
+        assert!(std::fs::read(&path).unwrap().ends_with(&[0xFF, 0xF8, 0x69, 0x08]));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_mp4_cover_flags_round_trip() {
+        let path = write_temp_file("flags.m4a", &build_m4a_file());
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0xFF, 0xD9];
+        let png = png_header(1, 1);
+
+        // The flag follows the image data, not a wrong declared type
+        let covers = vec![
+            mp4::Mp4Cover::for_image(jpeg.clone(), Some("image/png")).unwrap(),
+            mp4::Mp4Cover::for_image(png.clone(), None).unwrap(),
+        ];
+        assert_eq!(covers[0].type_flag, mp4::data_types::JPEG);
+        assert_eq!(covers[1].type_flag, mp4::data_types::PNG);
+        let metadata = mp4::Mp4Metadata { covers, ..Default::default() };
+        Mp4File::new(path.clone()).write_metadata(&metadata).unwrap();
+
+        let written = std::fs::read(&path).unwrap();
+        let covr = find_frame(&written, b"covr").unwrap();
+        assert_eq!(&written[covr + 12..covr + 16], &13u32.to_be_bytes());
+        let second = covr + 8 + 16 + jpeg.len();
+        assert_eq!(&written[second..second + 4], b"data");
+        assert_eq!(&written[second + 4..second + 8], &14u32.to_be_bytes());
+
+        let read: Vec<(Vec<u8>, Option<String>)> = audio.read_covers().unwrap().into_iter()
+            .map(|cover| (cover.data, cover.mime_type))
+            .collect();
+        assert_eq!(read, [(jpeg, Some("image/jpeg".to_string())), (png.clone(), Some("image/png".to_string()))]);
+
+        // Copying keeps both images and their flags
+        let copy = write_temp_file("flags-copy.m4a", &build_m4a_file());
+        let target = AudioFile::new(copy.clone()).unwrap();
+        target.copy_cover_from(&audio).unwrap();
+        assert_eq!(Mp4File::new(copy.clone()).read_metadata().unwrap().unwrap().covers, metadata.covers);
+
+        // GIF is written with flag 12 and a warning; WebP has no flag
+        let gif = b"GIF89a\x01\x00\x01\x00".to_vec();
+        let cover = CoverArt { data: gif, mime_type: None, description: None };
+        let update = serde_json::json!({"cover": cover});
+        let warnings = captured_warnings(|| audio.set_metadata(update.to_string()).unwrap());
+        assert!(warnings.iter().any(|warning| warning.contains("GIF cover")));
+        assert_eq!(audio.read_covers().unwrap()[0].mime_type.as_deref(), Some("image/gif"));
+
+        let webp = CoverArt { data: b"RIFF\x00\x00\x00\x00WEBPVP8 ".to_vec(), mime_type: None, description: None };
+        let update = serde_json::json!({"cover": webp});
+        assert!(matches!(audio.set_metadata(update.to_string()), Err(AudioFileError::UnsupportedFormat(_))));
+
+        std::fs::remove_file(path).ok();
+        std::fs::remove_file(copy).ok();
+    }
+
+    /// Build a two-channel 2.8224 MHz DSF file of `seconds` with `tag` after the samples
+    fn build_dsf_file(seconds: u32, tag: Option<&[u8]>) -> Vec<u8> {
+        let rate = 2_822_400u32;
+        let samples = vec![0x69u8; 256];
+        let mut fmt = b"fmt ".to_vec();
+        fmt.extend_from_slice(&52u64.to_le_bytes());
+        for value in [1u32, 0, 2, 2, rate, 1] {
+            fmt.extend_from_slice(&value.to_le_bytes());
+        }
+        fmt.extend_from_slice(&(rate as u64 * seconds as u64).to_le_bytes());
+        fmt.extend_from_slice(&4096u32.to_le_bytes());
+        fmt.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut data = vec![0u8; 28];
+        data[..4].copy_from_slice(b"DSD ");
+        data[4..12].copy_from_slice(&28u64.to_le_bytes());
+        data.extend_from_slice(&fmt);
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(12 + samples.len() as u64).to_le_bytes());
+        data.extend_from_slice(&samples);
+        let tag_offset = if tag.is_some() { data.len() as u64 } else { 0 };
+        data.extend_from_slice(tag.unwrap_or_default());
+        let file_size = data.len() as u64;
+        data[12..20].copy_from_slice(&file_size.to_le_bytes());
+        data[20..28].copy_from_slice(&tag_offset.to_le_bytes());
+        data
+    }
+
+    fn dsf_header(data: &[u8]) -> (u64, u64) {
+        let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        (u64_at(12), u64_at(20))
+    }
+
+    #[test]
+    fn test_dsf_tag_found_through_header_pointer() {
+        let id3 = build_id3v2_file(&[("TIT2", b"\x00Spanish Harlem".to_vec()), ("TPE1", b"\x00Rebecca Pidgeon".to_vec())]);
+        let tag = &id3[..id3.len() - 4];
+        let data = build_dsf_file(3, Some(tag));
+        let path = write_temp_file("tagged.dsf", &data);
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.format, Format::Dsf);
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Spanish Harlem"));
+        assert_eq!(metadata.artist.as_deref(), Some("Rebecca Pidgeon"));
+
+        let properties = audio.get_properties().unwrap();
+        assert_eq!(properties.duration_ms, 3000);
+        assert_eq!(properties.sample_rate, Some(2_822_400));
+        assert_eq!(properties.channels, Some(2));
+        assert_eq!(properties.codec, "DSD");
+        assert!(properties.lossless);
+        assert_eq!(audio.compute_audio_span().unwrap(), (92, 256));
+
+        // With the pointer cleared the same bytes are an untagged file
+        let mut untagged = data.clone();
+        untagged[20..28].fill(0);
+        std::fs::write(&path, &untagged).unwrap();
+        assert!(audio.read_metadata().unwrap().title.is_none());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_dsf_write_updates_header() {
+        let data = build_dsf_file(1, None);
+        let path = write_temp_file("untagged.dsf", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.read_metadata().unwrap().title.is_none());
+
+        // The first write appends a tag after the samples
+        let metadata = Metadata { title: Some("First".to_string()), ..Default::default() };
+        audio.write_metadata(&metadata).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(dsf_header(&written), (written.len() as u64, data.len() as u64));
+        assert_eq!(&written[28..data.len()], &data[28..]);
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("First"));
+
+        // A tag too large for the old space stays where it is and grows the file
+        let long = "x".repeat(4000);
+        let metadata = Metadata { title: Some(long.clone()), ..Default::default() };
+        audio.write_metadata(&metadata).unwrap();
+        let rewritten = std::fs::read(&path).unwrap();
+        assert!(rewritten.len() > written.len());
+        assert_eq!(dsf_header(&rewritten), (rewritten.len() as u64, data.len() as u64));
+        assert_eq!(&rewritten[28..data.len()], &data[28..]);
+        assert_eq!(audio.read_metadata().unwrap().title, Some(long));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_id3v2_custom_frames() {
+        let data = build_id3v2_file(&[
+            ("TIT2", b"\x00Title".to_vec()),
+            ("XSOP", b"\x00Beatles, The".to_vec()),
+            ("XTCP", b"1".to_vec()),
+            ("YCAF", b"Caf\xe9".to_vec()),
+        ]);
+        let path = write_temp_file("custom-frames.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        let pairs = |frames: &[(&str, &str)]| -> Vec<(String, String)> {
+            frames.iter().map(|(id, value)| (id.to_string(), value.to_string())).collect()
+        };
+        assert_eq!(
+            audio.list_id3v2_custom_frames().unwrap(),
+            pairs(&[("XSOP", "Beatles, The"), ("XTCP", "1"), ("YCAF", "Café")])
+        );
+
+        audio.set_id3v2_custom_frame("XSOP", "Beatles").unwrap();
+        audio.set_id3v2_custom_frame("XTCP", "").unwrap();
+        audio.set_id3v2_custom_frame("Z001", "Ωmega").unwrap();
+        assert!(audio.set_id3v2_custom_frame("TIT2", "x").is_err());
+        assert!(audio.set_id3v2_custom_frame("Xsop", "x").is_err());
+
+        // Metadata writes keep them
+        let metadata = Metadata { title: Some("New".to_string()), ..Default::default() };
+        audio.write_metadata(&metadata).unwrap();
+        assert_eq!(
+            audio.list_id3v2_custom_frames().unwrap(),
+            pairs(&[("YCAF", "Café"), ("XSOP", "Beatles"), ("Z001", "Ωmega")])
+        );
+        std::fs::remove_file(path).ok();
+    }
+
+    /// Insert an ICY metadata block after every `interval` bytes of `audio`, cycling through `titles`
+    ///
+    /// `None` gives an empty block, as servers send while the title is unchanged.
+    fn interleave_icy(audio: &[u8], interval: usize, titles: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (index, chunk) in audio.chunks(interval).enumerate() {
+            data.extend_from_slice(chunk);
+            if chunk.len() < interval {
+                break;
+            }
+            match titles[index % titles.len()] {
+                Some(title) => {
+                    let mut block = [&b"StreamTitle='"[..], title, b"';StreamUrl='';"].concat();
+                    block.resize(block.len().div_ceil(16) * 16, 0);
+                    data.push((block.len() / 16) as u8);
+                    data.extend(block);
+                }
+                None => data.push(0),
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_icy_blocks_skipped_and_stripped() {
+        let clean = build_vbr_mp3(400, false);
+        let tag_len = id3::v2::Id3v2Header::read(&mut clean.as_slice()).unwrap().unwrap().total_size() as usize;
+        let titles: [Option<&[u8]>; 4] = [Some(b"Artist - One"), None, None, Some(b"Caf\xe9 del Mar - Two")];
+        let mut data = clean[..tag_len].to_vec();
+        data.extend(interleave_icy(&clean[tag_len..], 8192, &titles));
+        let path = write_temp_file("icy.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let stream = audio.read_icy_stream().unwrap().unwrap();
+        assert_eq!(stream.interval, 8192);
+        assert_eq!(stream.blocks, (clean.len() - tag_len) / 8192);
+        assert_eq!(stream.stream_title.as_deref(), Some("Café del Mar - Two"));
+
+        // Tags read the same either way; icy_aware adds the title and clean properties
+        let options = ReadOptions { include_cover: false, include_properties: true, icy_aware: true, ..Default::default() };
+        let json: serde_json::Value = serde_json::from_str(&audio.get_metadata_with_options(&options).unwrap()).unwrap();
+        assert_eq!(json["stream_title"], "Café del Mar - Two");
+        assert_eq!(json["encoder"], "LAME3.100");
+        let expected_ms = 400 * 1152 * 1000 / 44100;
+        assert_eq!(json["properties"]["duration_ms"], expected_ms);
+
+        assert_eq!(audio.strip_icy_metadata().unwrap(), stream.blocks);
+        assert_eq!(std::fs::read(&path).unwrap(), clean);
+        assert!(audio.read_icy_stream().unwrap().is_none());
+        assert_eq!(audio.strip_icy_metadata().unwrap(), 0);
+        assert_eq!(audio.get_properties().unwrap().duration_ms, expected_ms);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_validate_cover_art_consistency() {
+        use flac::picture::PictureType;
+
+        let picture = |picture_type, data: Vec<u8>, mime_type: &str, description: &str, size: u32| FlacPicture {
+            picture_type,
+            width: size,
+            height: size,
+            ..FlacPicture::new(data, mime_type.to_string(), description.to_string())
+        };
+        let build = |pictures: &[FlacPicture]| {
+            let mut flac = build_flac_file("vendor", &[("TITLE", "Song")], 16);
+            let streaminfo_end = FLAC_SIGNATURE.len() + 4 + 34;
+            let blocks: Vec<u8> = pictures.iter()
+                .flat_map(|picture| FlacMetadataBlock::new(FlacMetadataBlockType::Picture, picture.to_bytes()).to_bytes(false))
+                .collect();
+            flac.splice(streaminfo_end..streaminfo_end, blocks);
+            flac
+        };
+
+        let consistent = build(&[
+            picture(PictureType::CoverFront, png_header(500, 500), "image/png", "Front", 500),
+            picture(PictureType::Other, png_header(20, 20), "image/png", "", 20),
+            picture(PictureType::Other, png_header(30, 30), "image/png", "", 30),
+        ]);
+        let path = write_temp_file("covers-consistent.flac", &consistent);
+        assert!(AudioFile::new(path.clone()).unwrap().validate_cover_art_consistency().unwrap().is_empty());
+
+        let inconsistent = build(&[
+            picture(PictureType::CoverFront, png_header(500, 500), "image/jpeg", "Front", 500),
+            picture(PictureType::CoverBack, png_header(250, 250), "image/png", "", 500),
+            picture(PictureType::CoverBack, png_header(10, 10), "image/png", "Back\u{1}", 0),
+            picture(PictureType::Media, Vec::new(), "image/png", "", 0),
+        ]);
+        std::fs::write(&path, inconsistent).unwrap();
+        assert_eq!(AudioFile::new(path.clone()).unwrap().validate_cover_art_consistency().unwrap(), vec![
+            "picture 1 [Cover (front)]: MIME type is image/jpeg but the data is image/png".to_string(),
+            "picture 2 [Cover (back)]: dimensions are 500×500 but the image is 250×250".to_string(),
+            "picture 3 [Cover (back)]: dimensions are 0×0 but the image is 10×10".to_string(),
+            "picture 3 [Cover (back)]: description \"Back\\u{1}\" contains non-printable characters".to_string(),
+            "picture 4 [Media]: image data is empty".to_string(),
+            "2 different [Cover (back)] pictures; players show only one".to_string(),
+        ]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_latin1_cover_description_moves_to_flac_as_utf8() {
+        // ID3v2 APIC with ISO-8859-1 encoding: "Café" is 0x43 0x61 0x66 0xE9
+        let mut apic = b"\x00image/png\x00\x03Caf\xe9\x00".to_vec();
+        apic.extend_from_slice(&png_header(2, 2));
+        let mp3 = AudioFile::new(write_temp_file("latin1-cover.mp3", &build_id3v2_file(&[("APIC", apic)]))).unwrap();
+        let flac_path = write_temp_file("latin1-cover.flac", &build_flac_file("vendor", &[("TITLE", "Song")], 16));
+        let flac = AudioFile::new(flac_path.clone()).unwrap();
+
+        flac.copy_cover_from(&mp3).unwrap();
+        let block = flac.get_all_metadata_blocks().unwrap().into_iter()
+            .find(|block| block.block_type == "PICTURE")
+            .unwrap();
+        let data = std::fs::read(&flac_path).unwrap();
+        let picture = &data[block.offset + 4..block.offset + 4 + block.size_bytes];
+        assert_eq!(&picture[8 + 9..8 + 9 + 4], &5u32.to_be_bytes());
+        assert_eq!(&picture[8 + 9 + 4..8 + 9 + 4 + 5], "Café".as_bytes());
+        assert_eq!(flac.read_covers().unwrap()[0].description.as_deref(), Some("Café"));
+
+        std::fs::remove_file(&mp3.path).ok();
+        std::fs::remove_file(flac_path).ok();
+    }
+
+    #[test]
+    fn test_read_fields_skips_unrequested_frames() {
+        let cover = vec![0xAB; 4 * 1024 * 1024];
+        let data = build_id3v2_file(&[
+            ("APIC", build_apic_frame(&cover)),
+            ("TIT2", b"\x00Title".to_vec()),
+            ("TPE1", b"\x00Artist".to_vec()),
+            ("APIC", build_apic_frame(&cover)),
+            ("TALB", b"\x00Album".to_vec()),
+        ]);
+        let path = write_temp_file("read-fields.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        // The first picture is seeked past and the scan stops after TIT2
+        let bytes_read = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = CountingReader { inner: std::io::Cursor::new(&data), bytes_read: bytes_read.clone() };
+        let metadata = audio.read_id3v2_fields(reader, &[StandardField::Title]).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Title"));
+        assert!(bytes_read.get() < 4096, "read {} bytes", bytes_read.get());
+
+        let metadata = audio.read_fields(&[StandardField::Album, StandardField::Cover]).unwrap();
+        assert_eq!(metadata.album.as_deref(), Some("Album"));
+        assert_eq!(metadata.cover.map(|cover| cover.data.len()), Some(cover.len()));
+        assert_eq!(metadata.title, None);
+        assert_eq!(metadata.artist, None);
+
+        // Whole-tag reads still skip the pictures
+        assert_eq!(audio.read_metadata_internal().unwrap().artist.as_deref(), Some("Artist"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tag_overhead_sums_to_file_size() {
+        let mut mp3 = build_id3v2_file(&[("TIT2", b"\x00Title".to_vec())]);
+        let id3v2_len = mp3.len() - 4;
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, 0);
+        mp3.extend_from_slice(&id3v1);
+
+        let mut ogg = build_ogg_file("vendor", &[("TITLE", "Title")]);
+        // The audio page is the last, 32-byte one; give it a granule position
+        let audio_page = ogg.len() - 32;
+        ogg[audio_page + 6] = 4;
+
+        let flac = build_flac_file("vendor", &[("TITLE", "Title")], 256);
+        let cases = [
+            ("overhead.mp3", mp3, id3v2_len as u64 + 128),
+            ("overhead.ogg", ogg.clone(), audio_page as u64),
+            ("overhead.flac", flac.clone(), flac.len() as u64 - 4),
+        ];
+        for (name, data, expected_metadata) in cases {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            let (metadata, audio_bytes) = audio.tag_overhead().unwrap();
+            assert_eq!(metadata + audio_bytes, data.len() as u64, "{}", name);
+            assert_eq!(metadata, expected_metadata, "{}", name);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_id3v1_extended_tag_read_and_preserved() {
+        let long_title = "A Title Running Well Past The Thirty Character Limit";
+        let long_album = "An Album Name That Also Needs The Extended Tag";
+        let tag = crate::id3::Id3v1Tag {
+            title: long_title.to_string(),
+            artist: "Artist".to_string(),
+            album: long_album.to_string(),
+            year: "1999".to_string(),
+            genre: 17,
+            extended: Some(Id3v1Extended {
+                speed: 3,
+                genre: "Post-Rock".to_string(),
+                start_time: "000:05".to_string(),
+                end_time: "004:30".to_string(),
+            }),
+            ..Default::default()
+        };
+        let trailer = [tag.extended_to_bytes().unwrap().as_slice(), &tag.to_bytes()].concat();
+        assert_eq!(&trailer[..4], b"TAG+");
+        let mut data = build_id3v2_file(&[("TIT2", b"\x03Title".to_vec())]);
+        let audio_len = 4;
+        data.extend_from_slice(&trailer);
+        let path = write_temp_file("id3v1-extended.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let read = Id3v1Tag::read_from_file(&path).unwrap().unwrap();
+        assert_eq!(read.title, long_title);
+        assert_eq!(read.album, long_album);
+        assert_eq!(read.extended, tag.extended);
+        assert_eq!(audio.get_audio_span().unwrap().1, audio_len);
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.album.as_deref(), Some(long_album));
+        assert_eq!(metadata.genre.as_deref(), Some("Post-Rock"));
+
+        // ID3v2 writes leave both blocks untouched, ID3v1 rewrites keep them together
+        audio.set_metadata(r#"{"title": "New"}"#.to_string()).unwrap();
+        assert!(std::fs::read(&path).unwrap().ends_with(&trailer));
+        audio.write_id3v1_tag(&read).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        assert!(written.ends_with(&trailer));
+        assert!(!written[..written.len() - trailer.len()].ends_with(b"TAG"));
+
+        let warnings = captured_warnings(|| assert!(audio.remove_id3v1_extended().unwrap()));
+        assert!(warnings[0].contains("TAG+"), "{:?}", warnings);
+        let stripped = std::fs::read(&path).unwrap();
+        assert_eq!(stripped.len(), written.len() - Id3v1Tag::EXTENDED_TAG_SIZE);
+        let read = Id3v1Tag::read_from_file(&path).unwrap().unwrap();
+        assert_eq!(read.extended, None);
+        assert_eq!(read.title, long_title[..30].trim_end());
+        assert!(!audio.remove_id3v1_extended().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_set_cover_for_type() {
+        let front = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        let back = png_header(10, 10);
+        let logo = png_header(20, 5);
+        let back_path = write_temp_file("cover-type-back.png", &back);
+        let logo_path = write_temp_file("cover-type-logo.png", &logo);
+
+        let mp3 = build_id3v2_file(&[("APIC", build_apic_frame(&front))]);
+        let flac = build_flac_file("vendor", &[("TITLE", "Title")], 64);
+        for (name, data) in [("cover-type.mp3", mp3), ("cover-type.flac", flac)] {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+
+            audio.set_cover_for_type(4, &back_path, "", "Back").unwrap();
+            audio.set_cover_for_type(19, &logo_path, "image/png", "Logo").unwrap();
+            // A second back cover replaces the first
+            audio.set_cover_for_type(4, &logo_path, "", "New back").unwrap();
+
+            let pictures = audio.read_pictures().unwrap();
+            let back_covers: Vec<_> = pictures.iter()
+                .filter(|picture| picture.picture_type == flac::picture::PictureType::CoverBack)
+                .collect();
+            assert_eq!(back_covers.len(), 1, "{}", name);
+            assert_eq!(back_covers[0].data, logo, "{}", name);
+            assert_eq!(back_covers[0].description, "New back", "{}", name);
+            assert_eq!(back_covers[0].mime_type, "image/png", "{}", name);
+            let expected_types = if name.ends_with(".mp3") { vec![3, 4, 19] } else { vec![4, 19] };
+            assert_eq!(audio.list_cover_types().unwrap(), expected_types, "{}", name);
+
+            assert!(audio.set_cover_for_type(21, &logo_path, "", "").is_err());
+            std::fs::remove_file(&path).unwrap();
+        }
+        std::fs::remove_file(&back_path).unwrap();
+        std::fs::remove_file(&logo_path).unwrap();
+    }
+
+    #[test]
+    fn test_new_cover_keeps_other_picture_types() {
+        use flac::picture::PictureType;
+
+        let front = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        let back = png_header(10, 10);
+        let new_front = png_header(30, 30);
+        let front_path = write_temp_file("keep-types-front.jpg", &front);
+        let back_path = write_temp_file("keep-types-back.png", &back);
+
+        let check = |name: &str, data: Vec<u8>| {
+            let path = write_temp_file(name, &data);
+            let audio = AudioFile::new(path.clone()).unwrap();
+            audio.set_cover_for_type(4, &back_path, "", "Back").unwrap();
+            audio.set_cover_for_type(3, &front_path, "", "Front").unwrap();
+
+            audio.set_cover_from_bytes(new_front.clone(), None).unwrap();
+            let pictures = audio.read_pictures().unwrap();
+            let types: Vec<_> = pictures.iter().map(|picture| picture.picture_type).collect();
+            assert_eq!(types, [PictureType::CoverFront, PictureType::CoverBack], "{}", name);
+            assert_eq!(pictures[0].data, new_front, "{}", name);
+            assert_eq!(pictures[1].data, back, "{}", name);
+            assert_eq!(audio.read_cover().unwrap().unwrap().data, new_front, "{}", name);
+
+            // Other changes leave both pictures alone
+            audio.set_metadata(r#"{"title": "Changed"}"#.to_string()).unwrap();
+            assert_eq!(audio.read_pictures().unwrap().len(), 2, "{}", name);
+            std::fs::remove_file(&path).unwrap();
+        };
+        check("keep-types.mp3", build_id3v2_file(&[("TIT2", b"\x00Title".to_vec())]));
+        check("keep-types.flac", build_flac_file("vendor", &[("TITLE", "Title")], 64));
+
+        std::fs::remove_file(&front_path).unwrap();
+        std::fs::remove_file(&back_path).unwrap();
+    }
+
+    #[test]
+    fn test_musicbrainz_fields() {
+        let metadata = Metadata {
+            title: Some("Song".to_string()),
+            artist: Some("One; Two / Three".to_string()),
+            album: Some("Album".to_string()),
+            year: Some("2001".to_string()),
+            track: Some("5/12".to_string()),
+            ..Default::default()
+        };
+        let fields = metadata.to_musicbrainz();
+        assert_eq!(fields.artists, vec!["One", "Two", "Three"]);
+        assert_eq!(fields.release.as_deref(), Some("Album"));
+        assert_eq!(fields.date.as_deref(), Some("2001"));
+        assert_eq!((fields.tracknumber, fields.totaltracks), (Some(5), Some(12)));
+        let value = serde_json::to_value(&fields).unwrap();
+        assert!(value["isrc"].is_null());
+        assert_eq!(value.as_object().unwrap().len(), 11);
+
+        let recording = b"7b3e7c4e-0000-4000-8000-000000000001";
+        let mut txxx = b"\x00MusicBrainz Album Id\x00".to_vec();
+        txxx.extend_from_slice(b"album-id");
+        let mp3 = build_id3v2_file(&[
+            ("TIT2", b"\x00Song".to_vec()),
+            ("TPOS", b"\x001/2".to_vec()),
+            ("TSRC", b"\x00USABC1234567".to_vec()),
+            ("TXXX", txxx),
+            ("UFID", id3::frames::encode_ufid_frame("http://musicbrainz.org", recording)),
+        ]);
+        let flac = build_flac_file("vendor", &[
+            ("TITLE", "Song"),
+            ("DISCNUMBER", "1"),
+            ("DISCTOTAL", "2"),
+            ("ISRC", "USABC1234567"),
+            ("MUSICBRAINZ_TRACKID", std::str::from_utf8(recording).unwrap()),
+            ("MUSICBRAINZ_ALBUMID", "album-id"),
+        ], 64);
+        for (name, data) in [("musicbrainz.mp3", mp3), ("musicbrainz.flac", flac)] {
+            let path = write_temp_file(name, &data);
+            let fields = AudioFile::new(path.clone()).unwrap().read_musicbrainz_fields().unwrap();
+            assert_eq!(fields.title.as_deref(), Some("Song"), "{}", name);
+            assert_eq!((fields.discnumber, fields.totaldiscs), (Some(1), Some(2)), "{}", name);
+            assert_eq!(fields.isrc.as_deref(), Some("USABC1234567"), "{}", name);
+            assert_eq!(fields.musicbrainz_trackid.as_deref().map(str::as_bytes), Some(recording.as_slice()), "{}", name);
+            assert_eq!(fields.musicbrainz_albumid.as_deref(), Some("album-id"), "{}", name);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_metadata_with_sources() {
+        // ID3v2 wins, its first non-empty frame per field wins, ID3v1 fills the gaps
+        let mut data = build_id3v2_file_version(4, &[
+            ("TIT2", b"\x03V2 Title".to_vec()),
+            ("TPE1", b"\x03".to_vec()),
+            ("TALB", b"\x03".to_vec()),
+            ("TALB", b"\x03V2 Album".to_vec()),
+            ("TCON", b"\x03(17)".to_vec()),
+        ]);
+        data.extend_from_slice(&build_id3v1_tag("V1 Title", "V1 Artist", "V1 Album", "1999", Some(3)));
+        let path = write_temp_file("sources.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+
+        let (metadata, sources) = audio.read_metadata_with_sources(&ReadOptions::default()).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("V2 Title"));
+        assert_eq!(metadata.artist.as_deref(), Some("V1 Artist"));
+        assert_eq!(metadata.album.as_deref(), Some("V2 Album"));
+        let source = |key: &str| sources.get(key).map(String::as_str);
+        assert_eq!(source("title"), Some("ID3v2.4/TIT2"));
+        assert_eq!(source("artist"), Some("ID3v1"));
+        assert_eq!(source("album"), Some("ID3v2.4/TALB"));
+        assert_eq!(source("genre"), Some("ID3v2.4/TCON"));
+        assert_eq!(source("genre_raw"), Some("ID3v2.4/TCON"));
+        assert_eq!(source("year"), Some("ID3v1"));
+        assert_eq!(source("track"), Some("ID3v1"));
+        assert_eq!(sources.len(), metadata.populated_keys().len());
+
+        let options = ReadOptions { with_sources: true, ..Default::default() };
+        let json: serde_json::Value = serde_json::from_str(&audio.get_metadata_with_options(&options).unwrap()).unwrap();
+        assert_eq!(json["title"], serde_json::json!({ "value": "V2 Title", "source": "ID3v2.4/TIT2" }));
+        assert_eq!(json["artist"]["source"], "ID3v1");
+        std::fs::remove_file(&path).unwrap();
+
+        // Vorbis comments are named by their key as stored
+        let data = build_flac_file("vendor", &[
+            ("title", ""),
+            ("TITLE", "First"),
+            ("TITLE", "Second"),
+            ("GENRE", "Rock"),
+            ("GENRE", "Pop"),
+            ("ORIGINALYEAR", "1970"),
+        ], 0);
+        let path = write_temp_file("sources.flac", &data);
+        let (metadata, sources) = AudioFile::new(path.clone()).unwrap()
+            .read_metadata_with_sources(&ReadOptions::default()).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("First"));
+        assert_eq!(sources.get("title").map(String::as_str), Some("FLAC/TITLE"));
+        assert_eq!(sources.get("genres").map(String::as_str), Some("FLAC/GENRE"));
+        assert_eq!(sources.get("original_date").map(String::as_str), Some("FLAC/ORIGINALYEAR"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_opus_output_gain() {
+        // Version 1, 2 channels, 312 samples pre-skip, 48 kHz input, +1 dB gain, mapping family 0
+        let mut ident = b"OpusHead\x01\x02".to_vec();
+        ident.extend_from_slice(&312u16.to_le_bytes());
+        ident.extend_from_slice(&48000u32.to_le_bytes());
+        ident.extend_from_slice(&256i16.to_le_bytes());
+        ident.push(0);
+        let comment = flac::vorbis::VorbisComment {
+            vendor_string: "libopus".to_string(),
+            comments: vec![("TITLE".to_string(), "Gain".to_string())],
+        };
+        let tags = [b"OpusTags".as_slice(), &comment.to_bytes()].concat();
+        let mut data = ogg_page(0, 0x02, &[ident.len() as u8], &ident);
+        data.extend(ogg_page(1, 0x00, &[tags.len() as u8], &tags));
+        data.extend(ogg_page(2, 0x04, &[4], b"\xAA\xBB\xCC\xDD"));
+        let path = write_temp_file("gain.opus", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.format, Format::Opus);
+        assert_eq!(audio.get_opus_output_gain().unwrap(), Some(1.0));
+
+        // Lower it by 3.5 dB: -2.5 dB is -640 in Q7.8
+        audio.set_opus_output_gain(1.0 - 3.5).unwrap();
+        assert_eq!(audio.get_opus_output_gain().unwrap(), Some(-2.5));
+        let new_data = std::fs::read(&path).unwrap();
+        let gain_start = 28 + opus::OUTPUT_GAIN_OFFSET;
+        assert_eq!(new_data[gain_start..gain_start + 2], (-640i16).to_le_bytes());
+        assert_eq!(new_data.len(), data.len());
+        let changed: Vec<usize> = (0..data.len()).filter(|&i| data[i] != new_data[i]).collect();
+        assert!(changed.iter().all(|&i| (22..26).contains(&i) || (gain_start..gain_start + 2).contains(&i)));
+
+        // The identification page carries a valid CRC and the comments are untouched
+        let mut page = new_data[..28 + ident.len()].to_vec();
+        let stored = u32::from_le_bytes(page[22..26].try_into().unwrap());
+        page[22..26].fill(0);
+        assert_eq!(ogg::page::page_crc(&page), stored);
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("Gain"));
+
+        assert!(audio.set_opus_output_gain(200.0).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        let path = write_temp_file("gain.flac", &build_flac_file("vendor", &[], 0));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_opus_output_gain().unwrap(), None);
+        assert!(audio.set_opus_output_gain(1.0).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "verify-audio")]
+    #[test]
+    fn test_flac_md5_matches_audio() {
+        // Nothing to verify against when the encoder left the MD5 unset
+        let path = write_temp_file("verify-md5.flac", &build_flac_file("vendor", &[], 16));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_flac_md5_matches_audio().unwrap(), None);
+
+        // The fake frame can't be decoded, whether or not `flac` is installed
+        let mut data = std::fs::read(&path).unwrap();
+        let streaminfo_start = FLAC_SIGNATURE.len() + 4;
+        data[streaminfo_start + 18..streaminfo_start + 34].fill(0xAB);
+        std::fs::write(&path, data).unwrap();
+        assert!(matches!(
+            audio.get_flac_md5_matches_audio(),
+            Err(AudioFileError::ToolNotFound(_) | AudioFileError::AudioIntegrityError(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "verify-audio")]
+    #[test]
+    fn test_flac_md5_matches_encoded_audio() {
+        use std::process::Command;
+
+        if Command::new("flac").arg("--version").output().is_err() {
+            eprintln!("flac is not on the PATH, skipping");
+            return;
+        }
+        // A tenth of a second of a 16-bit mono ramp, encoded by the reference encoder
+        let samples: Vec<u8> = (0..4410i16).flat_map(|i| (i * 7).to_le_bytes()).collect();
+        let raw = write_temp_file("verify-md5.raw", &samples);
+        let path = std::env::temp_dir().join(format!("oxidant-{}-verify-encoded.flac", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let status = Command::new("flac")
+            .args(["--silent", "--force", "--force-raw-format", "--endian=little", "--sign=signed"])
+            .args(["--channels=1", "--bps=16", "--sample-rate=44100", "-o", &path, &raw])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        std::fs::remove_file(&raw).unwrap();
+
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_flac_md5_matches_audio().unwrap(), Some(true));
+
+        let mut data = std::fs::read(&path).unwrap();
+        let streaminfo_start = FLAC_SIGNATURE.len() + 4;
+        data[streaminfo_start + 18] ^= 0xFF;
+        std::fs::write(&path, data).unwrap();
+        assert_eq!(audio.get_flac_md5_matches_audio().unwrap(), Some(false));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_track_number_and_total() {
+        let metadata = Metadata { track: Some("3/12".to_string()), ..Default::default() };
+        assert_eq!(metadata.track_number(), Some(3));
+        assert_eq!(metadata.track_total(), Some(12));
+
+        let metadata = Metadata { track: Some("03".to_string()), ..Default::default() };
+        assert_eq!((metadata.track_number(), metadata.track_total()), (Some(3), None));
+
+        let metadata = Metadata { track: Some("A1".to_string()), ..Default::default() };
+        assert_eq!((metadata.track_number(), metadata.track_total()), (None, None));
+        assert_eq!(Metadata::default().track_number(), None);
+    }
+
+    #[test]
+    fn test_copy_metadata_from_messy_mp3() {
+        // UTF-8 stored in an ISO-8859-1 frame, a NUL-separated ID3v2.4 multi-value,
+        // a genre reference, a slashed date, a zero-padded track, a Windows-1252
+        // comment and a stray control character in the lyrics
+        let source = write_temp_file("messy.mp3", &build_id3v2_file_version(4, &[
+            ("TIT2", b"\x00Caf\xC3\xA9".to_vec()),
+            ("TPE1", b"\x03Artist A\x00Artist B".to_vec()),
+            ("TCON", b"\x00(17)".to_vec()),
+            ("TDRC", b"\x002011/09/26".to_vec()),
+            ("TRCK", b"\x0003/12".to_vec()),
+            ("COMM", b"\x00eng\x00Caf\xE9 \x93quoted\x94".to_vec()),
+            ("USLT", b"\x03eng\x00Line 1\nLine 2\x01".to_vec()),
+        ]));
+        let source = AudioFile::new(source).unwrap();
+
+        let targets = [
+            write_temp_file("copy-target.flac", &build_flac_file("vendor", &[], 64)),
+            write_temp_file("copy-target.ogg", &build_ogg_file("vendor", &[])),
+            write_temp_file("copy-target.m4a", &build_m4a_file()),
+        ];
+        for path in &targets {
+            let target = AudioFile::new(path.clone()).unwrap();
+            target.copy_metadata_from(&source).unwrap();
+
+            let metadata = target.read_metadata().unwrap();
+            let format = target.format;
+            assert_eq!(metadata.title.as_deref(), Some("Café"), "{}", format);
+            assert_eq!(metadata.artist.as_deref(), Some("Artist A; Artist B"), "{}", format);
+            assert_eq!(metadata.genre.as_deref(), Some("Rock"), "{}", format);
+            assert_eq!(metadata.year.as_deref(), Some("2011-09-26"), "{}", format);
+            assert_eq!(metadata.track_number(), Some(3), "{}", format);
+            assert_eq!(metadata.comment.as_deref(), Some("Café “quoted”"), "{}", format);
+            assert_eq!(metadata.lyrics.as_deref(), Some("Line 1\nLine 2"), "{}", format);
+            std::fs::remove_file(path).unwrap();
+        }
+
+        // ID3v1 only holds the year
+        let mut metadata = source.read_metadata().unwrap();
+        metadata.normalize_for(Format::Id3v1);
+        assert_eq!(metadata.year.as_deref(), Some("2011"));
+        assert_eq!(metadata.genre_raw, None);
+        std::fs::remove_file(&source.path).unwrap();
+    }
+
+    #[test]
+    fn test_get_all_frame_ids() {
+        let path = write_temp_file("frame-ids.mp3", &build_id3v2_file_version(4, &[
+            ("TIT2", b"\x00Title".to_vec()),
+            ("GEOB", b"\x00application/octet-stream\x00file.bin\x00desc\x00\x01\x02".to_vec()),
+            ("TIT2", b"\x00Again".to_vec()),
+        ]));
+        let ids = AudioFile::new(path.clone()).unwrap().get_all_frame_ids().unwrap();
+        assert_eq!(ids, vec!["TIT2", "GEOB", "TIT2"]);
+        std::fs::remove_file(&path).unwrap();
+
+        let path = write_temp_file("frame-ids.flac", &build_flac_file("vendor", &[("TITLE", "x")], 16));
+        let ids = AudioFile::new(path.clone()).unwrap().get_all_frame_ids().unwrap();
+        assert_eq!(ids, vec!["STREAMINFO", "VORBIS_COMMENT", "PADDING"]);
+        std::fs::remove_file(&path).unwrap();
+
+        let path = write_temp_file("frame-ids.ogg", &build_ogg_file("vendor", &[("title", "x"), ("ARTIST", "y")]));
+        let ids = AudioFile::new(path.clone()).unwrap().get_all_frame_ids().unwrap();
+        assert_eq!(ids, vec!["title", "ARTIST"]);
+        std::fs::remove_file(&path).unwrap();
+
+        let path = write_temp_file("frame-ids.m4a", &build_m4a_file());
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert!(audio.get_all_frame_ids().unwrap().is_empty());
+        audio.set_metadata(r#"{"title": "Song", "original_date": "1977"}"#.to_string()).unwrap();
+        assert_eq!(audio.get_all_frame_ids().unwrap(), vec!["©nam", "----"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_keeps_id3v1_values_out_of_id3v2_and_clears_both() {
+        let mut data = build_id3v2_file(&[
+            ("TIT2", b"\x00Title".to_vec()),
+            ("TPE1", b"\x00Artist".to_vec()),
+        ]);
+        data.extend_from_slice(&build_id3v1_tag("Title", "Artist", "V1 Album", "1999", Some(4)));
+        let path = write_temp_file("id3v1-and-id3v2.mp3", &data);
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.format, Format::Id3v2);
+
+        // Editing one field doesn't copy the ID3v1-only fields into the ID3v2 tag
+        audio.set_metadata(r#"{"artist": "New Artist"}"#.to_string()).unwrap();
+        assert_eq!(audio.get_all_frame_ids().unwrap(), vec!["TIT2", "TPE1"]);
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.artist.as_deref(), Some("New Artist"));
+        assert_eq!(metadata.album.as_deref(), Some("V1 Album"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+
+        // A field held by both tags, and one held by ID3v1 alone, can be cleared
+        audio.set_metadata(r#"{"title": null, "album": null, "genre": null}"#.to_string()).unwrap();
+        let metadata = audio.read_metadata().unwrap();
+        assert_eq!(metadata.title, None);
+        assert_eq!(metadata.album, None);
+        assert_eq!(metadata.genre, None);
+        assert_eq!(metadata.year.as_deref(), Some("1999"));
+        assert_eq!(metadata.track.as_deref(), Some("4"));
+        let id3v1 = Id3v1Tag::read_from_file(&path).unwrap().unwrap();
+        assert_eq!((id3v1.title.as_str(), id3v1.album.as_str(), id3v1.genre), ("", "", 255));
+        assert_eq!(id3v1.artist, "Artist");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_id3v2_nonstandard_frame_ids_survive_a_write() {
+        let path = write_temp_file("nonstandard-frame-ids.mp3", &build_id3v2_file(&[
+            ("TIT2", b"\x00Title".to_vec()),
+            ("COM ", b"\x00engdesc\x00TIT2 looks like a frame".to_vec()),
+            ("xtra", b"experimental".to_vec()),
+        ]));
+        let audio = AudioFile::new(path.clone()).unwrap();
+        assert_eq!(audio.get_all_frame_ids().unwrap(), vec!["TIT2", "COM ", "xtra"]);
+
+        audio.set_metadata(r#"{"title": "New Title"}"#.to_string()).unwrap();
+        let mut ids = audio.get_all_frame_ids().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["COM ", "TIT2", "xtra"]);
+        let tag = audio.read_id3v2_tag().unwrap().unwrap();
+        let comment = tag.frames.iter().find(|frame| frame.frame_id == "COM ").unwrap();
+        assert_eq!(comment.data, b"\x00engdesc\x00TIT2 looks like a frame");
+        assert_eq!(audio.read_metadata().unwrap().title.as_deref(), Some("New Title"));
+        std::fs::remove_file(&path).unwrap();
+    }
 }
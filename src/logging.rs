@@ -0,0 +1,210 @@
+// Structured logs of what the library does
+//
+// Reads and writes report through `tracing`. `JsonLayer` renders each event
+// as one JSON object per line, with a fixed set of keys so pipelines can
+// rely on them:
+//
+//   timestamp  RFC 3339, UTC, milliseconds
+//   level      "ERROR", "WARN", "INFO", "DEBUG" or "TRACE"
+//   event      one of the names below
+//   spans      names of the enclosing spans, outermost first
+//
+// followed by the event's own fields:
+//
+//   file_opened       DEBUG  path, format
+//   metadata_written  INFO   path, format, fields_changed (comma-separated), bytes_written, duration_ms
+//   warning           WARN   path, message
+//   error             ERROR  path, message, duration_ms (for failed writes)
+//   span_closed       DEBUG  span, duration_ms
+//
+// `span_closed` is emitted by the layer itself when a "parse" or "write"
+// span ends, so it only appears when debug events are enabled.
+
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+/// Where `JsonLayer` sends each finished line
+type Sink = Box<dyn Fn(Level, &str) + Send + Sync>;
+
+/// A `tracing_subscriber` layer writing events as JSON lines
+pub struct JsonLayer {
+    level: Level,
+    sink: Sink,
+}
+
+impl JsonLayer {
+    /// Write events at `level` or more severe to `writer`, one line each
+    pub fn new<W: Write + Send + 'static>(writer: W, level: Level) -> Self {
+        let writer = Mutex::new(writer);
+        Self::with_sink(level, move |_, line| {
+            // Logging must never fail the operation being logged
+            if let Ok(mut writer) = writer.lock() {
+                let _ = writeln!(writer, "{}", line);
+                let _ = writer.flush();
+            }
+        })
+    }
+
+    /// Hand each line, without its newline, to `sink` along with its level
+    pub fn with_sink(level: Level, sink: impl Fn(Level, &str) + Send + Sync + 'static) -> Self {
+        JsonLayer { level, sink: Box::new(sink) }
+    }
+
+    fn emit(&self, level: Level, event: &str, spans: Vec<String>, fields: Map<String, Value>) {
+        let mut record = Map::new();
+        record.insert(
+            "timestamp".to_string(),
+            Value::from(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+        );
+        record.insert("level".to_string(), Value::from(level.as_str()));
+        record.insert("event".to_string(), Value::from(event));
+        record.insert("spans".to_string(), Value::from(spans));
+        for (key, value) in fields {
+            record.entry(key).or_insert(value);
+        }
+        (self.sink)(level, &Value::Object(record).to_string());
+    }
+}
+
+/// Names of `span` and its parents, outermost first
+fn span_names<S: for<'a> LookupSpan<'a>>(span: Option<SpanRef<'_, S>>) -> Vec<String> {
+    span.map(|span| span.scope().from_root().map(|span| span.name().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// When a span was created, kept in its extensions
+struct Opened(Instant);
+
+/// Event fields as JSON values
+#[derive(Default)]
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+}
+
+impl<S> Layer<S> for JsonLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, metadata: &tracing::Metadata<'_>, _: Context<'_, S>) -> bool {
+        *metadata.level() <= self.level
+    }
+
+    fn on_new_span(&self, _: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Opened(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let mut fields = Map::new();
+        fields.insert("span".to_string(), Value::from(span.name()));
+        if let Some(Opened(opened)) = span.extensions().get::<Opened>() {
+            fields.insert("duration_ms".to_string(), Value::from(opened.elapsed().as_millis() as u64));
+        }
+        self.emit(*span.metadata().level(), "span_closed", span_names(span.parent()), fields);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut fields = fields.0;
+        let name = match fields.remove("event").or_else(|| fields.remove("message")) {
+            Some(Value::String(name)) => name,
+            _ => event.metadata().name().to_string(),
+        };
+        self.emit(*event.metadata().level(), &name, span_names(ctx.event_span(event)), fields);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use crate::{apply, Metadata};
+
+    #[test]
+    fn test_json_log_records_batch_write() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let sink = lines.clone();
+        let layer = JsonLayer::with_sink(tracing::Level::DEBUG, move |_, line| {
+            sink.lock().unwrap().push(line.to_string());
+        });
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        let flac = build_flac_file("ref", &[], 64);
+        let mut paths: Vec<String> = (0..5).map(|i| write_temp_file(&format!("log-{}.flac", i), &flac)).collect();
+        paths.push("/nonexistent/log.flac".to_string());
+        let items = paths.iter()
+            .map(|path| (path.clone(), Metadata { title: Some("Logged".to_string()), ..Default::default() }))
+            .collect();
+        tracing::subscriber::with_default(subscriber, || apply(items, 3));
+
+        let records: Vec<serde_json::Value> = lines.lock().unwrap().iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        for record in &records {
+            assert!(record["timestamp"].is_string());
+            assert!(["ERROR", "WARN", "INFO", "DEBUG"].contains(&record["level"].as_str().unwrap()));
+            assert!(record["event"].is_string());
+            assert!(record["spans"].is_array());
+        }
+        let events = |name: &str| records.iter().filter(|record| record["event"] == name).collect::<Vec<_>>();
+
+        let written = events("metadata_written");
+        assert_eq!(written.len(), 5);
+        for record in &written {
+            assert_eq!(record["level"], "INFO");
+            assert_eq!(record["fields_changed"], "title");
+            assert_eq!(record["bytes_written"], std::fs::metadata(record["path"].as_str().unwrap()).unwrap().len());
+            assert!(record["duration_ms"].is_u64());
+            assert_eq!(record["spans"], serde_json::json!(["write"]));
+        }
+        assert_eq!(events("file_opened").len(), 5);
+        let errors = events("error");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["path"], "/nonexistent/log.flac");
+        assert!(errors[0]["message"].is_string());
+        let parses = events("span_closed").into_iter().filter(|record| record["span"] == "parse").count();
+        assert_eq!(parses, 5);
+
+        for path in &paths[..5] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
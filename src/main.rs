@@ -2,7 +2,8 @@
 //
 // This is the main entry point for the oxidant command-line tool.
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::io::Write;
 use std::process;
 
 /// Oxidant - Audio metadata CLI tool
@@ -13,34 +14,357 @@ use std::process;
 #[command(author = "xwsjjctz <xwsjjctz@icloud.com>")]
 struct Config {
     /// Output format
-    #[arg(short, long, value_enum, default_value = "pretty")]
+    #[arg(short, long, value_enum, default_value = "pretty", global = true)]
     format: OutputFormat,
 
     /// Quiet mode (suppress progress messages)
     #[arg(short, long)]
     quiet: bool,
 
+    /// Read and write files as this format instead of detecting it
+    /// (id3v2, id3v1, flac, ogg, opus, mp4, ape)
+    #[arg(long, global = true)]
+    force_format: Option<oxidant::Format>,
+
+    /// Log each operation as structured records (stdout is unaffected)
+    #[arg(long, value_enum, global = true)]
+    log_format: Option<LogFormat>,
+
+    /// Append log records to this file instead of stderr
+    #[arg(long, global = true, requires = "log_format")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Also log debug events, such as the time spent in each parser stage
+    #[arg(long, global = true)]
+    verbose: bool,
+
     /// Subcommand
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Config {
+    /// Install the logger chosen with `--log-format`, if any
+    fn init_logging(&self) -> std::io::Result<()> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let Some(LogFormat::Json) = self.log_format else {
+            return Ok(());
+        };
+        let level = if self.verbose { tracing::Level::DEBUG } else { tracing::Level::INFO };
+        let layer = match &self.log_file {
+            Some(path) => oxidant::logging::JsonLayer::new(
+                std::fs::OpenOptions::new().create(true).append(true).open(path)?,
+                level,
+            ),
+            None => oxidant::logging::JsonLayer::new(std::io::stderr(), level),
+        };
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+            .map_err(std::io::Error::other)
+    }
+
+    /// Open an audio file, honouring `--force-format`
+    fn open(&self, path: &str) -> oxidant::AudioResult<oxidant::AudioFile> {
+        match self.force_format {
+            Some(format) => oxidant::AudioFile::with_format(path.to_string(), format),
+            None => oxidant::AudioFile::new(path.to_string()),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Read metadata from audio file(s)
     Read {
-        /// Audio file path(s)
+        /// Audio file or directory path(s)
         files: Vec<String>,
 
+        #[command(flatten)]
+        walk: WalkArgs,
+
         /// Output to file instead of stdout
         #[arg(short, long)]
         output: Option<String>,
+
+        /// Include embedded cover art (base64) in the output
+        #[arg(long)]
+        with_cover: bool,
+
+        /// Include audio properties (duration, sample rate, codec, ...) in the output
+        #[arg(long)]
+        properties: bool,
+
+        /// Skip ICY metadata blocks in internet-radio rips and report their last StreamTitle
+        #[arg(long)]
+        icy: bool,
+
+        /// Show the tag and frame, comment or atom each field was read from
+        #[arg(long)]
+        sources: bool,
     },
     /// Detect file format
     Detect {
+        /// Audio file or directory path(s)
+        files: Vec<String>,
+
+        #[command(flatten)]
+        walk: WalkArgs,
+    },
+    /// Write the same metadata to audio file(s)
+    ///
+    /// Accepts the same template variables as `batch write`.
+    Write {
+        /// Audio file or directory path(s)
+        files: Vec<String>,
+
+        /// Metadata JSON template
+        #[arg(short, long)]
+        metadata: String,
+
+        #[command(flatten)]
+        walk: WalkArgs,
+
+        /// Write without confirmation when more than 10 files match
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Write byte-identical output for identical input
+        #[arg(long)]
+        deterministic: bool,
+
+        /// Record the edit in each file's edit history (ID3v2 and FLAC)
+        #[arg(long, conflicts_with = "deterministic")]
+        history: bool,
+
+        /// Clear the read-only attribute of files before writing them
+        #[arg(long)]
+        force: bool,
+
+        /// Set the iTunes media kind of MP4 files (`itunes.media_kind`)
+        #[arg(long, value_enum)]
+        media_kind: Option<MediaKind>,
+    },
+    /// Copy the tags of one file into other file(s), converting values for each target's format
+    ///
+    /// Fields the source lacks are left as they are in the targets.
+    Copy {
+        /// File to copy the tags from
+        source: String,
+
+        /// Audio file path(s) to copy the tags into
+        #[arg(required = true)]
+        targets: Vec<String>,
+    },
+    /// Check file(s) for tag problems
+    Validate {
         /// Audio file path(s)
         files: Vec<String>,
     },
+    /// Fix tag problems reported by `validate`
+    Repair {
+        /// Audio file path(s)
+        files: Vec<String>,
+
+        /// Rewrite the losing tag when ID3v1 and ID3v2 disagree
+        #[arg(long, value_enum)]
+        resolve: Option<Resolve>,
+
+        /// Remove ICY metadata blocks left in the audio of internet-radio rips
+        #[arg(long)]
+        icy: bool,
+    },
+    /// Remove tag overhead such as excess padding and duplicate frames
+    Optimize {
+        /// Audio file path(s)
+        files: Vec<String>,
+    },
+    /// Process all files in a directory
+    Batch {
+        /// Directory to process
+        #[arg(short, long)]
+        dir: String,
+
+        /// Glob pattern for files inside the directory
+        #[arg(short, long, default_value = "*")]
+        pattern: String,
+
+        #[command(subcommand)]
+        action: BatchAction,
+    },
+    /// Back up the tags of every audio file under a directory as JSON lines
+    ExportJson {
+        /// Directory to scan recursively
+        dir: String,
+
+        /// Backup file to write
+        #[arg(short, long)]
+        output: String,
+
+        /// Store covers as a SHA-256 reference or inline as base64
+        #[arg(long, value_enum, default_value = "sha")]
+        include_covers: IncludeCovers,
+    },
+    /// Re-apply tags from a backup written by `export-json`
+    ImportJson {
+        /// Backup file
+        backup: String,
+
+        /// Only fill fields the files don't already have
+        #[arg(long)]
+        only_missing: bool,
+
+        /// Find each file by its recorded path or by its audio checksum
+        #[arg(long, value_enum, default_value = "path")]
+        match_by: MatchBy,
+
+        /// Directory searched for files when matching by checksum
+        #[arg(long, default_value = ".")]
+        dir: String,
+    },
+    /// Show how many bytes each file spends on metadata vs audio
+    ///
+    /// Files whose embedded artwork makes up most of their metadata are marked.
+    Overhead {
+        /// Audio file or directory path(s)
+        files: Vec<String>,
+
+        #[command(flatten)]
+        walk: WalkArgs,
+    },
+    /// Show file information
+    Info {
+        /// Audio file or directory path(s)
+        files: Vec<String>,
+
+        #[command(flatten)]
+        walk: WalkArgs,
+
+        /// Show size and audio stream position
+        #[arg(short, long)]
+        detailed: bool,
+    },
+}
+
+/// Directory expansion options shared by the file-list commands
+#[derive(Args, Debug)]
+struct WalkArgs {
+    /// Walk directories and their subdirectories
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Only files with these extensions when walking, e.g. flac,mp3 (default: all audio files)
+    #[arg(long, value_delimiter = ',')]
+    ext: Vec<String>,
+
+    /// Follow symlinks when walking
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Include hidden files and directories when walking
+    #[arg(long)]
+    hidden: bool,
+}
+
+impl WalkArgs {
+    /// The given paths with directories expanded, sorted
+    fn expand(&self, files: &[String]) -> Vec<String> {
+        let options = oxidant::walk::WalkOptions {
+            recursive: self.recursive,
+            extensions: self.ext.clone(),
+            follow_symlinks: self.follow_symlinks,
+            hidden: self.hidden,
+        };
+        match oxidant::walk::expand(files, &options) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Files `write` changes without `--yes`
+const WRITE_CONFIRM_LIMIT: usize = 10;
+
+#[derive(Subcommand, Debug)]
+enum BatchAction {
+    /// Write metadata to every file
+    ///
+    /// String values may use {filename}, {parent_dir}, {auto_track} (e.g.
+    /// {auto_track:02}) and {existing.<field>} referencing current tags.
+    Write {
+        /// Metadata JSON template
+        #[arg(short, long)]
+        metadata: String,
+
+        /// Number files for {auto_track}
+        #[arg(long, value_enum)]
+        auto_track_from: Option<AutoTrackOrder>,
+
+        /// Write byte-identical output for identical input
+        #[arg(long)]
+        deterministic: bool,
+
+        /// Record the edit in each file's edit history (ID3v2 and FLAC)
+        #[arg(long, conflicts_with = "deterministic")]
+        history: bool,
+
+        /// Clear the read-only attribute of files before writing them
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum IncludeCovers {
+    /// SHA-256 and size only; import keeps the current cover
+    Sha,
+    /// The image as base64
+    Inline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MatchBy {
+    /// The path recorded in the backup
+    Path,
+    /// The audio checksum, which survives renames and tag edits
+    Checksum,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+enum AutoTrackOrder {
+    /// Sorted by file name
+    FilenameOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Resolve {
+    /// Keep the ID3v2 values and rewrite ID3v1
+    V2Wins,
+    /// Keep the ID3v1 values and rewrite ID3v2
+    V1Wins,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MediaKind {
+    Audiobook,
+    Music,
+    Tvshow,
+}
+
+impl MediaKind {
+    /// The `stik` value written for this kind
+    fn code(self) -> u8 {
+        let name = self.to_possible_value().expect("no variant is skipped");
+        oxidant::ItunesFields::media_kind_code(name.get_name()).expect("every kind has a code")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// One JSON object per line
+    Json,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, ValueEnum)]
@@ -48,34 +372,108 @@ enum OutputFormat {
     #[default]
     Pretty,
     Json,
+    /// Requires the `toml-output` feature
+    Toml,
+    /// One row per file under a single header row
+    Csv,
 }
 
+/// Columns of CSV output, in order
+const CSV_COLUMNS: &[&str] = &["path", "title", "artist", "album", "year", "track", "genre", "comment"];
+
 fn main() {
     let config = Config::parse();
+    if let Err(e) = config.init_logging() {
+        eprintln!("Error: cannot set up logging: {}", e);
+        process::exit(1);
+    }
 
     match &config.command {
-        Commands::Read { files, output } => {
-            command_read(files.clone(), output.clone(), &config);
+        Commands::Read { files, walk, output, with_cover, properties, icy, sources } => {
+            let options = oxidant::ReadOptions {
+                include_cover: *with_cover,
+                include_properties: *properties,
+                icy_aware: *icy,
+                with_sources: *sources,
+            };
+            command_read(walk.expand(files), output.clone(), &options, &config);
+        }
+        Commands::Detect { files, walk } => {
+            command_detect(walk.expand(files), &config);
+        }
+        Commands::Write { files, metadata, walk, yes, deterministic, history, force, media_kind } => {
+            let files = walk.expand(files);
+            if files.len() > WRITE_CONFIRM_LIMIT && !yes {
+                eprintln!(
+                    "Error: {} files match; pass --yes to write more than {} files",
+                    files.len(), WRITE_CONFIRM_LIMIT
+                );
+                process::exit(1);
+            }
+            let metadata = match media_kind {
+                Some(kind) => with_media_kind(metadata, *kind),
+                None => metadata.clone(),
+            };
+            write_files(&files, &metadata, false, *deterministic, *history, *force, &config);
+        }
+        Commands::Copy { source, targets } => {
+            command_copy(source, targets, &config);
+        }
+        Commands::Validate { files } => {
+            command_validate(files.clone(), &config);
+        }
+        Commands::Repair { files, resolve, icy } => {
+            command_repair(files.clone(), *resolve, *icy, &config);
+        }
+        Commands::Optimize { files } => {
+            command_optimize(files.clone(), &config);
+        }
+        Commands::Batch { dir, pattern, action } => {
+            command_batch(dir, pattern, action, &config);
         }
-        Commands::Detect { files } => {
-            command_detect(files.clone(), &config);
+        Commands::ExportJson { dir, output, include_covers } => {
+            command_export_json(dir, output, *include_covers, &config);
+        }
+        Commands::ImportJson { backup, only_missing, match_by, dir } => {
+            command_import_json(backup, *only_missing, *match_by, dir, &config);
+        }
+        Commands::Info { files, walk, detailed } => {
+            command_info(walk.expand(files), *detailed, &config);
+        }
+        Commands::Overhead { files, walk } => {
+            command_overhead(walk.expand(files), &config);
         }
     }
 }
 
-fn command_read(files: Vec<String>, _output: Option<String>, config: &Config) {
+fn command_read(files: Vec<String>, _output: Option<String>, options: &oxidant::ReadOptions, config: &Config) {
     if files.is_empty() {
         eprintln!("Error: No files specified");
         process::exit(1);
     }
 
+    if let Err(e) = read_files(&files, options, config, &mut std::io::stdout().lock()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Write the metadata of each file to `writer`; CSV output has one header row before the first file
+fn read_files(
+    files: &[String],
+    options: &oxidant::ReadOptions,
+    config: &Config,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut csv_header = true;
     for file_path in files {
-        match oxidant::AudioFile::new(file_path.clone()) {
+        match config.open(file_path) {
             Ok(audio) => {
-                match audio.get_metadata() {
+                match format_metadata(&audio, options, &config.format, csv_header) {
                     Ok(metadata) => {
                         if !config.quiet {
-                            println!("{}", metadata);
+                            writeln!(writer, "{}", metadata)?;
+                            csv_header = false;
                         }
                     }
                     Err(e) => {
@@ -88,6 +486,88 @@ fn command_read(files: Vec<String>, _output: Option<String>, config: &Config) {
             }
         }
     }
+    Ok(())
+}
+
+fn format_metadata(
+    audio: &oxidant::AudioFile,
+    options: &oxidant::ReadOptions,
+    format: &OutputFormat,
+    csv_header: bool,
+) -> Result<String, String> {
+    match format {
+        OutputFormat::Pretty if options.with_sources => format_sources(audio, options),
+        OutputFormat::Pretty | OutputFormat::Json => {
+            audio.get_metadata_with_options(options).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "toml-output")]
+        OutputFormat::Toml => {
+            let metadata = audio.read_metadata_with_options(options).map_err(|e| e.to_string())?;
+            toml::to_string(&metadata).map_err(|e| e.to_string())
+        }
+        #[cfg(not(feature = "toml-output"))]
+        OutputFormat::Toml => {
+            Err("TOML output requires building with the `toml-output` feature".to_string())
+        }
+        OutputFormat::Csv => {
+            let metadata = audio.read_metadata_with_options(options).map_err(|e| e.to_string())?;
+            let mut value = serde_json::to_value(&metadata).map_err(|e| e.to_string())?;
+            value["path"] = serde_json::Value::String(audio.path.clone());
+            let mut rows = Vec::new();
+            output_metadata_csv(&value, &mut rows, csv_header).map_err(|e| e.to_string())?;
+            Ok(String::from_utf8_lossy(&rows).trim_end_matches('\n').to_string())
+        }
+    }
+}
+
+/// Write `metadata` as one CSV row of `CSV_COLUMNS`, after a header row if `include_header`
+///
+/// Missing and null fields are left empty; values holding a comma, quote or
+/// line break are quoted per RFC 4180.
+fn output_metadata_csv(metadata: &serde_json::Value, writer: &mut impl Write, include_header: bool) -> std::io::Result<()> {
+    if include_header {
+        writeln!(writer, "{}", CSV_COLUMNS.join(","))?;
+    }
+    let row: Vec<String> = CSV_COLUMNS
+        .iter()
+        .map(|&column| {
+            let text = match metadata.get(column) {
+                None | Some(serde_json::Value::Null) => return String::new(),
+                Some(serde_json::Value::String(text)) => text.clone(),
+                Some(field) => field.to_string(),
+            };
+            if text.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            } else {
+                text
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", row.join(","))
+}
+
+/// One "field: value   [source]" line per populated field, in JSON key order
+fn format_sources(audio: &oxidant::AudioFile, options: &oxidant::ReadOptions) -> Result<String, String> {
+    let (metadata, sources) = audio.read_metadata_with_sources(options).map_err(|e| e.to_string())?;
+    let value = serde_json::to_value(&metadata).map_err(|e| e.to_string())?;
+    let mut lines = Vec::new();
+    for &key in oxidant::METADATA_JSON_KEY_ORDER {
+        let Some(field) = value.get(key) else {
+            continue;
+        };
+        let text = match (&metadata.cover, field) {
+            (Some(cover), _) if key == "cover" => format!(
+                "{} ({} bytes)",
+                cover.mime_type.as_deref().unwrap_or("unknown type"),
+                cover.data.len()
+            ),
+            (_, serde_json::Value::String(text)) => text.clone(),
+            _ => field.to_string(),
+        };
+        let source = sources.get(key).map_or(String::new(), |source| source.replacen('/', " ", 1));
+        lines.push(format!("{}: {}   [{}]", key, text, source));
+    }
+    Ok(lines.join("\n"))
 }
 
 fn command_detect(files: Vec<String>, config: &Config) {
@@ -97,10 +577,10 @@ fn command_detect(files: Vec<String>, config: &Config) {
     }
 
     for file_path in files {
-        match oxidant::AudioFile::new(file_path.clone()) {
+        match config.open(&file_path) {
             Ok(audio) => {
                 if !config.quiet {
-                    println!("  {}: {} (version: {})", file_path, audio.file_type,
+                    println!("  {}: {} (version: {})", file_path, audio.format,
                         audio.get_version().unwrap_or_else(|_| "N/A".to_string()));
                 }
             }
@@ -110,3 +590,574 @@ fn command_detect(files: Vec<String>, config: &Config) {
         }
     }
 }
+
+fn command_validate(files: Vec<String>, config: &Config) {
+    if files.is_empty() {
+        eprintln!("Error: No files specified");
+        process::exit(1);
+    }
+
+    let mut found_issues = false;
+
+    for file_path in files {
+        match config.open(&file_path) {
+            Ok(audio) => match audio.validate() {
+                Ok(issues) => {
+                    found_issues |= !issues.is_empty();
+                    if config.quiet {
+                        continue;
+                    }
+                    if config.format == OutputFormat::Json {
+                        let report = serde_json::json!({ "file": file_path, "issues": issues });
+                        println!("{}", report);
+                    } else if issues.is_empty() {
+                        println!("✓ {}", file_path);
+                    } else {
+                        println!("✗ {}", file_path);
+                        for issue in issues {
+                            println!("    {}", issue);
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("✗ {}: {}", file_path, e);
+                }
+            },
+            Err(e) => {
+                eprintln!("✗ {}: {}", file_path, e);
+            }
+        }
+    }
+
+    if found_issues {
+        process::exit(2);
+    }
+}
+
+fn command_repair(files: Vec<String>, resolve: Option<Resolve>, icy: bool, config: &Config) {
+    if files.is_empty() {
+        eprintln!("Error: No files specified");
+        process::exit(1);
+    }
+
+    let resolution = resolve.map(|resolve| match resolve {
+        Resolve::V2Wins => oxidant::TagResolution::V2Wins,
+        Resolve::V1Wins => oxidant::TagResolution::V1Wins,
+    });
+    if resolution.is_none() && !icy {
+        eprintln!("Error: Nothing to repair; pass --resolve v2-wins, --resolve v1-wins or --icy");
+        process::exit(1);
+    }
+
+    let mut failed = false;
+
+    for file_path in files {
+        let result = config.open(&file_path).and_then(|audio| {
+            let mut repairs = Vec::new();
+            if let Some(resolution) = resolution {
+                let mismatches = audio.resolve_id3_tags(resolution)?;
+                if !mismatches.is_empty() {
+                    let fields: Vec<&str> = mismatches.iter().map(|m| m.field.as_str()).collect();
+                    repairs.push(format!("resolved {}", fields.join(", ")));
+                }
+            }
+            if icy {
+                let blocks = audio.strip_icy_metadata()?;
+                if blocks > 0 {
+                    repairs.push(format!("removed {} ICY metadata blocks", blocks));
+                }
+            }
+            Ok(repairs)
+        });
+        match result {
+            Ok(repairs) => {
+                if config.quiet {
+                    continue;
+                }
+                if repairs.is_empty() {
+                    println!("✓ {}: nothing to repair", file_path);
+                } else {
+                    println!("✓ {}: {}", file_path, repairs.join("; "));
+                }
+            }
+            Err(e) => {
+                failed = true;
+                eprintln!("✗ {}: {}", file_path, e);
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn command_copy(source: &str, targets: &[String], config: &Config) {
+    let source = match config.open(source) {
+        Ok(audio) => audio,
+        Err(e) => {
+            eprintln!("✗ {}: {}", source, e);
+            process::exit(1);
+        }
+    };
+
+    let mut failed = false;
+
+    for target in targets {
+        let result = config.open(target)
+            .and_then(|audio| audio.copy_metadata_from(&source));
+        match result {
+            Ok(()) => {
+                if !config.quiet {
+                    println!("✓ {}", target);
+                }
+            }
+            Err(e) => {
+                failed = true;
+                eprintln!("✗ {}: {}", target, e);
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn command_optimize(files: Vec<String>, config: &Config) {
+    if files.is_empty() {
+        eprintln!("Error: No files specified");
+        process::exit(1);
+    }
+
+    let mut failed = false;
+
+    for file_path in files {
+        let result = config.open(&file_path)
+            .and_then(|audio| audio.optimize());
+        match result {
+            Ok(report) => {
+                if config.quiet {
+                    continue;
+                }
+                if config.format == OutputFormat::Json {
+                    let report = serde_json::json!({ "file": file_path, "report": report });
+                    println!("{}", report);
+                } else if report.changes.is_empty() {
+                    println!("✓ {}: already optimal", file_path);
+                } else {
+                    println!("✓ {}: saved {} bytes", file_path, report.bytes_saved);
+                    for change in report.changes {
+                        println!("    {}", change);
+                    }
+                }
+            }
+            Err(e) => {
+                failed = true;
+                eprintln!("✗ {}: {}", file_path, e);
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn command_batch(dir: &str, pattern: &str, action: &BatchAction, config: &Config) {
+    let full_pattern = std::path::Path::new(dir).join(pattern);
+    let mut files: Vec<String> = match glob::glob(&full_pattern.to_string_lossy()) {
+        Ok(paths) => paths
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect(),
+        Err(e) => {
+            eprintln!("Error: Invalid pattern: {}", e);
+            process::exit(1);
+        }
+    };
+    files.sort();
+
+    if files.is_empty() {
+        eprintln!("Error: No files matching {}", full_pattern.display());
+        process::exit(1);
+    }
+
+    match action {
+        BatchAction::Write { metadata, auto_track_from, deterministic, history, force } => {
+            write_files(&files, metadata, auto_track_from.is_some(), *deterministic, *history, *force, config);
+        }
+    }
+}
+
+/// Add `--media-kind` to the metadata JSON, overriding any `itunes.media_kind` in it
+///
+/// Invalid JSON is returned unchanged for `write_files` to report.
+fn with_media_kind(metadata: &str, kind: MediaKind) -> String {
+    let Ok(serde_json::Value::Object(mut object)) = serde_json::from_str::<serde_json::Value>(metadata) else {
+        return metadata.to_string();
+    };
+    let itunes = object.entry("itunes").or_insert_with(|| serde_json::json!({}));
+    if !itunes.is_object() {
+        *itunes = serde_json::json!({});
+    }
+    itunes["media_kind"] = serde_json::Value::from(kind.code());
+    serde_json::Value::Object(object).to_string()
+}
+
+/// Write the metadata JSON template to every file, exiting with 1 if any write fails
+fn write_files(
+    files: &[String],
+    metadata: &str,
+    auto_track: bool,
+    deterministic: bool,
+    history: bool,
+    force: bool,
+    config: &Config,
+) {
+    let template: serde_json::Value = match serde_json::from_str(metadata) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error: Invalid metadata JSON: {}", e);
+            process::exit(1);
+        }
+    };
+    if files.is_empty() {
+        eprintln!("Error: No files specified");
+        process::exit(1);
+    }
+
+    // Resolve every file first so a bad template never leaves a half-tagged batch
+    let rendered = match oxidant::template::render_for_files(files, &template, auto_track) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let options = oxidant::WriteOptions {
+        deterministic,
+        history,
+        ..Default::default()
+    };
+    let mut failed = 0;
+    for (file_path, metadata) in files.iter().zip(rendered) {
+        let opened = config.open(file_path).and_then(|audio| {
+            if force {
+                clear_read_only(file_path)?;
+            }
+            Ok(audio)
+        });
+        let result = match opened {
+            // Writes log their own outcome
+            Ok(audio) => audio.set_metadata_with_options(metadata.to_string(), &options),
+            Err(e) => {
+                tracing::error!(event = "error", path = %file_path, message = %e);
+                Err(e)
+            }
+        };
+        match result {
+            Ok(report) => {
+                if !config.quiet {
+                    println!("✓ {}", file_path);
+                }
+                for skipped in &report.skipped {
+                    eprintln!("  warning: {}", skipped);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("✗ {}: {}", file_path, e);
+                if matches!(e, oxidant::AudioFileError::ReadOnlyFile(_)) {
+                    eprintln!("  hint: use --force to clear the read-only attribute");
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// Make a read-only file writable by its owner
+fn clear_read_only(path: &str) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    if !permissions.readonly() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+}
+
+/// Every file under `dir` that oxidant can open, sorted by path
+fn audio_files_under(dir: &str) -> Vec<String> {
+    if !std::path::Path::new(dir).is_dir() {
+        eprintln!("Error: {} is not a directory", dir);
+        process::exit(1);
+    }
+    let options = oxidant::walk::WalkOptions { recursive: true, ..Default::default() };
+    match oxidant::walk::expand(&[dir.to_string()], &options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error: Invalid directory {}: {}", dir, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn command_export_json(dir: &str, output: &str, include_covers: IncludeCovers, config: &Config) {
+    let covers = match include_covers {
+        IncludeCovers::Sha => oxidant::backup::CoverMode::Hash,
+        IncludeCovers::Inline => oxidant::backup::CoverMode::Inline,
+    };
+
+    let mut lines = Vec::new();
+    let mut failed = 0;
+    for file_path in audio_files_under(dir) {
+        let line = oxidant::backup::BackupEntry::from_file(&file_path, covers)
+            .and_then(|entry| entry.to_line());
+        match line {
+            Ok(line) => lines.push(line),
+            Err(e) => {
+                failed += 1;
+                eprintln!("✗ {}: {}", file_path, e);
+            }
+        }
+    }
+
+    let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    if let Err(e) = std::fs::write(output, contents) {
+        eprintln!("Error: Cannot write {}: {}", output, e);
+        process::exit(1);
+    }
+    if !config.quiet {
+        println!("✓ Exported {} file(s) to {}", lines.len(), output);
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn command_import_json(backup: &str, only_missing: bool, match_by: MatchBy, dir: &str, config: &Config) {
+    let contents = match std::fs::read_to_string(backup) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Cannot read {}: {}", backup, e);
+            process::exit(1);
+        }
+    };
+
+    // Checksum of every candidate file, computed once up front
+    let by_checksum: std::collections::HashMap<String, String> = match match_by {
+        MatchBy::Path => Default::default(),
+        MatchBy::Checksum => audio_files_under(dir).into_iter()
+            .filter_map(|path| {
+                let checksum = oxidant::AudioFile::new(path.clone()).ok()?.get_audio_checksum().ok()?;
+                Some((oxidant::backup::format_checksum(checksum), path))
+            })
+            .collect(),
+    };
+
+    let mut failed = 0;
+    for (number, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let entry = match oxidant::backup::BackupEntry::parse(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                failed += 1;
+                eprintln!("✗ line {}: {}", number + 1, e);
+                continue;
+            }
+        };
+        let target = match match_by {
+            MatchBy::Path => Some(entry.path.clone()),
+            MatchBy::Checksum => by_checksum.get(&entry.audio_checksum).cloned(),
+        };
+        let Some(target) = target else {
+            failed += 1;
+            eprintln!("✗ {}: no file with audio checksum {}", entry.path, entry.audio_checksum);
+            continue;
+        };
+
+        let result = config.open(&target)
+            .and_then(|audio| entry.restore(&audio, only_missing));
+        match result {
+            Ok(()) => {
+                if !config.quiet {
+                    println!("✓ {}", target);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("✗ {}: {}", target, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn command_overhead(files: Vec<String>, config: &Config) {
+    if files.is_empty() {
+        eprintln!("Error: No files specified");
+        process::exit(1);
+    }
+
+    let mut failed = false;
+    let (mut total_metadata, mut total_audio, mut total_artwork) = (0u64, 0u64, 0u64);
+
+    for file_path in &files {
+        let result = config.open(file_path).and_then(|audio| {
+            let (metadata, audio_bytes) = audio.tag_overhead()?;
+            let artwork: u64 = audio.read_covers()?.iter().map(|cover| cover.data.len() as u64).sum();
+            Ok((metadata, audio_bytes, artwork))
+        });
+        let (metadata, audio, artwork) = match result {
+            Ok(sizes) => sizes,
+            Err(e) => {
+                failed = true;
+                eprintln!("✗ {}: {}", file_path, e);
+                continue;
+            }
+        };
+        total_metadata += metadata;
+        total_audio += audio;
+        total_artwork += artwork;
+        // Artwork dominates when it is more than half of the metadata
+        let artwork_heavy = artwork * 2 > metadata;
+
+        if config.quiet {
+            continue;
+        }
+        if config.format == OutputFormat::Json {
+            let report = serde_json::json!({
+                "file": file_path,
+                "metadata_bytes": metadata,
+                "audio_bytes": audio,
+                "artwork_bytes": artwork,
+                "artwork_heavy": artwork_heavy,
+            });
+            println!("{}", report);
+        } else {
+            println!(
+                "{:>10} metadata {:>12} audio {:>6.2}%  {}{}",
+                metadata, audio, percent(metadata, metadata + audio), file_path,
+                if artwork_heavy { format!("  [artwork {} bytes]", artwork) } else { String::new() },
+            );
+        }
+    }
+
+    if !config.quiet && config.format != OutputFormat::Json && files.len() > 1 {
+        println!(
+            "{:>10} metadata {:>12} audio {:>6.2}%  total ({} files, artwork {} bytes)",
+            total_metadata, total_audio, percent(total_metadata, total_metadata + total_audio), files.len(), total_artwork,
+        );
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `part` as a percentage of `whole`, 0 for an empty whole
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+fn command_info(files: Vec<String>, detailed: bool, config: &Config) {
+    if files.is_empty() {
+        eprintln!("Error: No files specified");
+        process::exit(1);
+    }
+
+    for file_path in files {
+        match config.open(&file_path) {
+            Ok(audio) => {
+                if config.quiet {
+                    continue;
+                }
+
+                println!("{}:", file_path);
+                println!("  Format:  {}", audio.format);
+                println!("  Version: {}", audio.get_version().unwrap_or_else(|_| "N/A".to_string()));
+                if let Ok(properties) = audio.get_properties() {
+                    println!("  Stream:  {}", properties.summary());
+                }
+
+                if detailed {
+                    if let Ok(meta) = std::fs::metadata(&file_path) {
+                        println!("  Size:    {} bytes", meta.len());
+                    }
+                    match audio.get_audio_span() {
+                        Ok((offset, length)) => {
+                            println!("  Audio:   offset {}, length {} bytes", offset, length);
+                        }
+                        Err(e) => {
+                            println!("  Audio:   unavailable ({})", e);
+                        }
+                    }
+                    if let Ok(properties) = audio.get_properties() {
+                        let seconds = properties.duration_ms / 1000;
+                        println!("  Length:  {}:{:02}.{:03}", seconds / 60, seconds % 60, properties.duration_ms % 1000);
+                    }
+                    if let Ok(blocks) = audio.get_all_metadata_blocks() {
+                        println!("  Blocks:");
+                        for block in blocks {
+                            println!("    {:<16} {:>8} bytes at {}", block.block_type, block.size_bytes, block.offset);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("✗ {}: {}", file_path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_csv_has_one_header_and_quotes_fields() {
+        let dir = std::env::temp_dir().join(format!("oxidant-csv-{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: Vec<String> = ["a.mp3", "b.mp3"].iter()
+            .map(|name| dir.join(name).to_string_lossy().to_string())
+            .collect();
+        let tags = [
+            r#"{"title": "Hello, \"World\"", "artist": "A", "track": "3/12"}"#,
+            r#"{"title": "Line\nTwo", "year": "1999"}"#,
+        ];
+        for (file, tag) in files.iter().zip(tags) {
+            std::fs::write(file, b"ID3\x03\x00\x00\x00\x00\x00\x00").unwrap();
+            oxidant::AudioFile::new(file.clone()).unwrap().set_metadata(tag.to_string()).unwrap();
+        }
+
+        let config = Config::parse_from(["oxidant", "--format", "csv", "read", &files[0], &files[1]]);
+        let mut output = Vec::new();
+        read_files(&files, &oxidant::ReadOptions::default(), &config, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "path,title,artist,album,year,track,genre,comment\n\
+                 {},\"Hello, \"\"World\"\"\",A,,,3/12,,\n\
+                 {},\"Line\nTwo\",,,1999,,,\n",
+                files[0], files[1]
+            )
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,6 +2,9 @@
 //
 // MP4 format uses "atoms" (boxes) to store metadata.
 // iTunes-style metadata is stored in ilst atom under moov/udta/meta.
+// Some muxers put meta directly under moov, or write QuickTime's meta
+// without the version/flags of the ISO full box; both are read and
+// rewritten in place.
 //
 // MP4 File Structure:
 // - ftyp: File type atom
@@ -20,9 +23,14 @@
 // - ©gen: Genre (genre) - [0xA9, g, e, n]
 // - ©cmt: Comment (comment) - [0xA9, c, m, t]
 // - ©lyr: Lyrics (lyrics) - [0xA9, l, y, r]
-// - covr: Cover art (cover)
+// - covr: Cover art (cover), one data atom per picture
+// - aART: Album artist (album_artist)
+// - ©wrt: Composer (composer) - [0xA9, w, r, t]
+// - ©too: Encoder (encoder) - [0xA9, t, o, o]
+// - ----: Freeform item named by its mean/name children, e.g.
+//   com.apple.iTunes:ORIGINALDATE (original_date)
 
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::fs::File;
 
 pub const MP4_SIGNATURE: &[u8; 4] = b"ftyp";
@@ -32,15 +40,19 @@ pub const MP4_SIGNATURE: &[u8; 4] = b"ftyp";
 pub mod atoms {
     #[allow(dead_code)]
     pub const FTYP: &[u8; 4] = b"ftyp";
-    #[allow(dead_code)]
     pub const MOOV: &[u8; 4] = b"moov";
-    #[allow(dead_code)]
     pub const UDTA: &[u8; 4] = b"udta";
     pub const META: &[u8; 4] = b"meta";
     pub const ILST: &[u8; 4] = b"ilst";
-    #[allow(dead_code)]
     pub const MDAT: &[u8; 4] = b"mdat";
     pub const DATA: &[u8; 4] = b"data";
+    pub const TRAK: &[u8; 4] = b"trak";
+    pub const MDIA: &[u8; 4] = b"mdia";
+    pub const MINF: &[u8; 4] = b"minf";
+    pub const STBL: &[u8; 4] = b"stbl";
+    pub const MVHD: &[u8; 4] = b"mvhd";
+    pub const HDLR: &[u8; 4] = b"hdlr";
+    pub const STSD: &[u8; 4] = b"stsd";
 
     // iTunes metadata keys
     pub const TITLE: &[u8; 4] = &[0xA9, b'n', b'a', b'm']; // ©nam
@@ -52,6 +64,45 @@ pub mod atoms {
     pub const COMMENT: &[u8; 4] = &[0xA9, b'c', b'm', b't']; // ©cmt
     pub const LYRICS: &[u8; 4] = &[0xA9, b'l', b'y', b'r']; // ©lyr
     pub const COVER: &[u8; 4] = b"covr";
+    pub const ALBUM_ARTIST: &[u8; 4] = b"aART";
+    pub const COMPOSER: &[u8; 4] = &[0xA9, b'w', b'r', b't']; // ©wrt
+    pub const ENCODER: &[u8; 4] = &[0xA9, b't', b'o', b'o']; // ©too
+    pub const GENRE_ID: &[u8; 4] = b"gnre";
+    pub const FREEFORM: &[u8; 4] = b"----";
+    pub const MEAN: &[u8; 4] = b"mean";
+    pub const NAME: &[u8; 4] = b"name";
+    pub const MEDIA_KIND: &[u8; 4] = b"stik";
+    pub const GAPLESS: &[u8; 4] = b"pgap";
+    pub const TV_SHOW: &[u8; 4] = b"tvsh";
+    pub const TV_SEASON: &[u8; 4] = b"tvsn";
+    pub const TV_EPISODE: &[u8; 4] = b"tves";
+
+    /// Items rebuilt from `Mp4Metadata` on write
+    pub const MANAGED: &[&[u8; 4]] = &[
+        TITLE, ARTIST, ALBUM, YEAR, TRACK, GENRE, GENRE_ID, COMMENT, LYRICS, COVER,
+        ALBUM_ARTIST, COMPOSER, ENCODER, MEDIA_KIND, GAPLESS, TV_SHOW, TV_SEASON, TV_EPISODE,
+    ];
+}
+
+/// Namespace of the freeform items this module reads and writes
+pub const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// Freeform item names rebuilt from `Mp4Metadata` on write
+pub const MANAGED_FREEFORM: &[&str] = &[
+    "ORIGINALDATE", "ORIGINALARTIST", "ORIGINALALBUM", "ENCODERSETTINGS", "iTunNORM",
+];
+
+/// Type flags of `data` atoms
+pub mod data_types {
+    pub const IMPLICIT: u32 = 0;
+    pub const UTF8: u32 = 1;
+    /// Defined but shown by few players
+    pub const GIF: u32 = 12;
+    pub const JPEG: u32 = 13;
+    pub const PNG: u32 = 14;
+    /// Big-endian signed integer of 1, 2, 4 or 8 bytes
+    pub const INTEGER: u32 = 21;
+    pub const BMP: u32 = 27;
 }
 
 /// MP4 atom header (reserved for future use)
@@ -79,161 +130,717 @@ impl Mp4File {
     pub fn read_metadata(&self) -> std::io::Result<Option<Mp4Metadata>> {
         let file_data = std::fs::read(&self.path)?;
 
-        // Find ilst atom
-        if let Some(ilst_data) = self.find_ilst_atom(&file_data) {
-            Ok(Some(self.parse_ilst(&ilst_data)))
-        } else {
-            Ok(None)
-        }
+        let Some(location) = find_ilst_path(&file_data) else {
+            return Ok(None);
+        };
+        let ilst = location.ilst();
+        let mut metadata = self.parse_ilst(&file_data[ilst.payload_start()..ilst.end()]);
+        metadata.warnings.extend(location.warning(&file_data));
+        metadata.warnings.extend(corruption_warning(&file_data, &location));
+        Ok(Some(metadata))
+    }
+
+    /// Types of the items in the ilst atom, in file order, e.g. "©nam" or "----"
+    ///
+    /// Only the atom headers are read. Bytes are mapped as Latin-1, so the
+    /// 0xA9 prefix of iTunes items reads as "©".
+    pub fn read_item_types(&self) -> std::io::Result<Vec<String>> {
+        let data = std::fs::read(&self.path)?;
+        let Some(location) = find_ilst_path(&data) else {
+            return Ok(Vec::new());
+        };
+        let ilst = location.ilst();
+        Ok(atoms_in(&data, ilst.payload_start(), ilst.end())
+            .into_iter()
+            .map(|item| item.atom_type(&data).iter().map(|&byte| byte as char).collect())
+            .collect())
     }
 
-    /// Find ilst atom in MP4 file data
-    fn find_ilst_atom(&self, data: &[u8]) -> Option<Vec<u8>> {
-        let mut pos = 0;
+    /// Read the sample description of the first sound track
+    pub fn read_audio_entry(&self) -> std::io::Result<Option<Mp4AudioEntry>> {
+        let data = std::fs::read(&self.path)?;
+        let Some(moov) = find_moov(&data) else {
+            return Ok(None);
+        };
+
+        for trak in atoms_in(&data, moov.payload_start(), moov.end()) {
+            if trak.atom_type(&data) != *atoms::TRAK {
+                continue;
+            }
+            let Some(mdia) = find_atom(&data, trak.payload_start(), trak.end(), atoms::MDIA) else {
+                continue;
+            };
+            // hdlr: version/flags, pre-defined, then the handler type
+            let is_sound = find_atom(&data, mdia.payload_start(), mdia.end(), atoms::HDLR)
+                .and_then(|hdlr| data.get(hdlr.payload_start() + 8..hdlr.payload_start() + 12))
+                .is_some_and(|handler| handler == b"soun");
+            if !is_sound {
+                continue;
+            }
 
-        while pos < data.len() {
-            if pos + 8 > data.len() {
-                break;
+            let stsd = [atoms::MINF, atoms::STBL, atoms::STSD].iter()
+                .try_fold(mdia, |parent, name| find_atom(&data, parent.payload_start(), parent.end(), name));
+            // stsd: version/flags and entry count, then the sample entries
+            let entry = stsd.and_then(|stsd| atoms_in(&data, stsd.payload_start() + 8, stsd.end()).into_iter().next());
+            if let Some(entry) = entry {
+                return Ok(Mp4AudioEntry::parse(&entry.atom_type(&data), &data[entry.payload_start()..entry.end()]));
             }
+        }
+        Ok(None)
+    }
 
-            let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
-            let atom_type = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+    /// Read the movie duration in milliseconds from moov/mvhd
+    pub fn read_duration_ms(&self) -> std::io::Result<Option<u64>> {
+        let data = std::fs::read(&self.path)?;
+        let mvhd = find_moov(&data)
+            .and_then(|moov| find_atom(&data, moov.payload_start(), moov.end(), atoms::MVHD));
+        let payload = match mvhd {
+            Some(atom) => &data[atom.payload_start()..atom.end()],
+            None => return Ok(None),
+        };
 
-            // Handle extended size (64-bit)
-            let actual_size = if size == 1 {
-                if pos + 16 > data.len() {
-                    break;
+        // Version 1 uses 64-bit times and duration
+        let (timescale, duration) = match payload.first() {
+            Some(1) if payload.len() >= 32 => (
+                u32::from_be_bytes(payload[20..24].try_into().unwrap()),
+                u64::from_be_bytes(payload[24..32].try_into().unwrap()),
+            ),
+            Some(0) if payload.len() >= 20 => (
+                u32::from_be_bytes(payload[12..16].try_into().unwrap()),
+                u32::from_be_bytes(payload[16..20].try_into().unwrap()) as u64,
+            ),
+            _ => return Ok(None),
+        };
+
+        if timescale == 0 {
+            return Ok(None);
+        }
+        Ok(Some(duration * 1000 / timescale as u64))
+    }
+
+    /// Parse ilst atom data
+    fn parse_ilst(&self, data: &[u8]) -> Mp4Metadata {
+        let mut metadata = Mp4Metadata::default();
+
+        for item in atoms_in(data, 0, data.len()) {
+            let atom_type = item.atom_type(data);
+            let freeform = freeform_name(data, item);
+
+            // Each item holds one or more data atoms: type flags(4) + locale(4) + value
+            for data_atom in atoms_in(data, item.payload_start(), item.end()) {
+                if data_atom.atom_type(data) != *atoms::DATA || data_atom.payload_start() + 8 > data_atom.end() {
+                    continue;
                 }
-                u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap())
-            } else {
-                size as u64
-            };
+                let flags_start = data_atom.payload_start();
+                let type_flag = u32::from_be_bytes(data[flags_start..flags_start + 4].try_into().unwrap()) & 0x00FF_FFFF;
+                let content = &data[flags_start + 8..data_atom.end()];
+                let text = || Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
 
-            let atom_end = pos + actual_size as usize;
-
-            // Check for meta atom (skip the 4-byte zero prefix)
-            if atom_type == *atoms::META {
-                // meta atom starts with 4 bytes of zeros
-                let meta_pos = if pos + 8 + 4 <= data.len() {
-                    pos + 8 + 4
-                } else {
-                    pos + 8
-                };
-
-                // Search for ilst within meta
-                let mut inner_pos = meta_pos;
-                while inner_pos < data.len().min(atom_end) {
-                    if inner_pos + 8 > data.len() {
-                        break;
+                // Map atom type to metadata field
+                if atom_type == *atoms::TITLE {
+                    metadata.title = text();
+                } else if atom_type == *atoms::ARTIST {
+                    metadata.artist = text();
+                } else if atom_type == *atoms::ALBUM {
+                    metadata.album = text();
+                } else if atom_type == *atoms::YEAR {
+                    metadata.year = text();
+                } else if atom_type == *atoms::TRACK {
+                    // Track number is stored as 2 bytes: track number / total tracks
+                    if content.len() >= 6 {
+                        let track_num = u16::from_be_bytes([content[2], content[3]]);
+                        metadata.track = Some(track_num.to_string());
+                    }
+                } else if atom_type == *atoms::GENRE {
+                    metadata.genre = text();
+                } else if atom_type == *atoms::COMMENT {
+                    metadata.comment = text();
+                } else if atom_type == *atoms::LYRICS {
+                    metadata.lyrics = text();
+                } else if atom_type == *atoms::ALBUM_ARTIST {
+                    metadata.album_artist = text();
+                } else if atom_type == *atoms::COMPOSER {
+                    metadata.composer = text();
+                } else if atom_type == *atoms::ENCODER {
+                    metadata.encoder = text();
+                } else if atom_type == *atoms::MEDIA_KIND {
+                    metadata.media_kind = integer_value(content).and_then(|kind| u8::try_from(kind).ok());
+                } else if atom_type == *atoms::GAPLESS {
+                    metadata.gapless = integer_value(content).map(|flag| flag != 0);
+                } else if atom_type == *atoms::TV_SHOW {
+                    metadata.tv_show = text();
+                } else if atom_type == *atoms::TV_SEASON {
+                    metadata.tv_season = integer_value(content).and_then(|season| u32::try_from(season).ok());
+                } else if atom_type == *atoms::TV_EPISODE {
+                    metadata.tv_episode = integer_value(content).and_then(|episode| u32::try_from(episode).ok());
+                } else if atom_type == *atoms::COVER {
+                    metadata.covers.push(Mp4Cover { data: content.to_vec(), type_flag });
+                } else if atom_type == *atoms::FREEFORM {
+                    match freeform.as_deref() {
+                        Some("ORIGINALDATE") => metadata.original_date = text(),
+                        Some("ORIGINALARTIST") => metadata.original_artist = text(),
+                        Some("ORIGINALALBUM") => metadata.original_album = text(),
+                        Some("ENCODERSETTINGS") => metadata.encoder_settings = text(),
+                        Some("iTunNORM") => metadata.itunnorm = text(),
+                        _ => {}
                     }
+                }
+            }
+        }
+
+        metadata
+    }
 
-                    let inner_size = u32::from_be_bytes(data[inner_pos..inner_pos + 4].try_into().unwrap()) as u64;
-                    let inner_type = [data[inner_pos + 4], data[inner_pos + 5], data[inner_pos + 6], data[inner_pos + 7]];
+    /// Write metadata to MP4 file
+    ///
+    /// Rebuilds the ilst atom (creating udta/meta/ilst if needed), keeping items
+    /// that `Mp4Metadata` doesn't cover. Chunk offsets are adjusted when the moov
+    /// atom sits before the media data and changes size.
+    pub fn write_metadata(&self, metadata: &Mp4Metadata) -> std::io::Result<()> {
+        let file_data = std::fs::read(&self.path)?;
+        let new_data = build_file_with_metadata(&file_data, metadata)?;
+        crate::utils::io::replace_file(&self.path, &new_data)
+    }
+}
 
-                    if inner_type == *atoms::ILST {
-                        // Return ilst content (skip header)
-                        let ilist_start = inner_pos + 8;
-                        let ilist_end = (inner_pos + inner_size as usize).min(data.len());
-                        return Some(data[ilist_start..ilist_end].to_vec());
-                    }
+/// Audio sample entry of an MP4 sound track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mp4AudioEntry {
+    /// Sample entry type, e.g. "mp4a" or "alac"
+    pub format: String,
+    pub channels: u16,
+    pub sample_size: u16,
+    pub sample_rate: u32,
+}
+
+impl Mp4AudioEntry {
+    /// Parse an AudioSampleEntry payload: 8 bytes of SampleEntry fields, 8
+    /// reserved, channel count, sample size, 4 reserved, 16.16 sample rate
+    fn parse(format: &[u8; 4], payload: &[u8]) -> Option<Self> {
+        let field = |range: std::ops::Range<usize>| payload.get(range);
+        Some(Mp4AudioEntry {
+            format: String::from_utf8_lossy(format).to_string(),
+            channels: u16::from_be_bytes(field(16..18)?.try_into().ok()?),
+            sample_size: u16::from_be_bytes(field(18..20)?.try_into().ok()?),
+            sample_rate: u32::from_be_bytes(field(24..28)?.try_into().ok()?) >> 16,
+        })
+    }
+
+    /// Codec name as used by `AudioProperties::codec`
+    pub fn codec(&self) -> String {
+        match self.format.as_str() {
+            "mp4a" => "AAC".to_string(),
+            "alac" => "ALAC".to_string(),
+            "fLaC" => "FLAC".to_string(),
+            "Opus" => "Opus".to_string(),
+            "ac-3" => "AC-3".to_string(),
+            "ec-3" => "E-AC-3".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// Location of an atom within file data
+#[derive(Debug, Clone, Copy)]
+struct AtomSpan {
+    offset: usize,
+    header_len: usize,
+    size: usize,
+}
+
+impl AtomSpan {
+    fn end(&self) -> usize {
+        self.offset + self.size
+    }
+
+    fn payload_start(&self) -> usize {
+        self.offset + self.header_len
+    }
+
+    fn atom_type(&self, data: &[u8]) -> [u8; 4] {
+        data[self.offset + 4..self.offset + 8].try_into().unwrap()
+    }
+}
+
+/// The meta atom is a full box: its children follow 4 bytes of version/flags
+const META_FLAGS_LEN: usize = 4;
 
-                    let inner_actual_size = if inner_size == 1 {
-                        inner_pos + 16 + (u64::from_be_bytes(data[inner_pos + 12..inner_pos + 20].try_into().unwrap()) as usize)
-                    } else {
-                        inner_pos + inner_size as usize
-                    };
+/// hdlr atom marking a meta atom as iTunes metadata
+const HDLR_MDIR: &[u8] = &[
+    0x00, 0x00, 0x00, 0x21, b'h', b'd', b'l', b'r',
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    b'm', b'd', b'i', b'r', b'a', b'p', b'p', b'l',
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00,
+];
 
-                    inner_pos = inner_actual_size;
+/// Containers that can hold chunk offset tables
+const SAMPLE_TABLE_PATH: &[&[u8; 4]] = &[atoms::TRAK, atoms::MDIA, atoms::MINF, atoms::STBL];
+
+/// Atoms parsed from a range, and why parsing stopped early if it did
+struct AtomList {
+    atoms: Vec<AtomSpan>,
+    /// Offset and description of the first malformed header
+    problem: Option<(usize, String)>,
+}
+
+/// Parse the atoms in data[start..end], stopping at the first malformed header
+///
+/// Size 0 ("extends to the end of the file") is accepted only for a
+/// `top_level` atom. Sizes smaller than the header, or larger than what is
+/// left, stop the parse. Every atom accepted is at least 8 bytes long, so
+/// the loop runs at most (end - start) / 8 times.
+fn parse_atoms(data: &[u8], start: usize, end: usize, top_level: bool) -> AtomList {
+    let end = end.min(data.len());
+    let mut atoms = Vec::new();
+    let mut pos = start;
+
+    let problem = loop {
+        if pos == end {
+            break None;
+        }
+        if pos + 8 > end {
+            break Some((pos, format!("{} trailing bytes are too short for an atom header", end - pos)));
+        }
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
+        let (header_len, size) = match size {
+            0 if top_level => (8, (end - pos) as u64),
+            1 => {
+                if pos + 16 > end {
+                    break Some((pos, "extended size field is truncated".to_string()));
                 }
+                (16, u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap()))
             }
+            _ => (8, size),
+        };
 
-            pos = atom_end;
+        if size < header_len as u64 {
+            break Some((pos, format!("size {} is smaller than its {}-byte header", size, header_len)));
+        }
+        if size > (end - pos) as u64 {
+            break Some((pos, format!("size {} runs past the {} bytes left", size, end - pos)));
         }
 
-        None
+        let span = AtomSpan { offset: pos, header_len, size: size as usize };
+        atoms.push(span);
+        pos = span.end();
+    };
+
+    AtomList { atoms, problem }
+}
+
+/// List the atoms in data[start..end], stopping at the first malformed header
+fn atoms_in(data: &[u8], start: usize, end: usize) -> Vec<AtomSpan> {
+    parse_atoms(data, start, end, false).atoms
+}
+
+/// Find the top-level moov atom
+fn find_moov(data: &[u8]) -> Option<AtomSpan> {
+    parse_atoms(data, 0, data.len(), true).atoms.into_iter().find(|atom| atom.atom_type(data) == *atoms::MOOV)
+}
+
+/// Describe the first malformed atom header at the top level, in moov or in the ilst
+fn corruption_warning(data: &[u8], location: &IlstPath) -> Option<String> {
+    let (moov, ilst) = (location.moov(), location.ilst());
+    let (offset, problem) = parse_atoms(data, 0, data.len(), true).problem
+        .or_else(|| parse_atoms(data, moov.payload_start(), moov.end(), false).problem)
+        .or_else(|| parse_atoms(data, ilst.payload_start(), ilst.end(), false).problem)?;
+    Some(format!("Corrupt MP4 atom at offset {}: {}", offset, problem))
+}
+
+/// Find the first atom of a given type in data[start..end]
+fn find_atom(data: &[u8], start: usize, end: usize, wanted: &[u8; 4]) -> Option<AtomSpan> {
+    atoms_in(data, start, end).into_iter().find(|atom| atom.atom_type(data) == *wanted)
+}
+
+/// Offset of the first child of a container atom
+///
+/// The ISO meta atom is a full box whose children follow 4 bytes of
+/// version/flags; QuickTime's meta has none and starts with its hdlr child.
+fn children_start(data: &[u8], atom: AtomSpan) -> usize {
+    let start = atom.payload_start();
+    if atom.atom_type(data) == *atoms::META && data.get(start + 4..start + 8) != Some(&atoms::HDLR[..]) {
+        start + META_FLAGS_LEN
+    } else {
+        start
     }
+}
 
-    /// Parse ilst atom data
-    fn parse_ilst(&self, data: &[u8]) -> Mp4Metadata {
-        let mut metadata = Mp4Metadata::default();
-        let mut pos = 0;
+/// Check that a meta atom's layout is understood: QuickTime style or a version 0 full box
+fn is_supported_meta(data: &[u8], meta: AtomSpan) -> bool {
+    children_start(data, meta) == meta.payload_start() || data.get(meta.payload_start()) == Some(&0)
+}
+
+/// Containers searched for an ilst outside the standard locations
+const SCANNED_CONTAINERS: &[&[u8; 4]] = &[atoms::UDTA, atoms::META, atoms::TRAK];
 
-        while pos < data.len() {
-            if pos + 8 > data.len() {
-                break;
+/// Atoms from moov down to the ilst holding the tags
+struct IlstPath {
+    atoms: Vec<AtomSpan>,
+    /// Found by scanning rather than at moov/udta/meta or moov/meta
+    scanned: bool,
+}
+
+impl IlstPath {
+    fn moov(&self) -> AtomSpan {
+        self.atoms[0]
+    }
+
+    fn ilst(&self) -> AtomSpan {
+        self.atoms[self.atoms.len() - 1]
+    }
+
+    /// Describe a nonstandard location, e.g. "moov/trak/udta/meta/ilst"
+    fn warning(&self, data: &[u8]) -> Option<String> {
+        if !self.scanned {
+            return None;
+        }
+        let names: Vec<String> = self.atoms.iter()
+            .map(|atom| String::from_utf8_lossy(&atom.atom_type(data)).to_string())
+            .collect();
+        Some(format!("MP4 tags found at nonstandard location {}", names.join("/")))
+    }
+}
+
+/// Locate the ilst atom: moov, an optional udta, a meta, then ilst
+///
+/// When neither moov/udta/meta/ilst nor moov/meta/ilst exists, the udta,
+/// meta and trak atoms under moov are searched for one. Meta atoms with an
+/// unknown version are never entered.
+fn find_ilst_path(data: &[u8]) -> Option<IlstPath> {
+    let moov = find_moov(data)?;
+
+    let follow = |names: &[&[u8; 4]]| {
+        let mut path = vec![moov];
+        for name in names {
+            let parent = path[path.len() - 1];
+            let atom = find_atom(data, children_start(data, parent), parent.end(), name)?;
+            if atom.atom_type(data) == *atoms::META && !is_supported_meta(data, atom) {
+                return None;
             }
+            path.push(atom);
+        }
+        Some(path)
+    };
+    let standard = follow(&[atoms::UDTA, atoms::META, atoms::ILST])
+        .or_else(|| follow(&[atoms::META, atoms::ILST]));
+    if let Some(atoms) = standard {
+        return Some(IlstPath { atoms, scanned: false });
+    }
+
+    let mut path = vec![moov];
+    scan_for_ilst(data, &mut path).then_some(IlstPath { atoms: path, scanned: true })
+}
+
+/// Depth-first search below the last atom of `path`, extending it to the first ilst found
+fn scan_for_ilst(data: &[u8], path: &mut Vec<AtomSpan>) -> bool {
+    let parent = path[path.len() - 1];
+    for child in atoms_in(data, children_start(data, parent), parent.end()) {
+        let atom_type = child.atom_type(data);
+        let inside_meta = parent.atom_type(data) == *atoms::META;
+        if atom_type == *atoms::ILST && inside_meta {
+            path.push(child);
+            return true;
+        }
+        if !SCANNED_CONTAINERS.contains(&&atom_type) || atom_type == *atoms::META && !is_supported_meta(data, child) {
+            continue;
+        }
+        path.push(child);
+        if scan_for_ilst(data, path) {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
 
-            let atom_size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
-            let atom_type = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+/// Rebuild the first atom of `path` with the last one replaced by `leaf`
+fn replace_nested(data: &[u8], path: &[AtomSpan], leaf: &[u8]) -> Vec<u8> {
+    let (container, rest) = path.split_first().expect("path must not be empty");
+    let Some(child) = rest.first() else {
+        return leaf.to_vec();
+    };
+    let mut payload = data[container.payload_start()..child.offset].to_vec();
+    payload.extend(replace_nested(data, rest, leaf));
+    payload.extend_from_slice(&data[child.end()..container.end()]);
+    wrap_atom(&container.atom_type(data), &payload, container.header_len == 16)
+}
 
-            // Extract data atom content
-            let data_pos = pos + 8; // Skip item atom header
-            if data_pos + 8 > data.len() {
-                break;
+/// Build an atom from its type and payload
+fn wrap_atom(atom_type: &[u8], payload: &[u8], extended: bool) -> Vec<u8> {
+    let mut result = Vec::with_capacity(payload.len() + 16);
+    if extended {
+        result.extend_from_slice(&1u32.to_be_bytes());
+        result.extend_from_slice(atom_type);
+        result.extend_from_slice(&(payload.len() as u64 + 16).to_be_bytes());
+    } else {
+        result.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+        result.extend_from_slice(atom_type);
+    }
+    result.extend_from_slice(payload);
+    result
+}
+
+/// Build a data atom: type flags, locale, value
+fn data_atom(type_flag: u32, value: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(value.len() + 8);
+    payload.extend_from_slice(&type_flag.to_be_bytes());
+    payload.extend_from_slice(&0u32.to_be_bytes());
+    payload.extend_from_slice(value);
+    wrap_atom(atoms::DATA, &payload, false)
+}
+
+/// Value of an integer data atom, stored big-endian in 1 to 8 bytes
+fn integer_value(content: &[u8]) -> Option<u64> {
+    if content.is_empty() || content.len() > 8 {
+        return None;
+    }
+    Some(content.iter().fold(0u64, |value, byte| value << 8 | u64::from(*byte)))
+}
+
+/// Build a new chain of container atoms ending in `leaf`
+fn new_atom_chain(path: &[&[u8; 4]], leaf: &[u8]) -> Vec<u8> {
+    match path.split_first() {
+        None => Vec::new(),
+        Some((_, [])) => leaf.to_vec(),
+        Some((name, rest)) => {
+            let mut payload = Vec::new();
+            if *name == atoms::META {
+                payload.extend_from_slice(&[0; META_FLAGS_LEN]);
+                payload.extend_from_slice(HDLR_MDIR);
             }
+            payload.extend(new_atom_chain(rest, leaf));
+            wrap_atom(*name, &payload, false)
+        }
+    }
+}
 
-            // Check for data atom
-            let data_atom_type = [data[data_pos + 4], data[data_pos + 5], data[data_pos + 6], data[data_pos + 7]];
-            if data_atom_type == *atoms::DATA {
-                // Data atom structure: size(4) + type(4) + reserved(4) + data
-                let content_start = data_pos + 16;
-                let content_end = (pos + atom_size).min(data.len());
-
-                if content_start < content_end {
-                    let content = &data[content_start..content_end];
-
-                    // Map atom type to metadata field
-                    if atom_type == *atoms::TITLE {
-                        metadata.title = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::ARTIST {
-                        metadata.artist = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::ALBUM {
-                        metadata.album = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::YEAR {
-                        metadata.year = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::TRACK {
-                        // Track number is stored as 2 bytes: track number / total tracks
-                        if content.len() >= 6 {
-                            let track_num = u16::from_be_bytes([content[2], content[3]]);
-                            metadata.track = Some(track_num.to_string());
-                        }
-                    } else if atom_type == *atoms::GENRE {
-                        metadata.genre = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::COMMENT {
-                        metadata.comment = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::LYRICS {
-                        metadata.lyrics = Some(String::from_utf8_lossy(content).trim_end_matches('\0').to_string());
-                    } else if atom_type == *atoms::COVER {
-                        metadata.cover = Some(content.to_vec());
-                    }
-                }
+/// Rebuild `container` with the atom at `path` replaced by `leaf`
+///
+/// Missing atoms along the path are created; everything else is copied as-is.
+fn rebuild_container(data: &[u8], container: AtomSpan, path: &[&[u8; 4]], leaf: &[u8]) -> Vec<u8> {
+    let start = children_start(data, container);
+    let mut payload = data[container.payload_start()..start].to_vec();
+
+    let (name, rest) = path.split_first().expect("path must not be empty");
+    match find_atom(data, start, container.end(), name) {
+        Some(child) => {
+            payload.extend_from_slice(&data[start..child.offset]);
+            if rest.is_empty() {
+                payload.extend_from_slice(leaf);
+            } else {
+                payload.extend(rebuild_container(data, child, rest, leaf));
             }
+            payload.extend_from_slice(&data[child.end()..container.end()]);
+        }
+        None => {
+            payload.extend_from_slice(&data[start..container.end()]);
+            payload.extend(new_atom_chain(path, leaf));
+        }
+    }
+
+    wrap_atom(&container.atom_type(data), &payload, container.header_len == 16)
+}
+
+/// Shift stco/co64 chunk offsets at or past `threshold` by `delta`
+fn adjust_chunk_offsets(data: &mut [u8], start: usize, end: usize, threshold: u64, delta: i64) -> std::io::Result<()> {
+    for atom in atoms_in(data, start, end) {
+        let atom_type = atom.atom_type(data);
 
-            pos += atom_size;
+        if SAMPLE_TABLE_PATH.contains(&&atom_type) {
+            adjust_chunk_offsets(data, atom.payload_start(), atom.end(), threshold, delta)?;
+            continue;
         }
 
-        metadata
+        let entry_len = match &atom_type {
+            b"stco" => 4,
+            b"co64" => 8,
+            _ => continue,
+        };
+
+        // version/flags(4) + entry count(4) + entries
+        let table_start = atom.payload_start() + 8;
+        if table_start > atom.end() {
+            continue;
+        }
+        let count = u32::from_be_bytes(data[table_start - 4..table_start].try_into().unwrap()) as usize;
+        let count = count.min((atom.end() - table_start) / entry_len);
+
+        for i in 0..count {
+            let pos = table_start + i * entry_len;
+            let entry = &mut data[pos..pos + entry_len];
+            let offset = if entry_len == 4 {
+                u32::from_be_bytes(entry.try_into().unwrap()) as u64
+            } else {
+                u64::from_be_bytes(entry.try_into().unwrap())
+            };
+            if offset < threshold {
+                continue;
+            }
+
+            let shifted = offset as i64 + delta;
+            if entry_len == 4 {
+                let shifted = u32::try_from(shifted).map_err(|_| std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Chunk offset no longer fits in stco",
+                ))?;
+                entry.copy_from_slice(&shifted.to_be_bytes());
+            } else {
+                entry.copy_from_slice(&(shifted as u64).to_be_bytes());
+            }
+        }
     }
 
-    /// Write metadata to MP4 file (reserved for future use)
-    #[allow(dead_code)]
-    pub fn write_metadata(&self, metadata: &Mp4Metadata) -> std::io::Result<()> {
-        // For MP4, we would need to rebuild the ilst atom
-        // This is a simplified implementation that preserves existing structure
-        // A full implementation would need to handle complex atom tree manipulation
+    Ok(())
+}
 
-        // Read the entire file
-        let file_data = std::fs::read(&self.path)?;
+/// Name of an iTunes freeform (`----`) item, or `None` for other items and namespaces
+fn freeform_name(data: &[u8], item: AtomSpan) -> Option<String> {
+    if item.atom_type(data) != *atoms::FREEFORM {
+        return None;
+    }
+
+    // mean and name hold version/flags(4) + text
+    let text_of = |wanted: &[u8; 4]| {
+        find_atom(data, item.payload_start(), item.end(), wanted)
+            .filter(|atom| atom.payload_start() + 4 <= atom.end())
+            .map(|atom| String::from_utf8_lossy(&data[atom.payload_start() + 4..atom.end()]).to_string())
+    };
+    if text_of(atoms::MEAN)? != ITUNES_MEAN {
+        return None;
+    }
+    text_of(atoms::NAME)
+}
+
+/// Build an iTunes freeform item holding UTF-8 text
+fn freeform_atom(name: &str, text: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend(wrap_atom(atoms::MEAN, &[&[0u8; 4][..], ITUNES_MEAN.as_bytes()].concat(), false));
+    payload.extend(wrap_atom(atoms::NAME, &[&[0u8; 4][..], name.as_bytes()].concat(), false));
+    payload.extend(data_atom(data_types::UTF8, text.as_bytes()));
+    wrap_atom(atoms::FREEFORM, &payload, false)
+}
+
+/// Build the ilst atom for `metadata`, keeping unmanaged items from `old_ilst`
+fn build_ilst(old_ilst: &[u8], metadata: &Mp4Metadata) -> Vec<u8> {
+    let mut payload = Vec::new();
+
+    for item in atoms_in(old_ilst, 0, old_ilst.len()) {
+        let managed = atoms::MANAGED.contains(&&item.atom_type(old_ilst))
+            || freeform_name(old_ilst, item).is_some_and(|name| MANAGED_FREEFORM.contains(&name.as_str()));
+        if !managed {
+            payload.extend_from_slice(&old_ilst[item.offset..item.end()]);
+        }
+    }
 
-        // For now, this is a placeholder - full implementation would
-        // parse the atom tree, modify ilst, and rebuild the file
-        let _ = (file_data, metadata);
+    let text_items = [
+        (atoms::TITLE, &metadata.title),
+        (atoms::ARTIST, &metadata.artist),
+        (atoms::ALBUM, &metadata.album),
+        (atoms::YEAR, &metadata.year),
+        (atoms::GENRE, &metadata.genre),
+        (atoms::COMMENT, &metadata.comment),
+        (atoms::LYRICS, &metadata.lyrics),
+        (atoms::ALBUM_ARTIST, &metadata.album_artist),
+        (atoms::COMPOSER, &metadata.composer),
+        (atoms::ENCODER, &metadata.encoder),
+        (atoms::TV_SHOW, &metadata.tv_show),
+    ];
+    for (atom_type, value) in text_items {
+        if let Some(text) = value {
+            payload.extend(wrap_atom(atom_type, &data_atom(data_types::UTF8, text.as_bytes()), false));
+        }
+    }
 
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Unsupported,
-            "MP4 metadata writing not yet implemented"
-        ))
+    if let Some(track) = &metadata.track {
+        // "3" or "3/12": reserved(2) + track(2) + total(2) + reserved(2)
+        let mut parts = track.split('/').map(|part| part.trim().parse::<u16>().unwrap_or(0));
+        let number = parts.next().unwrap_or(0);
+        let total = parts.next().unwrap_or(0);
+        let mut value = vec![0, 0];
+        value.extend_from_slice(&number.to_be_bytes());
+        value.extend_from_slice(&total.to_be_bytes());
+        value.extend_from_slice(&[0, 0]);
+        payload.extend(wrap_atom(atoms::TRACK, &data_atom(data_types::IMPLICIT, &value), false));
     }
+
+    // Apple writes stik and pgap as one byte, tvsn and tves as four
+    let integer_items = [
+        (atoms::MEDIA_KIND, metadata.media_kind.map(|kind| vec![kind])),
+        (atoms::GAPLESS, metadata.gapless.map(|gapless| vec![u8::from(gapless)])),
+        (atoms::TV_SEASON, metadata.tv_season.map(|season| season.to_be_bytes().to_vec())),
+        (atoms::TV_EPISODE, metadata.tv_episode.map(|episode| episode.to_be_bytes().to_vec())),
+    ];
+    for (atom_type, value) in integer_items {
+        if let Some(value) = value {
+            payload.extend(wrap_atom(atom_type, &data_atom(data_types::INTEGER, &value), false));
+        }
+    }
+
+    let freeform_items = [
+        ("ORIGINALDATE", &metadata.original_date),
+        ("ORIGINALARTIST", &metadata.original_artist),
+        ("ORIGINALALBUM", &metadata.original_album),
+        ("ENCODERSETTINGS", &metadata.encoder_settings),
+        ("iTunNORM", &metadata.itunnorm),
+    ];
+    for (name, value) in freeform_items {
+        if let Some(text) = value {
+            payload.extend(freeform_atom(name, text));
+        }
+    }
+
+    if !metadata.covers.is_empty() {
+        let mut covr = Vec::new();
+        for cover in &metadata.covers {
+            covr.extend(data_atom(cover.type_flag, &cover.data));
+        }
+        payload.extend(wrap_atom(atoms::COVER, &covr, false));
+    }
+
+    wrap_atom(atoms::ILST, &payload, false)
+}
+
+/// Return a copy of the file with its ilst replaced by `metadata`
+fn build_file_with_metadata(data: &[u8], metadata: &Mp4Metadata) -> std::io::Result<Vec<u8>> {
+    let (moov, mut new_moov) = match find_ilst_path(data) {
+        // Existing tags are rewritten where they are
+        Some(location) => {
+            let old_ilst = location.ilst();
+            let ilst = build_ilst(&data[old_ilst.payload_start()..old_ilst.end()], metadata);
+            (location.moov(), replace_nested(data, &location.atoms, &ilst))
+        }
+        None => {
+            let moov = find_moov(data).ok_or_else(|| std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "No moov atom found",
+            ))?;
+            let meta = find_atom(data, moov.payload_start(), moov.end(), atoms::UDTA)
+                .and_then(|udta| find_atom(data, udta.payload_start(), udta.end(), atoms::META));
+            if let Some(meta) = meta.filter(|meta| !is_supported_meta(data, *meta)) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Unsupported meta atom version {}", data[meta.payload_start()]),
+                ));
+            }
+            let ilst = build_ilst(&[], metadata);
+            (moov, rebuild_container(data, moov, &[atoms::UDTA, atoms::META, atoms::ILST], &ilst))
+        }
+    };
+
+    // Media data after moov moves by the size difference
+    let delta = new_moov.len() as i64 - moov.size as i64;
+    if delta != 0 {
+        let header_len = moov.header_len;
+        let len = new_moov.len();
+        adjust_chunk_offsets(&mut new_moov, header_len, len, moov.end() as u64, delta)?;
+    }
+
+    let mut result = Vec::with_capacity(data.len() + new_moov.len());
+    result.extend_from_slice(&data[..moov.offset]);
+    result.extend(new_moov);
+    result.extend_from_slice(&data[moov.end()..]);
+    Ok(result)
 }
 
 /// MP4 metadata structure
@@ -247,7 +854,73 @@ pub struct Mp4Metadata {
     pub genre: Option<String>,
     pub comment: Option<String>,
     pub lyrics: Option<String>,
-    pub cover: Option<Vec<u8>>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub original_date: Option<String>,
+    pub original_artist: Option<String>,
+    pub original_album: Option<String>,
+    pub encoder: Option<String>,
+    pub encoder_settings: Option<String>,
+    /// Raw Sound Check values (see `SoundCheck`)
+    pub itunnorm: Option<String>,
+    /// Media kind (`stik`), e.g. 1 for music or 2 for audiobook
+    pub media_kind: Option<u8>,
+    /// Part of a gapless album (`pgap`)
+    pub gapless: Option<bool>,
+    pub tv_show: Option<String>,
+    pub tv_season: Option<u32>,
+    pub tv_episode: Option<u32>,
+    pub covers: Vec<Mp4Cover>,
+    /// Problems noticed while reading, e.g. tags at a nonstandard location
+    pub warnings: Vec<String>,
+}
+
+/// Picture stored in a `covr` data atom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mp4Cover {
+    pub data: Vec<u8>,
+    /// Data atom type flag (13 = JPEG, 14 = PNG, 27 = BMP)
+    pub type_flag: u32,
+}
+
+impl Mp4Cover {
+    /// Create a cover, deriving the type flag from the MIME type
+    pub fn new(data: Vec<u8>, mime_type: &str) -> Self {
+        let type_flag = match mime_type {
+            "image/jpeg" | "image/jpg" => data_types::JPEG,
+            "image/png" => data_types::PNG,
+            "image/bmp" => data_types::BMP,
+            "image/gif" => data_types::GIF,
+            _ => data_types::IMPLICIT,
+        };
+        Mp4Cover { data, type_flag }
+    }
+
+    /// Create a cover for an image about to be written
+    ///
+    /// The type flag follows the sniffed image type, or `declared_mime` when
+    /// the data isn't recognized. Errs for images without a `covr` type flag.
+    pub fn for_image(data: Vec<u8>, declared_mime: Option<&str>) -> Result<Self, String> {
+        let mime_type = crate::utils::image::detect_mime_type(&data)
+            .or(declared_mime)
+            .unwrap_or("unrecognized image data");
+        let cover = Mp4Cover::new(data, mime_type);
+        if cover.type_flag == data_types::IMPLICIT {
+            return Err(format!("MP4 covers must be JPEG, PNG, BMP or GIF, not {}", mime_type));
+        }
+        Ok(cover)
+    }
+
+    /// MIME type from the type flag, falling back to sniffing the image data
+    pub fn mime_type(&self) -> Option<&'static str> {
+        match self.type_flag {
+            data_types::JPEG => Some("image/jpeg"),
+            data_types::PNG => Some("image/png"),
+            data_types::BMP => Some("image/bmp"),
+            data_types::GIF => Some("image/gif"),
+            _ => crate::utils::image::detect_mime_type(&self.data),
+        }
+    }
 }
 
 /// Detect if file is MP4/M4A format
@@ -263,25 +936,60 @@ pub fn is_mp4_file(path: &str) -> bool {
     false
 }
 
-/// Read MP4 atom header at position (reserved for future use)
-#[allow(dead_code)]
-pub fn read_atom_header(data: &[u8], pos: usize) -> Option<Mp4AtomHeader> {
-    if pos + 8 > data.len() {
-        return None;
-    }
+/// Find a top-level atom by type, returning (payload offset, payload length)
+pub fn find_top_level_atom<R: Read + Seek>(
+    reader: &mut R,
+    file_len: u64,
+    wanted: &[u8; 4],
+) -> std::io::Result<Option<(u64, u64)>> {
+    let mut pos = 0u64;
 
-    let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
-    let atom_type = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
-    let is_extended = size == 1;
+    while pos + 8 <= file_len {
+        reader.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
 
-    let actual_size = if is_extended {
-        if pos + 16 > data.len() {
-            return None;
+        let size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        let (header_len, atom_size) = match size {
+            // Size 0 means the atom extends to the end of the file
+            0 => (8, file_len - pos),
+            1 => {
+                if pos + 16 > file_len {
+                    break;
+                }
+                let mut extended = [0u8; 8];
+                reader.read_exact(&mut extended)?;
+                (16, u64::from_be_bytes(extended))
+            }
+            _ => (8, size),
+        };
+
+        // Every atom moves `pos` forward by at least 8 bytes, so this terminates
+        if atom_size < header_len || atom_size > file_len - pos {
+            break;
+        }
+
+        if &header[4..8] == wanted {
+            let length = atom_size.min(file_len - pos) - header_len;
+            return Ok(Some((pos + header_len, length)));
         }
-        u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap())
-    } else {
-        size
-    };
+
+        pos += atom_size;
+    }
+
+    Ok(None)
+}
+
+/// Read MP4 atom header at position (reserved for future use)
+///
+/// Size 0 is read as extending to the end of `data`. Returns `None` for a
+/// size smaller than the header or larger than the data left.
+#[allow(dead_code)]
+pub fn read_atom_header(data: &[u8], pos: usize) -> Option<Mp4AtomHeader> {
+    let atom = parse_atoms(data, pos, data.len(), true).atoms.into_iter().next()?;
+    let atom_type = atom.atom_type(data);
+    let is_extended = atom.header_len == 16;
+    let actual_size = atom.size as u64;
 
     Some(Mp4AtomHeader {
         offset: pos,
@@ -290,3 +998,48 @@ pub fn read_atom_header(data: &[u8], pos: usize) -> Option<Mp4AtomHeader> {
         is_extended,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_mp4_malformed_atom_sizes_terminate() {
+        let mut value = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        value.extend_from_slice(b"Song");
+        let title = mp4_atom(b"\xa9nam", &mp4_atom(b"data", &value));
+        let ftyp = mp4_atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+        let file_with_ilst = |ilst_payload: Vec<u8>| {
+            let meta = [vec![0, 0, 0, 0], mp4_atom(b"ilst", &ilst_payload)].concat();
+            [ftyp.clone(), mp4_atom(b"moov", &mp4_atom(b"udta", &mp4_atom(b"meta", &meta)))].concat()
+        };
+
+        // A zero-size item in the middle of the ilst, and an item claiming 4 bytes
+        let zero_size = [title.clone(), 0u32.to_be_bytes().to_vec(), b"\xa9ART".to_vec(), title.clone()].concat();
+        let size_four = [title.clone(), 4u32.to_be_bytes().to_vec(), b"\xa9ART".to_vec()].concat();
+        // A zero-size atom before moov swallows the rest of the file
+        let zero_top = [ftyp.clone(), 0u32.to_be_bytes().to_vec(), b"free".to_vec(), file_with_ilst(title.clone())].concat();
+        let fixtures = [
+            ("zero-size.m4a", file_with_ilst(zero_size), Some("Song")),
+            ("size-four.m4a", file_with_ilst(size_four), Some("Song")),
+            ("zero-top.m4a", zero_top, None),
+        ];
+
+        for (name, data, title) in fixtures {
+            let path = write_temp_file(name, &data);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let reader_path = path.clone();
+            std::thread::spawn(move || {
+                sender.send(Mp4File::new(reader_path).read_metadata().unwrap()).ok();
+            });
+            let metadata = receiver.recv_timeout(std::time::Duration::from_secs(5))
+                .unwrap_or_else(|_| panic!("{} did not parse in time", name));
+            assert_eq!(metadata.as_ref().and_then(|meta| meta.title.as_deref()), title, "{}", name);
+            if let Some(metadata) = metadata {
+                assert!(metadata.warnings.iter().any(|warning| warning.starts_with("Corrupt MP4 atom")), "{}", name);
+            }
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use std::io::{Read, BufRead};
-use crate::ogg::{OGG_SIGNATURE, OGG_HEADER_TYPE_BOS};
+use crate::ogg::{OGG_SIGNATURE, OGG_HEADER_TYPE_BOS, OGG_HEADER_TYPE_CONTINUATION};
+
+/// Maximum number of segments in one OGG page
+const MAX_SEGMENTS: usize = 255;
+
+/// Offset of the CRC field in the page header
+const CRC_OFFSET: usize = 22;
 
 /// OGG Page Header
 #[derive(Debug, Clone)]
@@ -92,24 +98,228 @@ impl OggPage {
         Some(OggPage { header, data })
     }
 
-    /// Read page and check if it contains Vorbis comment
-    /// Vorbis comment is in the second page (page_sequence == 1)
-    pub fn read_vorbis_comment_page<R: BufRead>(reader: &mut R) -> Option<Vec<u8>> {
-        loop {
+    /// Read the first `count` packets of the stream
+    ///
+    /// A lacing value of 255 means the packet continues in the next segment,
+    /// which may be on the next page; a value below 255 ends it. Packets are
+    /// joined across pages regardless of page sequence numbers.
+    pub fn read_packets<R: Read>(reader: &mut R, count: usize) -> Option<Vec<Vec<u8>>> {
+        let mut packets = Vec::new();
+        let mut current = Vec::new();
+
+        while packets.len() < count {
             let page = Self::read(reader)?;
-            if page.header.page_sequence == 1 {
-                // This is the comment header page
-                // Data starts with packet type (0x03) and "vorbis" identifier
-                if page.data.len() > 7 && page.data[0] == 0x03 && &page.data[1..7] == b"vorbis" {
-                    // Skip the header and return comment data
-                    return Some(page.data[7..].to_vec());
+            let mut pos = 0;
+
+            for &lacing in &page.header.segment_table {
+                let end = pos + lacing as usize;
+                current.extend_from_slice(&page.data[pos..end]);
+                pos = end;
+
+                if lacing < 255 {
+                    packets.push(std::mem::take(&mut current));
+                    if packets.len() == count {
+                        break;
+                    }
                 }
             }
-            // Stop if we've passed the comment page
-            if page.header.page_sequence > 1 {
-                break;
-            }
+        }
+
+        Some(packets)
+    }
+
+    /// Read the Vorbis comment packet (the second packet of the stream)
+    pub fn read_vorbis_comment_page<R: BufRead>(reader: &mut R) -> Option<Vec<u8>> {
+        let packets = Self::read_packets(reader, 2)?;
+        let packet = &packets[1];
+
+        // Data starts with packet type (0x03) and "vorbis" identifier
+        if packet.len() > 7 && packet[0] == 0x03 && &packet[1..7] == b"vorbis" {
+            return Some(packet[7..].to_vec());
         }
         None
     }
 }
+
+/// Offset of the bitstream serial number in the page header
+pub const SERIAL_OFFSET: usize = 14;
+
+/// Byte ranges of the complete pages at the start of `data`, in order
+///
+/// Stops at the first position that doesn't hold a whole page, so trailing
+/// garbage or a truncated last page is left out.
+pub fn page_ranges(data: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    while let Some(header) = data.get(pos..pos + 27) {
+        if &header[..4] != OGG_SIGNATURE {
+            break;
+        }
+        let segment_count = header[26] as usize;
+        let Some(segment_table) = data.get(pos + 27..pos + 27 + segment_count) else {
+            break;
+        };
+        let end = pos + 27 + segment_count + segment_table.iter().map(|&x| x as usize).sum::<usize>();
+        if end > data.len() {
+            break;
+        }
+        ranges.push(pos..end);
+        pos = end;
+    }
+    ranges
+}
+
+/// Lacing values for a packet of `size` bytes
+///
+/// A packet whose size is a multiple of 255 ends with a 0 lacing value.
+pub fn lacing_values(size: usize) -> Vec<u8> {
+    let mut table = vec![255u8; size / 255];
+    table.push((size % 255) as u8);
+    table
+}
+
+/// Rewrite the stream with the first packet of page 1 (the comment header) replaced
+///
+/// The comment packet may span several pages, before and after. The pages
+/// holding it are repaginated together with the packets that follow it on
+/// those pages (e.g. the Vorbis setup header), and the sequence numbers of
+/// the later pages of the stream are shifted to match, with their CRCs
+/// updated. `None` when there is no page 1.
+pub fn replace_comment_packet(data: &[u8], packet: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+    let sequence = |page: &[u8]| u32::from_le_bytes(page[18..22].try_into().unwrap());
+    let serial = |page: &[u8]| u32::from_le_bytes(page[SERIAL_OFFSET..SERIAL_OFFSET + 4].try_into().unwrap());
+
+    let ranges = page_ranges(data);
+    let Some(first) = ranges.iter().position(|range| sequence(&data[range.clone()]) == 1) else {
+        return Ok(None);
+    };
+    let first_page = &data[ranges[first].clone()];
+    if first_page[5] & OGG_HEADER_TYPE_CONTINUATION != 0 {
+        return Err(invalid("OGG page 1 continues a packet from page 0"));
+    }
+
+    // Split the pages holding the comment packet into packets, up to a page that ends one
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut current = Vec::new();
+    let mut last = first;
+    for (index, range) in ranges.iter().enumerate().skip(first) {
+        let page = &data[range.clone()];
+        if serial(page) != serial(first_page) {
+            continue;
+        }
+        let segment_count = page[26] as usize;
+        let mut pos = 27 + segment_count;
+        for &lacing in &page[27..27 + segment_count] {
+            current.extend_from_slice(&page[pos..pos + lacing as usize]);
+            pos += lacing as usize;
+            if lacing < 255 {
+                packets.push(std::mem::take(&mut current));
+            }
+        }
+        last = index;
+        if !packets.is_empty() && current.is_empty() {
+            break;
+        }
+    }
+    if packets.is_empty() || !current.is_empty() {
+        return Err(invalid("OGG comment packet is truncated"));
+    }
+    packets[0] = packet.to_vec();
+
+    let last_page = &data[ranges[last].clone()];
+    let new_pages = paginate(&packets, first_page, last_page);
+    let shift = new_pages.len() as i64 - ranges[first..=last].iter()
+        .filter(|range| serial(&data[(*range).clone()]) == serial(first_page))
+        .count() as i64;
+
+    let mut output = data[..ranges[first].start].to_vec();
+    for page in new_pages {
+        output.extend_from_slice(&page);
+    }
+    for range in &ranges[first + 1..] {
+        let mut page = data[range.clone()].to_vec();
+        if serial(&page) == serial(first_page) {
+            if range.start <= ranges[last].start {
+                continue;
+            }
+            if shift != 0 {
+                let new_sequence = (sequence(&page) as i64 + shift) as u32;
+                page[18..22].copy_from_slice(&new_sequence.to_le_bytes());
+                update_crc(&mut page);
+            }
+        }
+        output.extend_from_slice(&page);
+    }
+    output.extend_from_slice(&data[ranges.last().map_or(0, |range| range.end)..]);
+    Ok(Some(output))
+}
+
+/// Lay `packets` out on as many pages as they need, numbered from `first`'s sequence
+///
+/// Pages take their serial from `first`. A page on which no packet ends has
+/// granule position -1, the last page gets `last`'s granule position and
+/// end-of-stream flag, and the others 0, as header pages have.
+fn paginate(packets: &[Vec<u8>], first: &[u8], last: &[u8]) -> Vec<Vec<u8>> {
+    let lacing: Vec<u8> = packets.iter().flat_map(|packet| lacing_values(packet.len())).collect();
+    let body = packets.concat();
+    let page_count = lacing.len().div_ceil(MAX_SEGMENTS);
+
+    let mut pages = Vec::with_capacity(page_count);
+    let mut pos = 0;
+    let mut continued = false;
+    for (index, table) in lacing.chunks(MAX_SEGMENTS).enumerate() {
+        let size: usize = table.iter().map(|&x| x as usize).sum();
+        let is_last = index + 1 == page_count;
+        let granule = if is_last {
+            last[6..14].try_into().unwrap()
+        } else if table.iter().any(|&lacing| lacing < 255) {
+            [0u8; 8]
+        } else {
+            [0xFF; 8]
+        };
+        let mut header_type = if continued { OGG_HEADER_TYPE_CONTINUATION } else { 0 };
+        if is_last {
+            header_type |= last[5] & !OGG_HEADER_TYPE_CONTINUATION & !OGG_HEADER_TYPE_BOS;
+        }
+
+        let mut page = OGG_SIGNATURE.to_vec();
+        page.push(0);
+        page.push(header_type);
+        page.extend_from_slice(&granule);
+        page.extend_from_slice(&first[SERIAL_OFFSET..SERIAL_OFFSET + 4]);
+        let sequence = u32::from_le_bytes(first[18..22].try_into().unwrap()) + index as u32;
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0u8; 4]);
+        page.push(table.len() as u8);
+        page.extend_from_slice(table);
+        page.extend_from_slice(&body[pos..pos + size]);
+        update_crc(&mut page);
+
+        pos += size;
+        continued = table.last() == Some(&255);
+        pages.push(page);
+    }
+    pages
+}
+
+/// CRC-32 of a page as stored in its header (polynomial 0x04C11DB7, not reflected)
+///
+/// The CRC field itself must be zeroed before computing.
+pub fn page_crc(page: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in page {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Recompute and store the CRC of a raw page
+pub fn update_crc(page: &mut [u8]) {
+    page[CRC_OFFSET..CRC_OFFSET + 4].fill(0);
+    let crc = page_crc(page);
+    page[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+}
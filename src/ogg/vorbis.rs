@@ -25,8 +25,10 @@ impl OggVorbisFile {
 
         // Try to read the Vorbis comment page
         if let Some(comment_data) = crate::ogg::page::OggPage::read_vorbis_comment_page(&mut reader) {
+            // A comment that exists but can't be parsed is an error, not a missing
+            // comment, so writers never start over from an empty one
             let mut cursor = std::io::Cursor::new(comment_data);
-            return Ok(VorbisComment::read(&mut cursor).ok());
+            return VorbisComment::read(&mut cursor).map(Some);
         }
 
         Ok(None)
@@ -35,106 +37,26 @@ impl OggVorbisFile {
     /// Write Vorbis comment to OGG file
     #[allow(dead_code)]
     pub fn write_comment(&self, comment: &VorbisComment) -> std::io::Result<()> {
-        // Read the entire file
-        let mut file_data = std::fs::read(&self.path)?;
-
-        // Find and replace the comment page
-        let mut pos = 0;
-        let mut found_comment_page = false;
-
-        while pos < file_data.len() {
-            // Read page header
-            if pos + 27 > file_data.len() {
-                break;
-            }
-
-            // Check for OGG signature
-            if &file_data[pos..pos + 4] != b"OggS" {
-                break;
-            }
-
-            // Read segment count
-            let segment_count = file_data[pos + 26] as usize;
-            if pos + 27 + segment_count > file_data.len() {
-                break;
-            }
-
-            // Read segment table
-            let segment_table = &file_data[pos + 27..pos + 27 + segment_count];
-            let data_size: usize = segment_table.iter().map(|&x| x as usize).sum();
-
-            let header_size = 27 + segment_count;
-            let total_page_size = header_size + data_size;
-
-            // Check if this is page sequence 1 (comment page)
-            let page_sequence = u32::from_le_bytes(file_data[pos + 18..pos + 22].try_into().unwrap());
-
-            if page_sequence == 1 {
-                // This is the comment page - replace it
-                let new_comment_data = comment.to_bytes();
-
-                // Construct new page data with Vorbis comment header
-                let mut new_page_data = Vec::new();
-                new_page_data.push(0x03); // Packet type (comment header)
-                new_page_data.extend_from_slice(b"vorbis");
-                new_page_data.extend_from_slice(&new_comment_data);
-
-                // Update segment table for new data
-                let new_data_size = new_page_data.len();
-                let new_segment_table = Self::create_segment_table(new_data_size);
-
-                // Build new page
-                let mut new_page = Vec::new();
-                // Copy original header except segment table
-                new_page.extend_from_slice(&file_data[pos..pos + 26]);
-                // New segment count
-                new_page.push(new_segment_table.len() as u8);
-                // New segment table
-                new_page.extend_from_slice(&new_segment_table);
-                // New page data
-                new_page.extend_from_slice(&new_page_data);
-
-                // Replace page in file data
-                let mut new_file_data = Vec::new();
-                new_file_data.extend_from_slice(&file_data[..pos]);
-                new_file_data.extend_from_slice(&new_page);
-                new_file_data.extend_from_slice(&file_data[pos + total_page_size..]);
-
-                file_data = new_file_data;
-                found_comment_page = true;
-                break;
-            }
-
-            pos += total_page_size;
-        }
-
-        if !found_comment_page {
+        let file_data = std::fs::read(&self.path)?;
+
+        // The comment header is the first packet of page 1
+        let mut packet = Vec::new();
+        packet.push(0x03); // Packet type (comment header)
+        packet.extend_from_slice(b"vorbis");
+        packet.extend_from_slice(&comment.to_bytes());
+        packet.push(0x01); // Framing bit
+        let Some(file_data) = crate::ogg::page::replace_comment_packet(&file_data, &packet)? else {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "Vorbis comment page not found"
             ));
-        }
+        };
 
         // Write modified file
-        std::fs::write(&self.path, file_data)?;
+        crate::utils::io::replace_file(&self.path, &file_data)?;
 
         Ok(())
     }
-
-    /// Create segment table for given data size
-    #[allow(dead_code)]
-    fn create_segment_table(size: usize) -> Vec<u8> {
-        let mut table = Vec::new();
-        let mut remaining = size;
-
-        while remaining > 0 {
-            let segment_size = remaining.min(255);
-            table.push(segment_size as u8);
-            remaining -= segment_size;
-        }
-
-        table
-    }
 }
 
 /// Detect if file is OGG format
@@ -148,3 +70,56 @@ pub fn is_ogg_file(path: &str) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_ogg_write_spreads_large_comment_over_pages() {
+        use crate::ogg::page::{page_crc, page_ranges, OggPage};
+
+        let path = write_temp_file("large-comment.ogg", &build_ogg_file("vendor", &[("TITLE", "Song")]));
+        let file = OggVorbisFile::new(path.clone());
+        let check = |pages: usize| {
+            let data = std::fs::read(&path).unwrap();
+            let ranges = page_ranges(&data);
+            assert_eq!(ranges.len(), pages);
+            for (sequence, range) in ranges.iter().enumerate() {
+                let mut page = data[range.clone()].to_vec();
+                assert_eq!(u32::from_le_bytes(page[18..22].try_into().unwrap()), sequence as u32);
+                // The fixture leaves the CRCs zero; only rewritten pages get one
+                if sequence == 0 {
+                    continue;
+                }
+                let crc = u32::from_le_bytes(page[22..26].try_into().unwrap());
+                page[22..26].fill(0);
+                assert_eq!(page_crc(&page), crc);
+            }
+            // The setup header and the audio page come through unchanged
+            let packets = OggPage::read_packets(&mut std::io::Cursor::new(&data), 3).unwrap();
+            assert_eq!(packets[2], b"\x05vorbis-setup");
+            assert!(data.ends_with(b"\xAA\xBB\xCC\xDD"));
+        };
+
+        let mut comment = file.read_comment().unwrap().unwrap();
+        comment.set("LYRICS", &"x".repeat(70_000));
+        file.write_comment(&comment).unwrap();
+        assert_eq!(file.read_comment().unwrap().unwrap().get("LYRICS"), Some(&"x".repeat(70_000)));
+        check(4);
+        // Page 1 ends no packet and page 2 continues the comment from it
+        let data = std::fs::read(&path).unwrap();
+        let ranges = page_ranges(&data);
+        assert_eq!(data[ranges[1].start + 6..ranges[1].start + 14], [0xFF; 8]);
+        assert_eq!(data[ranges[2].start + 5], crate::ogg::OGG_HEADER_TYPE_CONTINUATION);
+
+        // Shrinking it again drops the extra page and renumbers the audio page back
+        comment.remove("LYRICS");
+        file.write_comment(&comment).unwrap();
+        assert_eq!(file.read_comment().unwrap().unwrap().get("TITLE").map(String::as_str), Some("Song"));
+        check(3);
+
+        std::fs::remove_file(path).ok();
+    }
+}
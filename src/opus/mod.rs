@@ -5,7 +5,8 @@
 //
 // OPUS File Structure:
 // - Uses OGG container (similar to OGG Vorbis)
-// - Identification header: "OpusHead" (8 bytes) in first page
+// - Identification header: "OpusHead" (8 bytes) in first page, followed by
+//   version, channel count, pre-skip, input sample rate and output gain
 // - Comment header: "OpusTags" (8 bytes) followed by Vorbis Comment in second page
 // - Audio data pages
 //
@@ -19,9 +20,11 @@ use std::fs::File;
 
 #[allow(dead_code)]
 pub const OPUS_SIGNATURE: &[u8; 8] = b"OpusHead";
-#[allow(dead_code)]
 pub const OPUS_TAGS: &[u8; 8] = b"OpusTags";
 
+/// Offset of the output gain (signed Q7.8 dB, little-endian) in the OpusHead packet
+pub const OUTPUT_GAIN_OFFSET: usize = 16;
+
 // Re-export FLAC's VorbisComment types since they're compatible
 pub use crate::flac::vorbis::VorbisComment;
 
@@ -43,175 +46,87 @@ impl OpusFile {
 
         // Try to read the OPUS comment page
         if let Some(comment_data) = read_opus_comment_page(&mut reader) {
+            // A comment that exists but can't be parsed is an error, not a missing
+            // comment, so writers never start over from an empty one
             let mut cursor = std::io::Cursor::new(comment_data);
-            return Ok(VorbisComment::read(&mut cursor).ok());
+            return VorbisComment::read(&mut cursor).map(Some);
         }
 
         Ok(None)
     }
 
-    /// Write Vorbis comment to OPUS file
-    #[allow(dead_code)]
-    pub fn write_comment(&self, comment: &VorbisComment) -> std::io::Result<()> {
-        // Read the entire file
-        let mut file_data = std::fs::read(&self.path)?;
-
-        // Find and replace the comment page
-        let mut pos = 0;
-        let mut found_comment_page = false;
-
-        while pos < file_data.len() {
-            // Read page header
-            if pos + 27 > file_data.len() {
-                break;
-            }
+    /// Read the output gain of the OpusHead packet, in 1/256 dB
+    ///
+    /// `None` if the first page doesn't hold an identification header.
+    pub fn read_output_gain(&self) -> std::io::Result<Option<i16>> {
+        let file = File::open(&self.path)?;
+        let mut reader = std::io::BufReader::new(file);
+        let Some(packets) = crate::ogg::page::OggPage::read_packets(&mut reader, 1) else {
+            return Ok(None);
+        };
+        Ok(output_gain_bytes(&packets[0]).map(i16::from_le_bytes))
+    }
 
-            // Check for OGG signature
-            if &file_data[pos..pos + 4] != b"OggS" {
-                break;
-            }
+    /// Rewrite the output gain of the OpusHead packet, in 1/256 dB
+    ///
+    /// Only the two gain bytes and the CRC of the first page change.
+    pub fn write_output_gain(&self, gain: i16) -> std::io::Result<()> {
+        let mut file_data = std::fs::read(&self.path)?;
+        let not_found = || std::io::Error::new(std::io::ErrorKind::NotFound, "OpusHead packet not found");
 
-            // Read segment count
-            let segment_count = file_data[pos + 26] as usize;
-            if pos + 27 + segment_count > file_data.len() {
-                break;
-            }
+        let page = crate::ogg::page::page_ranges(&file_data).into_iter().next().ok_or_else(not_found)?;
+        let data_start = page.start + 27 + file_data[page.start + 26] as usize;
+        if output_gain_bytes(&file_data[data_start..page.end]).is_none() {
+            return Err(not_found());
+        }
+        let gain_start = data_start + OUTPUT_GAIN_OFFSET;
+        file_data[gain_start..gain_start + 2].copy_from_slice(&gain.to_le_bytes());
+        crate::ogg::page::update_crc(&mut file_data[page]);
 
-            // Read segment table
-            let segment_table = &file_data[pos + 27..pos + 27 + segment_count];
-            let data_size: usize = segment_table.iter().map(|&x| x as usize).sum();
-
-            let header_size = 27 + segment_count;
-            let total_page_size = header_size + data_size;
-
-            // Check if this is page sequence 1 (comment page)
-            let page_sequence = u32::from_le_bytes(file_data[pos + 18..pos + 22].try_into().unwrap());
-
-            if page_sequence == 1 {
-                // This is the comment page - replace it
-                let new_comment_data = comment.to_bytes();
-
-                // Construct new page data with OPUS comment header
-                let mut new_page_data = Vec::new();
-                new_page_data.extend_from_slice(OPUS_TAGS);
-                new_page_data.extend_from_slice(&new_comment_data);
-
-                // Update segment table for new data
-                let new_data_size = new_page_data.len();
-                let new_segment_table = create_segment_table(new_data_size);
-
-                // Build new page
-                let mut new_page = Vec::new();
-                // Copy original header except segment table
-                new_page.extend_from_slice(&file_data[pos..pos + 26]);
-                // New segment count
-                new_page.push(new_segment_table.len() as u8);
-                // New segment table
-                new_page.extend_from_slice(&new_segment_table);
-                // New page data
-                new_page.extend_from_slice(&new_page_data);
-
-                // Replace page in file data
-                let mut new_file_data = Vec::new();
-                new_file_data.extend_from_slice(&file_data[..pos]);
-                new_file_data.extend_from_slice(&new_page);
-                new_file_data.extend_from_slice(&file_data[pos + total_page_size..]);
-
-                file_data = new_file_data;
-                found_comment_page = true;
-                break;
-            }
+        crate::utils::io::replace_file(&self.path, &file_data)
+    }
 
-            pos += total_page_size;
-        }
+    /// Write Vorbis comment to OPUS file
+    #[allow(dead_code)]
+    pub fn write_comment(&self, comment: &VorbisComment) -> std::io::Result<()> {
+        let file_data = std::fs::read(&self.path)?;
 
-        if !found_comment_page {
+        // The comment header is the first packet of page 1
+        let mut packet = Vec::new();
+        packet.extend_from_slice(OPUS_TAGS);
+        packet.extend_from_slice(&comment.to_bytes());
+        let Some(file_data) = crate::ogg::page::replace_comment_packet(&file_data, &packet)? else {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "OPUS comment page not found"
             ));
-        }
+        };
 
         // Write modified file
-        std::fs::write(&self.path, file_data)?;
+        crate::utils::io::replace_file(&self.path, &file_data)?;
 
         Ok(())
     }
 }
 
-/// Read OPUS comment page from reader
-fn read_opus_comment_page<R: BufRead>(reader: &mut R) -> Option<Vec<u8>> {
-    loop {
-        // Read page header
-        let mut header = [0u8; 27];
-        if reader.read_exact(&mut header).is_err() {
-            return None;
-        }
-
-        // Check OGG signature
-        if &header[0..4] != b"OggS" {
-            return None;
-        }
-
-        // Read segment count
-        let segment_count = header[26];
-        let mut segment_table = vec![0u8; segment_count as usize];
-        if reader.read_exact(&mut segment_table).is_err() {
-            return None;
-        }
-
-        // Calculate data size
-        let data_size: usize = segment_table.iter().map(|&x| x as usize).sum();
-
-        // Check page sequence
-        let page_sequence = u32::from_le_bytes(header[18..22].try_into().unwrap());
-
-        if page_sequence == 1 {
-            // This is the comment header page
-            // Read page data
-            let mut data = vec![0u8; data_size];
-            if reader.read_exact(&mut data).is_err() {
-                return None;
-            }
-
-            // Data starts with "OpusTags" (8 bytes), skip it and return comment data
-            if data.len() > 8 && &data[0..8] == OPUS_TAGS {
-                return Some(data[8..].to_vec());
-            }
-        } else {
-            // Skip the data
-            let mut skip_buf = vec![0u8; data_size.min(8192)];
-            let mut remaining = data_size;
-            while remaining > 0 {
-                let to_read = remaining.min(skip_buf.len());
-                if reader.read_exact(&mut skip_buf[0..to_read]).is_err() {
-                    return None;
-                }
-                remaining -= to_read;
-            }
-
-            // Stop if we've passed the comment page
-            if page_sequence > 1 {
-                break;
-            }
-        }
+/// The output gain bytes of an OpusHead packet (the packet is at least 19 bytes)
+fn output_gain_bytes(packet: &[u8]) -> Option<[u8; 2]> {
+    if packet.len() < 19 || !packet.starts_with(OPUS_SIGNATURE) {
+        return None;
     }
-    None
+    packet[OUTPUT_GAIN_OFFSET..OUTPUT_GAIN_OFFSET + 2].try_into().ok()
 }
 
-/// Create segment table for given data size
-#[allow(dead_code)]
-fn create_segment_table(size: usize) -> Vec<u8> {
-    let mut table = Vec::new();
-    let mut remaining = size;
-
-    while remaining > 0 {
-        let segment_size = remaining.min(255);
-        table.push(segment_size as u8);
-        remaining -= segment_size;
-    }
+/// Read the OPUS comment packet (the second packet of the stream)
+fn read_opus_comment_page<R: BufRead>(reader: &mut R) -> Option<Vec<u8>> {
+    let packets = crate::ogg::page::OggPage::read_packets(reader, 2)?;
+    let packet = &packets[1];
 
-    table
+    // Data starts with "OpusTags" (8 bytes), skip it and return comment data
+    if packet.len() > 8 && &packet[0..8] == OPUS_TAGS {
+        return Some(packet[8..].to_vec());
+    }
+    None
 }
 
 /// Detect if file is OPUS format
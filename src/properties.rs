@@ -0,0 +1,372 @@
+// Audio stream properties (duration, sample rate, channels, codec)
+//
+// Read from the codec headers rather than tags: MPEG frame headers (with the
+// Xing/Info or VBRI frame count when present), FLAC STREAMINFO, the OGG
+// Vorbis/Opus identification header plus the last granule position, and the
+// MP4 mvhd atom and audio sample entry. The LAME extension of the Xing/Info header adds the encoder
+// delay and padding needed for gapless playback.
+
+use crate::ogg::page::OggPageHeader;
+use serde::Serialize;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+/// Properties of the audio stream
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AudioProperties {
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bits_per_sample: Option<u8>,
+    /// Average bitrate of the audio span, in kbit/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate_kbps: Option<u32>,
+    /// Codec name, e.g. "MP3", "FLAC", "Vorbis", "Opus", "AAC", "ALAC" or "DSD"; empty if unknown
+    pub codec: String,
+    /// Whether the codec is lossless, derived from `codec`
+    pub lossless: bool,
+}
+
+impl AudioProperties {
+    /// One-line summary such as "3:42, 44.1 kHz, 16-bit, FLAC"
+    ///
+    /// The bit depth is replaced by the bitrate for codecs without one.
+    pub fn summary(&self) -> String {
+        let seconds = self.duration_ms / 1000;
+        let mut parts = vec![format!("{}:{:02}", seconds / 60, seconds % 60)];
+        if let Some(rate) = self.sample_rate {
+            parts.push(format!("{} kHz", rate as f64 / 1000.0));
+        }
+        if let Some(bits) = self.bits_per_sample {
+            parts.push(format!("{}-bit", bits));
+        } else if let Some(kbps) = self.bitrate_kbps.filter(|&kbps| kbps > 0) {
+            parts.push(format!("{} kbps", kbps));
+        }
+        if !self.codec.is_empty() {
+            parts.push(self.codec.clone());
+        }
+        parts.join(", ")
+    }
+}
+
+/// Whether `codec` (as in `AudioProperties::codec`) is lossless
+pub fn is_lossless(codec: &str) -> bool {
+    matches!(codec, "FLAC" | "ALAC" | "APE" | "WavPack" | "DSD")
+}
+
+/// Gapless playback and ReplayGain information from a LAME header
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LameHeader {
+    /// Encoder name and version, e.g. "LAME3.100"
+    pub encoder_version: String,
+    /// Samples of silence the encoder added at the start
+    pub encoder_delay: u16,
+    /// Samples of silence the encoder added at the end
+    pub encoder_padding: u16,
+    /// Track ("radio") gain in dB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaygain_track: Option<f32>,
+    /// Album ("audiophile") gain in dB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaygain_album: Option<f32>,
+    /// Duration without the encoder delay and padding
+    pub audio_length_ms: u64,
+}
+
+/// MPEG audio frame header fields needed for timing
+#[derive(Debug, Clone, Copy)]
+struct MpegFrame {
+    /// 1 for MPEG-1, 2 for MPEG-2 and MPEG-2.5
+    version: u8,
+    layer: u8,
+    sample_rate: u32,
+    channels: u8,
+    length: usize,
+    samples: u32,
+}
+
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+];
+const BITRATES_V2: [[u32; 15]; 2] = [
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+impl MpegFrame {
+    /// Parse a 4-byte frame header; free-format and reserved values are rejected
+    fn parse(header: &[u8]) -> Option<Self> {
+        if header.len() < 4 || header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version_bits = (header[1] >> 3) & 0x03;
+        let layer = match (header[1] >> 1) & 0x03 {
+            3 => 1,
+            2 => 2,
+            1 => 3,
+            _ => return None,
+        };
+        let bitrate_index = (header[2] >> 4) as usize;
+        let rate_index = ((header[2] >> 2) & 0x03) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+            return None;
+        }
+        let padding = ((header[2] >> 1) & 0x01) as usize;
+        let channels = if header[3] >> 6 == 3 { 1 } else { 2 };
+
+        let (version, base_rate) = match version_bits {
+            3 => (1, [44100, 48000, 32000]),
+            2 => (2, [22050, 24000, 16000]),
+            0 => (2, [11025, 12000, 8000]),
+            _ => return None,
+        };
+        let sample_rate = base_rate[rate_index];
+        let bitrate = if version == 1 {
+            BITRATES_V1[layer as usize - 1][bitrate_index]
+        } else {
+            BITRATES_V2[usize::from(layer != 1)][bitrate_index]
+        } * 1000;
+
+        let samples = match (layer, version) {
+            (1, _) => 384,
+            (3, 2) => 576,
+            _ => 1152,
+        };
+        let length = if layer == 1 {
+            (12 * bitrate / sample_rate) as usize * 4 + padding * 4
+        } else {
+            (samples / 8 * bitrate / sample_rate) as usize + padding
+        };
+
+        Some(MpegFrame { version, layer, sample_rate, channels, length, samples })
+    }
+
+    /// The Xing/Info header in this frame, if any
+    fn xing_header<'a>(&self, frame: &'a [u8]) -> Option<&'a [u8]> {
+        let side_info = match (self.version, self.channels) {
+            (1, 2) => 32,
+            (1, _) | (_, 2) => 17,
+            _ => 9,
+        };
+        let xing = frame.get(4 + side_info..)?;
+        (xing.starts_with(b"Xing") || xing.starts_with(b"Info")).then_some(xing)
+    }
+
+    /// The LAME extension following the Xing/Info header in this frame, if any
+    fn lame_extension<'a>(&self, frame: &'a [u8]) -> Option<&'a [u8]> {
+        let xing = self.xing_header(frame)?;
+        let flags = u32::from_be_bytes(xing.get(4..8)?.try_into().ok()?);
+        // Frame count, byte count, TOC and quality follow the flags when present
+        let skipped: usize = [(0x01, 4), (0x02, 4), (0x04, 100), (0x08, 4)].iter()
+            .filter(|(flag, _)| flags & flag != 0)
+            .map(|(_, len)| len)
+            .sum();
+        let lame = xing.get(8 + skipped..8 + skipped + 24)?;
+        [&b"LAME"[..], b"Lavc", b"Lavf"].iter().any(|tag| lame.starts_with(tag)).then_some(lame)
+    }
+
+    /// Frame count from a Xing/Info or VBRI header in this frame, if any
+    fn vbr_frame_count(&self, frame: &[u8]) -> Option<u32> {
+        if let Some(xing) = self.xing_header(frame) {
+            let flags = u32::from_be_bytes(xing.get(4..8)?.try_into().ok()?);
+            if flags & 0x01 != 0 {
+                return Some(u32::from_be_bytes(xing.get(8..12)?.try_into().ok()?));
+            }
+        }
+
+        // VBRI always sits 32 bytes after the header
+        let vbri = frame.get(36..)?;
+        if vbri.starts_with(b"VBRI") {
+            return Some(u32::from_be_bytes(vbri.get(14..18)?.try_into().ok()?));
+        }
+        None
+    }
+}
+
+/// Read the audio span of a file
+pub fn read_span(path: &str, span: (u64, u64)) -> std::io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    reader.seek(SeekFrom::Start(span.0))?;
+    let mut data = vec![0u8; span.1 as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Whether `data` starts with an MPEG audio frame, followed by a second one if it fits
+pub fn starts_with_mpeg_frame(data: &[u8]) -> bool {
+    let Some(frame) = MpegFrame::parse(data) else {
+        return false;
+    };
+    data.get(frame.length..)
+        .is_none_or(|next| next.len() < 4 || MpegFrame::parse(next).is_some())
+}
+
+/// Find the first frame whose successor is also a frame, skipping anything before it
+fn find_first_frame(data: &[u8]) -> Option<(usize, MpegFrame)> {
+    let mut pos = 0;
+    while pos + 4 <= data.len() {
+        if let Some(frame) = MpegFrame::parse(&data[pos..]) {
+            let next = pos + frame.length;
+            if next + 4 > data.len() || MpegFrame::parse(&data[next..]).is_some() {
+                return Some((pos, frame));
+            }
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Total sample count of the MPEG stream starting with `first` at `pos`
+fn total_samples(data: &[u8], mut pos: usize, first: MpegFrame) -> u64 {
+    match first.vbr_frame_count(&data[pos..]) {
+        Some(frames) => frames as u64 * first.samples as u64,
+        None => {
+            // No frame count: walk every frame
+            let mut samples = 0u64;
+            while let Some(frame) = data.get(pos..).and_then(MpegFrame::parse) {
+                if frame.layer != first.layer || frame.sample_rate != first.sample_rate {
+                    break;
+                }
+                samples += frame.samples as u64;
+                pos += frame.length;
+            }
+            samples
+        }
+    }
+}
+
+/// Read MPEG audio properties from the audio span of an MP3 file
+pub fn read_mpeg(path: &str, span: (u64, u64)) -> std::io::Result<Option<AudioProperties>> {
+    Ok(mpeg_properties(&read_span(path, span)?))
+}
+
+/// MPEG audio properties of an audio span already in memory
+pub fn mpeg_properties(data: &[u8]) -> Option<AudioProperties> {
+    let (pos, first) = find_first_frame(data)?;
+    let total_samples = total_samples(data, pos, first);
+
+    Some(AudioProperties {
+        duration_ms: total_samples * 1000 / first.sample_rate as u64,
+        sample_rate: Some(first.sample_rate),
+        channels: Some(first.channels),
+        codec: format!("MP{}", first.layer),
+        ..Default::default()
+    })
+}
+
+/// Read the LAME header from the first frame of an MP3 file's audio span
+pub fn read_lame_header(path: &str, span: (u64, u64)) -> std::io::Result<Option<LameHeader>> {
+    let data = read_span(path, span)?;
+    let Some((pos, first)) = find_first_frame(&data) else {
+        return Ok(None);
+    };
+    let Some(lame) = first.lame_extension(&data[pos..]) else {
+        return Ok(None);
+    };
+
+    let encoder_version = String::from_utf8_lossy(&lame[..9])
+        .trim_end_matches(['\0', ' '])
+        .to_string();
+    // 12 bits each: delay then padding
+    let delay_padding = u32::from_be_bytes([0, lame[21], lame[22], lame[23]]);
+    let encoder_delay = (delay_padding >> 12) as u16;
+    let encoder_padding = (delay_padding & 0xFFF) as u16;
+
+    let samples = total_samples(&data, pos, first)
+        .saturating_sub(encoder_delay as u64 + encoder_padding as u64);
+
+    Ok(Some(LameHeader {
+        encoder_version,
+        encoder_delay,
+        encoder_padding,
+        replaygain_track: replay_gain(&lame[15..17], 1),
+        replaygain_album: replay_gain(&lame[17..19], 2),
+        audio_length_ms: samples * 1000 / first.sample_rate as u64,
+    }))
+}
+
+/// Decode a LAME ReplayGain field, `None` unless it is set and of the expected kind
+///
+/// Bits 15-13 name the gain (1 track, 2 album), bits 12-10 its originator,
+/// bit 9 the sign and bits 8-0 the value in tenths of a dB.
+fn replay_gain(field: &[u8], name: u16) -> Option<f32> {
+    let field = u16::from_be_bytes(field.try_into().ok()?);
+    if field >> 13 != name || (field >> 10) & 0x07 == 0 {
+        return None;
+    }
+    let gain = (field & 0x1FF) as f32 / 10.0;
+    Some(if field & 0x200 != 0 { -gain } else { gain })
+}
+
+/// Read FLAC properties from the STREAMINFO block data
+pub fn read_flac_streaminfo(data: &[u8]) -> Option<AudioProperties> {
+    if data.len() < 18 {
+        return None;
+    }
+
+    let sample_rate = ((data[10] as u32) << 12) | ((data[11] as u32) << 4) | ((data[12] as u32) >> 4);
+    let channels = ((data[12] >> 1) & 0x07) + 1;
+    let bits_per_sample = (((data[12] & 0x01) << 4) | (data[13] >> 4)) + 1;
+    let total_samples = (((data[13] & 0x0F) as u64) << 32)
+        | u32::from_be_bytes(data[14..18].try_into().unwrap()) as u64;
+
+    if sample_rate == 0 {
+        return None;
+    }
+    Some(AudioProperties {
+        duration_ms: total_samples * 1000 / sample_rate as u64,
+        sample_rate: Some(sample_rate),
+        channels: Some(channels),
+        bits_per_sample: Some(bits_per_sample),
+        codec: "FLAC".to_string(),
+        ..Default::default()
+    })
+}
+
+/// Read OGG Vorbis or Opus properties from the identification header and last page
+pub fn read_ogg(path: &str) -> std::io::Result<Option<AudioProperties>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let ident = match crate::ogg::page::OggPage::read_packets(&mut reader, 1) {
+        Some(mut packets) => packets.remove(0),
+        None => return Ok(None),
+    };
+
+    // (codec, sample rate, channels, samples to skip at the start, granule rate)
+    let (codec, sample_rate, channels, pre_skip, granule_rate) = if ident.starts_with(b"\x01vorbis") && ident.len() >= 16 {
+        let rate = u32::from_le_bytes(ident[12..16].try_into().unwrap());
+        ("Vorbis", rate, ident[11], 0, rate)
+    } else if ident.starts_with(crate::opus::OPUS_SIGNATURE) && ident.len() >= 16 {
+        let pre_skip = u16::from_le_bytes(ident[10..12].try_into().unwrap());
+        let input_rate = u32::from_le_bytes(ident[12..16].try_into().unwrap());
+        // Opus granule positions always count 48 kHz samples
+        ("Opus", input_rate, ident[9], pre_skip as u64, 48000)
+    } else {
+        return Ok(None);
+    };
+    if granule_rate == 0 {
+        return Ok(None);
+    }
+
+    // The granule position of the last page is the total sample count
+    reader.seek(SeekFrom::Start(0))?;
+    let mut last_granule = 0;
+    while let Some(header) = OggPageHeader::read(&mut reader) {
+        if header.granule_position != u64::MAX {
+            last_granule = header.granule_position;
+        }
+        reader.seek(SeekFrom::Current(header.get_data_size() as i64))?;
+    }
+
+    Ok(Some(AudioProperties {
+        duration_ms: last_granule.saturating_sub(pre_skip) * 1000 / granule_rate as u64,
+        sample_rate: Some(sample_rate).filter(|&rate| rate > 0),
+        channels: Some(channels),
+        codec: codec.to_string(),
+        ..Default::default()
+    }))
+}
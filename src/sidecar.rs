@@ -0,0 +1,145 @@
+// Metadata sidecar files for media managers
+//
+// "nfo" is the `<track>` XML read by Kodi and Plex, "xmp" an Adobe XMP packet
+// using Dublin Core and the XMP Dynamic Media schema (Adobe's mapping of the
+// ID3 fields), "json" the same JSON as `get_metadata`. The XML is simple
+// enough to build by hand; only text content needs escaping. Cover art is
+// left out of the XML formats.
+
+use crate::{AudioFileError, AudioResult, Metadata};
+
+/// Sidecar format names accepted by `render`
+pub const FORMATS: &[&str] = &["nfo", "xmp", "json"];
+
+/// Render `metadata` as a sidecar file in `format` ("nfo", "xmp" or "json")
+pub fn render(metadata: &Metadata, format: &str) -> AudioResult<String> {
+    match format.to_ascii_lowercase().as_str() {
+        "nfo" => Ok(render_nfo(metadata)),
+        "xmp" => Ok(render_xmp(metadata)),
+        "json" => serde_json::to_string_pretty(metadata)
+            .map_err(|e| AudioFileError::ParseError(e.to_string())),
+        other => Err(AudioFileError::UnsupportedFormat(format!(
+            "Unknown sidecar format '{}', expected one of: {}", other, FORMATS.join(", ")
+        ))),
+    }
+}
+
+/// Escape text for use as XML element content or attribute value
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than tab and newlines aren't allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Genres as a list, whether or not the reader filled `genres`
+fn genres(metadata: &Metadata) -> Vec<String> {
+    if metadata.genres.is_empty() {
+        metadata.genre.as_deref().map(crate::utils::genre::split).unwrap_or_default()
+    } else {
+        metadata.genres.clone()
+    }
+}
+
+fn render_nfo(metadata: &Metadata) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<track>\n");
+    let mut element = |name: &str, value: &str| {
+        xml.push_str(&format!("  <{0}>{1}</{0}>\n", name, escape(value)));
+    };
+
+    let text_fields = [
+        ("title", &metadata.title),
+        ("artist", &metadata.artist),
+        ("album", &metadata.album),
+        ("albumartist", &metadata.album_artist),
+        ("year", &metadata.year),
+        ("position", &metadata.track),
+        ("composer", &metadata.composer),
+        ("originaldate", &metadata.original_date),
+        ("comment", &metadata.comment),
+        ("lyrics", &metadata.lyrics),
+    ];
+    for (name, value) in text_fields {
+        if let Some(value) = value {
+            element(name, value);
+        }
+    }
+    for genre in genres(metadata) {
+        element("genre", &genre);
+    }
+    if let Some(length_ms) = metadata.length_ms {
+        element("duration", &(length_ms / 1000).to_string());
+    }
+    for (instrument, name) in &metadata.performers {
+        xml.push_str(&format!("  <performer role=\"{}\">{}</performer>\n", escape(instrument), escape(name)));
+    }
+
+    xml.push_str("</track>\n");
+    xml
+}
+
+fn render_xmp(metadata: &Metadata) -> String {
+    let mut xmp = String::from(concat!(
+        "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+        " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+        "  <rdf:Description rdf:about=\"\"\n",
+        "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+        "    xmlns:xmpDM=\"http://ns.adobe.com/xmp/1.0/DynamicMedia/\">\n",
+    ));
+    // Language alternatives for dc:title and dc:description, sequences for dc:creator and dc:date
+    let mut container = |name: &str, kind: &str, values: &[&str]| {
+        let lang = if kind == "Alt" { " xml:lang=\"x-default\"" } else { "" };
+        xmp.push_str(&format!("   <{}>\n    <rdf:{}>\n", name, kind));
+        for value in values {
+            xmp.push_str(&format!("     <rdf:li{}>{}</rdf:li>\n", lang, escape(value)));
+        }
+        xmp.push_str(&format!("    </rdf:{}>\n   </{}>\n", kind, name));
+    };
+
+    if let Some(title) = &metadata.title {
+        container("dc:title", "Alt", &[title]);
+    }
+    if let Some(artist) = &metadata.artist {
+        container("dc:creator", "Seq", &[artist]);
+    }
+    if let Some(comment) = &metadata.comment {
+        container("dc:description", "Alt", &[comment]);
+    }
+    if let Some(year) = &metadata.year {
+        container("dc:date", "Seq", &[year]);
+    }
+
+    let simple_fields = [
+        ("xmpDM:artist", &metadata.artist),
+        ("xmpDM:album", &metadata.album),
+        ("xmpDM:albumArtist", &metadata.album_artist),
+        ("xmpDM:composer", &metadata.composer),
+        ("xmpDM:trackNumber", &metadata.track),
+        ("xmpDM:releaseDate", &metadata.year),
+        ("xmpDM:lyrics", &metadata.lyrics),
+    ];
+    for (name, value) in simple_fields {
+        if let Some(value) = value {
+            xmp.push_str(&format!("   <{0}>{1}</{0}>\n", name, escape(value)));
+        }
+    }
+    let genres = genres(metadata);
+    if !genres.is_empty() {
+        // xmpDM:genre is a single text value; several genres are comma-separated
+        xmp.push_str(&format!("   <xmpDM:genre>{}</xmpDM:genre>\n", escape(&genres.join(", "))));
+    }
+
+    xmp.push_str("  </rdf:Description>\n </rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>\n");
+    xmp
+}
@@ -0,0 +1,102 @@
+// iTunes Sound Check (iTunNORM) values
+//
+// Stored as ten space-separated 8-digit hex numbers in an ID3v2 COMM frame
+// described "iTunNORM" or an MP4 `----:com.apple.iTunes:iTunNORM` item:
+//
+//   1-2   volume adjustment per channel, as 1000 * 10^(-gain / 10)
+//   3-4   the same relative to 2500 instead of 1000
+//   5-6   not documented; usually a sample count
+//   7-8   peak sample value per channel (0-32768)
+//   9-10  not documented
+
+/// Name of the ID3v2 COMM description and MP4 freeform item
+pub const ITUNNORM: &str = "iTunNORM";
+
+/// Parsed iTunNORM values, left channel first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SoundCheck {
+    pub adjustment_1000: [u32; 2],
+    pub adjustment_2500: [u32; 2],
+    pub unknown_1: [u32; 2],
+    pub peak: [u32; 2],
+    pub unknown_2: [u32; 2],
+}
+
+impl SoundCheck {
+    /// Parse an iTunNORM string; `None` unless it holds exactly ten hex values
+    pub fn parse(text: &str) -> Option<Self> {
+        let values = text.split_whitespace()
+            .map(|value| u32::from_str_radix(value, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        if values.len() != 10 {
+            return None;
+        }
+
+        let pair = |index: usize| [values[index], values[index + 1]];
+        Some(SoundCheck {
+            adjustment_1000: pair(0),
+            adjustment_2500: pair(2),
+            unknown_1: pair(4),
+            peak: pair(6),
+            unknown_2: pair(8),
+        })
+    }
+
+    /// Encode as iTunes writes it: each value as " %08X"
+    pub fn to_itunnorm(&self) -> String {
+        [self.adjustment_1000, self.adjustment_2500, self.unknown_1, self.peak, self.unknown_2]
+            .iter()
+            .flatten()
+            .map(|value| format!(" {:08X}", value))
+            .collect()
+    }
+
+    /// Values applying `gain_db` to both channels
+    pub fn from_gain_db(gain_db: f64) -> Self {
+        let scale = 10f64.powf(-gain_db / 10.0);
+        let adjustment = |base: f64| (base * scale).round().clamp(0.0, u32::MAX as f64) as u32;
+        SoundCheck {
+            adjustment_1000: [adjustment(1000.0); 2],
+            adjustment_2500: [adjustment(2500.0); 2],
+            ..Default::default()
+        }
+    }
+
+    /// Gain in dB per channel, from the 1000-based adjustment
+    pub fn gain_db(&self) -> [f64; 2] {
+        self.adjustment_1000.map(|adjustment| {
+            if adjustment == 0 {
+                0.0
+            } else {
+                -10.0 * (adjustment as f64 / 1000.0).log10()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_sound_check_parse_known_itunnorm() {
+        let sound_check = SoundCheck::parse(ITUNNORM_SAMPLE).unwrap();
+        assert_eq!(sound_check.adjustment_1000, [0x292, 0x2A1]);
+        assert_eq!(sound_check.adjustment_2500, [0x668, 0x693]);
+        assert_eq!(sound_check.peak, [0x7FFF, 0x7D8F]);
+
+        // 0x292 = 658: 1000 * 10^(-gain / 10) = 658, so the gain is about +1.82 dB
+        let [left, right] = sound_check.gain_db();
+        assert!((left - 1.818).abs() < 0.001, "{}", left);
+        assert!((right - 1.720).abs() < 0.001, "{}", right);
+        assert_eq!(sound_check.to_itunnorm(), ITUNNORM_SAMPLE);
+
+        assert!(SoundCheck::parse("00000292 000002A1").is_none());
+        assert!(SoundCheck::parse(&ITUNNORM_SAMPLE.replace("0292", "zzzz")).is_none());
+
+        let quieter = SoundCheck::from_gain_db(-3.0);
+        assert_eq!(quieter.adjustment_1000, [1995, 1995]);
+        assert_eq!(quieter.adjustment_2500, [4988, 4988]);
+    }
+}
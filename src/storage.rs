@@ -0,0 +1,296 @@
+// Pluggable storage for audio files
+//
+// The parsers and writers work on files on disk. `Storage` lets audio live
+// elsewhere, e.g. in an object or content-addressed store: `StoredAudio`
+// copies an object into a private scratch file, runs the usual read or write
+// on it, and streams a changed file back through `open_write_temp` and
+// `commit`, so the stored object is replaced in one step or not at all.
+// `FsStorage` is the filesystem implementation that every in-place write
+// already goes through.
+
+use crate::{AudioFile, AudioFileError, AudioResult, Metadata};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Size and writability of a stored object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageMetadata {
+    pub len: u64,
+    pub readonly: bool,
+}
+
+/// Where audio files are read from and written to, addressed by key
+pub trait Storage {
+    /// Open the object `key` for reading
+    fn open_read(&self, key: &str) -> std::io::Result<Box<dyn Read + '_>>;
+
+    /// Start a replacement for `key`, returning its temporary key and a writer
+    ///
+    /// Nothing is visible under `key` until `commit`.
+    fn open_write_temp(&self, key: &str) -> std::io::Result<(String, Box<dyn Write + '_>)>;
+
+    /// Move the finished temporary object `temp_key` over `key`
+    fn commit(&self, temp_key: &str, key: &str) -> std::io::Result<()>;
+
+    /// Remove a temporary object that won't be committed
+    fn discard(&self, temp_key: &str) -> std::io::Result<()>;
+
+    /// Size and writability of `key`
+    fn metadata(&self, key: &str) -> std::io::Result<StorageMetadata>;
+
+    /// Read the whole object `key`
+    fn read(&self, key: &str) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open_read(key)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Replace `key` with `data`, leaving it untouched if anything fails
+    fn replace(&self, key: &str, data: &[u8]) -> std::io::Result<()> {
+        let (temp_key, mut writer) = self.open_write_temp(key)?;
+        let written = writer.write_all(data).and_then(|_| writer.flush());
+        drop(writer);
+        let result = written.and_then(|_| self.commit(&temp_key, key));
+        if result.is_err() {
+            self.discard(&temp_key).ok();
+        }
+        result
+    }
+}
+
+/// Files on the local filesystem, keyed by path
+///
+/// A replacement is written to a hidden file next to the original, takes
+/// over its permissions, is synced, and is then renamed over it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsStorage;
+
+/// A file whose `flush` also syncs it to disk
+struct SyncedFile(std::fs::File);
+
+impl Write for SyncedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.sync_all()
+    }
+}
+
+impl Storage for FsStorage {
+    fn open_read(&self, key: &str) -> std::io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(std::io::BufReader::new(std::fs::File::open(key)?)))
+    }
+
+    fn open_write_temp(&self, key: &str) -> std::io::Result<(String, Box<dyn Write + '_>)> {
+        let path = Path::new(key);
+        let file_name = path.file_name()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"))?;
+        let temp_path = path.with_file_name(format!(".{}.oxidant-tmp", file_name.to_string_lossy()));
+        let file = std::fs::File::create(&temp_path)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            if let Err(e) = file.set_permissions(metadata.permissions()) {
+                std::fs::remove_file(&temp_path).ok();
+                return Err(e);
+            }
+        }
+        Ok((temp_path.to_string_lossy().to_string(), Box::new(SyncedFile(file))))
+    }
+
+    fn commit(&self, temp_key: &str, key: &str) -> std::io::Result<()> {
+        std::fs::rename(temp_key, key)
+    }
+
+    fn discard(&self, temp_key: &str) -> std::io::Result<()> {
+        std::fs::remove_file(temp_key)
+    }
+
+    fn metadata(&self, key: &str) -> std::io::Result<StorageMetadata> {
+        let metadata = std::fs::metadata(key)?;
+        Ok(StorageMetadata { len: metadata.len(), readonly: metadata.permissions().readonly() })
+    }
+}
+
+/// A private copy of stored audio in the temporary directory, removed on drop
+pub(crate) struct ScratchFile {
+    path: PathBuf,
+}
+
+impl ScratchFile {
+    /// Write `data` to a new scratch file, keeping the extension of `name`
+    pub(crate) fn new(data: &[u8], name: &str) -> std::io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let extension = Path::new(name).extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+        let path = std::env::temp_dir().join(format!(
+            "oxidant-{}-{}{}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed), extension
+        ));
+        let mut file = std::fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+        let scratch = ScratchFile { path };
+        file.write_all(data)?;
+        Ok(scratch)
+    }
+
+    pub(crate) fn path(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// An audio file kept in a `Storage` backend
+///
+/// Each call copies the object into a scratch file and opens it as an
+/// `AudioFile`; errors may therefore name the scratch path. Writes go back
+/// to the store only if the file changed.
+pub struct StoredAudio<'a> {
+    storage: &'a dyn Storage,
+    key: String,
+}
+
+impl<'a> StoredAudio<'a> {
+    pub fn new(storage: &'a dyn Storage, key: impl Into<String>) -> Self {
+        StoredAudio { storage, key: key.into() }
+    }
+
+    /// Run a read-only operation on the object
+    pub fn with_audio<T>(&self, read: impl FnOnce(&AudioFile) -> AudioResult<T>) -> AudioResult<T> {
+        let scratch = ScratchFile::new(&self.storage.read(&self.key)?, &self.key)?;
+        read(&AudioFile::new(scratch.path())?)
+    }
+
+    /// Run an operation that may change the file, then store the result
+    pub fn modify<T>(&self, write: impl FnOnce(&AudioFile) -> AudioResult<T>) -> AudioResult<T> {
+        if self.storage.metadata(&self.key)?.readonly {
+            return Err(AudioFileError::ReadOnlyFile(self.key.clone()));
+        }
+        let original = self.storage.read(&self.key)?;
+        let scratch = ScratchFile::new(&original, &self.key)?;
+        let result = write(&AudioFile::new(scratch.path())?)?;
+
+        let modified = std::fs::read(scratch.path())?;
+        if modified != original {
+            self.storage.replace(&self.key, &modified)?;
+        }
+        Ok(result)
+    }
+
+    /// Read the object's metadata
+    pub fn read_metadata(&self) -> AudioResult<Metadata> {
+        self.with_audio(|audio| audio.read_metadata())
+    }
+
+    /// Write `metadata` to the object, as `AudioFile::write_metadata` does
+    pub fn write_metadata(&self, metadata: &Metadata) -> AudioResult<()> {
+        self.modify(|audio| audio.write_metadata(metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    /// Objects kept in memory, recording each storage call
+    #[derive(Default)]
+    struct MemoryStorage {
+        objects: std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>,
+        readonly: bool,
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    struct MemoryWriter<'a> {
+        storage: &'a MemoryStorage,
+        key: String,
+    }
+
+    impl std::io::Write for MemoryWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.storage.objects.lock().unwrap().entry(self.key.clone()).or_default().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Storage for MemoryStorage {
+        fn open_read(&self, key: &str) -> std::io::Result<Box<dyn Read + '_>> {
+            self.calls.lock().unwrap().push(format!("open_read {}", key));
+            let data = self.objects.lock().unwrap().get(key).cloned()
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+            Ok(Box::new(std::io::Cursor::new(data)))
+        }
+
+        fn open_write_temp(&self, key: &str) -> std::io::Result<(String, Box<dyn std::io::Write + '_>)> {
+            self.calls.lock().unwrap().push(format!("open_write_temp {}", key));
+            let temp_key = format!("{}.tmp", key);
+            self.objects.lock().unwrap().insert(temp_key.clone(), Vec::new());
+            Ok((temp_key.clone(), Box::new(MemoryWriter { storage: self, key: temp_key })))
+        }
+
+        fn commit(&self, temp_key: &str, key: &str) -> std::io::Result<()> {
+            self.calls.lock().unwrap().push(format!("commit {} {}", temp_key, key));
+            let mut objects = self.objects.lock().unwrap();
+            let data = objects.remove(temp_key).ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+            objects.insert(key.to_string(), data);
+            Ok(())
+        }
+
+        fn discard(&self, temp_key: &str) -> std::io::Result<()> {
+            self.calls.lock().unwrap().push(format!("discard {}", temp_key));
+            self.objects.lock().unwrap().remove(temp_key);
+            Ok(())
+        }
+
+        fn metadata(&self, key: &str) -> std::io::Result<StorageMetadata> {
+            self.calls.lock().unwrap().push(format!("metadata {}", key));
+            let len = self.objects.lock().unwrap().get(key).map(|data| data.len() as u64)
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+            Ok(StorageMetadata { len, readonly: self.readonly })
+        }
+    }
+
+    #[test]
+    fn test_stored_audio_reads_and_writes_through_storage() {
+        let key = "sha256/3f2a.flac";
+        let memory = MemoryStorage::default();
+        memory.objects.lock().unwrap().insert(key.to_string(), build_flac_file("ref", &[("TITLE", "Old")], 64));
+        let stored = StoredAudio::new(&memory, key);
+
+        assert_eq!(stored.read_metadata().unwrap().title.as_deref(), Some("Old"));
+        assert_eq!(*memory.calls.lock().unwrap(), vec![format!("open_read {}", key)]);
+        memory.calls.lock().unwrap().clear();
+
+        stored.write_metadata(&Metadata { title: Some("New".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(*memory.calls.lock().unwrap(), vec![
+            format!("metadata {}", key),
+            format!("open_read {}", key),
+            format!("open_write_temp {}", key),
+            format!("commit {}.tmp {}", key, key),
+        ]);
+        assert_eq!(memory.objects.lock().unwrap().len(), 1);
+        assert_eq!(stored.read_metadata().unwrap().title.as_deref(), Some("New"));
+
+        // An operation that changes nothing stores nothing
+        memory.calls.lock().unwrap().clear();
+        stored.modify(|audio| audio.read_metadata()).unwrap();
+        assert!(!memory.calls.lock().unwrap().iter().any(|call| call.starts_with("commit")));
+
+        // Read-only objects are refused before anything is read
+        let locked = MemoryStorage { readonly: true, ..Default::default() };
+        locked.objects.lock().unwrap().insert(key.to_string(), build_flac_file("ref", &[], 64));
+        let result = StoredAudio::new(&locked, key).write_metadata(&Metadata::default());
+        assert!(matches!(result, Err(AudioFileError::ReadOnlyFile(_))));
+        assert_eq!(*locked.calls.lock().unwrap(), vec![format!("metadata {}", key)]);
+    }
+}
@@ -0,0 +1,277 @@
+// Per-file template variables for metadata values
+//
+// Templates are plain strings with `{variable}` placeholders, e.g.
+// `"Live {existing.year}"` or `"{auto_track:02}"`. Use `{{` and `}}` for
+// literal braces. Shared by batch writing and file renaming.
+
+use crate::{AudioFile, AudioFileError, AudioResult, Metadata};
+use std::path::Path;
+
+/// Metadata fields that can be referenced as `{existing.<field>}` or `{<field>}`
+const FIELDS: &[&str] = &[
+    "title", "artist", "album", "year", "comment", "track", "genre",
+    "album_artist", "composer", "original_date", "original_artist", "original_album", "lyrics",
+];
+
+/// Values available to a template for one file
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    /// File name without extension
+    pub filename: String,
+    /// Name of the directory containing the file
+    pub parent_dir: String,
+    /// 1-based position of the file in the batch, if auto numbering is enabled
+    pub auto_track: Option<usize>,
+    /// Tags currently in the file
+    pub existing: Metadata,
+}
+
+impl TemplateContext {
+    /// Build the context for a file, reading its current tags
+    pub fn for_file(path: &str, auto_track: Option<usize>) -> AudioResult<Self> {
+        let file_path = Path::new(path);
+        let filename = file_path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let parent_dir = file_path.parent()
+            .and_then(|parent| parent.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let existing = AudioFile::new(path.to_string())?.read_metadata()?;
+
+        Ok(TemplateContext { filename, parent_dir, auto_track, existing })
+    }
+
+    /// Resolve a variable name (with optional `:width` spec) to its value
+    fn resolve(&self, variable: &str) -> AudioResult<String> {
+        let (name, spec) = match variable.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (variable, None),
+        };
+
+        let value = match name {
+            "filename" => self.filename.clone(),
+            "parent_dir" => self.parent_dir.clone(),
+            "auto_track" => {
+                let track = self.auto_track.ok_or_else(|| AudioFileError::ParseError(
+                    "{auto_track} requires automatic track numbering to be enabled".to_string()
+                ))?;
+                track.to_string()
+            }
+            _ => {
+                let field = name.strip_prefix("existing.").unwrap_or(name);
+                self.existing_field(field)
+                    .ok_or_else(|| unknown_variable(variable))?
+                    .unwrap_or_default()
+            }
+        };
+
+        match spec {
+            None => Ok(value),
+            Some(spec) => {
+                // `02` pads with zeros, `3` pads with spaces
+                let width: usize = spec.parse()
+                    .map_err(|_| AudioFileError::ParseError(format!("Invalid format spec in {{{}}}", variable)))?;
+                if spec.starts_with('0') {
+                    Ok(format!("{:0>width$}", value, width = width))
+                } else {
+                    Ok(format!("{:>width$}", value, width = width))
+                }
+            }
+        }
+    }
+
+    /// Look up a metadata field; `None` if the field name is unknown
+    fn existing_field(&self, field: &str) -> Option<Option<String>> {
+        let meta = &self.existing;
+        let value = match field {
+            "title" => &meta.title,
+            "artist" => &meta.artist,
+            "album" => &meta.album,
+            "year" => &meta.year,
+            "comment" => &meta.comment,
+            "track" => &meta.track,
+            "genre" => &meta.genre,
+            "album_artist" => &meta.album_artist,
+            "composer" => &meta.composer,
+            "original_date" => &meta.original_date,
+            "original_artist" => &meta.original_artist,
+            "original_album" => &meta.original_album,
+            "lyrics" => &meta.lyrics,
+            _ => return None,
+        };
+        Some(value.clone())
+    }
+}
+
+fn unknown_variable(variable: &str) -> AudioFileError {
+    AudioFileError::ParseError(format!("Unknown template variable: {{{}}}", variable))
+}
+
+/// Split a template into literal text and variable names
+fn parse(template: &str) -> AudioResult<Vec<(bool, String)>> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut variable = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => variable.push(c),
+                        None => {
+                            return Err(AudioFileError::ParseError(
+                                format!("Unclosed template variable in \"{}\"", template)
+                            ));
+                        }
+                    }
+                }
+                if !literal.is_empty() {
+                    parts.push((false, std::mem::take(&mut literal)));
+                }
+                parts.push((true, variable));
+            }
+            '}' => {
+                return Err(AudioFileError::ParseError(
+                    format!("Unmatched '}}' in \"{}\"", template)
+                ));
+            }
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        parts.push((false, literal));
+    }
+    Ok(parts)
+}
+
+/// Check that a template only uses known variables, without needing a file
+pub fn validate(template: &str) -> AudioResult<()> {
+    for (is_variable, text) in parse(template)? {
+        if !is_variable {
+            continue;
+        }
+        let name = text.split_once(':').map_or(text.as_str(), |(name, _)| name);
+        let field = name.strip_prefix("existing.").unwrap_or(name);
+        if !matches!(name, "filename" | "parent_dir" | "auto_track") && !FIELDS.contains(&field) {
+            return Err(unknown_variable(&text));
+        }
+    }
+    Ok(())
+}
+
+/// Render a template for one file
+pub fn render(template: &str, context: &TemplateContext) -> AudioResult<String> {
+    let mut result = String::new();
+    for (is_variable, text) in parse(template)? {
+        if is_variable {
+            result.push_str(&context.resolve(&text)?);
+        } else {
+            result.push_str(&text);
+        }
+    }
+    Ok(result)
+}
+
+/// Render every string value of a metadata JSON object
+pub fn render_json(template: &serde_json::Value, context: &TemplateContext) -> AudioResult<serde_json::Value> {
+    match template {
+        serde_json::Value::String(text) => Ok(serde_json::Value::String(render(text, context)?)),
+        serde_json::Value::Object(map) => {
+            let mut rendered = serde_json::Map::new();
+            for (key, value) in map {
+                rendered.insert(key.clone(), render_json(value, context)?);
+            }
+            Ok(serde_json::Value::Object(rendered))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+/// Check every string value of a metadata JSON object
+pub fn validate_json(template: &serde_json::Value) -> AudioResult<()> {
+    match template {
+        serde_json::Value::String(text) => validate(text),
+        serde_json::Value::Object(map) => map.values().try_for_each(validate_json),
+        _ => Ok(()),
+    }
+}
+
+/// Render a metadata JSON template for each file in order
+///
+/// All templates are resolved before returning, so callers can write the
+/// results knowing that no file will fail halfway through the batch. With
+/// `auto_track`, files are numbered by their position in `paths`.
+pub fn render_for_files(
+    paths: &[String],
+    template: &serde_json::Value,
+    auto_track: bool,
+) -> AudioResult<Vec<serde_json::Value>> {
+    validate_json(template)?;
+
+    paths.iter()
+        .enumerate()
+        .map(|(index, path)| {
+            let context = TemplateContext::for_file(path, auto_track.then_some(index + 1))?;
+            render_json(template, &context)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_template_tags_directory_in_order() {
+        let dir = std::env::temp_dir().join(format!("oxidant-{}-rip", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for name in ["b.mp3", "a.mp3", "c.mp3"] {
+            let path = dir.join(name);
+            std::fs::write(&path, build_id3v2_file(&[("TYER", b"\x002003".to_vec())])).unwrap();
+            paths.push(path.to_string_lossy().to_string());
+        }
+        paths.sort();
+
+        let template: serde_json::Value = serde_json::from_str(
+            r#"{"album": "Live {existing.year} ({parent_dir})", "title": "{filename}", "track": "{auto_track:02}"}"#
+        ).unwrap();
+        let rendered = render_for_files(&paths, &template, true).unwrap();
+        for (path, metadata) in paths.iter().zip(rendered) {
+            AudioFile::new(path.clone()).unwrap().set_metadata(metadata.to_string()).unwrap();
+        }
+
+        let dir_name = dir.file_name().unwrap().to_string_lossy().to_string();
+        for (index, (path, name)) in paths.iter().zip(["a", "b", "c"]).enumerate() {
+            let metadata = AudioFile::new(path.clone()).unwrap().read_metadata().unwrap();
+            assert_eq!(metadata.track, Some(format!("{:02}", index + 1)));
+            assert_eq!(metadata.title.as_deref(), Some(name));
+            assert_eq!(metadata.album, Some(format!("Live 2003 ({})", dir_name)));
+        }
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_template_rejects_unknown_variables() {
+        let template = serde_json::json!({"album": "{albun}"});
+        let paths = vec!["/nonexistent/file.mp3".to_string()];
+        let err = render_for_files(&paths, &template, false).unwrap_err();
+        assert!(err.to_string().contains("Unknown template variable"));
+        assert!(validate("{{literal}} {auto_track:02}").is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+// Fixtures shared by the unit tests of several modules
+
+use crate::flac::vorbis::VorbisComment;
+use crate::flac::{FlacMetadataBlock, FlacMetadataBlockType, FLAC_SIGNATURE};
+use std::io::{Read, Seek};
+
+/// An iTunNORM value as iTunes writes it
+pub(crate) const ITUNNORM_SAMPLE: &str = " 00000292 000002A1 00000668 00000693 00024CA8 00024CA8 00007FFF 00007D8F 00024CA8 00024CA8";
+
+/// Write `data` to a unique file in the system temp directory
+pub(crate) fn write_temp_file(name: &str, data: &[u8]) -> String {
+    let path = std::env::temp_dir().join(format!("oxidant-{}-{}", std::process::id(), name));
+    std::fs::write(&path, data).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+/// Build an ID3v2.3 tag from (frame id, frame data) pairs followed by fake audio
+pub(crate) fn build_id3v2_file(frames: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    build_id3v2_file_version(3, frames)
+}
+
+pub(crate) fn build_id3v2_file_version(major: u8, frames: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (frame_id, data) in frames {
+        body.extend_from_slice(frame_id.as_bytes());
+        let size = data.len() as u32;
+        match major {
+            2 => body.extend_from_slice(&size.to_be_bytes()[1..]),
+            3 => body.extend_from_slice(&size.to_be_bytes()),
+            _ => body.extend_from_slice(&[
+                ((size >> 21) & 0x7F) as u8,
+                ((size >> 14) & 0x7F) as u8,
+                ((size >> 7) & 0x7F) as u8,
+                (size & 0x7F) as u8,
+            ]),
+        }
+        if major > 2 {
+            body.extend_from_slice(&[0, 0]);
+        }
+        body.extend_from_slice(data);
+    }
+    let size = body.len() as u32;
+    let mut file = vec![b'I', b'D', b'3', major, 0, 0];
+    file.extend_from_slice(&[
+        ((size >> 21) & 0x7F) as u8,
+        ((size >> 14) & 0x7F) as u8,
+        ((size >> 7) & 0x7F) as u8,
+        (size & 0x7F) as u8,
+    ]);
+    file.extend_from_slice(&body);
+    file.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    file
+}
+
+pub(crate) fn build_apic_frame(image: &[u8]) -> Vec<u8> {
+    let mut apic = b"\x00image/jpeg\x00\x03\x00".to_vec();
+    apic.extend_from_slice(image);
+    apic
+}
+
+pub(crate) fn ogg_page(sequence: u32, header_type: u8, segments: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut page = b"OggS\x00".to_vec();
+    page.push(header_type);
+    page.extend_from_slice(&[0u8; 8]);
+    page.extend_from_slice(&1u32.to_le_bytes());
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&[0u8; 4]);
+    page.push(segments.len() as u8);
+    page.extend_from_slice(segments);
+    page.extend_from_slice(data);
+    page
+}
+
+pub(crate) fn build_flac_file(vendor: &str, comments: &[(&str, &str)], padding: usize) -> Vec<u8> {
+    let comment = VorbisComment {
+        vendor_string: vendor.to_string(),
+        comments: comments.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+    };
+    let mut data = FLAC_SIGNATURE.to_vec();
+    data.extend(FlacMetadataBlock::new(FlacMetadataBlockType::StreamInfo, vec![0u8; 34]).to_bytes(false));
+    data.extend(FlacMetadataBlock::new(FlacMetadataBlockType::VorbisComment, comment.to_bytes()).to_bytes(false));
+    data.extend(FlacMetadataBlock::padding(padding).to_bytes(true));
+    data.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08]);
+    data
+}
+
+pub(crate) fn build_ogg_file(vendor: &str, comments: &[(&str, &str)]) -> Vec<u8> {
+    let ident = b"\x01vorbis\x00\x00\x00\x00";
+    let comment = VorbisComment {
+        vendor_string: vendor.to_string(),
+        comments: comments.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+    };
+    let mut packet = b"\x03vorbis".to_vec();
+    packet.extend(comment.to_bytes());
+    packet.push(1);
+    let setup = b"\x05vorbis-setup";
+
+    let mut data = ogg_page(0, 0x02, &[ident.len() as u8], ident);
+    let page1_data = [packet.as_slice(), setup].concat();
+    data.extend(ogg_page(1, 0x00, &[packet.len() as u8, setup.len() as u8], &page1_data));
+    data.extend(ogg_page(2, 0x04, &[4], b"\xAA\xBB\xCC\xDD"));
+    data
+}
+
+/// Reader that counts the bytes read through it; seeks are free
+pub(crate) struct CountingReader<R> {
+    pub(crate) inner: R,
+    pub(crate) bytes_read: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read.set(self.bytes_read.get() + n);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+pub(crate) fn mp4_atom(atom_type: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(atom_type);
+    atom.extend_from_slice(payload);
+    atom
+}
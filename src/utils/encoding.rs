@@ -35,9 +35,9 @@ pub fn decode_text(data: &[u8], encoding: TextEncoding) -> String {
         TextEncoding::Utf16 => {
             // Detect BOM
             if data.len() >= 2 {
-                if &data[0..2] == [0xFF, 0xFE] {
+                if data[0..2] == [0xFF, 0xFE] {
                     UTF_16LE.decode(&data[2..]).0.to_string()
-                } else if &data[0..2] == [0xFE, 0xFF] {
+                } else if data[0..2] == [0xFE, 0xFF] {
                     UTF_16BE.decode(&data[2..]).0.to_string()
                 } else {
                     UTF_16LE.decode(data).0.to_string()
@@ -55,6 +55,22 @@ pub fn decode_text(data: &[u8], encoding: TextEncoding) -> String {
     }
 }
 
+/// Undo UTF-8 text that was decoded as Windows-1252, e.g. "CafÃ©" for "Café"
+///
+/// Tagging tools often store UTF-8 in frames marked ISO-8859-1. `None` when
+/// `text` doesn't read as such mojibake: it must map back to Windows-1252
+/// bytes that form valid UTF-8 with at least one multi-byte character.
+pub fn repair_mojibake(text: &str) -> Option<String> {
+    if text.is_ascii() {
+        return None;
+    }
+    let (bytes, _, unmappable) = WINDOWS_1252.encode(text);
+    if unmappable {
+        return None;
+    }
+    String::from_utf8(bytes.into_owned()).ok()
+}
+
 /// Encode text with specified encoding
 #[allow(dead_code)]
 pub fn encode_text(text: &str, encoding: TextEncoding) -> Vec<u8> {
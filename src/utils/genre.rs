@@ -0,0 +1,185 @@
+// Genre names and normalization
+//
+// ID3v1 stores the genre as an index into a fixed list; ID3v2.3 TCON frames
+// may reference the same list as "(17)". Indices 80 and up are the Winamp
+// extensions, which most taggers also accept.
+
+/// ID3v1 genres (0-79) followed by the Winamp extensions (80-191)
+pub const STANDARD_GENRES: &[&str] = &[
+    "Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop",
+    "Jazz", "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap",
+    "Reggae", "Rock", "Techno", "Industrial", "Alternative", "Ska", "Death Metal", "Pranks",
+    "Soundtrack", "Euro-Techno", "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance",
+    "Classical", "Instrumental", "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise",
+    "AlternRock", "Bass", "Soul", "Punk", "Space", "Meditative", "Instrumental Pop", "Instrumental Rock",
+    "Ethnic", "Gothic", "Darkwave", "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream",
+    "Southern Rock", "Comedy", "Cult", "Gangsta", "Top 40", "Christian Rap", "Pop/Funk", "Jungle",
+    "Native American", "Cabaret", "New Wave", "Psychedelic", "Rave", "Showtunes", "Trailer", "Lo-Fi",
+    "Tribal", "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll", "Hard Rock",
+    "Folk", "Folk-Rock", "National Folk", "Swing", "Fast Fusion", "Bebop", "Latin", "Revival",
+    "Celtic", "Bluegrass", "Avantgarde", "Gothic Rock", "Progressive Rock", "Psychedelic Rock", "Symphonic Rock", "Slow Rock",
+    "Big Band", "Chorus", "Easy Listening", "Acoustic", "Humour", "Speech", "Chanson", "Opera",
+    "Chamber Music", "Sonata", "Symphony", "Booty Bass", "Primus", "Porn Groove", "Satire", "Slow Jam",
+    "Club", "Tango", "Samba", "Folklore", "Ballad", "Power Ballad", "Rhythmic Soul", "Freestyle",
+    "Duet", "Punk Rock", "Drum Solo", "A cappella", "Euro-House", "Dance Hall", "Goa", "Drum & Bass",
+    "Club-House", "Hardcore Techno", "Terror", "Indie", "BritPop", "Negerpunk", "Polsk Punk", "Beat",
+    "Christian Gangsta Rap", "Heavy Metal", "Black Metal", "Crossover", "Contemporary Christian", "Christian Rock", "Merengue", "Salsa",
+    "Thrash Metal", "Anime", "Jpop", "Synthpop", "Abstract", "Art Rock", "Baroque", "Bhangra",
+    "Big Beat", "Breakbeat", "Chillout", "Downtempo", "Dub", "EBM", "Eclectic", "Electro",
+    "Electroclash", "Emo", "Experimental", "Garage", "Global", "IDM", "Illbient", "Industro-Goth",
+    "Jam Band", "Krautrock", "Leftfield", "Lounge", "Math Rock", "New Romantic", "Nu-Breakz", "Post-Punk",
+    "Post-Rock", "Psytrance", "Shoegaze", "Space Rock", "Trop Rock", "World Music", "Neoclassical", "Audiobook",
+    "Audio Theatre", "Neue Deutsche Welle", "Podcast", "Indie Rock", "G-Funk", "Dubstep", "Garage Rock", "Psybient",
+];
+
+/// Look up a genre by its ID3v1 index
+pub fn by_index(index: u8) -> Option<&'static str> {
+    STANDARD_GENRES.get(index as usize).copied()
+}
+
+/// Map a genre to its standard name, or `None` if it isn't a standard genre
+///
+/// Accepts names in any case, bare indices ("17") and ID3v2.3 references
+/// ("(17)", "(RX)", "(CR)"). A reference followed by text, as in
+/// "(17)Hard Rock", is a refinement and the text is used instead.
+pub fn normalize(genre: &str) -> Option<String> {
+    let genre = genre.trim();
+
+    if let Some(rest) = genre.strip_prefix('(') {
+        if let Some((reference, refinement)) = rest.split_once(')') {
+            if !refinement.trim().is_empty() {
+                return normalize(refinement);
+            }
+            return resolve_reference(reference);
+        }
+    }
+
+    if let Ok(index) = genre.parse::<u8>() {
+        return by_index(index).map(str::to_string);
+    }
+
+    STANDARD_GENRES.iter()
+        .find(|name| name.eq_ignore_ascii_case(genre))
+        .map(|name| name.to_string())
+}
+
+/// Separator between entries in the joined `genre` string
+pub const SEPARATOR: &str = "; ";
+
+/// Split a joined genre string back into its entries
+pub fn split(joined: &str) -> Vec<String> {
+    joined.split(';')
+        .map(str::trim)
+        .filter(|genre| !genre.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Resolve an ID3v2 genre reference ("17", "RX", "CR") to its name
+fn resolve_reference(reference: &str) -> Option<String> {
+    match reference {
+        "RX" => Some("Remix".to_string()),
+        "CR" => Some("Cover".to_string()),
+        index => index.parse().ok().and_then(by_index).map(str::to_string),
+    }
+}
+
+/// Parse the text of an ID3v2 TCON frame into a list of genres
+///
+/// Entries are NUL-separated (ID3v2.4). Within each entry the ID3v2.3 grammar
+/// applies: any number of "(17)" references followed by free text, with "(("
+/// escaping a literal parenthesis. A bare "17", "RX" or "CR" entry is an
+/// ID3v2.4 reference. Free text repeating the reference before it, as in
+/// "(17)Rock", is not listed twice.
+pub fn parse_tcon(text: &str) -> Vec<String> {
+    let mut genres: Vec<String> = Vec::new();
+
+    for entry in text.split('\0') {
+        let mut rest = entry.trim();
+        let mut referenced = false;
+        while let Some(inner) = rest.strip_prefix('(') {
+            if inner.starts_with('(') {
+                rest = inner;
+                break;
+            }
+            let Some((name, after)) = inner.split_once(')')
+                .and_then(|(reference, after)| Some((resolve_reference(reference)?, after)))
+            else {
+                break;
+            };
+            genres.push(name);
+            rest = after;
+            referenced = true;
+        }
+
+        let rest = rest.trim();
+        if rest.is_empty() {
+            continue;
+        }
+        let name = match resolve_reference(rest) {
+            Some(name) if !referenced => name,
+            _ => rest.to_string(),
+        };
+        if !(referenced && genres.last().is_some_and(|last| last.eq_ignore_ascii_case(&name))) {
+            genres.push(name);
+        }
+    }
+    genres
+}
+
+/// Format genres for an ID3v2.3 TCON frame
+///
+/// Standard genres become "(17)" references, in order; ID3v2.3 allows only
+/// one free-text refinement, so other genres follow them joined with "/".
+pub fn format_tcon_v23(genres: &[String]) -> String {
+    let mut references = String::new();
+    let mut custom = Vec::new();
+    for genre in genres {
+        match genre.as_str() {
+            "Remix" => references.push_str("(RX)"),
+            "Cover" => references.push_str("(CR)"),
+            _ => match STANDARD_GENRES.iter().position(|name| name == genre) {
+                Some(index) => references.push_str(&format!("({})", index)),
+                None => custom.push(genre.as_str()),
+            },
+        }
+    }
+
+    let custom = custom.join("/");
+    if custom.starts_with('(') {
+        references.push('(');
+    }
+    references + &custom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genre_normalize() {
+        assert_eq!(STANDARD_GENRES.len(), 192);
+        assert_eq!(normalize("(17)").as_deref(), Some("Rock"));
+        assert_eq!(normalize("17").as_deref(), Some("Rock"));
+        assert_eq!(normalize(" hip-hop ").as_deref(), Some("Hip-Hop"));
+        assert_eq!(normalize("(17)hard rock").as_deref(), Some("Hard Rock"));
+        assert_eq!(normalize("(RX)").as_deref(), Some("Remix"));
+        assert_eq!(normalize("(191)").as_deref(), Some("Psybient"));
+        assert_eq!(normalize("(192)"), None);
+        assert_eq!(normalize("Vaporwave"), None);
+    }
+
+    #[test]
+    fn test_parse_tcon() {
+        assert_eq!(parse_tcon("(17)(79)Custom"), vec!["Rock", "Hard Rock", "Custom"]);
+        assert_eq!(parse_tcon("(17)Rock"), vec!["Rock"]);
+        assert_eq!(parse_tcon("Rock\0Indie"), vec!["Rock", "Indie"]);
+        assert_eq!(parse_tcon("17\0RX\0(CR)"), vec!["Rock", "Remix", "Cover"]);
+        assert_eq!(parse_tcon("((Bracketed)"), vec!["(Bracketed)"]);
+        assert_eq!(parse_tcon("(999)Unknown"), vec!["(999)Unknown"]);
+
+        let genres = ["Rock", "Custom", "Hard Rock", "Other Custom"].map(String::from);
+        assert_eq!(format_tcon_v23(&genres), "(17)(79)Custom/Other Custom");
+        assert_eq!(format_tcon_v23(&["(Bracketed)".to_string()]), "((Bracketed)");
+    }
+}
@@ -0,0 +1,48 @@
+// Downloading cover art over HTTP (the `http` feature)
+
+use crate::{AudioFileError, AudioResult};
+use std::io::Read;
+use std::time::Duration;
+
+/// Limits applied by `download`
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Largest response body accepted, in bytes
+    pub max_bytes: u64,
+    /// Time allowed for the whole request, connecting included
+    pub timeout: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions { max_bytes: 10 * 1024 * 1024, timeout: Duration::from_secs(30) }
+    }
+}
+
+/// Fetch `url`, returning the body and its Content-Type when that names an image type
+///
+/// Bodies larger than `options.max_bytes` are rejected without being read
+/// in full, whether or not the server announced their length.
+pub fn download(url: &str, options: &DownloadOptions) -> AudioResult<(Vec<u8>, Option<String>)> {
+    let too_large = || AudioFileError::ParseError(format!(
+        "Download from {} is larger than {} bytes", url, options.max_bytes
+    ));
+
+    let agent = ureq::AgentBuilder::new().timeout(options.timeout).build();
+    let response = agent.get(url).call().map_err(|e| {
+        AudioFileError::IoError(std::io::Error::other(format!("Cannot download {}: {}", url, e)))
+    })?;
+
+    let announced = response.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+    if announced.is_some_and(|length| length > options.max_bytes) {
+        return Err(too_large());
+    }
+    let content_type = Some(response.content_type().to_string()).filter(|mime| mime.starts_with("image/"));
+
+    let mut data = Vec::new();
+    response.into_reader().take(options.max_bytes + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > options.max_bytes {
+        return Err(too_large());
+    }
+    Ok((data, content_type))
+}
@@ -0,0 +1,123 @@
+// Image utilities for embedded cover art
+
+/// Detect image MIME type from magic bytes
+pub fn detect_mime_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if data.starts_with(b"BM") {
+        Some("image/bmp")
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some("image/tiff")
+    } else {
+        None
+    }
+}
+
+/// Check whether PNG data is an animated PNG (has an `acTL` chunk before the image data)
+pub fn is_animated_png(data: &[u8]) -> bool {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return false;
+    }
+    let mut offset = 8;
+    while offset + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize;
+        match &data[offset + 4..offset + 8] {
+            b"acTL" => return true,
+            b"IDAT" | b"IEND" => return false,
+            _ => {}
+        }
+        // Chunk length, type, data and CRC
+        offset = match offset.checked_add(12).and_then(|start| start.checked_add(length)) {
+            Some(next) => next,
+            None => return false,
+        };
+    }
+    false
+}
+
+/// Check whether image data is missing its end-of-image marker
+///
+/// Only JPEG (`FFD9`), PNG (`IEND` chunk) and GIF (`3B` trailer) are checked;
+/// other formats are never reported as truncated.
+pub fn appears_truncated(data: &[u8]) -> bool {
+    // Some encoders pad the image with trailing zero bytes
+    let end = data.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    let data_trimmed = &data[..end];
+
+    match detect_mime_type(data) {
+        Some("image/jpeg") => !data_trimmed.ends_with(&[0xFF, 0xD9]),
+        Some("image/png") => {
+            // IEND chunk: length (0), type "IEND", CRC
+            data.len() < 12 || &data[data.len() - 8..data.len() - 4] != b"IEND"
+        }
+        Some("image/gif") => !data_trimmed.ends_with(&[0x3B]),
+        _ => false,
+    }
+}
+
+/// Read the width and height from the image header without decoding the image
+///
+/// Supports PNG, JPEG, GIF, BMP and WebP; `None` for anything else or a
+/// header too short to hold the size.
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be32 = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+    let le16 = |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le24 = |at: usize| Some(u32::from_le_bytes([*data.get(at)?, *data.get(at + 1)?, *data.get(at + 2)?, 0]));
+
+    match detect_mime_type(data)? {
+        "image/png" if data.get(12..16) == Some(b"IHDR") => Some((be32(16)?, be32(20)?)),
+        "image/gif" => Some((le16(6)?, le16(8)?)),
+        "image/bmp" => {
+            // Height is negative for top-down bitmaps
+            let width = i32::from_le_bytes(data.get(18..22)?.try_into().ok()?);
+            let height = i32::from_le_bytes(data.get(22..26)?.try_into().ok()?);
+            Some((width.unsigned_abs(), height.unsigned_abs()))
+        }
+        "image/webp" => match data.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        },
+        "image/jpeg" => jpeg_dimensions(data),
+        _ => None,
+    }
+}
+
+/// Find the frame size in the first SOF segment of a JPEG
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            // Fill bytes before a marker
+            0xFF => pos += 1,
+            // Standalone markers have no length
+            0x01 | 0xD0..=0xD7 => pos += 2,
+            // Start of scan: no frame header before the image data
+            0xDA | 0xD9 => return None,
+            _ => {
+                let length = u16::from_be_bytes(data.get(pos + 2..pos + 4)?.try_into().ok()?) as usize;
+                // SOF0-SOF15, except DHT (C4), JPG (C8) and DAC (CC)
+                if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                    let height = u16::from_be_bytes(data.get(pos + 5..pos + 7)?.try_into().ok()?);
+                    let width = u16::from_be_bytes(data.get(pos + 7..pos + 9)?.try_into().ok()?);
+                    return Some((width as u32, height as u32));
+                }
+                pos += 2 + length;
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 // I/O utilities for reading audio files
 
 use std::io::{Read, Seek, SeekFrom};
+use crate::storage::{FsStorage, Storage};
+use std::path::Path;
 
 /// Read big-endian 16-bit integer
 #[allow(dead_code)]
@@ -52,5 +54,39 @@ pub fn check_signature<R: Read + Seek>(reader: &mut R, signature: &[u8]) -> std:
     let mut buffer = vec![0u8; signature.len()];
     reader.read_exact(&mut buffer)?;
     reader.seek(SeekFrom::Start(pos))?;
-    Ok(&buffer == signature)
-}
\ No newline at end of file
+    Ok(buffer == signature)
+}
+
+/// Compute a stable 64-bit FNV-1a checksum over `length` bytes starting at `offset`
+pub fn checksum_range<R: Read + Seek>(reader: &mut R, offset: u64, length: u64) -> std::io::Result<u64> {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut buffer = [0u8; 8192];
+    let mut remaining = length;
+
+    while remaining > 0 {
+        let to_read = remaining.min(buffer.len() as u64) as usize;
+        reader.read_exact(&mut buffer[..to_read])?;
+        for &byte in &buffer[..to_read] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        remaining -= to_read as u64;
+    }
+
+    Ok(hash)
+}
+
+/// Replace the contents of `path` with `data` without ever leaving it half-written
+///
+/// The data goes to a temporary file next to `path`, which takes over the
+/// original's permissions and is then renamed over it (see `FsStorage`). If
+/// anything fails the temporary file is removed and `path` is untouched.
+pub fn replace_file(path: impl AsRef<Path>, data: &[u8]) -> std::io::Result<()> {
+    let path = path.as_ref().to_str()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path is not valid UTF-8"))?;
+    FsStorage.replace(path, data)
+}
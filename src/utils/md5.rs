@@ -0,0 +1,126 @@
+// MD5 message digest (RFC 1321)
+//
+// Only used to check decoded audio against the signature in FLAC STREAMINFO,
+// so it favours a small incremental implementation over speed.
+
+/// Per-round left rotations
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Incremental MD5 hasher
+pub struct Md5 {
+    state: [u32; 4],
+    /// Per-round additive constants: floor(abs(sin(i + 1)) * 2^32)
+    constants: [u32; 64],
+    /// Bytes of the current, incomplete block
+    buffer: Vec<u8>,
+    /// Total number of bytes hashed
+    length: u64,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Md5 {
+            state: [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476],
+            constants: std::array::from_fn(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32),
+            buffer: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+}
+
+impl Md5 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash `data`, which may be split across calls anywhere
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if !self.buffer.is_empty() {
+            let take = data.len().min(64 - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() < 64 {
+                return;
+            }
+            let block: [u8; 64] = self.buffer[..].try_into().unwrap();
+            self.compress(&block);
+            self.buffer.clear();
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block.try_into().unwrap());
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    /// The 16-byte digest of everything hashed
+    pub fn finalize(mut self) -> [u8; 16] {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        padding.resize((55usize.wrapping_sub(self.buffer.len()) % 64) + 1, 0);
+        padding.extend_from_slice(&bit_length.to_le_bytes());
+        self.update(&padding);
+
+        let mut digest = [0u8; 16];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        let words: Vec<u32> = block.chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for (i, (&shift, &constant)) in SHIFTS.iter().zip(&self.constants).enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(constant).wrapping_add(words[g]).rotate_left(shift);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (word, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5_digests() {
+        let hex = |digest: [u8; 16]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        let digest = |data: &[u8]| {
+            let mut md5 = Md5::new();
+            md5.update(data);
+            hex(md5.finalize())
+        };
+        // RFC 1321 test suite
+        assert_eq!(digest(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(digest(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(digest(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+        let digits = b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        assert_eq!(digest(digits), "57edf4a22be3c955ac49da2e2107b67a");
+        // Updates may split the input anywhere
+        let mut md5 = Md5::new();
+        for chunk in digits.chunks(7) {
+            md5.update(chunk);
+        }
+        assert_eq!(hex(md5.finalize()), "57edf4a22be3c955ac49da2e2107b67a");
+    }
+}
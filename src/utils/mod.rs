@@ -1,4 +1,11 @@
 // Utility functions for audio metadata processing
 
 pub mod encoding;
-pub mod io;
\ No newline at end of file
+pub mod genre;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod image;
+pub mod io;
+pub mod language;
+#[cfg(feature = "verify-audio")]
+pub mod md5;
@@ -0,0 +1,151 @@
+// Expanding command-line paths into audio files
+//
+// Files named explicitly are always kept. Directories are walked when
+// `recursive` is set, keeping files with one of the given extensions or,
+// without an extension filter, every file oxidant can open. Hidden entries
+// (names starting with '.') and symlinks are skipped during the walk unless
+// enabled; a followed link back to a directory being walked is ignored, so
+// link cycles terminate. The result is sorted and free of duplicates.
+
+use crate::{AudioFile, AudioResult};
+use std::path::{Path, PathBuf};
+
+/// How directories are expanded by `expand`
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Walk directories and their subdirectories; otherwise they are kept as given
+    pub recursive: bool,
+    /// File extensions to keep, without the dot and case-insensitive; empty keeps audio files
+    pub extensions: Vec<String>,
+    /// Follow symlinks to files and directories
+    pub follow_symlinks: bool,
+    /// Include files and directories whose name starts with '.'
+    pub hidden: bool,
+}
+
+impl WalkOptions {
+    /// Whether a file found while walking should be kept
+    fn keeps(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return AudioFile::new(path.to_string_lossy().to_string()).is_ok();
+        }
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                self.extensions.iter().any(|wanted| wanted.trim_start_matches('.').eq_ignore_ascii_case(extension))
+            })
+    }
+}
+
+/// Expand `paths` into a sorted list of files according to `options`
+pub fn expand(paths: &[String], options: &WalkOptions) -> AudioResult<Vec<String>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path_buf = PathBuf::from(path);
+        if options.recursive && path_buf.is_dir() {
+            walk(&path_buf, options, &mut Vec::new(), &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Walk `dir`, whose parent directories (resolved) are in `ancestors`
+fn walk(dir: &Path, options: &WalkOptions, ancestors: &mut Vec<PathBuf>, files: &mut Vec<String>) -> AudioResult<()> {
+    let resolved = dir.canonicalize()?;
+    if ancestors.contains(&resolved) {
+        return Ok(());
+    }
+    ancestors.push(resolved);
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !options.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() && !options.follow_symlinks {
+            continue;
+        }
+
+        // A followed symlink takes the type of its target; broken links are skipped
+        if path.is_dir() {
+            walk(&path, options, ancestors, files)?;
+        } else if path.is_file() && options.keeps(&path) {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn test_walk_expand_nested_tree() {
+
+        let root = std::env::temp_dir().join(format!("oxidant-{}-walk", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        let flac = build_flac_file("vendor", &[("TITLE", "Song")], 16);
+        let fixtures: [(&str, Vec<u8>); 7] = [
+            ("a.flac", flac.clone()),
+            ("b.mp3", build_id3v2_file(&[("TIT2", b"\x00Song".to_vec())])),
+            ("notes.txt", b"not audio".to_vec()),
+            (".hidden.flac", flac.clone()),
+            ("sub/c.FLAC", flac.clone()),
+            ("sub/deeper/d.ogg", build_ogg_file("vendor", &[])),
+            (".git/e.flac", flac),
+        ];
+        for (name, data) in &fixtures {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+        let root_str = root.to_string_lossy().to_string();
+        let relative = |files: Vec<String>| -> Vec<String> {
+            files.iter().map(|file| file[root_str.len() + 1..].to_string()).collect()
+        };
+        let roots = vec![root_str.clone()];
+        let run = |options: WalkOptions| relative(expand(&roots, &options).unwrap());
+
+        // Without --recursive a directory is passed through unchanged
+        assert_eq!(expand(&roots, &WalkOptions::default()).unwrap(), roots);
+
+        let recursive = WalkOptions { recursive: true, ..Default::default() };
+        assert_eq!(run(recursive.clone()), vec!["a.flac", "b.mp3", "sub/c.FLAC", "sub/deeper/d.ogg"]);
+        let flac_only = WalkOptions { extensions: vec!["flac".to_string()], ..recursive.clone() };
+        assert_eq!(run(flac_only), vec!["a.flac", "sub/c.FLAC"]);
+        let two_exts = WalkOptions { extensions: vec![".MP3".to_string(), "ogg".to_string()], ..recursive.clone() };
+        assert_eq!(run(two_exts), vec!["b.mp3", "sub/deeper/d.ogg"]);
+        let hidden = WalkOptions { hidden: true, ..recursive.clone() };
+        assert_eq!(run(hidden).len(), 6);
+
+        // Explicit files are kept whatever the filter, and duplicates collapse
+        let explicit = root.join("notes.txt").to_string_lossy().to_string();
+        let files = expand(&[explicit.clone(), root_str.clone(), explicit.clone()], &recursive).unwrap();
+        assert_eq!(files.iter().filter(|file| **file == explicit).count(), 1);
+        assert_eq!(files.len(), 5);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("sub"), root.join("link")).unwrap();
+            // A cycle back to the root
+            std::os::unix::fs::symlink(&root, root.join("sub/deeper/loop")).unwrap();
+
+            assert_eq!(run(recursive.clone()).len(), 4);
+            let follow = WalkOptions { follow_symlinks: true, ..recursive };
+            assert_eq!(run(follow), vec![
+                "a.flac", "b.mp3", "link/c.FLAC", "link/deeper/d.ogg", "sub/c.FLAC", "sub/deeper/d.ogg",
+            ]);
+        }
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}